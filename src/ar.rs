@@ -0,0 +1,138 @@
+//! Reads ar archives (as used by .deb packages and static libraries) in the common, GNU and BSD variants.
+//! Each member is a 60 byte header of ASCII fields followed by its content, padded to 2 bytes.
+
+use std::io::{self, ErrorKind, Read};
+use std::path::PathBuf;
+
+use crate::utils::CountingReader;
+
+pub const MAGIC: &[u8] = b"!<arch>\n";
+/// Members refer to files outside of the archive
+pub const MAGIC_THIN: &[u8] = b"!<thin>\n";
+
+const HEADER_SIZE: u64 = 60;
+/// Headers and contents start at multiples of it
+pub const ALIGNMENT: u64 = 2;
+/// BSD: The name is the first <len> bytes of the content
+const BSD_LONG_NAME: &str = "#1/";
+
+#[derive(Debug, Clone)]
+pub struct ArMember {
+    pub header_offset: u64,
+    pub data_offset: u64,
+    pub name: PathBuf,
+    pub mtime: i64,
+    pub uid: u64,
+    pub gid: u64,
+    pub mode: u32,
+    pub size: u64,
+}
+
+/// Calls on_member for every member with a reader for its content, which doesn't need to be consumed.
+/// Symbol tables are left out. Returns the offset behind the last member.
+pub fn read_members<R, F>(reader: R, mut on_member: F) -> io::Result<u64>
+    where
+        R: Read,
+        F: FnMut(&ArMember, &mut dyn Read) -> io::Result<()> {
+    let mut reader = CountingReader::new(reader);
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(io::Error::new(ErrorKind::InvalidData, "no ar archive"));
+    }
+
+    // GNU: The names longer than 15 bytes, each terminated by "/\n"
+    let mut long_names = vec!();
+    loop {
+        let header_offset = reader.offset;
+        let mut header = [0u8; HEADER_SIZE as usize];
+        let read = read_fully(&mut reader, &mut header)?;
+        if read == 0 {
+            return Ok(header_offset);
+        }
+        if read < header.len() || &header[58..60] != b"`\n" {
+            return Err(invalid(header_offset));
+        }
+
+        let field = |range: std::ops::Range<usize>| String::from_utf8_lossy(&header[range]).trim_end().to_owned();
+        let raw_name = field(0..16);
+        let number = |range: std::ops::Range<usize>, radix: u32| {
+            let value = field(range);
+            if value.is_empty() {
+                return Ok(0);
+            }
+            u64::from_str_radix(&value, radix).map_err(|_| invalid(header_offset))
+        };
+        let mut size = number(48..58, 10)?;
+        let mut data_offset = reader.offset;
+
+        let name = if raw_name == "/" || raw_name == "/SYM64/" || raw_name.starts_with("__.SYMDEF") {
+            None
+        } else if raw_name == "//" {
+            // Grown as it's read rather than allocated upfront, so a corrupt size can't exceed the archive
+            long_names.clear();
+            (&mut reader).take(size).read_to_end(&mut long_names)?;
+            if (long_names.len() as u64) < size {
+                return Err(io::Error::new(ErrorKind::UnexpectedEof, format!("long names table at offset {} ends early", header_offset)));
+            }
+            size = 0;
+            None
+        } else if let Some(index) = raw_name.strip_prefix(BSD_LONG_NAME) {
+            let len: u64 = index.parse().map_err(|_| invalid(header_offset))?;
+            if len > libc::PATH_MAX as u64 {
+                return Err(invalid(header_offset));
+            }
+            let mut name = vec![0u8; len as usize];
+            reader.read_exact(&mut name)?;
+            // Padded with NULs
+            let name = String::from_utf8_lossy(&name).trim_end_matches('\0').to_owned();
+            data_offset += len;
+            size = size.checked_sub(len).ok_or_else(|| invalid(header_offset))?;
+            Some(name)
+        } else if let Some(index) = raw_name.strip_prefix('/') {
+            let start: usize = index.parse().map_err(|_| invalid(header_offset))?;
+            let name = long_names.get(start..).ok_or_else(|| invalid(header_offset))?;
+            let end = name.iter().position(|b| *b == b'\n').unwrap_or(name.len());
+            Some(String::from_utf8_lossy(&name[..end]).trim_end_matches('/').to_owned())
+        } else {
+            Some(raw_name.trim_end_matches('/').to_owned())
+        };
+
+        let mut content = (&mut reader).take(size);
+        if let Some(name) = name {
+            let member = ArMember {
+                header_offset,
+                data_offset,
+                name: PathBuf::from(name),
+                mtime: number(16..28, 10)? as i64,
+                uid: number(28..34, 10)?,
+                gid: number(34..40, 10)?,
+                mode: number(40..48, 8)? as u32,
+                size,
+            };
+            on_member(&member, &mut content)?;
+        }
+        io::copy(&mut content, &mut io::sink())?;
+        if content.limit() > 0 {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, format!("ar archive is truncated, {} bytes of the member at offset {} missing", content.limit(), header_offset)));
+        }
+        // The padding may be missing after the last member
+        reader.skip_padding(ALIGNMENT)?;
+    }
+}
+
+fn invalid(header_offset: u64) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("invalid ar header at offset {}", header_offset))
+}
+
+/// Like read_exact, but returns how much was read at the end of the input
+fn read_fully<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..])? {
+            0 => break,
+            len => read += len,
+        }
+    }
+    Ok(read)
+}
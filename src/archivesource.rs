@@ -0,0 +1,134 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::fs::FileExt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::eviction::CacheStats;
+
+/// Abstracts over where the bytes of an archive come from.
+/// The indexer consumes the archive once as a sequential stream, all later reads are random access.
+pub trait ArchiveSource: fmt::Debug + Send + Sync {
+    /// Returns a reader streaming the whole archive from its very beginning. Used for indexing.
+    fn stream(&self) -> io::Result<Box<dyn Read + '_>>;
+
+    /// Fills buf with the bytes starting at offset
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+
+    /// The total size of the archive in bytes
+    fn size(&self) -> io::Result<u64>;
+
+    /// When the archive was last modified, if known
+    fn modified(&self) -> Option<SystemTime> {
+        None
+    }
+
+    /// The hits and misses of the cache in front of the archive, if there is one
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
+}
+
+impl ArchiveSource for File {
+    fn stream(&self) -> io::Result<Box<dyn Read + '_>> {
+        let mut file = self;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(Box::new(file))
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        FileExt::read_exact_at(self, buf, offset)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        self.metadata().and_then(|m| m.modified()).ok()
+    }
+}
+
+/// The sources of several archives (or parts of one), one after the other
+#[derive(Debug)]
+pub struct ConcatSource {
+    /// Each source with the offset it starts at, in order
+    pub parts: Vec<(u64, Arc<dyn ArchiveSource>)>,
+    size: u64,
+}
+
+impl ConcatSource {
+    pub fn new(sources: Vec<Arc<dyn ArchiveSource>>) -> io::Result<ConcatSource> {
+        let mut parts = Vec::with_capacity(sources.len());
+        let mut size = 0;
+        for source in sources {
+            let part_size = source.size()?;
+            parts.push((size, source));
+            size += part_size;
+        }
+        Ok(ConcatSource { parts, size })
+    }
+}
+
+impl ArchiveSource for ConcatSource {
+    fn stream(&self) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(SourceReader::new(self, 0, self.size)))
+    }
+
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            let i = self.parts.partition_point(|(start, _)| *start <= offset);
+            let (start, source) = match i.checked_sub(1).and_then(|i| self.parts.get(i)) {
+                Some(part) => part,
+                None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "read before the first part")),
+            };
+            let end = self.parts.get(i).map_or(self.size, |(next_start, _)| *next_start);
+            if offset >= end {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "read past the last part"));
+            }
+            let n = (buf.len() as u64).min(end - offset) as usize;
+            let (part_buf, rest) = buf.split_at_mut(n);
+            source.read_exact_at(part_buf, offset - start)?;
+            buf = rest;
+            offset += n as u64;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.size)
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        self.parts.iter().filter_map(|(_, source)| source.modified()).max()
+    }
+}
+
+/// Sequentially reads the range [pos, end) of a source via random access reads
+pub struct SourceReader<'s> {
+    source: &'s dyn ArchiveSource,
+    pos: u64,
+    end: u64,
+}
+
+impl SourceReader<'_> {
+    pub fn new(source: &dyn ArchiveSource, pos: u64, end: u64) -> SourceReader<'_> {
+        SourceReader {
+            source,
+            pos,
+            end,
+        }
+    }
+}
+
+impl Read for SourceReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.end - self.pos;
+        let n = (buf.len() as u64).min(left) as usize;
+        self.source.read_exact_at(&mut buf[..n], self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
@@ -1,5 +1,7 @@
 
-#[derive(Debug)]
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone)]
 pub struct Arena<T> {
     arena: Vec<T>,
 }
@@ -17,25 +19,40 @@ impl<T> Arena<T>
         where
             Indexer: Fn(&T) -> usize {
         let index = indexer(&entry);
-        self.arena.insert(index, entry);
-        (index, self.arena.get_mut(index).unwrap())
+        if index >= self.arena.len() {
+            self.arena.resize_with(index + 1, T::default);
+        }
+        self.arena[index] = entry;
+        (index, &mut self.arena[index])
     }
 
     pub fn get(&self, index: usize) -> Option<&T> {
         self.arena.get(index)
     }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.arena.get_mut(index)
+    }
+
+    /// The index the next entry appended to the arena gets
+    pub fn next_index(&self) -> usize {
+        self.arena.len()
+    }
 }
 
 pub struct ChildrenIterator<'a, T> {
     arena: &'a Arena<T>,
+    /// Maps the inos in children to arena indices
+    ino_map: &'a BTreeMap<u64, usize>,
     children: &'a Vec<u64>,
     index: usize,
 }
 
 impl<'a, T> ChildrenIterator<'a, T> {
-    pub fn new(arena: &'a Arena<T>, children: &'a Vec<u64>) -> ChildrenIterator<'a, T> {
+    pub fn new(arena: &'a Arena<T>, ino_map: &'a BTreeMap<u64, usize>, children: &'a Vec<u64>) -> ChildrenIterator<'a, T> {
         ChildrenIterator {
             arena,
+            ino_map,
             children,
             index: 0,
         }
@@ -52,10 +69,15 @@ impl<'a, T> Iterator for ChildrenIterator<'a, T>
         match child_ino_opt {
             None => None,
             Some(child_ino) => {
-                // TODO ino_to_arena_index
-                let arena_index = (child_ino - 1) as usize;
-                self.arena.get(arena_index)
+                let arena_index = self.ino_map.get(child_ino)?;
+                self.arena.get(*arena_index)
             },
         }
     }
+
+    /// Without walking the skipped children, so paging through huge directories (readdir offsets) stays cheap
+    fn nth(&mut self, n: usize) -> Option<&'a T> {
+        self.index = self.index.saturating_add(n);
+        self.next()
+    }
 }
\ No newline at end of file
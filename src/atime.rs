@@ -0,0 +1,73 @@
+//! Access time emulation (`--atime`): tracks synthetic atimes of files in memory, updated when they are read and
+//! presented by getattr, for tools looking at atimes (e.g. retention policies). They are never persisted.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use fuser::FileAttr;
+
+/// Like the kernel's relatime, atimes older than this get updated on any read
+const RELATIME_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How atimes are presented
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum AtimeMode {
+    /// The atimes of the archive (or mtimes, where it has none), never updated
+    #[default]
+    Static,
+    /// Updated on reads if older than mtime or ctime, or more than a day old (like mount -o relatime)
+    Relatime,
+    /// Updated on every read (like mount -o strictatime)
+    Strict,
+}
+
+impl FromStr for AtimeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<AtimeMode, String> {
+        match s {
+            "static" => Ok(AtimeMode::Static),
+            "relatime" => Ok(AtimeMode::Relatime),
+            "strict" => Ok(AtimeMode::Strict),
+            _ => Err(format!("unknown atime mode: {}", s)),
+        }
+    }
+}
+
+/// The atimes of the files read since mounting, by ino
+#[derive(Debug)]
+pub struct Atimes {
+    mode: AtimeMode,
+    atimes: HashMap<u64, SystemTime>,
+}
+
+impl Atimes {
+    /// None for AtimeMode::Static, which has nothing to track
+    pub fn new(mode: AtimeMode) -> Option<Atimes> {
+        (mode != AtimeMode::Static).then(|| Atimes { mode, atimes: HashMap::new() })
+    }
+
+    /// Records a read of the file with attrs
+    pub fn read(&mut self, attrs: &FileAttr) {
+        let now = SystemTime::now();
+        let atime = self.atimes.get(&attrs.ino).copied().unwrap_or(attrs.atime);
+        let update = match self.mode {
+            AtimeMode::Static => false,
+            AtimeMode::Relatime => atime <= attrs.mtime || atime <= attrs.ctime
+                || now.duration_since(atime).is_ok_and(|age| age >= RELATIME_INTERVAL),
+            AtimeMode::Strict => true,
+        };
+        if update {
+            self.atimes.insert(attrs.ino, now);
+        }
+    }
+
+    /// attrs with the atime tracked for them, if any
+    pub fn presented(&self, attrs: &FileAttr) -> FileAttr {
+        match self.atimes.get(&attrs.ino) {
+            Some(atime) => FileAttr { atime: *atime, ..*attrs },
+            None => *attrs,
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! Records who opened and read what (`--audit-log`), one JSON object per line:
+//! {"time":"2024-05-01T12:00:00Z","uid":1000,"pid":4242,"op":"read","path":"/dir/file","bytes":4096}
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::time::SystemTime;
+
+use log::error;
+
+use crate::utils::{iso8601_date, json_string};
+
+#[derive(Debug)]
+pub struct AuditLog {
+    /// Opened for appending, so every record is written in one go even if others (or logrotate) write to it as well
+    file: File,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> io::Result<AuditLog> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .mode(0o600)
+            .open(path)?;
+        Ok(AuditLog { file })
+    }
+
+    /// Records an op on the entry at path (e.g. "./dir/file") by the process pid of user uid
+    pub fn record(&self, uid: u32, pid: u32, op: &str, path: &Path, bytes: u64) {
+        let path = Path::new("/").join(path.strip_prefix(".").unwrap_or(path));
+        let line = format!("{{\"time\":\"{}\",\"uid\":{},\"pid\":{},\"op\":\"{}\",\"path\":{},\"bytes\":{}}}\n",
+            iso8601_date(SystemTime::now()), uid, pid, op, json_string(&path.to_string_lossy()), bytes);
+        if let Err(e) = (&self.file).write_all(line.as_bytes()) {
+            error!("Unable to write to the audit log: {}", e);
+        }
+    }
+}
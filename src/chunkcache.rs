@@ -0,0 +1,243 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Read;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::{debug, info, warn};
+
+use crate::archivesource::{ArchiveSource, SourceReader};
+use crate::eviction::{CacheStats, EvictionPolicy, Evictor};
+
+/// Granularity in which remote content is fetched and cached
+const CHUNK_SIZE: u64 = 1024 * 1024;
+/// Number of chunks fetched in advance once sequential access is detected
+const READAHEAD_CHUNKS: u64 = 8;
+
+const SIDECAR_MAGIC: &str = "tarfs-chunk-cache";
+/// The format of the sidecar written. Older ones are read as well and written in this one on drop.
+const SIDECAR_VERSION: u32 = 1;
+
+pub struct ChunkCacheOptions {
+    /// Directory holding the cache files. One cache file (plus sidecar) per remote archive.
+    pub dir: PathBuf,
+    /// Upper bound for the cached bytes. Chunks get evicted beyond that, as the policy picks them.
+    pub max_size: u64,
+    pub policy: EvictionPolicy,
+}
+
+/// Wraps a (remote) source and caches all fetched bytes in a local sparse file.
+/// The set of present chunks is persisted in a sidecar file on drop, so re-mounts reuse the cache.
+#[derive(Debug)]
+pub struct ChunkCachedSource<S: ArchiveSource> {
+    inner: S,
+    size: u64,
+    file: File,
+    sidecar_path: PathBuf,
+    identity: String,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Debug)]
+struct CacheState {
+    /// The present chunks
+    chunks: Evictor,
+    /// End of the last read, used to detect sequential access
+    last_end: u64,
+}
+
+impl<S: ArchiveSource> ChunkCachedSource<S> {
+    /// identity is used to tell whether an existing cache belongs to inner, usually the URL
+    pub fn new(inner: S, identity: &str, options: &ChunkCacheOptions) -> io::Result<ChunkCachedSource<S>> {
+        fs::create_dir_all(&options.dir)?;
+        let size = inner.size()?;
+        let name = cache_file_name(identity);
+        let cache_path = options.dir.join(&name);
+        let sidecar_path = options.dir.join(format!("{}.chunks", name));
+
+        let mut state = CacheState {
+            chunks: Evictor::new(options.policy, (options.max_size / CHUNK_SIZE) as usize),
+            last_end: 0,
+        };
+        let identity = format!("{} {}", identity, size);
+        match read_sidecar(&sidecar_path, &identity)? {
+            Some(chunks) => {
+                info!("Reusing {} cached chunks from {}", chunks.len(), cache_path.display());
+                for chunk in chunks {
+                    state.chunks.insert(chunk);
+                }
+            },
+            None => {
+                debug!("No reusable chunk cache at {}", cache_path.display());
+                if cache_path.exists() {
+                    fs::remove_file(&cache_path)?;
+                }
+            },
+        }
+        // Until we write it again on a clean shutdown, the sidecar is not to be trusted
+        if sidecar_path.exists() {
+            fs::remove_file(&sidecar_path)?;
+        }
+
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&cache_path)?;
+        file.set_len(size)?;
+
+        Ok(ChunkCachedSource {
+            inner,
+            size,
+            file,
+            sidecar_path,
+            identity,
+            state: Mutex::new(state),
+        })
+    }
+
+    fn last_chunk(&self) -> u64 {
+        self.size.saturating_sub(1) / CHUNK_SIZE
+    }
+
+    /// Fetches the chunks [first, last] with a single request to the inner source
+    fn fetch(&self, state: &mut CacheState, first: u64, last: u64) -> io::Result<()> {
+        let start = first * CHUNK_SIZE;
+        let end = ((last + 1) * CHUNK_SIZE).min(self.size);
+        debug!("fetching chunks {}-{} (bytes {}-{})", first, last, start, end);
+        let mut buf = vec![0; (end - start) as usize];
+        self.inner.read_exact_at(&mut buf, start)?;
+        self.file.write_all_at(&buf, start)?;
+        for chunk in first..=last {
+            state.chunks.insert(chunk);
+        }
+        Ok(())
+    }
+
+    fn evict(&self, state: &mut CacheState) {
+        for chunk in state.chunks.evict() {
+            if let Err(e) = punch_hole(&self.file, chunk * CHUNK_SIZE, CHUNK_SIZE) {
+                warn!("Unable to free cached chunk {}: {}", chunk, e);
+            }
+        }
+    }
+}
+
+impl<S: ArchiveSource> ArchiveSource for ChunkCachedSource<S> {
+    fn stream(&self) -> io::Result<Box<dyn Read + '_>> {
+        // Going through the cache lets sequential readahead coalesce the index pass into large requests
+        Ok(Box::new(SourceReader::new(self, 0, self.size)))
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let end = offset + buf.len() as u64;
+        if end > self.size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "read beyond end of archive"));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let first = offset / CHUNK_SIZE;
+        let last = (end - 1) / CHUNK_SIZE;
+        let fetch_last = if offset == state.last_end {
+            (last + READAHEAD_CHUNKS).min(self.last_chunk())
+        } else {
+            last
+        };
+        state.last_end = end;
+        for chunk in first..=last {
+            state.chunks.access(chunk);
+        }
+
+        // Fetch each run of missing chunks with one request
+        let mut chunk = first;
+        while chunk <= fetch_last {
+            if state.chunks.contains(chunk) {
+                chunk += 1;
+                continue;
+            }
+            let run_start = chunk;
+            while chunk <= fetch_last && !state.chunks.contains(chunk) {
+                chunk += 1;
+            }
+            self.fetch(&mut state, run_start, chunk - 1)?;
+        }
+
+        FileExt::read_exact_at(&self.file, buf, offset)?;
+        self.evict(&mut state);
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.size)
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        Some(self.state.lock().unwrap().chunks.stats())
+    }
+}
+
+impl<S: ArchiveSource> Drop for ChunkCachedSource<S> {
+    fn drop(&mut self) {
+        let state = self.state.lock().unwrap();
+        let stats = state.chunks.stats();
+        info!("Chunk cache ({}): {} hits, {} misses, {} evictions", stats.policy, stats.hits, stats.misses, stats.evictions);
+        let mut content = format!("{} {}\n{}\n", SIDECAR_MAGIC, SIDECAR_VERSION, self.identity);
+        for chunk in state.chunks.keys() {
+            content.push_str(&format!("{}\n", chunk));
+        }
+        if let Err(e) = fs::write(&self.sidecar_path, content) {
+            warn!("Unable to persist chunk cache state to {}: {}", self.sidecar_path.display(), e);
+        }
+    }
+}
+
+/// The chunks listed in the sidecar at path, None if there is none (or it's not usable). Fails for sidecars of a newer
+/// format, rather than discarding a cache a newer version of tarfs still uses.
+fn read_sidecar(path: &Path, identity: &str) -> io::Result<Option<Vec<u64>>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => return Ok(None),
+    };
+    let mut lines = content.lines();
+    let version = lines.next()
+        .and_then(|line| line.strip_prefix(SIDECAR_MAGIC))
+        .and_then(|version| version.trim().parse::<u32>().ok());
+    match version {
+        Some(version) if version > SIDECAR_VERSION => Err(io::Error::other(format!(
+            "the chunk cache {} was written by a newer version of tarfs (format {}, this one knows up to {}), use another cache dir",
+            path.display(), version, SIDECAR_VERSION))),
+        // The first format, nothing to migrate yet
+        Some(1) if lines.next() == Some(identity) => Ok(lines.map(|l| l.parse::<u64>().ok()).collect()),
+        _ => Ok(None),
+    }
+}
+
+/// A file name that is stable across runs and recognizable for humans
+fn cache_file_name(identity: &str) -> String {
+    // FNV-1a
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in identity.bytes() {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    let base: String = identity.rsplit('/')
+        .next()
+        .unwrap_or("")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '-' || *c == '_')
+        .take(64)
+        .collect();
+    format!("{}-{:016x}", base, hash)
+}
+
+fn punch_hole(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    let res = unsafe {
+        libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE, offset as i64, len as i64)
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,163 @@
+//! Combines the indexes of several archives into one, presenting each archive as a directory at its own subpath (`--at`).
+//! The archives' sources are concatenated, so the combined index reads from them like it would from a single archive.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+
+use log::info;
+
+use crate::archivesource::{ArchiveSource, ConcatSource};
+use crate::tarindex::{IndexEntry, RawHeader, TarIndex, ROOT_INO};
+use crate::tarindexer::{check_memory_usage, set_newest_root_time, update_link_counts, InoMode, Inos, Options, RootTime, TarIndexer, PATH_INO_BIT};
+use crate::deterministic;
+use crate::TarFsError;
+
+/// Checks the subpaths given to --at and returns them relative to the root, e.g. "/data" becomes "data"
+pub fn relative_subpath(subpath: &Path) -> Result<PathBuf, TarFsError> {
+    let mut relative = PathBuf::new();
+    for component in subpath.components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::RootDir | Component::CurDir => (),
+            _ => return Err(TarFsError::MountError {
+                msg: format!("invalid subpath {}: must not contain ..", subpath.display()),
+            }),
+        }
+    }
+    if relative.as_os_str().is_empty() {
+        return Err(TarFsError::MountError {
+            msg: format!("invalid subpath {}: must not be the root", subpath.display()),
+        });
+    }
+    Ok(relative)
+}
+
+/// Creates an index containing the entries of each index at its subpath (relative to the root).
+/// Directories above the subpaths look like the root.
+pub fn combine(parts: Vec<(PathBuf, TarIndex)>, options: &Options) -> Result<TarIndex, TarFsError> {
+    let now = Instant::now();
+    for (i, (subpath, _)) in parts.iter().enumerate() {
+        if let Some((other, _)) = parts[..i].iter().find(|(other, _)| subpath.starts_with(other) || other.starts_with(subpath)) {
+            return Err(TarFsError::MountError {
+                msg: format!("subpaths /{} and /{} overlap", other.display(), subpath.display()),
+            });
+        }
+    }
+
+    let source = ConcatSource::new(parts.iter().map(|(_, index)| index.shared_source()).collect())?;
+    let modified = source.modified();
+    let bases: Vec<u64> = source.parts.iter().map(|(start, _)| *start).collect();
+    let capacity = parts.iter().map(|(_, index)| index.entries().count()).sum::<usize>() + 1;
+    let mut combined = TarIndex::new(Box::new(source), capacity);
+    combined.set_normalization(options.normalize);
+    let ino_mode = options.ino_mode;
+    let root = TarIndexer{}.create_root_entry(ROOT_INO, modified, options);
+    let mut inos = Inos::new(ino_mode, 1);
+    let mut dirs: HashMap<PathBuf, u64> = HashMap::new();
+    dirs.insert(PathBuf::new(), ROOT_INO);
+    let mut entries = vec!(root.clone());
+
+    for (i, (subpath, index)) in parts.iter().enumerate() {
+        // Synthesize the directories above subpath
+        let mut parent_ino = ROOT_INO;
+        let mut dir = PathBuf::new();
+        let parent = subpath.parent().unwrap_or_else(|| Path::new(""));
+        for name in parent.iter() {
+            dir.push(name);
+            parent_ino = match dirs.get(&dir) {
+                Some(ino) => *ino,
+                None => {
+                    let ino = inos.for_path(&Path::new(".").join(&dir));
+                    dirs.insert(dir.clone(), ino);
+                    add_child(&mut entries, parent_ino, ino);
+                    entries.push(IndexEntry {
+                        id: ino,
+                        parent_ino: Some(parent_ino),
+                        path: Path::new(".").join(&dir),
+                        name: PathBuf::from(name),
+                        attrs: fuser::FileAttr { ino, ..root.attrs },
+                        children: vec!(),
+                        ..IndexEntry::default()
+                    });
+                    ino
+                },
+            };
+        }
+
+        // Header inos are derived from offsets smaller than the archive's size, so shifting them by the archive's offset
+        // (plus 2 per archive, as they start at 2) keeps them apart from the ones of all other archives.
+        let ino_shift = bases[i] + 2 * i as u64;
+        let mut new_inos = HashMap::new();
+        for entry in index.entries() {
+            let path = combined_path(subpath, &entry.path);
+            let ino = match ino_mode {
+                InoMode::Offset if entry.id != ROOT_INO && entry.id & PATH_INO_BIT == 0 => entry.id + ino_shift,
+                _ => inos.for_path(&path),
+            };
+            new_inos.insert(entry.id, ino);
+        }
+        let remap = |ino: u64| new_inos[&ino];
+        for entry in index.entries() {
+            let mut entry = entry.clone();
+            entry.path = combined_path(subpath, &entry.path);
+            let is_root = entry.id == ROOT_INO;
+            if is_root {
+                entry.parent_ino = Some(parent_ino);
+                entry.name = PathBuf::from(subpath.file_name().expect("subpaths are relative and not empty"));
+            } else {
+                entry.parent_ino = entry.parent_ino.map(remap);
+                entry.link_target_ino = entry.link_target_ino.map(remap);
+            }
+            entry.id = remap(entry.id);
+            entry.attrs.ino = entry.id;
+            entry.children = entry.children.iter().copied().map(remap).collect();
+            for pointer in &mut entry.file_offsets {
+                pointer.raw_file_offset += bases[i];
+            }
+            if is_root {
+                add_child(&mut entries, parent_ino, entry.id);
+            }
+            entries.push(entry);
+        }
+        // Numbered on from the members of the archives before
+        combined.add_raw_headers(index.raw_headers().iter().map(|header| RawHeader { offset: header.offset + bases[i], ..*header }));
+    }
+
+    let mut taken = HashSet::new();
+    for entry in entries {
+        if !taken.insert(entry.id) {
+            return Err(TarFsError::IndexError {
+                msg: format!("ino {} of {} is taken already", entry.id, entry.path.display()),
+            });
+        }
+        combined.insert(entry);
+    }
+    update_link_counts(&mut combined);
+    if options.root_time == RootTime::Newest {
+        set_newest_root_time(&mut combined);
+    }
+    if options.deterministic {
+        deterministic::renumber(&mut combined);
+    }
+    check_memory_usage(&combined, options)?;
+    info!("Combined {} archives. Took {}s.", parts.len(), now.elapsed().as_secs());
+    Ok(combined)
+}
+
+/// Adds child to the children of the directory parent_ino combined so far, one of the few above the subpaths
+fn add_child(entries: &mut [IndexEntry], parent_ino: u64, child: u64) {
+    let parent = entries.iter_mut().rev().find(|entry| entry.id == parent_ino).expect("parents come first");
+    parent.children.push(child);
+}
+
+/// Where the entry at path (e.g. "./dir/file") of the archive at subpath ends up
+fn combined_path(subpath: &Path, path: &Path) -> PathBuf {
+    let mut combined = Path::new(".").join(subpath);
+    for component in path.components() {
+        if let Component::Normal(name) = component {
+            combined.push(name);
+        }
+    }
+    combined
+}
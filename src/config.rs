@@ -0,0 +1,236 @@
+//! Configuration files in (a subset of) TOML: a [defaults] table plus any number of [mounts.<name>] tables, e.g.
+//!
+//! ```toml
+//! [defaults]
+//! inos = "offset"
+//!
+//! [mounts.backup]
+//! archive = "/srv/backup.tar"
+//! mountpoint = "/mnt/backup"
+//! watch = true
+//! ```
+//!
+//! Keys are the long names of the command line flags. Only strings, integers and booleans are supported.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::TarFsError;
+
+/// Read if no config file is given explicitly (and it exists)
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/tarfs.conf";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+impl fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigValue::String(s) => write!(f, "{}", s),
+            ConfigValue::Integer(i) => write!(f, "{}", i),
+            ConfigValue::Boolean(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+pub type ConfigTable = BTreeMap<String, ConfigValue>;
+
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Apply to all mounts. Keys outside of any table count as defaults as well.
+    pub defaults: ConfigTable,
+    pub mounts: BTreeMap<String, ConfigTable>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, TarFsError> {
+        let content = fs::read_to_string(path).map_err(|e| config_error(path, 0, &e.to_string()))?;
+        parse(&content).map_err(|(line, msg)| config_error(path, line, &msg))
+    }
+
+    /// The defaults overridden by the settings of the given mount
+    pub fn settings(&self, mount: Option<&str>) -> Result<ConfigTable, TarFsError> {
+        let mut settings = self.defaults.clone();
+        if let Some(name) = mount {
+            let mount = self.mounts.get(name).ok_or_else(|| TarFsError::ConfigError {
+                msg: format!("no mount named {:?} configured (known: {})", name, self.mounts.keys().cloned().collect::<Vec<_>>().join(", ")),
+            })?;
+            settings.extend(mount.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        Ok(settings)
+    }
+}
+
+fn config_error(path: &Path, line: usize, msg: &str) -> TarFsError {
+    let location = match line {
+        0 => path.display().to_string(),
+        line => format!("{}:{}", path.display(), line),
+    };
+    TarFsError::ConfigError {
+        msg: format!("{}: {}", location, msg),
+    }
+}
+
+fn parse(content: &str) -> Result<Config, (usize, String)> {
+    let mut config = Config::default();
+    // None: [defaults] (or no table yet), Some: [mounts.<name>]
+    let mut current: Option<String> = None;
+    for (number, line) in (1..).zip(content.lines()) {
+        let mut cursor = Cursor { rest: line.trim() };
+        if cursor.rest.is_empty() || cursor.rest.starts_with('#') {
+            continue;
+        }
+
+        if cursor.eat('[') {
+            let mut keys = vec![cursor.key().map_err(|e| (number, e))?];
+            while cursor.eat('.') {
+                keys.push(cursor.key().map_err(|e| (number, e))?);
+            }
+            if !cursor.eat(']') {
+                return Err((number, String::from("expected ] after table name")));
+            }
+            cursor.end().map_err(|e| (number, e))?;
+            current = match keys.as_slice() {
+                [table] if table == "defaults" => None,
+                [table, name] if table == "mounts" => {
+                    if config.mounts.insert(name.clone(), ConfigTable::new()).is_some() {
+                        return Err((number, format!("mount {:?} defined twice", name)));
+                    }
+                    Some(name.clone())
+                },
+                _ => return Err((number, format!("unknown table [{}], expected [defaults] or [mounts.<name>]", keys.join(".")))),
+            };
+            continue;
+        }
+
+        let key = cursor.key().map_err(|e| (number, e))?;
+        if !cursor.eat('=') {
+            return Err((number, format!("expected = after {}", key)));
+        }
+        let value = cursor.value().map_err(|e| (number, e))?;
+        cursor.end().map_err(|e| (number, e))?;
+        let table = match &current {
+            None => &mut config.defaults,
+            Some(name) => config.mounts.get_mut(name).unwrap(),
+        };
+        if table.insert(key.clone(), value).is_some() {
+            return Err((number, format!("{} set twice", key)));
+        }
+    }
+    Ok(config)
+}
+
+struct Cursor<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    /// Skips whitespace, then c if it's next
+    fn eat(&mut self, c: char) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Only whitespace and comments may follow
+    fn end(&mut self) -> Result<(), String> {
+        self.rest = self.rest.trim_start();
+        if self.rest.is_empty() || self.rest.starts_with('#') {
+            Ok(())
+        } else {
+            Err(format!("unexpected {:?}", self.rest))
+        }
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        self.rest = self.rest.trim_start();
+        if self.rest.starts_with('"') || self.rest.starts_with('\'') {
+            return self.string();
+        }
+        let len = self.rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_')).unwrap_or(self.rest.len());
+        if len == 0 {
+            return Err(String::from("expected a key"));
+        }
+        let (key, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Ok(key.to_owned())
+    }
+
+    fn value(&mut self) -> Result<ConfigValue, String> {
+        self.rest = self.rest.trim_start();
+        if self.rest.starts_with('"') || self.rest.starts_with('\'') {
+            return self.string().map(ConfigValue::String);
+        }
+        let len = self.rest.find(|c: char| c.is_whitespace() || c == '#').unwrap_or(self.rest.len());
+        let (token, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        match token {
+            "true" => Ok(ConfigValue::Boolean(true)),
+            "false" => Ok(ConfigValue::Boolean(false)),
+            _ => parse_integer(token)
+                .map(ConfigValue::Integer)
+                .ok_or_else(|| format!("unsupported value {:?} (only strings, decimal integers and booleans are)", token)),
+        }
+    }
+
+    /// A basic ("...", with escapes) or literal ('...') string
+    fn string(&mut self) -> Result<String, String> {
+        let literal = self.rest.starts_with('\'');
+        let mut chars = self.rest.char_indices().skip(1);
+        let mut s = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\'' if literal => {
+                    self.rest = &self.rest[i + 1..];
+                    return Ok(s);
+                },
+                '"' if !literal => {
+                    self.rest = &self.rest[i + 1..];
+                    return Ok(s);
+                },
+                '\\' if !literal => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                                .ok_or_else(|| format!("invalid escape \\u{}", hex))?
+                        },
+                        other => return Err(format!("invalid escape \\{}", other.map(String::from).unwrap_or_default())),
+                    };
+                    s.push(escaped);
+                },
+                c => s.push(c),
+            }
+        }
+        Err(String::from("unterminated string"))
+    }
+}
+
+fn parse_integer(token: &str) -> Option<i64> {
+    let digits = token.strip_prefix('+').unwrap_or(token);
+    let unsigned = digits.strip_prefix('-').unwrap_or(digits);
+    // Underscores are allowed between digits only
+    if unsigned.is_empty() || unsigned.starts_with('_') || unsigned.ends_with('_') || unsigned.contains("__") {
+        return None;
+    }
+    if !unsigned.chars().all(|c| c.is_ascii_digit() || c == '_') {
+        return None;
+    }
+    digits.replace('_', "").parse().ok()
+}
@@ -0,0 +1,144 @@
+//! The control socket: a unix socket accepting one command per line, each answered with one line of JSON
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+
+use log::{debug, error, info};
+
+use crate::fusemount;
+use crate::handles::SharedHandles;
+use crate::health::{self, Health};
+use crate::stats::SharedStats;
+use crate::tarfs::{KernelCache, SharedIndex};
+use crate::tarindex::TarIndex;
+use crate::utils::json_string;
+use crate::TarFsError;
+
+/// Builds the index of the archive to serve instead
+pub type Reload = Box<dyn Fn(&Path, &TarIndex) -> Result<TarIndex, TarFsError> + Send + Sync>;
+
+/// What the commands act on
+pub struct Controlled {
+    pub index: SharedIndex,
+    pub stats: SharedStats,
+    pub handles: SharedHandles,
+    pub mountpoint: PathBuf,
+    /// Only local archives can be replaced
+    pub reload: Option<Reload>,
+    /// Set once mounted
+    pub kernel_cache: Arc<OnceLock<KernelCache>>,
+    pub health: Health,
+}
+
+/// Listens on path until the process exits. A socket left over by a previous mount gets replaced.
+pub fn spawn(path: &Path, controlled: Controlled) -> io::Result<()> {
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if meta.file_type().is_socket() {
+            fs::remove_file(path)?;
+        }
+    }
+    let listener = UnixListener::bind(path)?;
+    let controlled = Arc::new(controlled);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let controlled = controlled.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = handle(stream, &controlled) {
+                            debug!("control connection: {}", e);
+                        }
+                    });
+                },
+                Err(e) => error!("control socket: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle(stream: UnixStream, controlled: &Controlled) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let (command, args) = match line.trim().split_once(' ') {
+            Some((command, args)) => (command, args.trim_start()),
+            None => (line.trim(), ""),
+        };
+        if command.is_empty() {
+            continue;
+        }
+        let (force, arg) = match args.strip_prefix("--force") {
+            Some(arg) => (true, arg.trim_start()),
+            None => (false, args),
+        };
+        let response = match command {
+            "stats" => controlled.stats.to_json(),
+            "busy" => busy(controlled),
+            "healthz" => health::json(&controlled.health.check()),
+            "unmount" => unmount(controlled, force),
+            "reload" => reload(controlled, Path::new(arg), force),
+            command => error_json(&format!("unknown command: {}", command)),
+        };
+        writeln!(writer, "{}", response)?;
+    }
+    Ok(())
+}
+
+/// Whether files are open, and which
+fn busy(controlled: &Controlled) -> String {
+    let inos = controlled.handles.open_inos();
+    let index = controlled.index.read().unwrap();
+    let paths: Vec<String> = inos.iter()
+        .filter_map(|ino| index.get_entry_by_ino(*ino))
+        .map(|entry| json_string(&Path::new("/").join(entry.path.strip_prefix(".").unwrap_or(&entry.path)).to_string_lossy()))
+        .collect();
+    format!("{{\"busy\":{},\"open_files\":[{}]}}", !inos.is_empty(), paths.join(","))
+}
+
+/// Refuses while files are open, unless forced: then the mount is detached and the open files are served until closed
+fn unmount(controlled: &Controlled, force: bool) -> String {
+    if controlled.handles.is_busy() && !force {
+        return error_json("busy: files are open (see busy, or unmount --force)");
+    }
+    info!("Unmounting {} as requested via the control socket", controlled.mountpoint.display());
+    match fusemount::unmount(&controlled.mountpoint, force) {
+        Ok(()) => String::from("{\"unmounted\":true}"),
+        Err(e) => error_json(&format!("unable to unmount: {}", e)),
+    }
+}
+
+/// Swaps in the index of the archive at path, making the kernel forget what changed. The old archive is served until then.
+/// Refused while files are open, unless forced: their readers would get the content of the new archive's entries with the same inos.
+fn reload(controlled: &Controlled, path: &Path, force: bool) -> String {
+    let (build_index, kernel_cache) = match (&controlled.reload, controlled.kernel_cache.get()) {
+        (Some(build_index), Some(kernel_cache)) => (build_index, kernel_cache),
+        (None, _) => return error_json("only local archives can be reloaded"),
+        (_, None) => return error_json("not mounted yet"),
+    };
+    if path.as_os_str().is_empty() {
+        return error_json("reload needs the path of the archive");
+    }
+    if controlled.handles.is_busy() && !force {
+        return error_json("busy: files are open (see busy, or reload --force)");
+    }
+    info!("Reloading from {} as requested via the control socket", path.display());
+    let built = build_index(path, &controlled.index.read().unwrap());
+    match built {
+        Ok(new_index) => {
+            kernel_cache.swap_index(&controlled.index, new_index);
+            info!("Now serving {}", path.display());
+            String::from("{\"reloaded\":true}")
+        },
+        Err(e) => error_json(&format!("unable to reload from {}, still serving the old archive: {}", path.display(), e)),
+    }
+}
+
+fn error_json(msg: &str) -> String {
+    format!("{{\"error\":{}}}", json_string(msg))
+}
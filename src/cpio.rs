@@ -0,0 +1,150 @@
+//! Reads cpio archives in the "new ASCII" (newc) format, as used for Linux initramfs images.
+//! Each entry is a 110 byte header of hex numbers, the name and the content, both padded to 4 bytes.
+//! Initramfs images are often several archives concatenated (e.g. early microcode + the actual one), which are all read.
+
+use std::io::{self, ErrorKind, Read};
+use std::path::PathBuf;
+use std::os::unix::ffi::OsStringExt;
+use std::ffi::OsString;
+
+use crate::problems;
+use crate::utils::CountingReader;
+
+pub const MAGIC_NEWC: &[u8] = b"070701";
+/// Same as newc, plus a checksum of the content we don't verify
+pub const MAGIC_CRC: &[u8] = b"070702";
+/// The "old portable" ASCII format
+pub const MAGIC_ODC: &[u8] = b"070707";
+
+const HEADER_SIZE: u64 = 110;
+/// Headers and contents start at multiples of it
+pub const ALIGNMENT: u64 = 4;
+const TRAILER: &[u8] = b"TRAILER!!!";
+/// Longer names and symlink targets don't resolve on Linux anyway, a header claiming one is corrupt
+const PATH_MAX: u64 = libc::PATH_MAX as u64;
+
+pub fn is_cpio(magic: &[u8]) -> bool {
+    magic.starts_with(MAGIC_NEWC) || magic.starts_with(MAGIC_CRC)
+}
+
+#[derive(Debug, Clone)]
+pub struct CpioEntry {
+    pub header_offset: u64,
+    pub data_offset: u64,
+    pub name: PathBuf,
+    pub ino: u64,
+    pub mode: u32,
+    pub uid: u64,
+    pub gid: u64,
+    pub nlink: u64,
+    pub mtime: i64,
+    pub filesize: u64,
+    pub dev: (u64, u64),
+    /// The content of symlinks
+    pub link_name: Option<PathBuf>,
+}
+
+impl CpioEntry {
+    pub fn file_type(&self) -> u32 {
+        self.mode & libc::S_IFMT
+    }
+}
+
+/// Reads all entries of the (concatenated) archives in reader.
+/// Returns them together with the offset behind the last trailer.
+pub fn read_entries<R: Read>(reader: R) -> io::Result<(Vec<CpioEntry>, u64)> {
+    let mut reader = CountingReader::new(reader);
+    let mut entries = vec!();
+    let mut end_offset = 0;
+    loop {
+        let mut header = [0u8; HEADER_SIZE as usize];
+        if !read_header(&mut reader, &mut header)? {
+            return Ok((entries, end_offset));
+        }
+        let header_offset = reader.offset - HEADER_SIZE;
+        if !is_cpio(&header) {
+            if end_offset > 0 {
+                // E.g. a compressed archive appended to an uncompressed one
+                problems::report(format!("Ignoring everything behind the cpio archive ending at offset {}", end_offset));
+                return Ok((entries, end_offset));
+            }
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("no cpio header found at offset {}", header_offset)));
+        }
+
+        let field = |i: usize| parse_hex(&header[6 + i * 8..6 + (i + 1) * 8], header_offset);
+        let namesize = field(11)?;
+        let filesize = field(6)?;
+        if namesize > PATH_MAX {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("name of {} bytes in the cpio header at offset {}", namesize, header_offset)));
+        }
+        let mut name = vec![0u8; namesize as usize];
+        reader.read_exact(&mut name)?;
+        // The name is NUL terminated
+        if name.last() == Some(&0) {
+            name.pop();
+        }
+        skip(&mut reader, 0)?;
+
+        if name == TRAILER {
+            end_offset = reader.offset;
+            continue;
+        }
+
+        let mut entry = CpioEntry {
+            header_offset,
+            data_offset: reader.offset,
+            name: PathBuf::from(OsString::from_vec(name)),
+            ino: field(0)?,
+            mode: field(1)? as u32,
+            uid: field(2)?,
+            gid: field(3)?,
+            nlink: field(4)?,
+            mtime: field(5)? as i64,
+            filesize,
+            dev: (field(7)?, field(8)?),
+            link_name: None,
+        };
+        if entry.file_type() == libc::S_IFLNK {
+            if filesize > PATH_MAX {
+                return Err(io::Error::new(ErrorKind::InvalidData, format!("symlink target of {} bytes in the cpio header at offset {}", filesize, header_offset)));
+            }
+            let mut target = vec![0u8; filesize as usize];
+            reader.read_exact(&mut target)?;
+            entry.link_name = Some(PathBuf::from(OsString::from_vec(target)));
+        } else {
+            skip(&mut reader, filesize)?;
+        }
+        skip(&mut reader, 0)?;
+        entries.push(entry);
+    }
+}
+
+/// Fills header, skipping the NUL padding between concatenated archives. Returns false at the end of the input.
+fn read_header<R: Read>(reader: &mut CountingReader<R>, header: &mut [u8]) -> io::Result<bool> {
+    loop {
+        let mut first = [0u8; 1];
+        if reader.read(&mut first)? == 0 {
+            return Ok(false);
+        }
+        if first[0] != 0 {
+            header[0] = first[0];
+            reader.read_exact(&mut header[1..])?;
+            return Ok(true);
+        }
+    }
+}
+
+fn parse_hex(field: &[u8], header_offset: u64) -> io::Result<u64> {
+    std::str::from_utf8(field).ok()
+        .and_then(|s| u64::from_str_radix(s, 16).ok())
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, format!("invalid cpio header at offset {}", header_offset)))
+}
+
+/// Skips len bytes plus the padding after them
+fn skip<R: Read>(reader: &mut CountingReader<R>, len: u64) -> io::Result<()> {
+    let expected = len + (reader.offset + len).next_multiple_of(ALIGNMENT) - (reader.offset + len);
+    if reader.skip(len)? + reader.skip_padding(ALIGNMENT)? < expected {
+        return Err(io::Error::new(ErrorKind::UnexpectedEof, format!("cpio archive is truncated at offset {}", reader.offset)));
+    }
+    Ok(())
+}
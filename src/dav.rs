@@ -0,0 +1,85 @@
+//! Serves the index as read-only WebDAV share (RFC 4918, class 1): PROPFIND for listings, GET (with Range) for content
+
+use fuser::FileType;
+
+use crate::httpserver::{content_type, dir_listing, file_response, etag, html_escape, resolve, url_path, Request, Response};
+use crate::tarindex::{IndexEntry, TarIndex};
+use crate::utils::{http_date, iso8601_date};
+
+const ALLOW: &str = "OPTIONS, GET, HEAD, PROPFIND";
+
+pub fn handle(index: &TarIndex, request: &Request) -> Response {
+    match request.method.as_str() {
+        "OPTIONS" => return options(),
+        "GET" | "HEAD" | "PROPFIND" => (),
+        // Everything else would modify the share
+        _ => return Response::new(405).header("Allow", ALLOW),
+    }
+    let entry = match index.lookup_path(&request.path).and_then(|entry| resolve(index, entry)) {
+        Some(entry) => entry,
+        None => return Response::new(404),
+    };
+    match (request.method.as_str(), entry.attrs.kind) {
+        ("PROPFIND", _) => propfind(index, request, entry),
+        (_, FileType::Directory) => dir_listing(index, entry),
+        _ => file_response(index, request, entry, content_type(&entry.name)),
+    }
+}
+
+fn options() -> Response {
+    Response::new(200)
+        .header("DAV", "1")
+        .header("Allow", ALLOW)
+        // Makes Windows' WebClient talk WebDAV
+        .header("MS-Author-Via", "DAV")
+}
+
+/// Always answers with all properties, whatever got asked for
+fn propfind(index: &TarIndex, request: &Request, entry: &IndexEntry) -> Response {
+    let depth = request.header("Depth").unwrap_or("infinity");
+    if depth != "0" && depth != "1" {
+        // Listing the whole archive at once isn't what clients want
+        return Response::new(403).body("application/xml; charset=utf-8", multistatus_error("propfind-finite-depth"));
+    }
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n");
+    push_response(&mut xml, index, entry, entry);
+    if depth == "1" && entry.attrs.kind == FileType::Directory {
+        for child in index.children_iter(entry) {
+            // Dangling symlinks can't be presented
+            if let Some(resolved) = resolve(index, child) {
+                push_response(&mut xml, index, child, resolved);
+            }
+        }
+    }
+    xml.push_str("</D:multistatus>\n");
+    Response::new(207).body("application/xml; charset=utf-8", xml.into_bytes())
+}
+
+/// Describes entry (the name) with the properties of resolved (what symlinks point to)
+fn push_response(xml: &mut String, index: &TarIndex, entry: &IndexEntry, resolved: &IndexEntry) {
+    let attrs = &resolved.attrs;
+    let href = match (resolved.attrs.kind, entry.attrs.kind) {
+        (FileType::Directory, FileType::Symlink) => format!("{}/", url_path(entry)),
+        _ => url_path(entry),
+    };
+    xml.push_str("<D:response>\n");
+    xml.push_str(&format!("<D:href>{}</D:href>\n", html_escape(&href)));
+    xml.push_str("<D:propstat>\n<D:prop>\n");
+    xml.push_str(&format!("<D:displayname>{}</D:displayname>\n", html_escape(&entry.name.to_string_lossy())));
+    xml.push_str(&format!("<D:creationdate>{}</D:creationdate>\n", iso8601_date(attrs.ctime)));
+    xml.push_str(&format!("<D:getlastmodified>{}</D:getlastmodified>\n", http_date(attrs.mtime)));
+    if attrs.kind == FileType::Directory {
+        xml.push_str("<D:resourcetype><D:collection/></D:resourcetype>\n");
+    } else {
+        xml.push_str("<D:resourcetype/>\n");
+        xml.push_str(&format!("<D:getcontentlength>{}</D:getcontentlength>\n", attrs.size));
+        xml.push_str(&format!("<D:getcontenttype>{}</D:getcontenttype>\n", content_type(&entry.name)));
+        xml.push_str(&format!("<D:getetag>{}</D:getetag>\n", html_escape(&etag(index, resolved))));
+    }
+    xml.push_str("</D:prop>\n<D:status>HTTP/1.1 200 OK</D:status>\n</D:propstat>\n</D:response>\n");
+}
+
+fn multistatus_error(condition: &str) -> Vec<u8> {
+    format!("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:error xmlns:D=\"DAV:\"><D:{}/></D:error>\n", condition).into_bytes()
+}
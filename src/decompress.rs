@@ -0,0 +1,150 @@
+//! Presents gzip compressed members ("access.log.gz") decompressed under their name without the extension ("access.log").
+//! The decompressed size is determined while indexing. Reading decompresses from the start of the member,
+//! so decoders are kept around to continue sequential reads where the previous one ended.
+
+use std::fmt;
+use std::io;
+use std::io::{BufReader, Read};
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use flate2::read::MultiGzDecoder;
+use fuser::FileType;
+use log::{debug, info};
+
+use crate::archivesource::ArchiveSource;
+use crate::tarindex::{IndexEntry, TarIndex};
+use crate::problems;
+
+const EXTENSION: &str = "gz";
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// How many members can be read sequentially at the same time without restarting their decompression
+const MAX_DECODERS: usize = 8;
+
+pub fn decompress_members(index: &mut TarIndex) {
+    let now = Instant::now();
+    let candidates: Vec<u64> = index.entries()
+        .filter(|entry| is_candidate(entry))
+        .map(|entry| entry.id)
+        .collect();
+
+    let mut decompressed = 0;
+    for id in candidates {
+        let entry = index.get_entry_by_ino(id).expect("candidates are indexed");
+        let name = PathBuf::from(entry.name.file_stem().expect("candidates have an extension"));
+        let parent_ino = entry.parent_ino.expect("candidates have a parent");
+        if index.lookup_child(parent_ino, name.clone()).is_some() {
+            debug!("Not decompressing {}: {} exists already", entry.path.display(), name.display());
+            continue;
+        }
+        let size = match decompressed_size(index.source(), entry) {
+            Ok(size) => size,
+            Err(e) => {
+                problems::report(format!("Not decompressing {}: {}", entry.path.display(), e));
+                continue;
+            },
+        };
+        let mut entry = entry.clone();
+        entry.attrs.size = size;
+        entry.compressed = true;
+        index.insert(entry);
+        index.rename(id, &name);
+        decompressed += 1;
+    }
+    info!("Decompressed {} members. Took {}s.", decompressed, now.elapsed().as_secs());
+}
+
+/// Regular files ending with .gz which aren't hard linked (the links would keep the old name)
+fn is_candidate(entry: &IndexEntry) -> bool {
+    entry.attrs.kind == FileType::RegularFile
+        && entry.link_target_ino.is_none()
+        && entry.link_count == 0
+        && !entry.file_offsets.is_empty()
+        && entry.parent_ino.is_some()
+        && entry.name.extension().is_some_and(|extension| extension == EXTENSION)
+        && entry.name.file_stem().is_some_and(|stem| !stem.is_empty())
+}
+
+fn decompressed_size(source: &dyn ArchiveSource, entry: &IndexEntry) -> io::Result<u64> {
+    let pointer = &entry.file_offsets[0];
+    let mut magic = [0; 2];
+    if pointer.filesize < magic.len() as u64 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not gzip compressed"));
+    }
+    source.read_exact_at(&mut magic, pointer.raw_file_offset)?;
+    if magic != GZIP_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not gzip compressed"));
+    }
+    let reader = MemberReader { source, pos: pointer.raw_file_offset, end: pointer.raw_file_offset + pointer.filesize };
+    io::copy(&mut MultiGzDecoder::new(BufReader::new(reader)), &mut io::sink())
+}
+
+/// Reads the compressed content of a member
+struct MemberReader<S> {
+    source: S,
+    pos: u64,
+    end: u64,
+}
+
+impl<'s, S: Deref<Target = dyn ArchiveSource + 's>> Read for MemberReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = (buf.len() as u64).min(self.end - self.pos) as usize;
+        self.source.read_exact_at(&mut buf[..n], self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+struct Decoder {
+    ino: u64,
+    /// Offset in the decompressed content the decoder is at
+    pos: u64,
+    decoder: MultiGzDecoder<BufReader<MemberReader<Arc<dyn ArchiveSource>>>>,
+}
+
+/// The decoders of the members read last, most recently used last
+#[derive(Default)]
+pub struct Decoders {
+    decoders: Mutex<Vec<Decoder>>,
+}
+
+impl fmt::Debug for Decoders {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Decoders({})", self.decoders.lock().unwrap().len())
+    }
+}
+
+impl Decoders {
+    /// Fills buf with the decompressed content of entry starting at offset, as far as there is content
+    pub fn read(&self, source: &Arc<dyn ArchiveSource>, entry: &IndexEntry, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let ino = entry.ino();
+        let mut decoders = self.decoders.lock().unwrap();
+        let mut decoder = match decoders.iter().position(|decoder| decoder.ino == ino && decoder.pos <= offset) {
+            Some(i) => decoders.remove(i),
+            None => {
+                let pointer = &entry.file_offsets[0];
+                let reader = MemberReader { source: source.clone(), pos: pointer.raw_file_offset, end: pointer.raw_file_offset + pointer.filesize };
+                Decoder { ino, pos: 0, decoder: MultiGzDecoder::new(BufReader::new(reader)) }
+            },
+        };
+        // Seeking forward means decompressing up to there
+        let skipped = io::copy(&mut (&mut decoder.decoder).take(offset - decoder.pos), &mut io::sink())?;
+        decoder.pos += skipped;
+        let mut filled = 0;
+        while filled < buf.len() {
+            match decoder.decoder.read(&mut buf[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        decoder.pos += filled as u64;
+
+        if decoders.len() >= MAX_DECODERS {
+            decoders.remove(0);
+        }
+        decoders.push(decoder);
+        Ok(filled)
+    }
+}
@@ -0,0 +1,114 @@
+//! Finds regular files with identical content (same size, then same hash, then compared byte by byte)
+//! and makes them read from the same place in the archive, optionally sharing one inode like hard links.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hasher;
+use std::io;
+use std::time::Instant;
+
+use fuser::FileType;
+use log::info;
+
+use crate::tarindex::{IndexEntry, TarIndex};
+use crate::tarindexer::DedupMode;
+
+/// How much content is read at once
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+pub fn dedup(index: &mut TarIndex, mode: DedupMode) -> io::Result<()> {
+    let now = Instant::now();
+
+    // Only files of the same size can be identical
+    let mut by_size: BTreeMap<u64, Vec<u64>> = BTreeMap::new();
+    for entry in index.entries().filter(|entry| is_candidate(entry)) {
+        by_size.entry(entry.attrs.size).or_default().push(entry.id);
+    }
+
+    let mut duplicates = vec!();
+    for ids in by_size.values().filter(|ids| ids.len() > 1) {
+        let mut by_hash: HashMap<u64, Vec<u64>> = HashMap::new();
+        for id in ids {
+            by_hash.entry(content_hash(index, *id)?).or_default().push(*id);
+        }
+        for ids in by_hash.values().filter(|ids| ids.len() > 1) {
+            // Hashes might collide
+            let mut originals: Vec<u64> = vec!();
+            for id in ids {
+                let mut original = None;
+                for candidate in &originals {
+                    if same_content(index, *candidate, *id)? {
+                        original = Some(*candidate);
+                        break;
+                    }
+                }
+                match original {
+                    Some(original) => duplicates.push((original, *id)),
+                    None => originals.push(*id),
+                }
+            }
+        }
+    }
+
+    let mut saved = 0;
+    for (original_id, id) in &duplicates {
+        let original = index.get_entry_by_ino(*original_id).expect("duplicates are indexed").clone();
+        let mut duplicate = index.get_entry_by_ino(*id).expect("duplicates are indexed").clone();
+        saved += duplicate.attrs.size;
+        duplicate.file_offsets = original.file_offsets.clone();
+        // Files already hard linked keep their inode
+        if mode == DedupMode::Inode && duplicate.link_count == 0 {
+            share_inode(index, original, duplicate);
+        } else {
+            index.insert(duplicate);
+        }
+    }
+    info!("Deduplicated {} files ({} bytes). Took {}s.", duplicates.len(), saved, now.elapsed().as_secs());
+    Ok(())
+}
+
+/// Regular files with content of their own, as stored in the archive
+fn is_candidate(entry: &IndexEntry) -> bool {
+    entry.attrs.kind == FileType::RegularFile && entry.link_target_ino.is_none() && !entry.file_offsets.is_empty() && entry.attrs.size > 0
+        && !entry.compressed
+}
+
+/// Turns duplicate into a hard link of original. The link counts are up to the caller.
+fn share_inode(index: &mut TarIndex, original: IndexEntry, mut duplicate: IndexEntry) {
+    duplicate.link_target_ino = Some(original.id);
+    duplicate.attrs = original.attrs;
+    index.insert(duplicate);
+}
+
+fn content_hash(index: &TarIndex, id: u64) -> io::Result<u64> {
+    let entry = index.get_entry_by_ino(id).expect("candidates are indexed");
+    let mut hasher = DefaultHasher::new();
+    let mut offset = 0;
+    while offset < entry.attrs.size {
+        let chunk = read_chunk(index, entry, offset)?;
+        hasher.write(&chunk);
+        offset += chunk.len() as u64;
+    }
+    Ok(hasher.finish())
+}
+
+fn same_content(index: &TarIndex, id: u64, other_id: u64) -> io::Result<bool> {
+    let entry = index.get_entry_by_ino(id).expect("candidates are indexed");
+    let other = index.get_entry_by_ino(other_id).expect("candidates are indexed");
+    let mut offset = 0;
+    while offset < entry.attrs.size {
+        let chunk = read_chunk(index, entry, offset)?;
+        if chunk != read_chunk(index, other, offset)? {
+            return Ok(false);
+        }
+        offset += chunk.len() as u64;
+    }
+    Ok(true)
+}
+
+fn read_chunk(index: &TarIndex, entry: &IndexEntry, offset: u64) -> io::Result<Vec<u8>> {
+    let pointer = &entry.file_offsets[0];
+    let mut chunk = vec![0; CHUNK_SIZE.min(pointer.filesize - offset) as usize];
+    index.source().read_exact_at(&mut chunk, pointer.raw_file_offset + offset)?;
+    Ok(chunk)
+}
@@ -0,0 +1,41 @@
+//! Deterministic presentation (--deterministic): the same archive looks the same in every mount, on every machine.
+//! Children are listed byte-wise sorted by name, and inos are numbered in that order (depth first from the root),
+//! so neither depend on the order of the archive's members or on where their headers are.
+
+use std::collections::HashMap;
+use std::os::unix::ffi::OsStrExt;
+
+use crate::tarindex::{IndexEntry, TarIndex, ROOT_INO};
+
+/// Sorts the children of all directories by name and renumbers all entries in that order
+pub fn renumber(index: &mut TarIndex) {
+    let mut order = vec!();
+    let mut pending = vec!(ROOT_INO);
+    while let Some(ino) = pending.pop() {
+        let entry = match index.get_entry_by_ino(ino) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        order.push(ino);
+        let mut children: Vec<&IndexEntry> = index.children_iter(entry).collect();
+        children.sort_by(|a, b| a.name.as_os_str().as_bytes().cmp(b.name.as_os_str().as_bytes()));
+        pending.extend(children.iter().rev().map(|child| child.id));
+    }
+    let new_inos: HashMap<u64, u64> = order.iter().zip(ROOT_INO..).map(|(old, new)| (*old, new)).collect();
+    let remap = |ino: u64| new_inos.get(&ino).copied().unwrap_or(ino);
+
+    let mut entries: Vec<IndexEntry> = order.iter()
+        .filter_map(|ino| index.get_entry_by_ino(*ino))
+        .cloned()
+        .collect();
+    for entry in &mut entries {
+        entry.id = remap(entry.id);
+        entry.parent_ino = entry.parent_ino.map(remap);
+        entry.link_target_ino = entry.link_target_ino.map(remap);
+        entry.attrs.ino = remap(entry.attrs.ino);
+        // Depth first numbering follows the sorted names of each directory's children
+        entry.children = entry.children.iter().map(|ino| remap(*ino)).collect();
+        entry.children.sort_unstable();
+    }
+    index.replace_entries(entries);
+}
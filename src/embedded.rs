@@ -0,0 +1,25 @@
+//! Mounting archives embedded into the binary, so applications can ship their assets inside it and still
+//! expose them as a filesystem at runtime:
+//!
+//! ```no_run
+//! # use std::path::Path;
+//! tarfslib::embedded::mount(include_bytes!("../tests/embedded.tar"), Path::new("/run/app/assets"))?;
+//! # Ok::<(), tarfslib::TarFsError>(())
+//! ```
+
+use std::path::Path;
+use std::sync::mpsc;
+
+use crate::memorysource::MemorySource;
+use crate::{apply_log_level, mount_source, MountOptions, TarFsError};
+
+/// Mounts the archive at mountpoint with the default options, blocking until it's unmounted
+pub fn mount(archive: &'static [u8], mountpoint: &Path) -> Result<(), TarFsError> {
+    mount_with(archive, mountpoint, &MountOptions::default(), None)
+}
+
+/// Mounts the archive at mountpoint, blocking until it's unmounted. start_signal is sent to once it's mounted.
+pub fn mount_with(archive: &'static [u8], mountpoint: &Path, options: &MountOptions, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(), TarFsError> {
+    apply_log_level(options);
+    mount_source(Box::new(MemorySource::new(archive)), mountpoint, options, start_signal)
+}
@@ -0,0 +1,76 @@
+//! Rate limited error logging: a client hammering a missing path or a broken member would otherwise flood the log with
+//! identical lines. Each kind of error (operation and ino) is logged once per window, the ones suppressed meanwhile are
+//! counted and summarized with the next one logged.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use log::error;
+
+/// How long errors of the same kind are suppressed after one got logged
+const WINDOW: Duration = Duration::from_secs(10);
+/// Kinds of errors tracked at most, those whose window passed get forgotten beyond that
+const MAX_KINDS: usize = 4096;
+
+type LoggedMap = HashMap<(&'static str, u64), Logged>;
+
+struct Logged {
+    at: Instant,
+    suppressed: u64,
+}
+
+fn logged() -> &'static Mutex<LoggedMap> {
+    static LOGGED: OnceLock<Mutex<LoggedMap>> = OnceLock::new();
+    LOGGED.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Logs msg as error unless one of op on ino got logged within the window
+pub fn error(op: &'static str, ino: u64, msg: fmt::Arguments) {
+    let now = Instant::now();
+    let mut logged = logged().lock().unwrap();
+    let suppressed = match logged.get_mut(&(op, ino)) {
+        Some(last) if now.duration_since(last.at) < WINDOW => {
+            last.suppressed += 1;
+            return;
+        },
+        Some(last) => std::mem::replace(last, Logged { at: now, suppressed: 0 }).suppressed,
+        None => {
+            if logged.len() >= MAX_KINDS {
+                forget_expired(&mut logged, now);
+            }
+            logged.insert((op, ino), Logged { at: now, suppressed: 0 });
+            0
+        },
+    };
+    match suppressed {
+        0 => error!("{}", msg),
+        n => error!("{} ({} similar errors suppressed)", msg, n),
+    }
+}
+
+/// Logs how many errors got suppressed since the last one of their kind, e.g. before unmounting
+pub fn flush() {
+    let mut logged = logged().lock().unwrap();
+    for ((op, ino), last) in logged.iter_mut() {
+        report_suppressed(op, *ino, last);
+    }
+}
+
+fn forget_expired(logged: &mut LoggedMap, now: Instant) {
+    logged.retain(|(op, ino), last| {
+        if now.duration_since(last.at) < WINDOW {
+            return true;
+        }
+        report_suppressed(op, *ino, last);
+        false
+    });
+}
+
+fn report_suppressed(op: &str, ino: u64, last: &mut Logged) {
+    if last.suppressed > 0 {
+        error!("{}: {} similar errors of ino {} suppressed", op, last.suppressed, ino);
+        last.suppressed = 0;
+    }
+}
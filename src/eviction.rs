@@ -0,0 +1,321 @@
+//! Which cached items make room for new ones (--cache-policy): `Evictor` tracks the keys of a cache of bounded
+//! capacity, e.g. the chunks of the chunk cache, and picks the ones to evict by one of the policies of EvictionPolicy.
+//! Reads of datasets scanned once evict well by LRU, while container boots re-reading the same few files keep those
+//! cached better by LFU. ARC adapts between the two.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// How a full cache picks the items to evict
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// The least recently used
+    #[default]
+    Lru,
+    /// The least frequently used, the least recently used of those
+    Lfu,
+    /// Adaptive replacement cache: balances between recently and frequently used ones, as the hits tell
+    Arc,
+}
+
+impl EvictionPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EvictionPolicy::Lru => "lru",
+            EvictionPolicy::Lfu => "lfu",
+            EvictionPolicy::Arc => "arc",
+        }
+    }
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<EvictionPolicy, String> {
+        match s {
+            "lru" => Ok(EvictionPolicy::Lru),
+            "lfu" => Ok(EvictionPolicy::Lfu),
+            "arc" => Ok(EvictionPolicy::Arc),
+            _ => Err(format!("unknown cache policy: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for EvictionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Counters of a cache since it was created
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub policy: EvictionPolicy,
+    /// Accesses of cached keys
+    pub hits: u64,
+    /// Accesses of keys not cached
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    pub fn to_json(&self) -> String {
+        format!("{{\"policy\":\"{}\",\"hits\":{},\"misses\":{},\"evictions\":{}}}", self.policy, self.hits, self.misses, self.evictions)
+    }
+}
+
+/// The keys cached, and which of them to evict once there are more than capacity
+#[derive(Debug)]
+pub struct Evictor {
+    capacity: usize,
+    resident: Resident,
+    stats: CacheStats,
+}
+
+#[derive(Debug)]
+enum Resident {
+    Lru(LruList),
+    Lfu(Lfu),
+    Arc(Box<ArcState>),
+}
+
+impl Evictor {
+    pub fn new(policy: EvictionPolicy, capacity: usize) -> Evictor {
+        let resident = match policy {
+            EvictionPolicy::Lru => Resident::Lru(LruList::default()),
+            EvictionPolicy::Lfu => Resident::Lfu(Lfu::default()),
+            EvictionPolicy::Arc => Resident::Arc(Box::default()),
+        };
+        Evictor {
+            capacity,
+            resident,
+            stats: CacheStats { policy, ..Default::default() },
+        }
+    }
+
+    pub fn contains(&self, key: u64) -> bool {
+        match &self.resident {
+            Resident::Lru(list) => list.contains(key),
+            Resident::Lfu(lfu) => lfu.counts.contains_key(&key),
+            Resident::Arc(arc) => arc.t1.contains(key) || arc.t2.contains(key),
+        }
+    }
+
+    /// The number of keys cached
+    pub fn len(&self) -> usize {
+        match &self.resident {
+            Resident::Lru(list) => list.len(),
+            Resident::Lfu(lfu) => lfu.counts.len(),
+            Resident::Arc(arc) => arc.t1.len() + arc.t2.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The keys cached, in no particular order
+    pub fn keys(&self) -> Vec<u64> {
+        match &self.resident {
+            Resident::Lru(list) => list.keys().collect(),
+            Resident::Lfu(lfu) => lfu.counts.keys().copied().collect(),
+            Resident::Arc(arc) => arc.t1.keys().chain(arc.t2.keys()).collect(),
+        }
+    }
+
+    /// Records an access of key, counted as hit if it's cached. Returns whether it is; if not, the caller fetches it
+    /// and inserts it.
+    pub fn access(&mut self, key: u64) -> bool {
+        let hit = match &mut self.resident {
+            Resident::Lru(list) => list.contains(key) && {
+                list.push(key);
+                true
+            },
+            Resident::Lfu(lfu) => lfu.bump(key),
+            Resident::Arc(arc) => (arc.t1.remove(key) || arc.t2.remove(key)) && {
+                // Used more than once
+                arc.t2.push(key);
+                true
+            },
+        };
+        match hit {
+            true => self.stats.hits += 1,
+            false => self.stats.misses += 1,
+        }
+        hit
+    }
+
+    /// Caches key, without evicting anything yet (see evict). Keys cached already are left as they are.
+    pub fn insert(&mut self, key: u64) {
+        if self.contains(key) {
+            return;
+        }
+        let capacity = self.capacity;
+        match &mut self.resident {
+            Resident::Lru(list) => list.push(key),
+            Resident::Lfu(lfu) => lfu.insert(key),
+            Resident::Arc(arc) => arc.insert(key, capacity),
+        }
+    }
+
+    /// Evicts keys until at most capacity are cached, and returns them
+    pub fn evict(&mut self) -> Vec<u64> {
+        let mut evicted = vec!();
+        while self.len() > self.capacity {
+            let victim = match &mut self.resident {
+                Resident::Lru(list) => list.pop_lru(),
+                Resident::Lfu(lfu) => lfu.pop_least(),
+                Resident::Arc(arc) => arc.replace(),
+            };
+            match victim {
+                Some(key) => evicted.push(key),
+                None => break,
+            }
+        }
+        if let Resident::Arc(arc) = &mut self.resident {
+            arc.trim_ghosts(self.capacity);
+        }
+        self.stats.evictions += evicted.len() as u64;
+        evicted
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
+
+/// Keys from the least to the most recently pushed
+#[derive(Debug, Default)]
+struct LruList {
+    order: BTreeMap<u64, u64>,
+    ticks: HashMap<u64, u64>,
+    tick: u64,
+}
+
+impl LruList {
+    fn contains(&self, key: u64) -> bool {
+        self.ticks.contains_key(&key)
+    }
+
+    fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    fn keys(&self) -> impl Iterator<Item = u64> + '_ {
+        self.order.values().copied()
+    }
+
+    /// Adds key as the most recently used one, moving it if it's there already
+    fn push(&mut self, key: u64) {
+        self.tick += 1;
+        if let Some(tick) = self.ticks.insert(key, self.tick) {
+            self.order.remove(&tick);
+        }
+        self.order.insert(self.tick, key);
+    }
+
+    fn remove(&mut self, key: u64) -> bool {
+        match self.ticks.remove(&key) {
+            Some(tick) => {
+                self.order.remove(&tick);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn pop_lru(&mut self) -> Option<u64> {
+        let (_, key) = self.order.pop_first()?;
+        self.ticks.remove(&key);
+        Some(key)
+    }
+}
+
+/// The number of accesses of each key, and the tick of its last one to break ties by
+#[derive(Debug, Default)]
+struct Lfu {
+    counts: HashMap<u64, (u64, u64)>,
+    /// (count, tick, key), the least frequently used first
+    order: BTreeSet<(u64, u64, u64)>,
+    tick: u64,
+}
+
+impl Lfu {
+    /// Counts an access of key, if it's cached
+    fn bump(&mut self, key: u64) -> bool {
+        let (count, tick) = match self.counts.get(&key) {
+            Some(used) => *used,
+            None => return false,
+        };
+        self.order.remove(&(count, tick, key));
+        self.set(key, count + 1);
+        true
+    }
+
+    fn insert(&mut self, key: u64) {
+        self.set(key, 1);
+    }
+
+    fn set(&mut self, key: u64, count: u64) {
+        self.tick += 1;
+        self.counts.insert(key, (count, self.tick));
+        self.order.insert((count, self.tick, key));
+    }
+
+    fn pop_least(&mut self) -> Option<u64> {
+        let (_, _, key) = self.order.pop_first()?;
+        self.counts.remove(&key);
+        Some(key)
+    }
+}
+
+/// Adaptive replacement (Megiddo and Modha): t1 holds the keys used once recently, t2 the ones used more often. b1 and
+/// b2 remember the keys evicted from either; a miss of one of those shifts the target size p of t1 in its favor.
+#[derive(Debug, Default)]
+struct ArcState {
+    t1: LruList,
+    t2: LruList,
+    b1: LruList,
+    b2: LruList,
+    p: usize,
+}
+
+impl ArcState {
+    fn insert(&mut self, key: u64, capacity: usize) {
+        let (b1, b2) = (self.b1.len(), self.b2.len());
+        if self.b1.remove(key) {
+            // Evicted from t1 too early: t1 deserves more room
+            self.p = (self.p + (b2 / b1).max(1)).min(capacity);
+            self.t2.push(key);
+        } else if self.b2.remove(key) {
+            self.p = self.p.saturating_sub((b1 / b2).max(1));
+            self.t2.push(key);
+        } else {
+            self.t1.push(key);
+        }
+    }
+
+    /// Evicts from t1 while it's beyond its target size, from t2 otherwise, remembering the key as evicted
+    fn replace(&mut self) -> Option<u64> {
+        if !self.t1.is_empty() && (self.t1.len() > self.p || self.t2.is_empty()) {
+            let key = self.t1.pop_lru()?;
+            self.b1.push(key);
+            Some(key)
+        } else {
+            let key = self.t2.pop_lru()?;
+            self.b2.push(key);
+            Some(key)
+        }
+    }
+
+    /// Remembers no more evicted keys than capacity for t1, and twice the capacity overall
+    fn trim_ghosts(&mut self, capacity: usize) {
+        while self.t1.len() + self.b1.len() > capacity && self.b1.pop_lru().is_some() {}
+        while self.t1.len() + self.t2.len() + self.b1.len() + self.b2.len() > 2 * capacity && self.b2.pop_lru().is_some() {}
+    }
+}
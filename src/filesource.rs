@@ -0,0 +1,137 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+
+use log::{error, warn};
+
+use crate::archivesource::ArchiveSource;
+use crate::utils::system_time_from_unix;
+
+/// Identifies a specific version of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Fingerprint {
+    dev: u64,
+    ino: u64,
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+}
+
+impl Fingerprint {
+    fn of(meta: &fs::Metadata) -> Fingerprint {
+        Fingerprint {
+            dev: meta.dev(),
+            ino: meta.ino(),
+            size: meta.size(),
+            mtime: meta.mtime(),
+            mtime_nsec: meta.mtime_nsec(),
+        }
+    }
+}
+
+/// A local archive file which makes sure the content it serves still matches the one it was indexed from.
+/// In-place modifications (appending, truncating, overwriting) make all reads fail.
+/// Replacing the file at its path (rename) is only warned about, as we keep reading the original version.
+/// A growing source (see open_growing) tolerates appends, only truncating it makes reads fail.
+#[derive(Debug)]
+pub struct FileSource {
+    file: File,
+    path: PathBuf,
+    fingerprint: Fingerprint,
+    growing: bool,
+    changed: AtomicBool,
+    replaced: AtomicBool,
+}
+
+impl FileSource {
+    pub fn open(path: &Path) -> io::Result<FileSource> {
+        FileSource::open_with(path, false)
+    }
+
+    /// Opens an archive that is still being appended to
+    pub fn open_growing(path: &Path) -> io::Result<FileSource> {
+        FileSource::open_with(path, true)
+    }
+
+    fn open_with(path: &Path, growing: bool) -> io::Result<FileSource> {
+        let file = File::open(path)?;
+        let fingerprint = Fingerprint::of(&file.metadata()?);
+        Ok(FileSource {
+            file,
+            path: path.to_path_buf(),
+            fingerprint,
+            growing,
+            changed: AtomicBool::new(false),
+            replaced: AtomicBool::new(false),
+        })
+    }
+
+    fn check_unchanged(&self) -> io::Result<()> {
+        let current = Fingerprint::of(&self.file.metadata()?);
+        let changed = if self.growing {
+            current.size < self.fingerprint.size
+        } else {
+            current != self.fingerprint
+        };
+        if changed {
+            if !self.changed.swap(true, Ordering::Relaxed) {
+                error!("Archive {} was modified after it was indexed! Failing all reads, please remount.", self.path.display());
+            }
+            return Err(io::Error::other(format!("archive {} changed since it was indexed", self.path.display())));
+        }
+
+        let replaced = match fs::metadata(&self.path) {
+            Ok(meta) => meta.dev() != current.dev || meta.ino() != current.ino,
+            Err(_) => true,
+        };
+        if replaced && !self.replaced.swap(true, Ordering::Relaxed) {
+            warn!("Archive {} was replaced, still serving the version that was mounted", self.path.display());
+        }
+        Ok(())
+    }
+}
+
+impl ArchiveSource for FileSource {
+    fn stream(&self) -> io::Result<Box<dyn Read + '_>> {
+        self.file.stream()
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.check_unchanged()?;
+        ArchiveSource::read_exact_at(&self.file, buf, offset)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        if self.growing {
+            return Ok(self.file.metadata()?.len());
+        }
+        Ok(self.fingerprint.size)
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        Some(system_time_from_unix(self.fingerprint.mtime, self.fingerprint.mtime_nsec as u32))
+    }
+}
+
+/// The parts of an archive split with split(1) using numeric suffixes (e.g. "backup.tar.000", "backup.tar.001", ...), in order,
+/// if path is its first part. The parts are the ones numbered on from it without a gap.
+pub fn split_parts(path: &Path) -> Option<Vec<PathBuf>> {
+    let suffix = path.extension()?.to_str()?;
+    if !suffix.bytes().all(|b| b.is_ascii_digit()) || suffix.parse::<u64>().ok()? != 0 {
+        return None;
+    }
+    let width = suffix.len();
+    let parts: Vec<PathBuf> = (0..)
+        .map(|n| path.with_extension(format!("{:0width$}", n, width = width)))
+        .take_while(|part| part.is_file())
+        .collect();
+    if parts.is_empty() {
+        return None;
+    }
+    Some(parts)
+}
@@ -0,0 +1,136 @@
+//! `tarfs find`: searches the paths of the members of an archive via its index, like `tar -tvf archive.tar | grep` would.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use fuser::FileType;
+use regex::Regex;
+
+use crate::tarindex::{TarIndex, ROOT_INO};
+use crate::utils::{file_type_bits, iso8601_date, mode_string, relative};
+use crate::TarFsError;
+
+/// What paths to find: a glob matches names (e.g. "*.log"), or paths relative to the root if it has a slash
+/// (e.g. "logs/*/app.log", "**" spanning directories); a regex is searched for in those paths
+#[derive(Debug, Clone)]
+pub struct FindPattern {
+    regex: Regex,
+    /// Match the name, not the whole path
+    names: bool,
+}
+
+impl FindPattern {
+    pub fn glob(glob: &str) -> Result<FindPattern, TarFsError> {
+        let mut regex = String::from("^");
+        let mut chars = glob.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    regex.push_str(".*");
+                },
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                '[' => {
+                    let class: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                    match class.strip_prefix('!') {
+                        Some(negated) => regex.push_str(&format!("[^{}]", negated.replace('\\', "\\\\"))),
+                        None => regex.push_str(&format!("[{}]", class.replace('\\', "\\\\"))),
+                    }
+                },
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+        Ok(FindPattern {
+            regex: compile(&regex, glob)?,
+            names: !glob.contains('/'),
+        })
+    }
+
+    pub fn regex(regex: &str) -> Result<FindPattern, TarFsError> {
+        Ok(FindPattern {
+            regex: compile(regex, regex)?,
+            names: false,
+        })
+    }
+
+    /// path is relative to the root, e.g. "dir/file"
+    pub fn matches(&self, path: &Path) -> bool {
+        let candidate = match (self.names, path.file_name()) {
+            (true, Some(name)) => name,
+            _ => path.as_os_str(),
+        };
+        self.regex.is_match(&candidate.to_string_lossy())
+    }
+}
+
+fn compile(regex: &str, pattern: &str) -> Result<Regex, TarFsError> {
+    Regex::new(regex).map_err(|e| TarFsError::ConfigError {
+        msg: format!("invalid pattern {}: {}", pattern, e),
+    })
+}
+
+/// A member whose path matched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Found {
+    /// Relative to the root, e.g. "dir/file"
+    pub path: PathBuf,
+    /// Including the file type bits, as st_mode
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub mtime: SystemTime,
+    /// What a symlink points to, or the path of the file a hard link is another name of
+    pub link_target: Option<PathBuf>,
+    pub hard_link: bool,
+}
+
+/// Like a line of tar -tv, e.g. "-rw-r--r-- 0/0 1234 2019-04-26T11:12:14Z dir/file"
+impl fmt::Display for Found {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut mode = mode_string(self.mode);
+        if self.hard_link {
+            mode.replace_range(..1, "h");
+        }
+        write!(f, "{} {}/{} {} {} {}", mode, self.uid, self.gid, self.size, iso8601_date(self.mtime), self.path.display())?;
+        match &self.link_target {
+            Some(target) if self.hard_link => write!(f, " link to {}", target.display()),
+            Some(target) => write!(f, " -> {}", target.display()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The members whose paths match, sorted by path
+pub fn find(index: &TarIndex, pattern: &FindPattern) -> Vec<Found> {
+    let mut found: Vec<Found> = index.entries()
+        .filter(|entry| entry.id != ROOT_INO)
+        .filter_map(|entry| {
+            let path = relative(&entry.path);
+            if !pattern.matches(&path) {
+                return None;
+            }
+            let hard_link = entry.link_target_ino.is_some();
+            Some(Found {
+                path,
+                mode: file_type_bits(entry.attrs.kind) | entry.attrs.perm as u32,
+                uid: entry.attrs.uid,
+                gid: entry.attrs.gid,
+                size: entry.attrs.size,
+                mtime: entry.attrs.mtime,
+                link_target: match (entry.attrs.kind, hard_link) {
+                    // Hard links name the file by its archive path, symlinks point wherever they like
+                    (_, true) => entry.link_name.as_deref().map(relative),
+                    (FileType::Symlink, _) => entry.link_name.clone(),
+                    _ => None,
+                },
+                hard_link,
+            })
+        })
+        .collect();
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    found
+}
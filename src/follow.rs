@@ -0,0 +1,44 @@
+use std::thread;
+use std::time::Duration;
+
+use log::{error, info};
+
+use crate::TarFsError;
+use crate::tarfs::{KernelCache, SharedIndex};
+use crate::tarindexer::{Options, TarIndexer};
+
+/// How often the archive is checked for new entries
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Periodically adds entries appended to the archive to the live index, making them appear under the mount.
+pub fn spawn_follower(index: SharedIndex, kernel_cache: KernelCache, options: Options) {
+    thread::spawn(move || {
+        loop {
+            thread::sleep(FOLLOW_INTERVAL);
+            if let Err(e) = follow(&index, &kernel_cache, &options) {
+                error!("Stopped following the archive: {}", e);
+                return;
+            }
+        }
+    });
+}
+
+fn follow(index: &SharedIndex, kernel_cache: &KernelCache, options: &Options) -> Result<(), TarFsError> {
+    let indexer = TarIndexer{};
+    // Scanning reads from the archive, so only block other requests while actually adding the entries
+    let appended = indexer.scan_appended(&index.read().unwrap(), options)?;
+    if appended.is_empty() {
+        return Ok(());
+    }
+    let changed = {
+        let mut index = index.write().unwrap();
+        let changed = indexer.append(&mut index, appended, options)?;
+        kernel_cache.index_changed(&index);
+        changed
+    };
+    info!("Added {} appended entries", changed.len());
+
+    // Drops cached lookups that failed before the entries were there
+    kernel_cache.invalidate_entries(changed.iter());
+    Ok(())
+}
@@ -0,0 +1,74 @@
+//! Why FUSE requests fail and the errno each reason is answered with, in one place so the same failure gets the same answer
+//! from every operation.
+
+use fuser::FileType;
+use libc::c_int;
+
+use super::tarindex::{IndexEntry, TarIndex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    /// No entry with that name or ino
+    NotFound,
+    /// Needs a directory (readdir, lookups in it)
+    NotADirectory,
+    /// Doesn't work on directories (read)
+    IsADirectory,
+    /// readlink of anything but a symlink
+    NotASymlink,
+    /// Opening a symlink with O_NOFOLLOW
+    SymlinkLoop,
+    /// Reading the archive failed, or its content isn't what it should be
+    Io,
+    PermissionDenied,
+    /// Anything that would modify the archive
+    ReadOnly,
+    /// No xattr of that name
+    NoAttribute,
+    /// xattrs are disabled, which makes the kernel stop asking
+    NotSupported,
+    /// The xattr doesn't fit into the buffer
+    BufferTooSmall,
+    /// As many files are open as allowed (see --max-open-files)
+    TooManyOpenFiles,
+}
+
+impl FsError {
+    pub fn errno(self) -> c_int {
+        match self {
+            FsError::NotFound => libc::ENOENT,
+            FsError::NotADirectory => libc::ENOTDIR,
+            FsError::IsADirectory => libc::EISDIR,
+            FsError::NotASymlink => libc::EINVAL,
+            FsError::SymlinkLoop => libc::ELOOP,
+            FsError::Io => libc::EIO,
+            FsError::PermissionDenied => libc::EACCES,
+            FsError::ReadOnly => libc::EROFS,
+            FsError::NoAttribute => libc::ENODATA,
+            FsError::NotSupported => libc::ENOSYS,
+            FsError::BufferTooSmall => libc::ERANGE,
+            FsError::TooManyOpenFiles => libc::EMFILE,
+        }
+    }
+}
+
+/// What an operation needs an entry to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expect {
+    Any,
+    Directory,
+    NotDirectory,
+    Symlink,
+}
+
+/// The entry with ino, if it's what the operation expects
+pub fn entry_for(index: &TarIndex, ino: u64, expect: Expect) -> Result<&IndexEntry, FsError> {
+    let entry = index.get_entry_by_ino(ino).ok_or(FsError::NotFound)?;
+    let kind = entry.attrs.kind;
+    match expect {
+        Expect::Directory if kind != FileType::Directory => Err(FsError::NotADirectory),
+        Expect::NotDirectory if kind == FileType::Directory => Err(FsError::IsADirectory),
+        Expect::Symlink if kind != FileType::Symlink => Err(FsError::NotASymlink),
+        _ => Ok(entry),
+    }
+}
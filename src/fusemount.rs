@@ -0,0 +1,127 @@
+//! Mounting as root as well as non-root user: the latter goes through fusermount3 (taken care of by fuser),
+//! but fails with bare OS errors if something is missing. These checks explain what to do instead.
+
+use std::env;
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use fuser::MountOption;
+use log::{info, warn};
+
+const FUSE_DEVICE: &str = "/dev/fuse";
+const FUSE_CONF: &str = "/etc/fuse.conf";
+const FUSERMOUNT_BINS: [&str; 2] = ["fusermount3", "fusermount"];
+
+/// max_read: maximum size of a single read request in bytes (the kernel's default is unlimited, up to max_pages)
+/// default_permissions: let the kernel check permissions, instead of tarfs itself
+pub fn mount_options(max_read: Option<u32>, default_permissions: bool) -> Vec<MountOption> {
+    let mut options = vec![
+        MountOption::FSName("tarfs".to_owned()),
+        MountOption::Subtype("tarfs".to_owned()),
+        MountOption::RO,                    // Let the kernel refuse writes early (see the mutating ops of TarFs)
+    ];
+    if default_permissions {
+        // http://manpages.ubuntu.com/manpages/bionic/en/man8/mount.fuse.8.html#options
+        options.push(MountOption::DefaultPermissions);  // Enable default kernel permission handling
+    }
+    if let Some(max_read) = max_read {
+        options.push(MountOption::CUSTOM(format!("max_read={}", max_read)));
+    }
+    if is_root() || user_allow_other() {
+        options.push(MountOption::AllowOther);  // Allow other users to access the files
+    } else {
+        info!("Only the mounting user can access the mount, add user_allow_other to {} to allow others", FUSE_CONF);
+    }
+    options
+}
+
+/// Fails with an actionable message where mounting would fail with a bare OS error
+pub fn check_prerequisites() -> io::Result<()> {
+    if let Err(e) = OpenOptions::new().read(true).write(true).open(FUSE_DEVICE) {
+        let msg = match e.kind() {
+            ErrorKind::NotFound => format!("{} is missing: load the fuse kernel module (modprobe fuse), or pass the device into the container (e.g. docker run --device {})", FUSE_DEVICE, FUSE_DEVICE),
+            ErrorKind::PermissionDenied => match fs::metadata(FUSE_DEVICE) {
+                Ok(meta) if meta.mode() & 0o060 == 0o060 => {
+                    format!("no permission to open {}: add the user to its group ({}) and log in again", FUSE_DEVICE, group_name(meta.gid()))
+                },
+                _ => format!("no permission to open {}: it's usually accessible by everyone (chmod 0666 {})", FUSE_DEVICE, FUSE_DEVICE),
+            },
+            _ => return Err(e),
+        };
+        return Err(io::Error::new(e.kind(), msg));
+    }
+
+    if !is_root() {
+        let fusermount = match find_fusermount() {
+            Some(path) => path,
+            None => return Err(io::Error::new(ErrorKind::NotFound,
+                "fusermount3 is needed to mount as non-root user, but neither fusermount3 nor fusermount were found: install fuse3 (e.g. apt install fuse3)")),
+        };
+        let setuid = fs::metadata(&fusermount).map(|meta| meta.permissions().mode() & 0o4000 != 0).unwrap_or(false);
+        if !setuid {
+            warn!("{} is not setuid root, mounting as non-root user will likely fail", fusermount.display());
+        }
+    }
+    Ok(())
+}
+
+/// Unmounts the mount at mountpoint, failing with EBUSY while it's in use unless lazy: then it's detached right away
+/// and the files still open keep being served until they are closed.
+pub fn unmount(mountpoint: &Path, lazy: bool) -> io::Result<()> {
+    let path = CString::new(mountpoint.as_os_str().as_bytes())?;
+    let flags = if lazy { libc::MNT_DETACH } else { 0 };
+    if unsafe { libc::umount2(path.as_ptr(), flags) } == 0 {
+        return Ok(());
+    }
+    let e = io::Error::last_os_error();
+    if e.kind() != ErrorKind::PermissionDenied {
+        return Err(e);
+    }
+    // Only root may unmount directly, users go through fusermount
+    let fusermount = find_fusermount().ok_or(e)?;
+    let mut command = Command::new(fusermount);
+    command.arg("-u");
+    if lazy {
+        command.arg("-z");
+    }
+    let output = command.arg(mountpoint).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    Ok(())
+}
+
+pub fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// allow_other is reserved to root unless enabled in /etc/fuse.conf
+fn user_allow_other() -> bool {
+    fs::read_to_string(FUSE_CONF)
+        .map(|conf| conf.lines().any(|line| line.trim() == "user_allow_other"))
+        .unwrap_or(false)
+}
+
+/// Where fuser would look for it
+fn find_fusermount() -> Option<PathBuf> {
+    let mut dirs: Vec<PathBuf> = env::var_os("PATH").map(|path| env::split_paths(&path).collect()).unwrap_or_default();
+    dirs.push(PathBuf::from("/bin"));
+    FUSERMOUNT_BINS.iter()
+        .flat_map(|bin| dirs.iter().map(move |dir| dir.join(bin)))
+        .find(|path| path.is_file())
+}
+
+fn group_name(gid: u32) -> String {
+    let name = fs::read_to_string("/etc/group").ok().and_then(|groups| {
+        groups.lines()
+            .map(|line| line.split(':').collect::<Vec<_>>())
+            .find(|fields| fields.len() > 2 && fields[2] == gid.to_string())
+            .map(|fields| fields[0].to_owned())
+    });
+    name.unwrap_or_else(|| format!("gid {}", gid))
+}
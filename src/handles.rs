@@ -0,0 +1,44 @@
+//! Which entries are open: A mount with open files is busy, unmounting or reloading it would pull the files away from their readers.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub type SharedHandles = Arc<OpenHandles>;
+
+#[derive(Debug, Default)]
+pub struct OpenHandles {
+    /// Open handles by ino, entries without handles are removed
+    counts: Mutex<HashMap<u64, u64>>,
+}
+
+impl OpenHandles {
+    pub fn opened(&self, ino: u64) {
+        *self.counts.lock().unwrap().entry(ino).or_insert(0) += 1;
+    }
+
+    pub fn released(&self, ino: u64) {
+        let mut counts = self.counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&ino) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&ino);
+            }
+        }
+    }
+
+    /// How many handles are open, of all entries
+    pub fn count(&self) -> u64 {
+        self.counts.lock().unwrap().values().sum()
+    }
+
+    pub fn is_busy(&self) -> bool {
+        !self.counts.lock().unwrap().is_empty()
+    }
+
+    /// The inos of the open entries
+    pub fn open_inos(&self) -> Vec<u64> {
+        let mut inos: Vec<u64> = self.counts.lock().unwrap().keys().copied().collect();
+        inos.sort_unstable();
+        inos
+    }
+}
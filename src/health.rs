@@ -0,0 +1,94 @@
+//! Liveness checks for supervisors (e.g. Kubernetes probes): `GET /healthz` on --health-address, and the `healthz`
+//! command of the control socket. A mount is healthy while its FUSE session runs and answers a canary stat on the root.
+
+use std::fs;
+use std::io;
+use std::ffi::CString;
+use std::net::TcpListener;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use log::error;
+
+use crate::httpserver::{self, Response};
+use crate::tarfs::SharedIndex;
+use crate::utils::json_string;
+
+/// A mount not answering the canary within this time counts as wedged
+const CANARY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the checks look at
+#[derive(Clone, Debug)]
+pub struct Health {
+    pub mountpoint: PathBuf,
+    /// Set while the FUSE session runs
+    pub mounted: Arc<AtomicBool>,
+}
+
+impl Health {
+    pub fn check(&self) -> Result<(), String> {
+        if !self.mounted.load(Ordering::Relaxed) {
+            return Err(String::from("the FUSE session is not running"));
+        }
+        // A wedged mount blocks the canary for good, it's left behind then
+        let (tx, rx) = mpsc::channel();
+        let mountpoint = self.mountpoint.clone();
+        thread::spawn(move || {
+            let _ = tx.send(canary(&mountpoint));
+        });
+        match rx.recv_timeout(CANARY_TIMEOUT) {
+            Ok(result) => result,
+            Err(_) => Err(format!("the canary stat on {} did not return within {}s", self.mountpoint.display(), CANARY_TIMEOUT.as_secs())),
+        }
+    }
+
+}
+
+/// {"healthy":true}, or {"healthy":false,"error":"..."}
+pub fn json(checked: &Result<(), String>) -> String {
+    match checked {
+        Ok(()) => String::from("{\"healthy\":true}"),
+        Err(e) => format!("{{\"healthy\":false,\"error\":{}}}", json_string(e)),
+    }
+}
+
+/// Stats the root, which the kernel might answer from its cache, and the file system, which always makes a round trip to
+/// the FUSE session
+fn canary(mountpoint: &Path) -> Result<(), String> {
+    let root = fs::metadata(mountpoint).map_err(|e| format!("unable to stat {}: {}", mountpoint.display(), e))?;
+    let parent = fs::metadata(mountpoint.join("..")).map_err(|e| format!("unable to stat the parent of {}: {}", mountpoint.display(), e))?;
+    if root.dev() == parent.dev() {
+        return Err(format!("{} is not mounted anymore", mountpoint.display()));
+    }
+    let path = CString::new(mountpoint.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+    let mut statfs: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut statfs) } != 0 {
+        return Err(format!("unable to statfs {}: {}", mountpoint.display(), io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+/// Serves GET /healthz on listener until the process exits: 200 while healthy, 503 otherwise, with the json of the check as body
+pub fn spawn_server(listener: TcpListener, index: SharedIndex, health: Health) {
+    thread::spawn(move || {
+        let served = httpserver::serve(index, listener, move |_, request| {
+            match (request.method.as_str(), request.path.to_str()) {
+                ("GET", Some("/healthz")) | ("HEAD", Some("/healthz")) => {
+                    let checked = health.check();
+                    let status = if checked.is_ok() { 200 } else { 503 };
+                    Response::new(status).body("application/json", format!("{}\n", json(&checked)).into_bytes())
+                },
+                (_, Some("/healthz")) => Response::new(405).header("Allow", "GET, HEAD"),
+                _ => Response::new(404),
+            }
+        });
+        if let Err(e) = served {
+            error!("health endpoint: {}", e);
+        }
+    });
+}
@@ -0,0 +1,458 @@
+//! A minimal HTTP/1.1 server for the serving modes (WebDAV, static files): keep-alive, requests bodies with Content-Length only, no TLS
+
+use std::ffi::{OsStr, OsString};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use fuser::FileType;
+use log::{debug, info, warn};
+use percent_encoding::{percent_decode, percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::tarfs::SharedIndex;
+use crate::tarindex::{IndexEntry, TarIndex};
+use crate::utils::{back_off_after_accept_error, http_date};
+use crate::errorlog;
+
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+/// Content is read from the archive and sent in chunks this large
+const CHUNK_SIZE: u64 = 1024 * 1024;
+/// Like Linux' MAXSYMLINKS
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Everything but unreserved characters (RFC 3986) and the path separator
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'/').remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+
+pub struct Request {
+    pub method: String,
+    /// Percent-decoded, without query
+    pub path: PathBuf,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub enum Body {
+    Empty,
+    Bytes(Vec<u8>),
+    /// Content of the entry with the ino, read from the archive while sending it
+    Entry { ino: u64, offset: u64, len: u64 },
+}
+
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Body,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: vec!(),
+            body: Body::Empty,
+        }
+    }
+
+    pub fn header<V: ToString>(mut self, name: &str, value: V) -> Response {
+        self.headers.push((name.to_owned(), value.to_string()));
+        self
+    }
+
+    pub fn body(self, content_type: &str, body: Vec<u8>) -> Response {
+        let mut response = self.header("Content-Type", content_type);
+        response.body = Body::Bytes(body);
+        response
+    }
+
+    fn body_len(&self) -> u64 {
+        match &self.body {
+            Body::Empty => 0,
+            Body::Bytes(bytes) => bytes.len() as u64,
+            Body::Entry { len, .. } => *len,
+        }
+    }
+}
+
+/// Serves requests with handler until the listener fails, one thread per connection
+pub fn serve<H>(index: SharedIndex, listener: TcpListener, handler: H) -> io::Result<()>
+    where
+        H: Fn(&TarIndex, &Request) -> Response + Send + Sync + 'static {
+    info!("Serving HTTP on {}", listener.local_addr()?);
+    let handler = Arc::new(handler);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Unable to accept HTTP connection: {}", e);
+                back_off_after_accept_error(&e);
+                continue;
+            },
+        };
+        let index = index.clone();
+        let handler = handler.clone();
+        thread::spawn(move || {
+            if let Err(e) = serve_connection(&index, stream, handler.as_ref()) {
+                debug!("HTTP connection closed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn serve_connection<H>(index: &SharedIndex, stream: TcpStream, handler: &H) -> io::Result<()>
+    where
+        H: Fn(&TarIndex, &Request) -> Response {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    loop {
+        let (request, keep_alive) = match read_request(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                debug!("Bad HTTP request: {}", e);
+                let response = Response::new(400).body("text/plain", format!("{}\n", e).into_bytes());
+                return write_response(index, &mut writer, "GET", response, false);
+            },
+        };
+        debug!("{} {}", request.method, request.path.display());
+        let response = handler(&index.read().unwrap(), &request);
+        write_response(index, &mut writer, &request.method, response, keep_alive)?;
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+/// Returns the next request on the connection and whether to keep it open afterwards
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<(Request, bool)>> {
+    let mut head = vec!();
+    loop {
+        let len = head.len();
+        if reader.by_ref().take((MAX_HEADER_SIZE - len) as u64).read_until(b'\n', &mut head)? == 0 {
+            if head.is_empty() {
+                return Ok(None);
+            }
+            return Err(invalid("incomplete request header"));
+        }
+        if head.ends_with(b"\r\n\r\n") || head.ends_with(b"\n\n") {
+            break;
+        }
+        if head.len() >= MAX_HEADER_SIZE {
+            return Err(invalid("request header too large"));
+        }
+        if head == b"\r\n" || head == b"\n" {
+            head.clear();   // Tolerate empty lines between requests
+        }
+    }
+    let head = String::from_utf8(head).map_err(|_| invalid("request header is not UTF-8"))?;
+    let mut lines = head.lines();
+
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) => (method, target, version),
+        _ => return Err(invalid("malformed request line")),
+    };
+    let headers: Vec<(String, String)> = lines
+        .filter(|line| !line.is_empty())
+        .map(|line| match line.split_once(':') {
+            Some((name, value)) => Ok((name.trim().to_owned(), value.trim().to_owned())),
+            None => Err(invalid("malformed header")),
+        })
+        .collect::<io::Result<_>>()?;
+
+    let mut request = Request {
+        method: method.to_owned(),
+        path: decode_path(target),
+        headers,
+        body: vec!(),
+    };
+    if request.header("Transfer-Encoding").is_some() {
+        return Err(invalid("chunked request bodies are not supported"));
+    }
+    if let Some(len) = request.header("Content-Length") {
+        let len: usize = len.parse().map_err(|_| invalid("invalid Content-Length"))?;
+        if len > MAX_BODY_SIZE {
+            return Err(invalid("request body too large"));
+        }
+        request.body.resize(len, 0);
+        reader.read_exact(&mut request.body)?;
+    }
+
+    let connection = request.header("Connection").unwrap_or_default().to_ascii_lowercase();
+    let keep_alive = match version {
+        "HTTP/1.0" => connection == "keep-alive",
+        _ => connection != "close",
+    };
+    Ok(Some((request, keep_alive)))
+}
+
+fn write_response(index: &SharedIndex, writer: &mut TcpStream, method: &str, response: Response, keep_alive: bool) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, reason(response.status));
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if response.status != 304 {
+        head.push_str(&format!("Content-Length: {}\r\n", response.body_len()));
+    }
+    head.push_str("Server: tarfs\r\n");
+    if !keep_alive {
+        head.push_str("Connection: close\r\n");
+    }
+    head.push_str("\r\n");
+    writer.write_all(head.as_bytes())?;
+    if method == "HEAD" {
+        return Ok(());
+    }
+
+    match response.body {
+        Body::Empty => (),
+        Body::Bytes(bytes) => writer.write_all(&bytes)?,
+        Body::Entry { ino, offset, len } => {
+            // Don't block index updates while the client takes its time
+            let mut sent = 0;
+            let mut chunk = vec![0; CHUNK_SIZE.min(len) as usize];
+            while sent < len {
+                let chunk = &mut chunk[..CHUNK_SIZE.min(len - sent) as usize];
+                {
+                    let index = index.read().unwrap();
+                    let entry = index.get_entry_by_ino(ino)
+                        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("ino {} vanished", ino)))?;
+                    let n = index.read_into(entry, offset + sent, chunk).map_err(|e| {
+                        errorlog::error("http read", ino, format_args!("{}", e));
+                        io::Error::other(e.to_string())
+                    })?;
+                    if n < chunk.len() {
+                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("ino {} got shorter", ino)));
+                    }
+                }
+                writer.write_all(chunk)?;
+                sent += chunk.len() as u64;
+            }
+        },
+    }
+    writer.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        206 => "Partial Content",
+        207 => "Multi-Status",
+        301 => "Moved Permanently",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        416 => "Range Not Satisfiable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Serves the content of a regular file, honoring Range, If-Range and If-None-Match
+pub fn file_response(index: &TarIndex, request: &Request, entry: &IndexEntry, content_type: &str) -> Response {
+    let size = entry.attrs.size;
+    let etag = etag(index, entry);
+    let response = |status| Response::new(status)
+        .header("ETag", &etag)
+        .header("Last-Modified", http_date(entry.attrs.mtime))
+        .header("Accept-Ranges", "bytes");
+
+    if request.header("If-None-Match").is_some_and(|tags| tags == "*" || tags.split(',').any(|tag| tag.trim() == etag)) {
+        return response(304);
+    }
+
+    let range = match request.header("If-Range") {
+        Some(if_range) if if_range != etag => None,
+        _ => request.header("Range").and_then(|range| parse_range(range, size)),
+    };
+    let whole = Body::Entry { ino: entry.ino(), offset: 0, len: size };
+    let mut response = match range {
+        None => {
+            let mut response = response(200);
+            response.body = whole;
+            response
+        },
+        Some(Ok((start, end))) => {
+            let mut response = response(206).header("Content-Range", format!("bytes {}-{}/{}", start, end, size));
+            response.body = Body::Entry { ino: entry.ino(), offset: start, len: end - start + 1 };
+            response
+        },
+        Some(Err(())) => return response(416).header("Content-Range", format!("bytes */{}", size)),
+    };
+    response.headers.push(("Content-Type".to_owned(), content_type.to_owned()));
+    response
+}
+
+/// Parses a single byte range (e.g. "bytes=0-99", "bytes=100-" or "bytes=-100") into first and last byte.
+/// None if the header should be ignored (e.g. multiple ranges), Err if it is not satisfiable.
+fn parse_range(range: &str, size: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = range.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            if suffix == 0 {
+                return Some(Err(()));
+            }
+            (size.saturating_sub(suffix), size.saturating_sub(1))
+        },
+        (start, "") => (start.parse().ok()?, size.saturating_sub(1)),
+        (start, end) => {
+            let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+            if end < start {
+                return None;
+            }
+            (start, end.min(size.saturating_sub(1)))
+        },
+    };
+    if start >= size {
+        return Some(Err(()));
+    }
+    Some(Ok((start, end)))
+}
+
+/// Guesses the Content-Type from the file extension
+pub fn content_type(name: &Path) -> &'static str {
+    let extension = name.extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" | "log" | "md" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "yaml" | "yml" => "application/yaml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" | "tgz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Changes whenever the content might, as it's derived from the content's position in the archive
+pub fn etag(index: &TarIndex, entry: &IndexEntry) -> String {
+    let offset = entry.file_offsets.first().map(|p| p.raw_file_offset).unwrap_or(0);
+    format!("\"{:x}-{:x}-{:x}\"", offset, entry.attrs.size, index.generation())
+}
+
+/// A simple HTML page linking the children of dir
+pub fn dir_listing(index: &TarIndex, dir: &IndexEntry) -> Response {
+    let url = url_path(dir);
+    let title = html_escape(&String::from_utf8_lossy(&decode(&url)));
+    let mut html = format!("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title></head><body>\n<h1>{}</h1>\n<ul>\n", title, title);
+    if dir.parent_ino.is_some() {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for child in index.children_iter(dir) {
+        let href = url_path(child);
+        let name = html_escape(&child.name.to_string_lossy());
+        let slash = if child.attrs.kind == FileType::Directory { "/" } else { "" };
+        html.push_str(&format!("<li><a href=\"{}\">{}{}</a></li>\n", href, name, slash));
+    }
+    html.push_str("</ul>\n</body></html>\n");
+    Response::new(200).body("text/html; charset=utf-8", html.into_bytes())
+}
+
+/// The percent-encoded URL path of an entry, directories ending with a slash
+pub fn url_path(entry: &IndexEntry) -> String {
+    let mut path = vec!();
+    for component in entry.path.components() {
+        if let Component::Normal(name) = component {
+            path.push(b'/');
+            path.extend_from_slice(name.as_bytes());
+        }
+    }
+    if entry.attrs.kind == FileType::Directory {
+        path.push(b'/');
+    }
+    percent_encode(&path, PATH_SEGMENT).to_string()
+}
+
+/// Resolves symlinks (relative to the archive's root if absolute), None if they dangle, loop or point outside of the archive.
+/// Also resolves hard links to the entry holding the content.
+pub fn resolve<'i>(index: &'i TarIndex, entry: &'i IndexEntry) -> Option<&'i IndexEntry> {
+    let mut entry = index.get_entry_by_ino(entry.ino())?;
+    for _ in 0..MAX_SYMLINK_HOPS {
+        if entry.attrs.kind != FileType::Symlink {
+            return Some(entry);
+        }
+        let target = entry.link_name.as_ref()?;
+        let mut path: Vec<&OsStr> = if target.is_absolute() {
+            vec!()
+        } else {
+            entry.path.parent()?.components().filter_map(normal_component).collect()
+        };
+        for component in target.components() {
+            match component {
+                Component::ParentDir => {
+                    path.pop()?;
+                },
+                Component::Normal(name) => path.push(name),
+                _ => (),
+            }
+        }
+        let path: PathBuf = path.iter().collect();
+        entry = index.get_entry_by_ino(index.lookup_path(&path)?.ino())?;
+    }
+    None
+}
+
+fn normal_component(component: Component<'_>) -> Option<&OsStr> {
+    match component {
+        Component::Normal(name) => Some(name),
+        _ => None,
+    }
+}
+
+fn decode_path(target: &str) -> PathBuf {
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    PathBuf::from(OsString::from_vec(decode(path)))
+}
+
+fn decode(path: &str) -> Vec<u8> {
+    percent_decode(path.as_bytes()).collect()
+}
+
+pub fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,84 @@
+use std::io;
+use std::io::Read;
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use crate::archivesource::ArchiveSource;
+
+const HTTP_PARTIAL_CONTENT: u16 = 206;
+
+/// Serves an archive hosted on a HTTP(S) server.
+/// Indexing streams the whole archive with a single GET, reads later fetch only the requested byte ranges.
+#[derive(Debug)]
+pub struct HttpSource {
+    url: String,
+    agent: ureq::Agent,
+    size: u64,
+    timeout: Duration,
+}
+
+impl HttpSource {
+    /// timeout applies to each range request as a whole, and to each single socket operation while streaming the archive
+    pub fn new(url: &str, timeout: Duration) -> io::Result<HttpSource> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(timeout)
+            .timeout_read(timeout)
+            .build();
+        let head = agent.head(url).timeout(timeout).call().map_err(to_io_error)?;
+        match head.header("Accept-Ranges") {
+            Some("bytes") => (),
+            // Some servers support range requests without advertising it: Let the first read decide.
+            _ => warn!("{} does not advertise support for range requests", url),
+        }
+        let size = match head.header("Content-Length").map(|l| l.parse::<u64>()) {
+            Some(Ok(size)) => size,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} does not report a Content-Length", url))),
+        };
+        Ok(HttpSource {
+            url: url.to_owned(),
+            agent,
+            size,
+            timeout,
+        })
+    }
+}
+
+impl ArchiveSource for HttpSource {
+    fn stream(&self) -> io::Result<Box<dyn Read + '_>> {
+        let response = self.agent.get(&self.url).call().map_err(to_io_error)?;
+        Ok(Box::new(response.into_reader()))
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let range = format!("bytes={}-{}", offset, offset + buf.len() as u64 - 1);
+        debug!("GET {} Range: {}", self.url, range);
+        let response = self.agent.get(&self.url)
+            .set("Range", &range)
+            .timeout(self.timeout)
+            .call()
+            .map_err(to_io_error)?;
+        if response.status() != HTTP_PARTIAL_CONTENT {
+            let msg = format!("expected partial content for range {}, got status {}", range, response.status());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        }
+        response.into_reader().read_exact(buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.size)
+    }
+}
+
+fn to_io_error(err: ureq::Error) -> io::Error {
+    let kind = match &err {
+        ureq::Error::Status(401, _) | ureq::Error::Status(403, _) => io::ErrorKind::PermissionDenied,
+        ureq::Error::Status(404, _) | ureq::Error::Status(410, _) => io::ErrorKind::NotFound,
+        ureq::Error::Status(416, _) => io::ErrorKind::InvalidInput,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, err)
+}
@@ -0,0 +1,155 @@
+//! Ignore files (`.tarfsignore` next to the archive, or `--ignore-file`): patterns in gitignore syntax hiding the
+//! entries they match, e.g. to keep `.git`, caches or secrets of backups out of the mount.
+
+use std::fs;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::TarFsError;
+
+/// The name of the ignore file picked up from the directory of a local archive
+pub const IGNORE_FILE_NAME: &str = ".tarfsignore";
+
+#[derive(Debug)]
+struct Rule {
+    /// Matches paths relative to the root, e.g. "dir/file"
+    regex: Regex,
+    /// "!pattern" includes what earlier patterns excluded
+    negated: bool,
+    /// "pattern/" only matches directories
+    dirs_only: bool,
+}
+
+/// The patterns of an ignore file, in order
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    pub fn load(path: &Path) -> Result<IgnoreRules, TarFsError> {
+        let content = fs::read_to_string(path).map_err(|e| TarFsError::ConfigError {
+            msg: format!("unable to read the ignore file {}: {}", path.display(), e),
+        })?;
+        IgnoreRules::parse(&content).map_err(|e| TarFsError::ConfigError {
+            msg: format!("{}: {}", path.display(), e),
+        })
+    }
+
+    /// One pattern per line like .gitignore, blank lines and lines starting with "#" are skipped
+    pub fn parse(content: &str) -> Result<IgnoreRules, String> {
+        let mut rules = vec!();
+        for (n, line) in content.lines().enumerate() {
+            let line = trim_trailing_spaces(line);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, pattern) = match line.strip_prefix('!') {
+                Some(pattern) => (true, pattern),
+                None => (false, line),
+            };
+            let (dirs_only, pattern) = match pattern.strip_suffix('/') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern),
+            };
+            // A slash anywhere but at the end anchors the pattern at the root, otherwise it matches names at any depth
+            let (anchored, pattern) = match pattern.strip_prefix('/') {
+                Some(pattern) => (true, pattern),
+                None => (pattern.contains('/'), pattern),
+            };
+            if pattern.is_empty() {
+                continue;
+            }
+            let prefix = if anchored { "^" } else { "^(?:.*/)?" };
+            let regex = Regex::new(&format!("{}{}$", prefix, glob_regex(pattern)))
+                .map_err(|e| format!("invalid pattern in line {}: {}: {}", n + 1, line, e))?;
+            rules.push(Rule { regex, negated, dirs_only });
+        }
+        Ok(IgnoreRules { rules })
+    }
+
+    /// Whether the entry at path (e.g. "./dir/file") is hidden, being matched itself or below a directory that is.
+    /// Like git, entries below an excluded directory can't be included again.
+    pub fn ignores(&self, path: &Path, is_dir: bool) -> bool {
+        let path = path.strip_prefix(".").unwrap_or(path);
+        let mut prefix = String::new();
+        let mut components = path.iter().peekable();
+        while let Some(name) = components.next() {
+            if !prefix.is_empty() {
+                prefix.push('/');
+            }
+            prefix.push_str(&name.to_string_lossy());
+            let last = components.peek().is_none();
+            if self.matches(&prefix, !last || is_dir) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The last matching pattern decides
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for rule in &self.rules {
+            if (is_dir || !rule.dirs_only) && rule.regex.is_match(path) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+/// Trailing spaces are ignored unless escaped with a backslash
+fn trim_trailing_spaces(line: &str) -> &str {
+    let trimmed = line.trim_end_matches(' ');
+    if trimmed.ends_with('\\') && trimmed.len() < line.len() {
+        &line[..trimmed.len() + 1]
+    } else {
+        trimmed
+    }
+}
+
+/// "*" and "?" don't match "/", "**" matches across directories when it's a whole component ("**/x", "x/**", "x/**/y")
+fn glob_regex(pattern: &str) -> String {
+    let mut regex = String::new();
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let at_component_start = i == 0 || chars[i - 1] == '/';
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') && at_component_start && chars.get(i + 2) == Some(&'/') => {
+                regex.push_str("(?:.*/)?");
+                i += 3;
+                continue;
+            },
+            '*' if chars.get(i + 1) == Some(&'*') && at_component_start && i + 2 == chars.len() => {
+                regex.push_str(".*");
+                i += 2;
+                continue;
+            },
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => match chars[i + 1..].iter().position(|c| *c == ']') {
+                Some(len) => {
+                    let class: String = chars[i + 1..i + 1 + len].iter().collect();
+                    match class.strip_prefix('!') {
+                        Some(negated) => regex.push_str(&format!("[^{}]", negated.replace('\\', "\\\\"))),
+                        None => regex.push_str(&format!("[{}]", class.replace('\\', "\\\\"))),
+                    }
+                    i += len + 2;
+                    continue;
+                },
+                None => regex.push_str("\\["),
+            },
+            '\\' if i + 1 < chars.len() => {
+                regex.push_str(&regex::escape(&chars[i + 1].to_string()));
+                i += 2;
+                continue;
+            },
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    regex
+}
@@ -0,0 +1,187 @@
+//! `TarIndexSet`: the indexes of several archives, each at its subpath, answering lookups as if they were one tree.
+//! Mounting several archives at subpaths (`--at`) builds one and combines it into a single index.
+
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use fuser::FileType;
+use log::info;
+
+use crate::combine;
+use crate::memberstat::{self, MemberStat};
+use crate::tarindex::TarIndex;
+use crate::tarindexer::{archive_path, Options};
+use crate::utils::relative;
+use crate::{MountOptions, TarFsError};
+
+/// Picks the member presented at a path found in several archives, given their metadata in the order the archives
+/// were added. Returns the position of the winning member in the slice.
+pub type ConflictResolver = dyn Fn(&Path, &[MemberStat]) -> usize + Send + Sync;
+
+/// The indexes of several local archives, each at a subpath of one tree. Subpaths may overlap (e.g. layers all at
+/// the root), lookups of paths found in more than one archive are answered by the conflict resolver.
+pub struct TarIndexSet {
+    options: Options,
+    /// Relative to the root, empty for the root itself
+    archives: Vec<(PathBuf, TarIndex)>,
+    resolve: Box<ConflictResolver>,
+}
+
+impl TarIndexSet {
+    /// An empty set indexing archives the way mounting them would
+    pub fn new(options: &MountOptions) -> TarIndexSet {
+        crate::apply_log_level(options);
+        TarIndexSet::with_index_options(crate::index_options_with(crate::own_permissions(), options))
+    }
+
+    pub(crate) fn with_index_options(options: Options) -> TarIndexSet {
+        TarIndexSet {
+            options,
+            archives: vec!(),
+            // Like layers stacked with overlayfs: the archive added last is on top
+            resolve: Box::new(|_, members| members.len() - 1),
+        }
+    }
+
+    /// Replaces the conflict resolver, which lets the archive added last win by default
+    pub fn on_conflict<F>(&mut self, resolve: F) -> &mut TarIndexSet
+    where F: Fn(&Path, &[MemberStat]) -> usize + Send + Sync + 'static {
+        self.resolve = Box::new(resolve);
+        self
+    }
+
+    /// Indexes the local archive at filepath and adds it at subpath (e.g. "/data", or "/" for the root)
+    pub fn add(&mut self, subpath: &Path, filepath: &Path) -> Result<&mut TarIndexSet, TarFsError> {
+        let subpath = relative(&archive_path(subpath));
+        let index = crate::index_file(filepath, &self.part_options(&subpath))?;
+        self.archives.push((subpath, index));
+        Ok(self)
+    }
+
+    /// Indexes the local archives (subpath, filepath) like add would, up to threads of them at once, and adds them in
+    /// the order given. Fails with the error of the first archive failing to index, without adding any of them.
+    pub fn add_all(&mut self, archives: &[(PathBuf, PathBuf)], threads: usize) -> Result<&mut TarIndexSet, TarFsError> {
+        let parts: Vec<(PathBuf, Options)> = archives.iter().map(|(subpath, _)| {
+            let subpath = relative(&archive_path(subpath));
+            let options = self.part_options(&subpath);
+            (subpath, options)
+        }).collect();
+        let indexed: Vec<Mutex<Option<Result<TarIndex, TarFsError>>>> = archives.iter().map(|_| Mutex::new(None)).collect();
+        let (next, done, failed) = (AtomicUsize::new(0), AtomicUsize::new(0), AtomicBool::new(false));
+        thread::scope(|scope| {
+            for _ in 0..threads.clamp(1, archives.len().max(1)) {
+                scope.spawn(|| {
+                    // Each worker takes the next archive not taken yet, until all are or one failed
+                    while !failed.load(Ordering::Relaxed) {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let filepath = match archives.get(i) {
+                            Some((_, filepath)) => filepath,
+                            None => break,
+                        };
+                        let index = crate::index_file(filepath, &parts[i].1);
+                        failed.fetch_or(index.is_err(), Ordering::Relaxed);
+                        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                        info!("Indexed {} of {} archives ({})", done, archives.len(), filepath.display());
+                        *indexed[i].lock().unwrap() = Some(index);
+                    }
+                });
+            }
+        });
+        let mut added = vec!();
+        for ((subpath, _), index) in parts.into_iter().zip(indexed) {
+            match index.into_inner().unwrap() {
+                Some(index) => added.push((subpath, index?)),
+                // Left out after another one failed
+                None => continue,
+            }
+        }
+        self.archives.append(&mut added);
+        Ok(self)
+    }
+
+    /// The options to index an archive added at subpath with
+    fn part_options(&self, subpath: &Path) -> Options {
+        // Symlinks get rewritten to where their archive ends up
+        Options {
+            mountpoint: self.options.mountpoint.as_ref().map(|mountpoint| mountpoint.join(subpath)),
+            ..self.options.clone()
+        }
+    }
+
+    /// The number of archives added
+    pub fn len(&self) -> usize {
+        self.archives.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.archives.is_empty()
+    }
+
+    /// The archive (by the order they were added) presenting the member at path (e.g. "data/file") and its metadata.
+    /// Its path is the one in the tree, its ino the one in its archive. Directories above the subpaths aren't members.
+    pub fn lookup(&self, path: &Path) -> Option<(usize, MemberStat)> {
+        let path = relative(&archive_path(path));
+        let mut archives = vec!();
+        let mut members = vec!();
+        for (i, (subpath, index)) in self.archives.iter().enumerate() {
+            let inner = match path.strip_prefix(subpath) {
+                Ok(inner) => inner,
+                Err(_) => continue,
+            };
+            if let Ok(mut member) = memberstat::stat(index, inner) {
+                member.path = path.clone();
+                archives.push(i);
+                members.push(member);
+            }
+        }
+        let chosen = match members.len() {
+            0 => return None,
+            1 => 0,
+            _ => (self.resolve)(&path, &members),
+        };
+        if chosen >= members.len() {
+            log::error!("The conflict resolver picked member {} of {} at {}", chosen, members.len(), path.display());
+            return None;
+        }
+        Some((archives[chosen], members.swap_remove(chosen)))
+    }
+
+    /// The names in the directory at path, of all archives presenting it, sorted. None if no archive has a directory there.
+    pub fn read_dir(&self, path: &Path) -> Option<Vec<OsString>> {
+        let path = relative(&archive_path(path));
+        let mut found = false;
+        let mut names = BTreeSet::new();
+        for (subpath, index) in &self.archives {
+            if let Ok(inner) = path.strip_prefix(subpath) {
+                let dir = match index.lookup_path(&archive_path(inner)) {
+                    Some(entry) if entry.attrs.kind == FileType::Directory => entry,
+                    _ => continue,
+                };
+                found = true;
+                names.extend(dir.children.iter()
+                    .filter_map(|ino| index.get_entry_by_ino(*ino))
+                    .map(|child| child.name.clone().into_os_string()));
+            } else if let Ok(below) = subpath.strip_prefix(&path) {
+                // Directories above a subpath lead to it
+                found = true;
+                names.extend(below.iter().next().map(|name| name.to_os_string()));
+            }
+        }
+        match found {
+            true => Some(names.into_iter().collect()),
+            false => None,
+        }
+    }
+
+    /// One index of all archives, for mounting them. Their subpaths must neither be the root nor nested.
+    pub(crate) fn combine(self) -> Result<TarIndex, TarFsError> {
+        for (subpath, _) in &self.archives {
+            combine::relative_subpath(&Path::new("/").join(subpath))?;
+        }
+        combine::combine(self.archives, &self.options)
+    }
+}
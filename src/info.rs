@@ -0,0 +1,111 @@
+//! `tarfs info`: totals of an archive (entries by type, sizes, largest files, deepest paths, owners), from its index.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use fuser::FileType;
+
+use crate::tarindex::{TarIndex, ROOT_INO};
+use crate::utils::relative;
+
+/// How many of the largest files and deepest paths are listed
+const TOP: usize = 10;
+
+/// Totals of an archive as indexed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ArchiveInfo {
+    /// The format detected from the first header, e.g. "tar (gnu)"
+    pub format: String,
+    pub files: u64,
+    pub dirs: u64,
+    pub symlinks: u64,
+    /// Hard links beyond the first name of a file
+    pub hard_links: u64,
+    /// Devices, fifos and sockets
+    pub others: u64,
+    /// The size of the files' content as presented, hard linked ones counted once
+    pub content_bytes: u64,
+    /// Files stored gzip compressed in the archive (see --decompress-members)
+    pub compressed_files: u64,
+    /// What the compressed files take in the archive
+    pub compressed_bytes: u64,
+    /// Where the archive ends (e.g. before trailing zeros)
+    pub archive_bytes: u64,
+    /// The largest files with their size, largest first
+    pub largest: Vec<(PathBuf, u64)>,
+    /// The most deeply nested paths with the number of their components, deepest first
+    pub deepest: Vec<(PathBuf, usize)>,
+    /// How many entries each uid:gid owns, most first
+    pub owners: Vec<((u32, u32), u64)>,
+}
+
+impl fmt::Display for ArchiveInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Format: {}", self.format)?;
+        writeln!(f, "Entries: {} files, {} directories, {} symlinks, {} hard links, {} others",
+            self.files, self.dirs, self.symlinks, self.hard_links, self.others)?;
+        write!(f, "Content: {} KiB", self.content_bytes / 1024)?;
+        if self.compressed_files > 0 {
+            write!(f, " ({} files gzip compressed to {} KiB)", self.compressed_files, self.compressed_bytes / 1024)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Archive: {} KiB", self.archive_bytes / 1024)?;
+        writeln!(f, "Largest files:")?;
+        for (path, size) in &self.largest {
+            writeln!(f, "  {:>12} {}", size, path.display())?;
+        }
+        writeln!(f, "Deepest paths:")?;
+        for (path, depth) in &self.deepest {
+            writeln!(f, "  {:>3} {}", depth, path.display())?;
+        }
+        write!(f, "Owners (uid:gid):")?;
+        for ((uid, gid), count) in &self.owners {
+            write!(f, "\n  {}:{} {}", uid, gid, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// The totals of all entries of index but the root
+pub fn info(index: &TarIndex) -> ArchiveInfo {
+    let mut info = ArchiveInfo {
+        format: index.format().to_owned(),
+        archive_bytes: index.end_offset(),
+        ..Default::default()
+    };
+    let mut files = vec!();
+    let mut paths = vec!();
+    let mut owners = BTreeMap::new();
+    for entry in index.entries().filter(|entry| entry.id != ROOT_INO) {
+        let path = relative(&entry.path);
+        *owners.entry((entry.attrs.uid, entry.attrs.gid)).or_insert(0) += 1;
+        match entry.attrs.kind {
+            _ if entry.link_target_ino.is_some() => info.hard_links += 1,
+            FileType::Directory => info.dirs += 1,
+            FileType::Symlink => info.symlinks += 1,
+            FileType::RegularFile => {
+                info.files += 1;
+                info.content_bytes += entry.attrs.size;
+                if entry.compressed {
+                    info.compressed_files += 1;
+                    info.compressed_bytes += entry.file_offsets.iter().map(|pointer| pointer.filesize).sum::<u64>();
+                }
+                files.push((path.clone(), entry.attrs.size));
+            },
+            _ => info.others += 1,
+        }
+        paths.push((path.components().count(), path));
+    }
+
+    files.sort_by(|(a, a_size), (b, b_size)| b_size.cmp(a_size).then_with(|| a.cmp(b)));
+    files.truncate(TOP);
+    info.largest = files;
+    paths.sort_by(|(a_depth, a), (b_depth, b)| b_depth.cmp(a_depth).then_with(|| a.cmp(b)));
+    info.deepest = paths.into_iter().take(TOP).map(|(depth, path)| (path, depth)).collect();
+    let mut owners: Vec<((u32, u32), u64)> = owners.into_iter().collect();
+    owners.sort_by_key(|(owner, count)| (Reverse(*count), *owner));
+    info.owners = owners;
+    info
+}
@@ -0,0 +1,79 @@
+//! Fault and latency injection (`--inject`): randomly delays and fails reads of the mount, for testing how systems
+//! built on top of it handle a slow or flaky source.
+
+use std::process;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What to inject into reads, e.g. "errors=0.01,latency=50ms"
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Inject {
+    /// The share of reads that fail with EIO, from 0 to 1
+    pub errors: f64,
+    /// Reads are delayed by a random time up to this
+    pub latency: Duration,
+}
+
+impl FromStr for Inject {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Inject, String> {
+        let mut inject = Inject::default();
+        for setting in s.split(',') {
+            match setting.split_once('=') {
+                Some(("errors", rate)) => {
+                    inject.errors = rate.parse().map_err(|e| format!("invalid error rate {}: {}", rate, e))?;
+                    if !(0.0..=1.0).contains(&inject.errors) {
+                        return Err(format!("invalid error rate {}: must be between 0 and 1", rate));
+                    }
+                },
+                Some(("latency", latency)) => inject.latency = parse_latency(latency)?,
+                _ => return Err(format!("invalid injection {}: expected errors=<rate> or latency=<duration>", setting)),
+            }
+        }
+        Ok(inject)
+    }
+}
+
+/// A number of milliseconds, or of seconds with the suffix "s" (e.g. "50ms", "2s")
+fn parse_latency(s: &str) -> Result<Duration, String> {
+    let invalid = |e: std::num::ParseIntError| format!("invalid latency {}: {}", s, e);
+    if let Some(ms) = s.strip_suffix("ms") {
+        Ok(Duration::from_millis(ms.parse().map_err(invalid)?))
+    } else if let Some(secs) = s.strip_suffix('s') {
+        Ok(Duration::from_secs(secs.parse().map_err(invalid)?))
+    } else {
+        Ok(Duration::from_millis(s.parse().map_err(invalid)?))
+    }
+}
+
+/// Applies an Inject to reads, with a generator that's good enough for spreading faults (xorshift64*)
+pub struct Injector {
+    inject: Inject,
+    state: u64,
+}
+
+impl Injector {
+    pub fn new(inject: Inject) -> Injector {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+        // The state must never be 0
+        Injector { inject, state: (nanos ^ ((process::id() as u64) << 32)) | 1 }
+    }
+
+    /// Delays the read, and tells whether it should fail
+    pub fn before_read(&mut self) -> bool {
+        if !self.inject.latency.is_zero() {
+            thread::sleep(self.inject.latency.mul_f64(self.next()));
+        }
+        self.next() < self.inject.errors
+    }
+
+    /// Uniformly distributed in [0, 1)
+    fn next(&mut self) -> f64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
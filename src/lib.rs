@@ -1,60 +1,803 @@
-use failure::Fail;
-
 mod tarindex;
 mod tarindexer;
+mod cpio;
+mod ar;
+mod dedup;
+mod decompress;
+mod combine;
+mod indexset;
+mod symlinks;
+mod owners;
+mod userns;
 mod tarfs;
+mod fserror;
+mod fusemount;
 mod utils;
 mod arena;
+mod archivesource;
+mod filesource;
+mod httpsource;
+mod stdinsource;
+mod memorysource;
+mod chunkcache;
+mod eviction;
+mod retry;
+mod watch;
+mod follow;
+mod xdr;
+mod nfs;
+mod httpserver;
+mod dav;
+mod staticfiles;
+mod stats;
+mod lookups;
+mod handles;
+mod readpool;
+mod permissions;
+mod audit;
+mod profile;
+mod sums;
+mod mime;
+mod warmup;
+mod selftest;
+mod inject;
+mod atime;
+mod normalize;
+mod problems;
+mod errorlog;
+mod find;
+mod memberstat;
+mod info;
+mod walk;
+mod control;
+mod health;
+mod manager;
+mod ignore;
+mod deterministic;
+pub mod embedded;
+mod config;
+mod sandbox;
+#[cfg(feature = "object-store")]
+mod objectstoresource;
+
+use thiserror::Error;
 
-use failure::Error;
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, OnceLock, RwLock};
 
-use std::{fs, fs::File};
-use std::path::Path;
-use std::sync::mpsc;
 
-use tarindexer::{TarIndexer, Options, Permissions};
-use tarfs::TarFs;
+pub use tarindex::{EntryRef, TarIndex};
+pub use walk::{Reader, Walk};
+use tarindexer::{TarIndexer, Options, Permissions, Selection};
+pub use tarindexer::{CollisionPolicy, DedupMode, DirAttrs, DirMode, InoMode, LimitPolicy, Limits, RootTime, SymlinkRewrite, TimePolicy, UnsupportedPolicy};
+use tarfs::{TarFs, Hooks, KernelCache, SharedIndex};
+pub use tarfs::FuseTuning;
+use stats::Stats;
+use handles::SharedHandles;
+use control::{Controlled, Reload};
+use health::Health;
+use audit::AuditLog;
+use profile::ProfileRecorder;
+use sums::{Sums, Verifier};
+pub use warmup::Warmup;
+pub use userns::{IdMap, UsernsMode};
+pub use sums::Verification;
+pub use selftest::SelfTest;
+pub use inject::Inject;
+pub use atime::AtimeMode;
+pub use normalize::Normalization;
+pub use ignore::IgnoreRules;
+pub use find::{FindPattern, Found};
+pub use memberstat::MemberStat;
+pub use info::ArchiveInfo;
+pub use indexset::{ConflictResolver, TarIndexSet};
+use archivesource::{ArchiveSource, ConcatSource};
+use filesource::FileSource;
+use httpsource::HttpSource;
+use stdinsource::StdinSource;
+#[cfg(feature = "object-store")]
+use objectstoresource::ObjectStoreSource;
+use chunkcache::ChunkCachedSource;
+pub use chunkcache::ChunkCacheOptions;
+pub use eviction::{CacheStats, EvictionPolicy, Evictor};
+use retry::RetryingSource;
+pub use retry::RetryPolicy;
+pub use log::LevelFilter;
+pub use config::{Config, ConfigTable, ConfigValue, DEFAULT_CONFIG_PATH};
 
-#[derive(Debug, Fail)]
+#[derive(Debug, Error)]
 pub enum TarFsError {
-    #[fail(display = "{}", msg)]
+    #[error("{msg}")]
     MountError {
         msg: String,
     },
-    #[fail(display = "{}", msg)]
+    #[error("{msg}")]
     IndexError {
         msg: String,
+    },
+    /// Indexing failed at an entry, located for inspecting it, e.g. with `dd skip=<offset> iflag=skip_bytes`
+    #[error("entry #{index} at offset {offset}: {msg}")]
+    EntryError {
+        /// The position of the entry in the archive, counting from 0. PAX extended headers and GNU long names count as
+        /// part of the entry they apply to, other metadata (e.g. global PAX headers, volume labels) as entries of their own.
+        index: u64,
+        /// Where its header starts in the archive
+        offset: u64,
+        /// Its path in the archive, unless it couldn't be read
+        path: Option<PathBuf>,
+        /// What's wrong with it, naming its path
+        msg: String,
+    },
+    #[error("invalid configuration: {msg}")]
+    ConfigError {
+        msg: String,
+    },
+    #[error("unsupported archive format: {msg}")]
+    UnsupportedFormat {
+        msg: String,
+    },
+    #[error("unable to read {} at offset {offset} of the archive: {source}", path.display())]
+    ReadError {
+        /// The path of the entry inside the archive
+        path: PathBuf,
+        /// Where the read started in the archive (where the content starts, for compressed members)
+        offset: u64,
+        source: io::Error,
+    },
+    #[error("{msg}")]
+    FuseUnavailable {
+        msg: String,
+    },
+    #[error("{msg}")]
+    PermissionDenied {
+        msg: String,
+    },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// The causes of failures, as told apart by the exit codes of the CLI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    Other,
+    /// Invalid arguments or configuration
+    Usage,
+    /// The archive can't be opened, indexed or read, or doesn't match its checksums
+    BadArchive,
+    /// The mountpoint is missing, not a directory or not empty, or mounting on it failed
+    Mountpoint,
+    /// /dev/fuse or fusermount is missing or not accessible
+    FuseUnavailable,
+    /// Access to the archive or mountpoint was denied
+    PermissionDenied,
+}
+
+impl FailureKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            FailureKind::Other => 1,
+            FailureKind::Usage => 2,
+            FailureKind::BadArchive => 3,
+            FailureKind::Mountpoint => 4,
+            FailureKind::FuseUnavailable => 5,
+            FailureKind::PermissionDenied => 6,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FailureKind::Other => "other",
+            FailureKind::Usage => "usage",
+            FailureKind::BadArchive => "bad_archive",
+            FailureKind::Mountpoint => "mountpoint",
+            FailureKind::FuseUnavailable => "fuse_unavailable",
+            FailureKind::PermissionDenied => "permission_denied",
+        }
+    }
+
+    /// A line like {"error":"bad_archive","exit_code":3,"message":"..."}
+    pub fn json(self, message: &str) -> String {
+        format!("{{\"error\":{},\"exit_code\":{},\"message\":{}}}", utils::json_string(self.name()), self.exit_code(), utils::json_string(message))
+    }
+}
+
+impl TarFsError {
+    pub fn kind(&self) -> FailureKind {
+        match self {
+            TarFsError::ConfigError { .. } => FailureKind::Usage,
+            TarFsError::IndexError { .. } | TarFsError::EntryError { .. } | TarFsError::UnsupportedFormat { .. } | TarFsError::ReadError { .. } => FailureKind::BadArchive,
+            TarFsError::MountError { .. } => FailureKind::Mountpoint,
+            TarFsError::FuseUnavailable { .. } => FailureKind::FuseUnavailable,
+            TarFsError::PermissionDenied { .. } => FailureKind::PermissionDenied,
+            TarFsError::Io(e) if e.kind() == io::ErrorKind::PermissionDenied => FailureKind::PermissionDenied,
+            TarFsError::Io(_) => FailureKind::Other,
+        }
     }
 }
 
-pub fn setup_tar_mount(filepath: &Path, mountpoint: &Path, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(), Error> {
-    ensure_mountpoint_dir_exists(mountpoint)?;
+/// Options for all kinds of mounts
+#[derive(Default, Clone)]
+pub struct MountOptions {
+    /// Re-index the archive whenever it gets replaced or rewritten (local archives only)
+    pub watch: bool,
+    /// Keep adding entries appended to the archive while it's mounted (local archives only)
+    pub follow: bool,
+    /// Rewrites the times of all entries
+    pub time_policy: TimePolicy,
+    /// What to do with entries of types tarfs can't present
+    pub unsupported: UnsupportedPolicy,
+    /// Permissions for directories not part of the archive, but parents of entries
+    pub dir_mode: DirMode,
+    /// Where directories missing in the archive take their owner and times from
+    pub dir_attrs: DirAttrs,
+    /// Take the root directory's attrs from the archive's "./" entry instead of the mountpoint
+    pub root_from_archive: bool,
+    /// Where the root directory takes its times from otherwise
+    pub root_time: RootTime,
+    /// How entries get their inode numbers
+    pub ino_mode: InoMode,
+    /// Present the archive the same way on every machine, e.g. for reproducible builds: children sorted by name, inos
+    /// numbered in that order, and the root and directories missing in the archive owned by root at the epoch
+    pub deterministic: bool,
+    /// Present gzip compressed members decompressed, without their .gz extension
+    pub decompress_members: bool,
+    /// Make files with identical content share their content (and optionally their inode)
+    pub dedup: Option<DedupMode>,
+    /// Rewrite absolute symlink targets so they don't escape the mount
+    pub rewrite_absolute_symlinks: Option<SymlinkRewrite>,
+    /// Present symlinks resolving inside the archive as what they point to
+    pub follow_symlinks: bool,
+    /// Present the uids/gids of the archive as they are, instead of mapping the user/group names to local ones
+    pub numeric_owner: bool,
+    /// The Unicode normalization form of names, applied when indexing and in lookups
+    pub normalize: Normalization,
+    /// What to do with distinct members whose names become the same once normalized
+    pub collisions: CollisionPolicy,
+    /// Give up indexing rather than letting the index grow beyond this many bytes
+    pub max_memory: Option<u64>,
+    /// Refuse archives defining a path again with a different type, instead of the last definition winning
+    pub strict: bool,
+    /// How owners are translated for user namespaces
+    pub userns: UsernsMode,
+    /// Limits protecting against archives crafted to exhaust resources
+    pub limits: Limits,
+    /// Only present the members at these paths, everything below them and their parents (like tar's --files-from)
+    pub files_from: Option<Vec<PathBuf>>,
+    /// Hide the members matching these patterns. Local archives pick up a .tarfsignore next to them if not given.
+    pub ignore: Option<Arc<IgnoreRules>>,
+    /// Check permissions in tarfs instead of the kernel (no default_permissions), e.g. to make access(2) authoritative
+    pub check_permissions: bool,
+    /// Allow exporting the mount via NFS. Should be combined with InoMode::Offset so file handles survive remounts.
+    pub nfs_export: bool,
+    /// Request sizes and queue lengths of the FUSE connection, e.g. for higher throughput
+    pub tuning: FuseTuning,
+    /// Record every open and read (with the uid and pid of the requesting process) in this file
+    pub audit_log: Option<PathBuf>,
+    /// Present the MIME type of files, guessed from their first bytes, as xattr user.mime_type
+    pub mime_types: bool,
+    /// Check the content of members against the SHA-256 digests in this manifest (as written by sha256sum) on their first read
+    pub verify_reads: Option<PathBuf>,
+    /// Randomly delay and fail reads, for testing what's built on top of the mount
+    pub inject: Option<Inject>,
+    /// Fail opens with EMFILE while this many files are open via the mount
+    pub max_open_files: Option<u64>,
+    /// How many threads serve reads, preload or prefetch in the background, and how many archives mounted together (or by
+    /// the manager) get indexed at once. Derived from the number of CPUs if not given.
+    pub threads: Option<usize>,
+    /// Whether reads update the atimes presented (in memory only)
+    pub atime: AtimeMode,
+    /// Read the content of the members at these paths (and below) once mounted, to get it cached. Empty reads all of them.
+    pub preload: Option<Vec<PathBuf>>,
+    /// Record which files get opened, in order, to this file when unmounting
+    pub record_profile: Option<PathBuf>,
+    /// Read the files listed in this profile (see record_profile) ahead in the background
+    pub prefetch_profile: Option<PathBuf>,
+    /// Unix socket to query the mount's runtime statistics on
+    pub control_socket: Option<PathBuf>,
+    /// Serve GET /healthz on this address (e.g. "0.0.0.0:8081"), for supervisors to restart wedged mounts
+    pub health_address: Option<String>,
+    /// Caps what tarfs logs, on top of the logger's own filter. None leaves the max level of the log crate alone.
+    pub log_level: Option<LevelFilter>,
+    /// Restrict the process to the syscalls needed for serving once mounted (seccomp). Applies to all of its threads!
+    pub sandbox: bool,
+    /// Create the mountpoint (and its parents) if missing
+    pub mkdir: bool,
+    /// Allow mounting over a directory that isn't empty, hiding its content
+    pub nonempty: bool,
+}
 
-    // Make the fs root dir permissions the ones from the mountpoint
-    let mountpoint_meta = mountpoint.metadata()?;
-    let options = Options {
-        root_permissions: permissions_from_mountpoint(&mountpoint_meta),
+pub fn setup_tar_mount(filepath: &Path, mountpoint: &Path, options: &MountOptions, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(), TarFsError> {
+    apply_log_level(options);
+    if options.follow && options.deterministic {
+        return Err(TarFsError::MountError {
+            msg: String::from("growing archives can't be presented deterministically"),
+        });
+    }
+    ensure_mountpoint_dir_exists(mountpoint, options)?;
+    let index_options = Options {
+        // Re-indexed on changes, or on reload via the control socket
+        extendable: !options.follow && (options.watch || options.control_socket.is_some()),
+        ..index_options_for(mountpoint, options)?
+    };
+
+    let index = if options.follow {
+        index_growing_file(filepath, &index_options)?
+    } else {
+        index_file(filepath, &index_options)?
+    };
+    let index = Arc::new(RwLock::new(index));
+    let shared_index = index.clone();
+    // Following appends to the index, which a reload would pull away
+    let reload: Option<Reload> = if options.follow {
+        None
+    } else {
+        let index_options = index_options.clone();
+        Some(Box::new(move |path: &Path, current: &TarIndex| reindex_file(path, current, &index_options)))
+    };
+    mount_index(index, mountpoint, options, reload, start_signal, |kernel_cache| {
+        if options.watch {
+            watch::spawn_watcher(filepath, shared_index, kernel_cache, move |path, current| reindex_file(path, current, &index_options))?;
+        } else if options.follow {
+            follow::spawn_follower(shared_index, kernel_cache, index_options);
+        }
+        Ok(())
+    })
+}
+
+/// Mounts several local archives at once, each as directory at its subpath (e.g. "/data") of the mountpoint
+pub fn setup_combined_mount(archives: &[(PathBuf, PathBuf)], mountpoint: &Path, options: &MountOptions, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(), TarFsError> {
+    apply_log_level(options);
+    if options.watch || options.follow {
+        return Err(TarFsError::MountError {
+            msg: String::from("archives mounted at subpaths can't be watched or followed"),
+        });
+    }
+    ensure_mountpoint_dir_exists(mountpoint, options)?;
+    let mut set = TarIndexSet::with_index_options(index_options_for(mountpoint, options)?);
+    for (subpath, _) in archives {
+        combine::relative_subpath(subpath)?;
+    }
+    set.add_all(archives, options.threads.unwrap_or_else(readpool::default_threads))?;
+    let index = set.combine()?;
+    mount_index(Arc::new(RwLock::new(index)), mountpoint, options, None, start_signal, |_| Ok(()))
+}
+
+/// Serves a local tar archive via NFSv3 on address (e.g. "0.0.0.0:2049") instead of mounting it,
+/// so any NFS client can mount it without FUSE. Blocks forever.
+pub fn serve_tar_nfs(filepath: &Path, address: &str, options: &MountOptions, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(), TarFsError> {
+    let (index, listener) = prepare_serving(filepath, address, options, start_signal)?;
+    nfs::serve(index, listener)?;
+    Ok(())
+}
+
+/// Serves a local tar archive as read-only WebDAV share on address (e.g. "127.0.0.1:8080"), for platforms without FUSE. Blocks forever.
+pub fn serve_tar_dav(filepath: &Path, address: &str, options: &MountOptions, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(), TarFsError> {
+    let (index, listener) = prepare_serving(filepath, address, options, start_signal)?;
+    httpserver::serve(index, listener, dav::handle)?;
+    Ok(())
+}
+
+/// Serves the members of a local tar archive via plain HTTP on address (e.g. "127.0.0.1:8080"), like a static web server would a directory.
+/// Directories are served as their index.html or a listing. Blocks forever.
+pub fn serve_tar_http(filepath: &Path, address: &str, options: &MountOptions, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(), TarFsError> {
+    let (index, listener) = prepare_serving(filepath, address, options, start_signal)?;
+    httpserver::serve(index, listener, staticfiles::handle)?;
+    Ok(())
+}
+
+/// Mounts and unmounts local archives as requested via the unix socket at path (see the manager module), e.g. for the
+/// node plugin of a container orchestrator. options apply to all mounts. Blocks forever.
+pub fn serve_manager(path: &Path, options: &MountOptions, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(), TarFsError> {
+    apply_log_level(options);
+    manager::serve(path, options, start_signal)
+}
+
+/// Indexes an archive to be served via network instead of mounted, and binds address
+fn prepare_serving(filepath: &Path, address: &str, options: &MountOptions, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(SharedIndex, TcpListener), TarFsError> {
+    apply_log_level(options);
+    utils::raise_fd_limit();
+    if options.rewrite_absolute_symlinks == Some(SymlinkRewrite::Mountpoint) {
+        return Err(TarFsError::MountError {
+            msg: String::from("symlinks can't be rewritten to the mountpoint when serving, the clients decide where it is mounted"),
+        });
+    }
+    let index = index_file(filepath, &index_options_with(own_permissions(), options))?;
+    let listener = TcpListener::bind(address).map_err(|e| TarFsError::MountError {
+        msg: format!("unable to listen on {}: {}", address, e),
+    })?;
+    if options.sandbox {
+        sandbox::enable().map_err(|e| TarFsError::MountError {
+            msg: format!("unable to enable the sandbox: {}", e),
+        })?;
+    }
+    if let Some(start_signal) = start_signal {
+        let _ = start_signal.send(());
+    }
+    Ok((Arc::new(RwLock::new(index)), listener))
+}
+
+/// Reads the content of the members at paths (and below, all members if none) of a local archive once,
+/// to get it into the page cache before a workload mounting it starts
+pub fn warmup_tar(filepath: &Path, paths: &[PathBuf], options: &MountOptions) -> Result<Warmup, TarFsError> {
+    apply_log_level(options);
+    let index = index_file(filepath, &index_options_with(own_permissions(), options))?;
+    let ranges = warmup::select(&index, paths)?;
+    Ok(warmup::warm_up(index.source(), &ranges, |_| ())?)
+}
+
+/// Compares the files of a local archive to the SHA-256 digests of a manifest as written by sha256sum
+pub fn verify_tar(filepath: &Path, sums: &Path, options: &MountOptions) -> Result<Verification, TarFsError> {
+    apply_log_level(options);
+    let sums = Sums::load(sums).map_err(|e| TarFsError::IndexError {
+        msg: format!("unable to read the checksums {}: {}", sums.display(), e),
+    })?;
+    let index = index_file(filepath, &index_options_with(own_permissions(), options))?;
+    sums::verify(&index, &sums)
+}
+
+/// Packs dir (a synthetic tree covering links, long and unicode names and holes if none), mounts the archive in a
+/// temporary location and compares the mount to it
+pub fn selftest(dir: Option<&Path>, options: &MountOptions) -> Result<SelfTest, TarFsError> {
+    apply_log_level(options);
+    selftest::run(dir, options)
+}
+
+/// Finds the members of a local archive whose paths match pattern, sorted by path
+pub fn find_in_tar(filepath: &Path, pattern: &FindPattern, options: &MountOptions) -> Result<Vec<Found>, TarFsError> {
+    apply_log_level(options);
+    let index = index_file(filepath, &index_options_with(own_permissions(), options))?;
+    Ok(find::find(&index, pattern))
+}
+
+/// The metadata of the member at path (e.g. "dir/file") of a local archive
+pub fn stat_in_tar(filepath: &Path, path: &Path, options: &MountOptions) -> Result<MemberStat, TarFsError> {
+    apply_log_level(options);
+    let index = index_file(filepath, &index_options_with(own_permissions(), options))?;
+    memberstat::stat(&index, path)
+}
+
+/// Indexes a local archive the way mounting it would, e.g. to walk it (see TarIndex::walk)
+pub fn index_tar(filepath: &Path, options: &MountOptions) -> Result<TarIndex, TarFsError> {
+    apply_log_level(options);
+    index_file(filepath, &index_options_with(own_permissions(), options))
+}
+
+/// Totals of a local archive: entries by type, sizes, largest files, deepest paths and owners
+pub fn info_tar(filepath: &Path, options: &MountOptions) -> Result<ArchiveInfo, TarFsError> {
+    apply_log_level(options);
+    let index = index_file(filepath, &index_options_with(own_permissions(), options))?;
+    Ok(info::info(&index))
+}
+
+/// What mounting an archive would give, see dry_run_tar
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DryRun {
+    pub files: u64,
+    pub dirs: u64,
+    pub symlinks: u64,
+    /// Hard links beyond the first name of a file
+    pub hard_links: u64,
+    /// The size of the files' content, hard linked ones counted once
+    pub content_bytes: u64,
+    /// Where the archive ends (e.g. before trailing zeros)
+    pub archive_bytes: u64,
+    /// What the index is estimated to take in memory
+    pub memory_bytes: u64,
+    /// Entries skipped or presented differently than the archive has them, as warned about while indexing
+    pub problems: Vec<String>,
+}
+
+/// Indexes a local archive the way mounting it would, without mounting it
+pub fn dry_run_tar(filepath: &Path, options: &MountOptions) -> Result<DryRun, TarFsError> {
+    apply_log_level(options);
+    problems::take();
+    let index = index_file(filepath, &index_options_with(own_permissions(), options))?;
+    let mut dry_run = DryRun {
+        archive_bytes: index.end_offset(),
+        memory_bytes: index.memory_usage(),
+        problems: problems::take(),
+        ..Default::default()
     };
+    for entry in index.entries().filter(|entry| entry.id != tarindex::ROOT_INO) {
+        match entry.attrs.kind {
+            _ if entry.link_target_ino.is_some() => dry_run.hard_links += 1,
+            fuser::FileType::Directory => dry_run.dirs += 1,
+            fuser::FileType::Symlink => dry_run.symlinks += 1,
+            _ => {
+                dry_run.files += 1;
+                dry_run.content_bytes += entry.attrs.size;
+            },
+        }
+    }
+    Ok(dry_run)
+}
+
+/// For the root when there is no mountpoint to take its permissions from
+fn own_permissions() -> Permissions {
+    Permissions {
+        mode: 0o755,
+        uid: unsafe { libc::geteuid() } as u64,
+        gid: unsafe { libc::getegid() } as u64,
+    }
+}
+
+/// Options only applying to remote archives
+#[derive(Default)]
+pub struct RemoteOptions {
+    /// Cache fetched content locally
+    pub cache: Option<ChunkCacheOptions>,
+    /// How to deal with failing or hanging requests
+    pub retry: RetryPolicy,
+}
+
+/// Mounts a remote tar archive. HTTP(S) servers have to support range requests.
+/// With the "object-store" feature enabled s3://, gs:// and az:// URLs are supported as well.
+pub fn setup_url_mount(url: &str, mountpoint: &Path, options: &MountOptions, remote_options: &RemoteOptions, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(), TarFsError> {
+    apply_log_level(options);
+    #[cfg(feature = "object-store")]
+    {
+        if ObjectStoreSource::handles(url) {
+            let source = ObjectStoreSource::new(url, remote_options.retry.timeout)?;
+            return mount_remote_source(source, url, mountpoint, options, remote_options, start_signal);
+        }
+    }
+    let source = HttpSource::new(url, remote_options.retry.timeout)?;
+    mount_remote_source(source, url, mountpoint, options, remote_options, start_signal)
+}
+
+/// Mounts an archive piped to stdin, e.g. `ssh host 'cat big.tar' | tarfs - /mnt`.
+/// The archive is spooled to a temp file while being indexed.
+pub fn setup_stdin_mount(mountpoint: &Path, options: &MountOptions, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(), TarFsError> {
+    apply_log_level(options);
+    let source = StdinSource::new()?;
+    mount_source(Box::new(source), mountpoint, options, start_signal)
+}
+
+fn mount_remote_source<S: ArchiveSource + 'static>(source: S, url: &str, mountpoint: &Path, options: &MountOptions, remote_options: &RemoteOptions, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(), TarFsError> {
+    let source = RetryingSource::new(source, &remote_options.retry);
+    match &remote_options.cache {
+        Some(cache_options) => {
+            let source = ChunkCachedSource::new(source, url, cache_options)?;
+            mount_source(Box::new(source), mountpoint, options, start_signal)
+        },
+        None => mount_source(Box::new(source), mountpoint, options, start_signal),
+    }
+}
+
+fn mount_source(source: Box<dyn ArchiveSource>, mountpoint: &Path, options: &MountOptions, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(), TarFsError> {
+    ensure_mountpoint_dir_exists(mountpoint, options)?;
+    let index_options = index_options_for(mountpoint, options)?;
+
+    let indexer = TarIndexer{};
+    let index = indexer.build_index_for(source, &index_options)?;
+    mount_index(Arc::new(RwLock::new(index)), mountpoint, options, None, start_signal, |_| Ok(()))
+}
+
+fn index_file(filepath: &Path, options: &Options) -> Result<TarIndex, TarFsError> {
+    let options = with_adjacent_ignore_file(filepath, options)?;
+    let source = open_file(filepath)?;
+    let indexer = TarIndexer{};
+    indexer.build_index_for(source, &options)
+}
+
+/// Indexes the archive at filepath again, only its new entries if it's an extended version of the one current was built from
+fn reindex_file(filepath: &Path, current: &TarIndex, options: &Options) -> Result<TarIndex, TarFsError> {
+    let source = open_file(filepath)?;
+    let extend_options = with_adjacent_ignore_file(filepath, options)?;
+    match (TarIndexer{}).extend_index_for(current, source, &extend_options)? {
+        Some(index) => Ok(index),
+        None => index_file(filepath, options),
+    }
+}
 
-    // Open archive and index it
-    let file = File::open(filepath)?;
+fn index_growing_file(filepath: &Path, options: &Options) -> Result<TarIndex, TarFsError> {
+    if filesource::split_parts(filepath).is_some_and(|parts| parts.len() > 1) {
+        return Err(TarFsError::MountError {
+            msg: format!("{} is split into parts, which can't be followed", filepath.display()),
+        });
+    }
+    let options = with_adjacent_ignore_file(filepath, options)?;
+    let source = FileSource::open_growing(filepath).map_err(|e| open_error(filepath, e))?;
     let indexer = TarIndexer{};
-    let mut index = indexer.build_index_for(&file, &options)?;
+    indexer.build_growing_index_for(Box::new(source), &options)
+}
+
+/// The local archive at filepath, or all of its parts if it's the first part of a split archive (e.g. "backup.tar.000")
+fn open_file(filepath: &Path) -> Result<Box<dyn ArchiveSource>, TarFsError> {
+    let parts = match filesource::split_parts(filepath) {
+        Some(parts) if parts.len() > 1 => parts,
+        _ => return Ok(Box::new(FileSource::open(filepath).map_err(|e| open_error(filepath, e))?)),
+    };
+    log::info!("Reading {} as {} parts", filepath.display(), parts.len());
+    let sources = parts.iter()
+        .map(|part| FileSource::open(part).map(|source| Arc::new(source) as Arc<dyn ArchiveSource>).map_err(|e| open_error(part, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Box::new(ConcatSource::new(sources)?))
+}
+
+/// The options with the rules of the .tarfsignore in the directory of the archive, unless ignore rules were given
+fn with_adjacent_ignore_file<'o>(filepath: &Path, options: &'o Options) -> Result<Cow<'o, Options>, TarFsError> {
+    let ignore_file = filepath.parent().unwrap_or_else(|| Path::new(".")).join(ignore::IGNORE_FILE_NAME);
+    if options.ignore.is_some() || !ignore_file.is_file() {
+        return Ok(Cow::Borrowed(options));
+    }
+    log::info!("Hiding the entries matched by {}", ignore_file.display());
+    Ok(Cow::Owned(Options {
+        ignore: Some(Arc::new(IgnoreRules::load(&ignore_file)?)),
+        ..options.clone()
+    }))
+}
+
+fn open_error(filepath: &Path, e: io::Error) -> TarFsError {
+    let msg = format!("unable to open the archive {}: {}", filepath.display(), e);
+    match e.kind() {
+        io::ErrorKind::PermissionDenied => TarFsError::PermissionDenied { msg },
+        _ => TarFsError::IndexError { msg },
+    }
+}
+
+fn index_options_for(mountpoint: &Path, options: &MountOptions) -> Result<Options, TarFsError> {
+    // Make the fs root dir permissions the ones from the mountpoint
+    let mountpoint_meta = mountpoint.metadata()?;
+    Ok(Options {
+        mountpoint: Some(mountpoint.canonicalize()?),
+        ..index_options_with(permissions_from_mountpoint(&mountpoint_meta), options)
+    })
+}
 
-    // And finally: Mount it
+fn index_options_with(root_permissions: Permissions, options: &MountOptions) -> Options {
+    // Not the mountpoint's, which differ from machine to machine
+    let root_permissions = match options.deterministic {
+        true => Permissions { mode: 0o40755, uid: 0, gid: 0 },
+        false => root_permissions,
+    };
+    Options {
+        root_permissions,
+        time_policy: options.time_policy.clone(),
+        unsupported: options.unsupported,
+        dir_mode: options.dir_mode,
+        dir_attrs: options.dir_attrs,
+        root_from_archive: options.root_from_archive,
+        root_time: options.root_time,
+        ino_mode: options.ino_mode,
+        decompress_members: options.decompress_members,
+        dedup: options.dedup,
+        rewrite_absolute_symlinks: options.rewrite_absolute_symlinks,
+        follow_symlinks: options.follow_symlinks,
+        numeric_owner: options.numeric_owner,
+        normalize: options.normalize,
+        collisions: options.collisions,
+        selection: options.files_from.as_ref().map(|paths| {
+            let paths: Vec<PathBuf> = paths.iter().map(|path| options.normalize.path(path)).collect();
+            Arc::new(Selection::new(&paths))
+        }),
+        ignore: options.ignore.clone(),
+        limits: options.limits.clone(),
+        userns: options.userns.maps(),
+        mountpoint: None,
+        max_memory: options.max_memory,
+        strict: options.strict,
+        deterministic: options.deterministic,
+        extendable: false,
+    }
+}
+
+fn mount_index<F>(index: SharedIndex, mountpoint: &Path, options: &MountOptions, reload: Option<Reload>, start_signal: Option<mpsc::SyncSender<()>>, on_mounted: F) -> Result<(), TarFsError>
+    where
+        F: FnOnce(KernelCache) -> io::Result<()> {
+    fusemount::check_prerequisites().map_err(|e| TarFsError::FuseUnavailable { msg: e.to_string() })?;
+    if options.control_socket.is_some() && options.sandbox && !fusemount::is_root() {
+        // Users unmount via fusermount, which the sandbox doesn't allow to run
+        return Err(TarFsError::ConfigError {
+            msg: String::from("--control-socket can't be used with --sandbox unless running as root, it couldn't unmount"),
+        });
+    }
+    utils::raise_fd_limit();
     let start_signal = match start_signal {
         Some(s) => s,
         None => mpsc::sync_channel(1).0,
     };
-    let tar_fs = TarFs::new(&mut index, start_signal);
-    tar_fs.mount(mountpoint)?;
+    let stats = Arc::new(Stats::default());
+    stats.set_index_memory(index.read().unwrap().memory_usage());
+    stats.set_source(index.read().unwrap().shared_source());
+    let handles = SharedHandles::default();
+    let controlled_kernel_cache = Arc::new(OnceLock::new());
+    let health = Health {
+        mountpoint: mountpoint.to_path_buf(),
+        mounted: Arc::new(AtomicBool::new(false)),
+    };
+    if let Some(address) = &options.health_address {
+        let listener = TcpListener::bind(address).map_err(|e| TarFsError::MountError {
+            msg: format!("unable to listen on {}: {}", address, e),
+        })?;
+        health::spawn_server(listener, index.clone(), health.clone());
+    }
+    if let Some(socket) = &options.control_socket {
+        let controlled = Controlled {
+            index: index.clone(),
+            stats: stats.clone(),
+            handles: handles.clone(),
+            mountpoint: mountpoint.to_path_buf(),
+            reload,
+            kernel_cache: controlled_kernel_cache.clone(),
+            health: health.clone(),
+        };
+        control::spawn(socket, controlled).map_err(|e| TarFsError::MountError {
+            msg: format!("unable to listen on {}: {}", socket.display(), e),
+        })?;
+    }
+    let audit_log = options.audit_log.as_ref().map(|path| AuditLog::open(path).map_err(|e| TarFsError::MountError {
+        msg: format!("unable to open the audit log {}: {}", path.display(), e),
+    })).transpose()?.map(Arc::new);
+    let threads = options.threads.unwrap_or_else(readpool::default_threads);
+    // Loaded before the recorder might start overwriting it
+    let prefetch = options.prefetch_profile.as_ref().map(|path| profile::load(path).map_err(|e| TarFsError::MountError {
+        msg: format!("unable to read the access profile {}: {}", path.display(), e),
+    })).transpose()?;
+    let recorder = options.record_profile.as_ref().map(|path| ProfileRecorder::create(path).map_err(|e| TarFsError::MountError {
+        msg: format!("unable to open the access profile {}: {}", path.display(), e),
+    })).transpose()?;
+    if let Some(paths) = prefetch {
+        profile::spawn_prefetcher(index.clone(), paths, stats.clone(), threads);
+    }
+    if let Some(paths) = &options.preload {
+        let (source, ranges) = {
+            let index = index.read().unwrap();
+            (index.shared_source(), warmup::select(&index, paths)?)
+        };
+        warmup::spawn_preloader(source, ranges, stats.clone(), threads);
+    }
+    let verifier = options.verify_reads.as_ref().map(|path| Sums::load(path).map_err(|e| TarFsError::MountError {
+        msg: format!("unable to read the checksums {}: {}", path.display(), e),
+    })).transpose()?.map(Verifier::new);
+    let hooks = Hooks { audit_log, profile: recorder, verifier };
+    let tar_fs = TarFs::new(index, stats, handles, options, hooks, start_signal);
+    let res = tar_fs.mount(mountpoint, |kernel_cache| {
+        let _ = controlled_kernel_cache.set(kernel_cache.clone());
+        health.mounted.store(true, Ordering::Relaxed);
+        on_mounted(kernel_cache)?;
+        if options.sandbox {
+            sandbox::enable().map_err(|e| io::Error::new(e.kind(), format!("unable to enable the sandbox: {}", e)))?;
+        }
+        Ok(())
+    }).map_err(|e| {
+        let msg = format!("unable to mount at {}: {}", mountpoint.display(), e);
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => TarFsError::PermissionDenied { msg },
+            _ => TarFsError::MountError { msg },
+        }
+    });
+    health.mounted.store(false, Ordering::Relaxed);
+    if let Some(socket) = &options.control_socket {
+        let _ = fs::remove_file(socket);
+    }
+    res
+}
 
-    Ok(())
+fn apply_log_level(options: &MountOptions) {
+    if let Some(level) = options.log_level {
+        log::set_max_level(level);
+    }
 }
 
-fn ensure_mountpoint_dir_exists(mountpoint: &Path) -> Result<(), TarFsError> {
-    if !mountpoint.exists() || !mountpoint.is_dir() {
-        return Err(TarFsError::MountError{ msg: String::from("mountpoint is not a directory")}.into());
+fn ensure_mountpoint_dir_exists(mountpoint: &Path, options: &MountOptions) -> Result<(), TarFsError> {
+    if !mountpoint.exists() {
+        if !options.mkdir {
+            return Err(TarFsError::MountError{ msg: format!("mountpoint {} does not exist (see --mkdir)", mountpoint.display())});
+        }
+        fs::create_dir_all(mountpoint)?;
+    }
+    if !mountpoint.is_dir() {
+        return Err(TarFsError::MountError{ msg: String::from("mountpoint is not a directory")});
+    }
+    // Hiding files by accident is confusing at best
+    if !options.nonempty && fs::read_dir(mountpoint)?.next().is_some() {
+        return Err(TarFsError::MountError{ msg: format!("mountpoint {} is not empty (see --nonempty)", mountpoint.display())});
     }
     Ok(())
 }
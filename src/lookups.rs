@@ -0,0 +1,41 @@
+//! Which inodes the kernel still references: Every entry handed out in a lookup reply counts, until the kernel forgets it again.
+//! Inodes the kernel doesn't reference have nothing cached either, e.g. they don't need to be invalidated on reload.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub type SharedLookups = Arc<Lookups>;
+
+#[derive(Debug, Default)]
+pub struct Lookups {
+    /// Lookup count by ino, entries without references are removed
+    counts: Mutex<HashMap<u64, u64>>,
+}
+
+impl Lookups {
+    pub fn looked_up(&self, ino: u64) {
+        // Negative entries (ino 0) are never forgotten
+        if ino != 0 {
+            *self.counts.lock().unwrap().entry(ino).or_insert(0) += 1;
+        }
+    }
+
+    pub fn forget(&self, ino: u64, nlookup: u64) {
+        let mut counts = self.counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&ino) {
+            *count = count.saturating_sub(nlookup);
+            if *count == 0 {
+                counts.remove(&ino);
+            }
+        }
+    }
+
+    pub fn is_referenced(&self, ino: u64) -> bool {
+        self.counts.lock().unwrap().contains_key(&ino)
+    }
+
+    /// How many inodes the kernel references
+    pub fn referenced(&self) -> usize {
+        self.counts.lock().unwrap().len()
+    }
+}
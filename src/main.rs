@@ -1,36 +1,997 @@
-use env_logger;
 use tarfslib as lib;
 
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
-use std::path::PathBuf;
+use std::env;
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-fn main() -> Result<(), Box<dyn std::error::Error>>  {
-    let matches = App::new("tarfs")
+fn main() {
+    let matches = match parse_args() {
+        Ok(matches) => matches,
+        Err(e) => exit_with(&*e, json_errors_requested()),
+    };
+    if let Err(e) = run(&matches) {
+        exit_with(&*e, matches.is_present("json-errors"));
+    }
+}
+
+/// A failure found by tarfs itself (not by the library), e.g. a mismatch reported by verify
+#[derive(Debug)]
+struct Failure {
+    kind: lib::FailureKind,
+    msg: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.msg)
+    }
+}
+
+impl Error for Failure {}
+
+/// Reports the error (as JSON line with --json-errors) and exits with the code of its cause
+fn exit_with(e: &(dyn Error + 'static), json: bool) -> ! {
+    let kind = if let Some(e) = e.downcast_ref::<lib::TarFsError>() {
+        e.kind()
+    } else if let Some(failure) = e.downcast_ref::<Failure>() {
+        failure.kind
+    } else {
+        // Invalid arguments, values or combinations thereof
+        lib::FailureKind::Usage
+    };
+    match e.downcast_ref::<clap::Error>() {
+        // Only what's wrong, without the usage and colors
+        Some(e) if json => eprintln!("{}", kind.json(without_colors(e.message.lines().next().unwrap_or_default()).trim_start_matches("error: "))),
+        Some(e) => eprintln!("{}", e.message.trim_end()),
+        None if json => eprintln!("{}", kind.json(e.to_string().trim_end())),
+        None => eprintln!("Error: {}", e.to_string().trim_end()),
+    }
+    process::exit(kind.exit_code());
+}
+
+/// Drops the ANSI escape sequences clap colors its messages with
+fn without_colors(s: &str) -> String {
+    let mut plain = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+/// For errors in the arguments themselves, before it's known whether they are valid
+fn json_errors_requested() -> bool {
+    let opts = env::var("TARFS_OPTS").unwrap_or_default();
+    env::args_os().any(|arg| arg == "--json-errors") || opts.split_whitespace().any(|arg| arg == "--json-errors")
+}
+
+fn run(matches: &ArgMatches<'static>) -> Result<(), Box<dyn Error>> {
+    let log_level = log_level(matches);
+    // $TARFS_LOG takes the same filters as $RUST_LOG, and precedence over it
+    let filter_var = if env::var_os("TARFS_LOG").is_some() { "TARFS_LOG" } else { "RUST_LOG" };
+    let mut logger = env_logger::Builder::from_env(env_logger::Env::default().filter(filter_var));
+    if let Some(level) = log_level {
+        logger.filter_level(level);
+    }
+    logger.init();
+
+    if let Some(matches) = matches.subcommand_matches("serve-dav") {
+        let archive = PathBuf::from(matches.value_of("archive").unwrap());
+        lib::serve_tar_dav(&archive, matches.value_of("listen").unwrap(), &mount_options(matches)?, None)?;
+        return Ok(());
+    }
+    if let Some(matches) = matches.subcommand_matches("manager") {
+        let socket = PathBuf::from(matches.value_of("socket").unwrap());
+        lib::serve_manager(&socket, &mount_options(matches)?, None)?;
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("serve-http") {
+        let archive = PathBuf::from(matches.value_of("archive").unwrap());
+        lib::serve_tar_http(&archive, matches.value_of("listen").unwrap(), &mount_options(matches)?, None)?;
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("warmup") {
+        let archive = PathBuf::from(matches.value_of("archive").unwrap());
+        let paths: Vec<PathBuf> = matches.values_of("paths").map(|paths| paths.map(PathBuf::from).collect()).unwrap_or_default();
+        let warmup = lib::warmup_tar(&archive, &paths, &mount_options(matches)?)?;
+        println!("Warmed up {} files ({} KiB)", warmup.files, warmup.bytes / 1024);
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("verify") {
+        let archive = PathBuf::from(matches.value_of("archive").unwrap());
+        let sums = PathBuf::from(matches.value_of("sums").unwrap());
+        let verification = lib::verify_tar(&archive, &sums, &mount_options(matches)?)?;
+        for (label, paths) in [("MISMATCHED", &verification.mismatched), ("MISSING", &verification.missing), ("EXTRA", &verification.extra)] {
+            for path in paths {
+                println!("{} {}", label, path.display());
+            }
+        }
+        println!("{} files OK, {} mismatched, {} missing, {} extra",
+            verification.ok.len(), verification.mismatched.len(), verification.missing.len(), verification.extra.len());
+        if !verification.is_ok() {
+            return Err(Box::new(Failure {
+                kind: lib::FailureKind::BadArchive,
+                msg: format!("{} doesn't match {}", archive.display(), sums.display()),
+            }));
+        }
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("find") {
+        let pattern = matches.value_of("pattern").unwrap();
+        let pattern = match matches.is_present("regex") {
+            true => lib::FindPattern::regex(pattern)?,
+            false => lib::FindPattern::glob(pattern)?,
+        };
+        let archives: Vec<&str> = matches.values_of("archives").unwrap().collect();
+        let options = mount_options(matches)?;
+        for archive in &archives {
+            for found in lib::find_in_tar(Path::new(archive), &pattern, &options)? {
+                match archives.len() {
+                    1 => println!("{}", found),
+                    _ => println!("{}: {}", archive, found),
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("stat") {
+        let archive = PathBuf::from(matches.value_of("archive").unwrap());
+        let path = PathBuf::from(matches.value_of("path").unwrap());
+        println!("{}", lib::stat_in_tar(&archive, &path, &mount_options(matches)?)?);
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("info") {
+        let archive = PathBuf::from(matches.value_of("archive").unwrap());
+        println!("{}", lib::info_tar(&archive, &mount_options(matches)?)?);
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("selftest") {
+        let dir = matches.value_of("dir").map(PathBuf::from);
+        let selftest = lib::selftest(dir.as_deref(), &mount_options(matches)?)?;
+        for mismatch in &selftest.mismatches {
+            println!("MISMATCH {}", mismatch);
+        }
+        println!("{} entries checked, {} mismatches", selftest.checked, selftest.mismatches.len());
+        if !selftest.is_ok() {
+            return Err(Box::new(Failure {
+                kind: lib::FailureKind::Other,
+                msg: "the mount doesn't match the packed directory".to_string(),
+            }));
+        }
+        return Ok(());
+    }
+
+    if let Some(ats) = matches.values_of("at") {
+        // The only positional argument is the mountpoint then
+        let mountpoint = match (matches.value_of("archive"), matches.value_of("mountpoint")) {
+            (Some(mountpoint), None) | (None, Some(mountpoint)) => PathBuf::from(mountpoint),
+            _ => return Err("with --at, only the mountpoint is given".into()),
+        };
+        let archives = ats.map(parse_at).collect::<Result<Vec<_>, _>>()?;
+        lib::setup_combined_mount(&archives, &mountpoint, &mount_options(matches)?, None)?;
+        return Ok(());
+    }
+
+    let archive = matches.value_of("archive").ok_or("no archive given")?;
+    let options = mount_options(matches)?;
+    if matches.is_present("dry-run") {
+        if archive == "-" {
+            return Err("--dry-run is not supported for archives read from stdin".into());
+        }
+        let dry_run = lib::dry_run_tar(&PathBuf::from(archive), &options)?;
+        for problem in &dry_run.problems {
+            println!("PROBLEM {}", problem);
+        }
+        println!("{} files ({} KiB), {} directories, {} symlinks, {} hard links", dry_run.files, dry_run.content_bytes / 1024,
+            dry_run.dirs, dry_run.symlinks, dry_run.hard_links);
+        println!("{} KiB archive, index of ~{} KiB, {} problems", dry_run.archive_bytes / 1024, dry_run.memory_bytes / 1024, dry_run.problems.len());
+        return Ok(());
+    }
+    if let Some(address) = matches.value_of("nfs") {
+        if archive == "-" {
+            return Err("--nfs is not supported for archives read from stdin".into());
+        }
+        lib::serve_tar_nfs(&PathBuf::from(archive), address, &options, None)?;
+        return Ok(());
+    }
+
+    let mountpoint = PathBuf::from(matches.value_of("mountpoint").ok_or("no mountpoint given")?);
+    if matches.is_present("url") {
+        let cache_size = mib(matches, "cache-size")?.unwrap();
+        let cache_policy: lib::EvictionPolicy = matches.value_of("cache-policy").unwrap().parse()?;
+        let remote_options = lib::RemoteOptions {
+            cache: matches.value_of("cache-dir").map(|dir| lib::ChunkCacheOptions {
+                dir: PathBuf::from(dir),
+                max_size: cache_size,
+                policy: cache_policy,
+            }),
+            retry: lib::RetryPolicy {
+                retries: matches.value_of("retries").unwrap().parse()?,
+                timeout: Duration::from_secs(matches.value_of("timeout").unwrap().parse()?),
+                ..Default::default()
+            },
+        };
+        lib::setup_url_mount(archive, &mountpoint, &options, &remote_options, None)?;
+    } else if archive == "-" {
+        if matches.is_present("watch") || matches.is_present("follow") {
+            return Err("--watch and --follow are not supported for archives read from stdin".into());
+        }
+        lib::setup_stdin_mount(&mountpoint, &options, None)?;
+    } else {
+        lib::setup_tar_mount(&PathBuf::from(archive), &mountpoint, &options, None)?;
+    }
+
+    Ok(())
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("tarfs")
         .version("1.0")
         .author("Gero Posmyk-Leinemann <geroleinemann@gmx.de>")
         .about("A readonly FUSE filesystem that allows to mount tar files")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(Arg::with_name("archive")
             .short("a")
             .long("archive")
-            .help("The tar file that should be mounted")
-            .required(true)
+            .help("The tar file that should be mounted (or its URL, see --url). Use - to read it from stdin")
+            .required_unless_one(&["mount", "at"])
             .takes_value(true)
             .index(1))
         .arg(Arg::with_name("mountpoint")
             .short("m")
             .long("mountpoint")
             .help("The path to the directory where the archive should be mounted")
-            .required(true)
+            .required_unless_one(&["nfs", "mount", "at", "dry-run"])
             .takes_value(true)
             .index(2))
-        .get_matches();
+        .arg(Arg::with_name("at")
+            .long("at")
+            .help("Mount <archive> at <subpath> of the mountpoint. Can be given several times to mount several archives at once, e.g. --at /data=a.tar --at /configs=b.tar /mnt")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("subpath=archive")
+            .conflicts_with_all(&["mount", "url", "watch", "follow", "nfs"]))
+        .arg(Arg::with_name("config")
+            .long("config")
+            .env("TARFS_CONFIG")
+            .global(true)
+            .help("Read defaults and named mounts from this TOML file (default: /etc/tarfs.conf, if it exists)")
+            .takes_value(true)
+            .value_name("path"))
+        .arg(Arg::with_name("mount")
+            .long("mount")
+            .env("TARFS_MOUNT")
+            .help("Mount the archive of the mount with this name in the config file, with its settings")
+            .takes_value(true)
+            .value_name("name"))
+        .arg(Arg::with_name("mkdir")
+            .long("mkdir")
+            .help("Create the mountpoint (and its parents) if it doesn't exist"))
+        .arg(Arg::with_name("nonempty")
+            .long("nonempty")
+            .help("Mount even if the mountpoint isn't empty, hiding its content while mounted"))
+        .arg(Arg::with_name("cache-dir")
+            .long("cache-dir")
+            .env("TARFS_CACHE_DIR")
+            .help("Cache content fetched from remote archives in this directory, re-used across mounts")
+            .takes_value(true)
+            .requires("url"))
+        .arg(Arg::with_name("cache-size")
+            .long("cache-size")
+            .env("TARFS_CACHE_SIZE")
+            .help("Maximum size of the cache for the mounted archive in MiB")
+            .takes_value(true)
+            .default_value("1024"))
+        .arg(Arg::with_name("cache-policy")
+            .long("cache-policy")
+            .env("TARFS_CACHE_POLICY")
+            .help("Which cached chunks make room once the cache is full: the least recently used (lru), the least frequently used (lfu), or adaptively either (arc)")
+            .takes_value(true)
+            .possible_values(&["lru", "lfu", "arc"])
+            .default_value("lru"))
+        .arg(Arg::with_name("retries")
+            .long("retries")
+            .env("TARFS_RETRIES")
+            .help("How often failing requests to remote archives are retried (with exponential backoff)")
+            .takes_value(true)
+            .default_value("3"))
+        .arg(Arg::with_name("timeout")
+            .long("timeout")
+            .env("TARFS_TIMEOUT")
+            .help("Timeout for requests to remote archives in seconds")
+            .takes_value(true)
+            .default_value("30"))
+        .arg(Arg::with_name("watch")
+            .long("watch")
+            .help("Re-index the archive whenever it gets replaced or rewritten, without remounting")
+            .conflicts_with("url"))
+        .arg(Arg::with_name("follow")
+            .long("follow")
+            .help("Keep adding entries appended to the archive while mounted, e.g. for archives still being written")
+            .conflicts_with_all(&["url", "watch"]))
+        .arg(Arg::with_name("set-epoch")
+            .long("set-epoch")
+            .global(true)
+            .help("Set all times to $SOURCE_DATE_EPOCH (or 1970-01-01 if unset), e.g. for reproducible builds")
+            .conflicts_with_all(&["time-offset", "mtime-clamp"]))
+        .arg(Arg::with_name("time-offset")
+            .long("time-offset")
+            .env("TARFS_TIME_OFFSET")
+            .global(true)
+            .help("Shift all times by this many seconds (may be negative)")
+            .takes_value(true)
+            .allow_hyphen_values(true))
+        .arg(Arg::with_name("mtime-clamp")
+            .long("mtime-clamp")
+            .env("TARFS_MTIME_CLAMP")
+            .global(true)
+            .help("Set all times later than this date to it: YYYY-MM-DD[THH:MM:SS] (UTC) or seconds since the epoch")
+            .takes_value(true))
+        .arg(Arg::with_name("unsupported")
+            .long("unsupported")
+            .env("TARFS_UNSUPPORTED")
+            .global(true)
+            .help("What to do with entries of unsupported types (devices, fifos, sparse files, ...)")
+            .takes_value(true)
+            .possible_values(&["skip", "warn", "error", "as-file"])
+            .default_value("as-file"))
+        .arg(Arg::with_name("dir-mode")
+            .long("dir-mode")
+            .env("TARFS_DIR_MODE")
+            .global(true)
+            .help("Permissions (octal) of directories missing in the archive, or \"inherit\" to use the ones of the parent")
+            .takes_value(true)
+            .default_value("755"))
+        .arg(Arg::with_name("dir-attrs")
+            .long("dir-attrs")
+            .env("TARFS_DIR_ATTRS")
+            .global(true)
+            .help("Where directories missing in the archive take their owner and times from: the parent (and the archive's mtime), or their children (the owner of the first one, the newest mtime)")
+            .takes_value(true)
+            .possible_values(&["parent", "children"])
+            .default_value("parent"))
+        .arg(Arg::with_name("root-from-archive")
+            .long("root-from-archive")
+            .global(true)
+            .help("Take mode, owner and times of the root directory from the archive's \"./\" entry (if any) instead of the mountpoint"))
+        .arg(Arg::with_name("root-time")
+            .long("root-time")
+            .env("TARFS_ROOT_TIME")
+            .global(true)
+            .help("Where the root directory takes its times from: when mounting (now), the newest entry, the archive's mtime, or a date: YYYY-MM-DD[THH:MM:SS] (UTC) or seconds since the epoch, default: now")
+            .takes_value(true))
+        .arg(Arg::with_name("inos")
+            .long("inos")
+            .env("TARFS_INOS")
+            .global(true)
+            .help("How inode numbers are assigned: in indexing order, or derived from the entries' offsets in the archive (stable across remounts, e.g. for NFS)")
+            .takes_value(true)
+            .possible_values(&["sequential", "offset"])
+            .default_value("sequential"))
+        .arg(Arg::with_name("deterministic")
+            .long("deterministic")
+            .global(true)
+            .help("Present the archive the same way on every machine, e.g. for reproducible builds: children sorted by name, inos numbered in that order (instead of --inos), times clamped to $SOURCE_DATE_EPOCH if set")
+            .conflicts_with_all(&["follow", "atime"]))
+        .arg(Arg::with_name("decompress-members")
+            .long("decompress-members")
+            .global(true)
+            .help("Present gzip compressed members (foo.txt.gz) decompressed under their name without .gz (foo.txt), unless that exists as well"))
+        .arg(Arg::with_name("dedup")
+            .long("dedup")
+            .env("TARFS_DEDUP")
+            .global(true)
+            .help("Find files with identical content while indexing and let them share it: where it's read from, or the whole inode like hard links (including its mode, owner and times)")
+            .takes_value(true)
+            .possible_values(&["content", "inode"]))
+        .arg(Arg::with_name("rewrite-absolute-symlinks")
+            .long("rewrite-absolute-symlinks")
+            .global(true)
+            .help("Rewrite absolute symlink targets, which would escape the mount: relative to the symlink (default), or prefixed with the mountpoint")
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true)
+            .possible_values(&["relative", "mountpoint"]))
+        .arg(Arg::with_name("follow-symlinks")
+            .long("follow-symlinks")
+            .global(true)
+            .help("Present symlinks pointing inside the archive as what they point to: files as hard links, directories as copies"))
+        .arg(Arg::with_name("numeric-owner")
+            .long("numeric-owner")
+            .global(true)
+            .help("Present the uids/gids stored in the archive, instead of the local ids of the user/group names stored along with them"))
+        .arg(Arg::with_name("normalize")
+            .long("normalize")
+            .env("TARFS_NORMALIZE")
+            .global(true)
+            .help("The Unicode normalization form of names, applied when indexing and in lookups so names match in either form (e.g. NFD names of archives created on macOS)")
+            .takes_value(true)
+            .possible_values(&["nfc", "nfd", "none"])
+            .default_value("none"))
+        .arg(Arg::with_name("collisions")
+            .long("collisions")
+            .env("TARFS_COLLISIONS")
+            .global(true)
+            .help("What to do with distinct members whose names become the same once normalized: present the latter only (replace), present it with a ~1 suffix (rename), or refuse the archive (error)")
+            .takes_value(true)
+            .possible_values(&["replace", "rename", "error"])
+            .default_value("replace"))
+        .arg(Arg::with_name("userns-map")
+            .long("userns-map")
+            .env("TARFS_USERNS_MAP")
+            .global(true)
+            .help("How to present owners for user namespaces: through the maps of the namespace tarfs runs in (auto), not at all (none), or through the given ranges like 0:100000:65536 (ids inside:outside:count, for uids and gids)")
+            .takes_value(true)
+            .value_name("map")
+            .default_value("auto"))
+        .arg(Arg::with_name("check-permissions")
+            .long("check-permissions")
+            .help("Check permissions against the owners and modes of the archive in tarfs instead of the kernel, e.g. so access() is answered by tarfs"))
+        .arg(Arg::with_name("files-from")
+            .short("T")
+            .long("files-from")
+            .global(true)
+            .help("Only present the members listed in this file (one path per line, or NUL separated), everything below them and their parents, like tar -T")
+            .takes_value(true)
+            .value_name("file"))
+        .arg(Arg::with_name("ignore-file")
+            .long("ignore-file")
+            .env("TARFS_IGNORE_FILE")
+            .global(true)
+            .help("Hide the members matching the patterns (gitignore syntax) in this file, instead of the ones of a .tarfsignore next to the archive")
+            .takes_value(true)
+            .value_name("file"))
+        .arg(Arg::with_name("max-memory")
+            .long("max-memory")
+            .env("TARFS_MAX_MEMORY")
+            .global(true)
+            .help("Give up indexing rather than letting the index take more than this many MiB, e.g. for huge archives on small machines")
+            .takes_value(true)
+            .value_name("MiB"))
+        .arg(Arg::with_name("strict")
+            .long("strict")
+            .global(true)
+            .help("Refuse archives defining a path again with a different type (e.g. a file, later a directory) instead of presenting the last definition"))
+        .arg(Arg::with_name("max-depth")
+            .long("max-depth")
+            .env("TARFS_MAX_DEPTH")
+            .global(true)
+            .help("Limit how many directories deep entries may be nested, protecting against crafted archives (see --over-limit)")
+            .takes_value(true)
+            .value_name("n"))
+        .arg(Arg::with_name("max-entries")
+            .long("max-entries")
+            .env("TARFS_MAX_ENTRIES")
+            .global(true)
+            .help("Limit how many entries the archive may have (see --over-limit)")
+            .takes_value(true)
+            .value_name("n"))
+        .arg(Arg::with_name("max-member-size")
+            .long("max-member-size")
+            .env("TARFS_MAX_MEMBER_SIZE")
+            .global(true)
+            .help("Limit how large members may be in MiB (see --over-limit)")
+            .takes_value(true)
+            .value_name("MiB"))
+        .arg(Arg::with_name("over-limit")
+            .long("over-limit")
+            .env("TARFS_OVER_LIMIT")
+            .global(true)
+            .help("What to do with entries exceeding --max-depth, --max-entries or --max-member-size: fail indexing, or leave them out")
+            .takes_value(true)
+            .possible_values(&["error", "skip"])
+            .default_value("error"))
+        .arg(Arg::with_name("nfs-export")
+            .long("nfs-export")
+            .help("Allow exporting the mount via NFS (implies --inos offset unless given)"))
+        .arg(Arg::with_name("max-read")
+            .long("max-read")
+            .env("TARFS_MAX_READ")
+            .help("Maximum size of a single read request in KiB, e.g. 1024 for fewer, larger requests (the kernel caps it at its max_pages, usually 1 MiB)")
+            .takes_value(true)
+            .value_name("KiB"))
+        .arg(Arg::with_name("max-readahead")
+            .long("max-readahead")
+            .env("TARFS_MAX_READAHEAD")
+            .help("How far the kernel may read ahead of sequential reads in KiB")
+            .takes_value(true)
+            .value_name("KiB"))
+        .arg(Arg::with_name("max-background")
+            .long("max-background")
+            .env("TARFS_MAX_BACKGROUND")
+            .help("Maximum number of pending background requests (e.g. readahead) of the FUSE connection")
+            .takes_value(true)
+            .value_name("n"))
+        .arg(Arg::with_name("congestion-threshold")
+            .long("congestion-threshold")
+            .env("TARFS_CONGESTION_THRESHOLD")
+            .help("Number of pending background requests at which the kernel considers the FUSE connection congested (default: 3/4 of --max-background)")
+            .takes_value(true)
+            .value_name("n"))
+        .arg(Arg::with_name("control-socket")
+            .long("control-socket")
+            .env("TARFS_CONTROL_SOCKET")
+            .help("Listen on this unix socket for queries, e.g. \"stats\" for the mount's runtime statistics")
+            .takes_value(true)
+            .value_name("path"))
+        .arg(Arg::with_name("audit-log")
+            .long("audit-log")
+            .env("TARFS_AUDIT_LOG")
+            .help("Record every open and read (time, uid, pid, op, path, bytes) as JSON lines in this file")
+            .takes_value(true)
+            .value_name("file"))
+        .arg(Arg::with_name("mime-types")
+            .long("mime-types")
+            .help("Present the MIME type of files, guessed from their first bytes when first asked for, as extended attribute user.mime_type"))
+        .arg(Arg::with_name("verify-reads")
+            .long("verify-reads")
+            .env("TARFS_VERIFY_READS")
+            .help("Check the content of members against the SHA-256 digests in this manifest (as written by sha256sum) as they are read, failing the read completing a corrupt one and all later reads of it with EIO")
+            .takes_value(true)
+            .value_name("sums"))
+        .arg(Arg::with_name("preload")
+            .long("preload")
+            .help("Once mounted, read the members at these paths (and below, all if none are given) in the background to get them cached, e.g. --preload=/bin,/lib")
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true)
+            .use_delimiter(true)
+            .value_name("paths"))
+        .arg(Arg::with_name("record-profile")
+            .long("record-profile")
+            .env("TARFS_RECORD_PROFILE")
+            .help("Record which files get opened, in order, in this file when unmounting (see --prefetch-profile)")
+            .takes_value(true)
+            .value_name("file"))
+        .arg(Arg::with_name("prefetch-profile")
+            .long("prefetch-profile")
+            .env("TARFS_PREFETCH_PROFILE")
+            .help("Read the files recorded in this profile ahead in the background, e.g. to speed up repeated container starts")
+            .takes_value(true)
+            .value_name("file"))
+        .arg(Arg::with_name("nfs")
+            .long("nfs")
+            .help("Serve the archive via NFSv3 (and its MOUNT protocol) on this address (e.g. 0.0.0.0:2049) instead of mounting it")
+            .takes_value(true)
+            .value_name("address")
+            .conflicts_with_all(&["mountpoint", "url", "watch", "follow", "nfs-export", "control-socket", "audit-log", "verify-reads", "inject", "health-address", "max-open-files", "threads", "atime", "mime-types", "check-permissions", "record-profile", "prefetch-profile", "preload",
+                "max-read", "max-readahead", "max-background", "congestion-threshold"]))
+        .arg(Arg::with_name("dry-run")
+            .long("dry-run")
+            .help("Index the archive, print a summary (entries, sizes, estimated memory, problems found) and exit without mounting")
+            .conflicts_with_all(&["url", "nfs", "at", "watch", "follow"]))
+        .arg(Arg::with_name("json-errors")
+            .long("json-errors")
+            .global(true)
+            .help("Report a failure as JSON object on stderr, e.g. {\"error\":\"bad_archive\",\"exit_code\":3,\"message\":\"...\"}"))
+        .arg(Arg::with_name("health-address")
+            .long("health-address")
+            .env("TARFS_HEALTH_ADDRESS")
+            .help("Serve GET /healthz on this address (e.g. 0.0.0.0:8081): 200 while the FUSE session runs and answers a stat on the root, 503 otherwise")
+            .takes_value(true)
+            .value_name("address"))
+        .arg(Arg::with_name("max-open-files")
+            .long("max-open-files")
+            .env("TARFS_MAX_OPEN_FILES")
+            .help("Fail opening files with EMFILE while this many are open via the mount")
+            .takes_value(true)
+            .value_name("n"))
+        .arg(Arg::with_name("threads")
+            .long("threads")
+            .env("TARFS_THREADS")
+            .help("How many threads serve reads, preload or prefetch in the background, and index the archives of --at or the manager at once, default: one per CPU, up to 8")
+            .takes_value(true)
+            .value_name("n"))
+        .arg(Arg::with_name("atime")
+            .long("atime")
+            .env("TARFS_ATIME")
+            .help("Emulate atimes updated by reads, tracked in memory only: relatime (like mount -o relatime) or strict (every read), default: static")
+            .takes_value(true)
+            .possible_values(&["static", "relatime", "strict"]))
+        .arg(Arg::with_name("inject")
+            .long("inject")
+            .hidden(true)
+            .help("Randomly delay and fail reads, e.g. errors=0.01,latency=50ms")
+            .takes_value(true)
+            .value_name("spec"))
+        .arg(Arg::with_name("sandbox")
+            .long("sandbox")
+            .global(true)
+            .help("Once mounted (or listening), restrict tarfs to the syscalls needed for serving (seccomp), as archives are untrusted input"))
+        .arg(Arg::with_name("verbose")
+            .short("v")
+            .long("verbose")
+            .global(true)
+            .multiple(true)
+            .help("Log more: -v for info, -vv for debug, -vvv for trace messages (default: warnings, or $TARFS_LOG/$RUST_LOG if set)"))
+        .arg(Arg::with_name("quiet")
+            .short("q")
+            .long("quiet")
+            .global(true)
+            .help("Only log errors")
+            .conflicts_with("verbose"))
+        .arg(Arg::with_name("url")
+            .long("url")
+            .help("Treat <archive> as a URL (http(s)://, or s3://, gs://, az:// with the object-store feature) and fetch content lazily via range requests"))
+        .subcommand(SubCommand::with_name("warmup")
+            .about("Read members of the archive once to get them into the page cache, e.g. before a workload mounting it starts")
+            .arg(Arg::with_name("archive")
+                .help("The tar file to warm up")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("paths")
+                .help("The members to read, including everything below directories (default: all)")
+                .multiple(true)
+                .index(2)))
+        .subcommand(SubCommand::with_name("verify")
+            .about("Compare the files of the archive to a checksum manifest, reporting mismatched, missing and extra files")
+            .arg(Arg::with_name("sums")
+                .long("sums")
+                .help("The manifest of SHA-256 digests, as written by sha256sum (e.g. SHA256SUMS)")
+                .required(true)
+                .takes_value(true)
+                .value_name("file"))
+            .arg(Arg::with_name("archive")
+                .help("The tar file to verify")
+                .required(true)
+                .index(1)))
+        .subcommand(SubCommand::with_name("find")
+            .about("List the members whose paths match a glob (or regex), with their metadata like tar -tv, via the index")
+            .arg(Arg::with_name("regex")
+                .long("regex")
+                .help("The pattern is a regex searched for in the paths (e.g. dir/file), not a glob"))
+            .arg(Arg::with_name("archives")
+                .help("The tar files to search")
+                .required(true)
+                .multiple(true)
+                .index(1))
+            .arg(Arg::with_name("pattern")
+                .help("A glob matching names (e.g. '*.log'), or paths if it has a slash (e.g. 'logs/**/*.log')")
+                .required(true)
+                .index(2)))
+        .subcommand(SubCommand::with_name("stat")
+            .about("Print the full metadata of a member (mode, owner, times with nanoseconds, link target, offsets) from the index")
+            .arg(Arg::with_name("archive")
+                .help("The tar file")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("path")
+                .help("The path of the member, e.g. dir/file")
+                .required(true)
+                .index(2)))
+        .subcommand(SubCommand::with_name("info")
+            .about("Print totals of the archive: entries by type, sizes, largest files, deepest paths, owners and its format")
+            .arg(Arg::with_name("archive")
+                .help("The tar file")
+                .required(true)
+                .index(1)))
+        .subcommand(SubCommand::with_name("selftest")
+            .about("Pack a directory, mount it in a temporary location and compare the mount to it, to check the FUSE setup")
+            .arg(Arg::with_name("dir")
+                .help("The directory to pack (default: a synthetic tree with links, long and unicode names and holes)")
+                .index(1)))
+        .subcommand(SubCommand::with_name("serve-dav")
+            .about("Serve the archive as read-only WebDAV share instead of mounting it")
+            .arg(Arg::with_name("archive")
+                .help("The tar file that should be served")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("listen")
+                .long("listen")
+                .env("TARFS_LISTEN")
+                .help("The address to listen on")
+                .takes_value(true)
+                .default_value("127.0.0.1:8080")))
+        .subcommand(SubCommand::with_name("manager")
+            .about("Mount and unmount archives as requested via a unix socket (publish <archive> <target>, unpublish <target>, list), e.g. for a CSI node plugin")
+            .arg(Arg::with_name("socket")
+                .help("The unix socket to accept requests on")
+                .required(true)
+                .index(1)))
+        .subcommand(SubCommand::with_name("serve-http")
+            .about("Serve the archive's files via plain HTTP (like a static web server) instead of mounting it")
+            .arg(Arg::with_name("archive")
+                .help("The tar file that should be served")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("listen")
+                .long("listen")
+                .env("TARFS_LISTEN")
+                .help("The address to listen on")
+                .takes_value(true)
+                .default_value("127.0.0.1:8080")))
+}
 
-    let filename = PathBuf::from(matches.value_of("archive").unwrap());
-    let mountpoint = PathBuf::from(matches.value_of("mountpoint").unwrap());
+/// Parses the command line merged with the config file:
+/// flags given on the command line win over the settings of the selected mount, which win over [defaults]
+fn parse_args() -> Result<ArgMatches<'static>, Box<dyn std::error::Error>> {
+    let mut args: Vec<OsString> = env::args_os().collect();
+    // Extra flags, e.g. for containers whose command line is awkward to change
+    if let Ok(opts) = env::var("TARFS_OPTS") {
+        args.splice(1..1, opts.split_whitespace().map(OsString::from));
+    }
+    let matches = get_matches(&args)?;
+    let config = match matches.value_of("config") {
+        Some(path) => lib::Config::load(Path::new(path))?,
+        None if Path::new(lib::DEFAULT_CONFIG_PATH).exists() => lib::Config::load(Path::new(lib::DEFAULT_CONFIG_PATH))?,
+        None if matches.is_present("mount") => return Err("--mount requires a config file (see --config)".into()),
+        None => return Ok(matches),
+    };
 
-    env_logger::init();
-    lib::setup_tar_mount(&filename, &mountpoint, None)?;
+    let mut merged = vec![args[0].clone()];
+    let mut positionals = vec![];
+    for (key, value) in config.settings(matches.value_of("mount"))? {
+        if key == "config" || key == "mount" {
+            return Err(format!("{} can't be set in the config file", key).into());
+        }
+        if given_explicitly(&matches, &key) {
+            continue;
+        }
+        match value {
+            // Positional arguments can't be given by name
+            value if key == "archive" => positionals.insert(0, value.to_string().into()),
+            value if key == "mountpoint" => positionals.push(value.to_string().into()),
+            lib::ConfigValue::Boolean(false) => (),
+            lib::ConfigValue::Boolean(true) => merged.push(format!("--{}", key).into()),
+            value => merged.push(format!("--{}={}", key, value).into()),
+        }
+    }
+    merged.extend(args.into_iter().skip(1));
+    merged.extend(positionals);
+    // Unknown keys and invalid values get reported like flags given on the command line
+    get_matches(&merged)
+}
 
-    Ok(())
+/// Like App::get_matches_from, but leaves reporting errors to the caller
+fn get_matches(args: &[OsString]) -> Result<ArgMatches<'static>, Box<dyn Error>> {
+    match app().get_matches_from_safe(args) {
+        Ok(matches) => Ok(matches),
+        Err(e) if e.kind == clap::ErrorKind::HelpDisplayed || e.kind == clap::ErrorKind::VersionDisplayed => e.exit(),
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+/// Splits "/data=a.tar" into subpath and archive
+fn parse_at(at: &str) -> Result<(PathBuf, PathBuf), Box<dyn std::error::Error>> {
+    match at.split_once('=') {
+        Some((subpath, archive)) if !subpath.is_empty() && !archive.is_empty() => Ok((PathBuf::from(subpath), PathBuf::from(archive))),
+        _ => Err(format!("invalid --at {}: expected <subpath>=<archive>", at).into()),
+    }
+}
+
+/// Whether the flag was given on the command line (or $TARFS_OPTS), or via its environment variable
+fn given_explicitly(matches: &ArgMatches, name: &str) -> bool {
+    // Global flags might be given after a subcommand as well
+    matches.occurrences_of(name) > 0
+        || matches.subcommand().1.is_some_and(|sub| sub.occurrences_of(name) > 0)
+        || env::var_os(env_var(name)).is_some()
+}
+
+/// The environment variable for a flag, e.g. TARFS_CACHE_DIR for --cache-dir (for flags taking a value)
+fn env_var(name: &str) -> String {
+    format!("TARFS_{}", name.to_uppercase().replace('-', "_"))
+}
+
+fn mount_options(matches: &ArgMatches) -> Result<lib::MountOptions, Box<dyn std::error::Error>> {
+    Ok(lib::MountOptions {
+        watch: matches.is_present("watch"),
+        follow: matches.is_present("follow"),
+        time_policy: time_policy(matches)?,
+        unsupported: matches.value_of("unsupported").unwrap().parse()?,
+        dir_mode: matches.value_of("dir-mode").unwrap().parse()?,
+        dir_attrs: matches.value_of("dir-attrs").unwrap().parse()?,
+        root_from_archive: matches.is_present("root-from-archive"),
+        root_time: matches.value_of("root-time").map(root_time).transpose()?.unwrap_or_default(),
+        ino_mode: ino_mode(matches)?,
+        deterministic: matches.is_present("deterministic"),
+        decompress_members: matches.is_present("decompress-members"),
+        dedup: matches.value_of("dedup").map(|mode| mode.parse()).transpose()?,
+        rewrite_absolute_symlinks: match matches.is_present("rewrite-absolute-symlinks") {
+            true => Some(matches.value_of("rewrite-absolute-symlinks").unwrap_or("relative").parse()?),
+            false => None,
+        },
+        follow_symlinks: matches.is_present("follow-symlinks"),
+        numeric_owner: matches.is_present("numeric-owner"),
+        normalize: matches.value_of("normalize").unwrap().parse()?,
+        collisions: matches.value_of("collisions").unwrap().parse()?,
+        userns: matches.value_of("userns-map").unwrap().parse()?,
+        limits: lib::Limits {
+            max_depth: matches.value_of("max-depth").map(str::parse).transpose()?,
+            max_entries: matches.value_of("max-entries").map(str::parse).transpose()?,
+            max_member_size: mib(matches, "max-member-size")?,
+            exceeded: matches.value_of("over-limit").unwrap().parse()?,
+        },
+        files_from: matches.value_of("files-from").map(|path| files_from(Path::new(path))).transpose()?,
+        ignore: matches.value_of("ignore-file").map(|path| lib::IgnoreRules::load(Path::new(path)).map(Arc::new)).transpose()?,
+        max_memory: mib(matches, "max-memory")?,
+        strict: matches.is_present("strict"),
+        check_permissions: matches.is_present("check-permissions"),
+        nfs_export: matches.is_present("nfs-export"),
+        tuning: fuse_tuning(matches)?,
+        audit_log: matches.value_of("audit-log").map(PathBuf::from),
+        mime_types: matches.is_present("mime-types"),
+        verify_reads: matches.value_of("verify-reads").map(PathBuf::from),
+        inject: matches.value_of("inject").map(str::parse).transpose()?,
+        max_open_files: matches.value_of("max-open-files").map(str::parse).transpose()?,
+        threads: matches.value_of("threads").map(str::parse).transpose()?,
+        atime: matches.value_of("atime").map(str::parse).transpose()?.unwrap_or_default(),
+        preload: match matches.is_present("preload") {
+            true => Some(matches.values_of("preload").map(|paths| paths.map(PathBuf::from).collect()).unwrap_or_default()),
+            false => None,
+        },
+        record_profile: matches.value_of("record-profile").map(PathBuf::from),
+        prefetch_profile: matches.value_of("prefetch-profile").map(PathBuf::from),
+        control_socket: matches.value_of("control-socket").map(PathBuf::from),
+        health_address: matches.value_of("health-address").map(String::from),
+        log_level: log_level(matches),
+        sandbox: matches.is_present("sandbox"),
+        mkdir: matches.is_present("mkdir"),
+        nonempty: matches.is_present("nonempty"),
+    })
+}
+
+fn time_policy(matches: &ArgMatches) -> Result<lib::TimePolicy, Box<dyn std::error::Error>> {
+    let epoch = if matches.is_present("set-epoch") {
+        match env::var("SOURCE_DATE_EPOCH") {
+            Ok(secs) => Some(source_date_epoch(&secs)?),
+            Err(_) => Some(UNIX_EPOCH),
+        }
+    } else {
+        None
+    };
+    let clamp = match matches.value_of("mtime-clamp") {
+        Some(date) => Some(parse_date(date).ok_or_else(|| format!("invalid date for --mtime-clamp: {}", date))?),
+        None if matches.is_present("deterministic") => match env::var("SOURCE_DATE_EPOCH") {
+            Ok(secs) => Some(source_date_epoch(&secs)?),
+            Err(_) => None,
+        },
+        None => None,
+    };
+    let offset: i64 = matches.value_of("time-offset").map(|o| o.parse()).transpose()?.unwrap_or(0);
+    // The times of archives are about now, shifting those must stay representable
+    let now = SystemTime::now();
+    let shifted = match offset >= 0 {
+        true => now.checked_add(Duration::from_secs(offset as u64)),
+        false => now.checked_sub(Duration::from_secs(offset.unsigned_abs())),
+    };
+    if shifted.is_none() {
+        return Err(format!("--time-offset is out of range: {}", offset).into());
+    }
+    Ok(lib::TimePolicy { epoch, offset, clamp })
+}
+
+fn source_date_epoch(secs: &str) -> Result<SystemTime, Box<dyn std::error::Error>> {
+    secs.parse().ok().and_then(from_unix_secs).ok_or_else(|| format!("invalid SOURCE_DATE_EPOCH: {}", secs).into())
+}
+
+/// None if the time isn't representable
+fn from_unix_secs(secs: u64) -> Option<SystemTime> {
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+fn fuse_tuning(matches: &ArgMatches) -> Result<lib::FuseTuning, Box<dyn std::error::Error>> {
+    let kib = |name| -> Result<Option<u32>, Box<dyn std::error::Error>> {
+        match matches.value_of(name) {
+            Some(value) => {
+                let kib: u32 = value.parse()?;
+                Ok(Some(kib.checked_mul(1024).ok_or_else(|| format!("--{} is too large: {}", name, value))?))
+            },
+            None => Ok(None),
+        }
+    };
+    Ok(lib::FuseTuning {
+        max_read: kib("max-read")?,
+        max_readahead: kib("max-readahead")?,
+        max_background: matches.value_of("max-background").map(|n| n.parse()).transpose()?,
+        congestion_threshold: matches.value_of("congestion-threshold").map(|n| n.parse()).transpose()?,
+    })
+}
+
+/// The value of a flag given in MiB, in bytes
+fn mib(matches: &ArgMatches, name: &str) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+    match matches.value_of(name) {
+        Some(value) => {
+            let mib: u64 = value.parse()?;
+            Ok(Some(mib.checked_mul(1024 * 1024).ok_or_else(|| format!("--{} is too large: {}", name, value))?))
+        },
+        None => Ok(None),
+    }
+}
+
+/// The paths listed one per line, or separated by NUL bytes if there are any (like find -print0 writes them)
+fn files_from(path: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let content = fs::read(path).map_err(|e| format!("unable to read --files-from {}: {}", path.display(), e))?;
+    let separator = if content.contains(&0) { 0 } else { b'\n' };
+    Ok(content.split(|b| *b == separator)
+        .filter(|name| !name.is_empty())
+        .map(|name| PathBuf::from(OsStr::from_bytes(name)))
+        .collect())
+}
+
+fn ino_mode(matches: &ArgMatches) -> Result<lib::InoMode, Box<dyn std::error::Error>> {
+    // NFS file handles have to survive remounts
+    if matches.is_present("nfs-export") && !given_explicitly(matches, "inos") {
+        return Ok(lib::InoMode::Offset);
+    }
+    Ok(matches.value_of("inos").unwrap().parse()?)
+}
+
+/// None if neither -v nor -q were given and $RUST_LOG decides
+fn log_level(matches: &ArgMatches) -> Option<lib::LevelFilter> {
+    if matches.is_present("quiet") {
+        return Some(lib::LevelFilter::Error);
+    }
+    match matches.occurrences_of("verbose") {
+        0 if env::var_os("TARFS_LOG").is_some() || env::var_os("RUST_LOG").is_some() => None,
+        0 => Some(lib::LevelFilter::Warn),
+        1 => Some(lib::LevelFilter::Info),
+        2 => Some(lib::LevelFilter::Debug),
+        _ => Some(lib::LevelFilter::Trace),
+    }
+}
+
+/// A date for --root-time is a fixed time, anything else a source
+fn root_time(value: &str) -> Result<lib::RootTime, String> {
+    match parse_date(value) {
+        Some(time) => Ok(lib::RootTime::Fixed(time)),
+        None => value.parse(),
+    }
+}
+
+/// Parses seconds since the epoch or YYYY-MM-DD[THH:MM:SS[Z]] (UTC)
+fn parse_date(date: &str) -> Option<SystemTime> {
+    if let Ok(secs) = date.parse::<u64>() {
+        return from_unix_secs(secs);
+    }
+
+    let date = date.trim_end_matches('Z');
+    let (day, time) = match date.split_once(['T', ' ']) {
+        Some((day, time)) => (day, time),
+        None => (date, "00:00:00"),
+    };
+    let day: Vec<u32> = day.split('-').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let time: Vec<u32> = time.split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    match (day.as_slice(), time.as_slice()) {
+        ([y, m, d], [hh, mm, ss]) if (1..=12).contains(m) && (1..=31).contains(d) && *hh < 24 && *mm < 60 && *ss < 61 => {
+            let days = days_since_epoch(*y as i64, *m as i64, *d as i64);
+            let secs = days * 86400 + (*hh as i64) * 3600 + (*mm as i64) * 60 + *ss as i64;
+            if secs < 0 {
+                return None;
+            }
+            from_unix_secs(secs as u64)
+        },
+        _ => None,
+    }
+}
+
+/// Days from 1970-01-01 to the given date of the proleptic Gregorian calendar (see http://howardhinnant.github.io/date_algorithms.html)
+fn days_since_epoch(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
 }
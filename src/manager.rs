@@ -0,0 +1,278 @@
+//! The mount manager (`tarfs manager <socket>`): a long-running process mounting archives on request, for node plugins
+//! of container orchestrators (e.g. a CSI driver's NodePublishVolume/NodeUnpublishVolume) to mount artifact tarballs
+//! into pods without a tarfs process of their own per mount. Like the control socket, it accepts one command per line,
+//! each answered with one line of JSON:
+//!  - `publish <archive> <target>`: mounts the archive at target, creating it if missing
+//!  - `unpublish <target>`: unmounts target, and removes it if publish created it
+//!  - `list`: the published mounts, and the ones being published
+//!
+//! Both publish and unpublish are idempotent, as orchestrators retry them. Archives requested at once are indexed
+//! concurrently, as many at a time as there are threads (--threads).
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{debug, error, info};
+
+use crate::fusemount;
+use crate::readpool;
+use crate::utils::json_string;
+use crate::{setup_tar_mount, MountOptions, TarFsError};
+
+/// How long unpublish waits for the session of a lazily unmounted mount to end before leaving it to end in the background
+const SESSION_END_WAIT: Duration = Duration::from_secs(1);
+
+/// A mount made on request
+struct Published {
+    archive: PathBuf,
+    /// publish created the target, so unpublish removes it
+    created: bool,
+    /// Runs the mount, ends once it's unmounted
+    session: JoinHandle<Result<(), TarFsError>>,
+}
+
+struct Manager {
+    /// Applied to all mounts
+    options: MountOptions,
+    /// By target. Held while taking a target to mount at, so requests for the same target don't race.
+    published: Mutex<HashMap<PathBuf, Published>>,
+    /// The archives being mounted (indexed, mostly) by target, taken while holding published
+    publishing: Mutex<HashMap<PathBuf, PathBuf>>,
+    /// Bounds how many archives are mounted at once
+    permits: Permits,
+}
+
+/// A counting semaphore
+struct Permits {
+    free: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Permits {
+    /// Runs f once one of the permits is free
+    fn with<T>(&self, f: impl FnOnce() -> T) -> T {
+        let mut free = self.freed.wait_while(self.free.lock().unwrap(), |free| *free == 0).unwrap();
+        *free -= 1;
+        drop(free);
+        let result = f();
+        *self.free.lock().unwrap() += 1;
+        self.freed.notify_one();
+        result
+    }
+}
+
+/// Serves requests on the unix socket at path until the process exits. A socket left over by a previous manager gets replaced.
+pub fn serve(path: &Path, options: &MountOptions, start_signal: Option<mpsc::SyncSender<()>>) -> Result<(), TarFsError> {
+    for (set, flag) in [(options.control_socket.is_some(), "--control-socket"), (options.health_address.is_some(), "--health-address"), (options.sandbox, "--sandbox")] {
+        if set {
+            return Err(TarFsError::ConfigError {
+                msg: format!("{} can't be used with the mount manager, it applies to a single mount", flag),
+            });
+        }
+    }
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if meta.file_type().is_socket() {
+            fs::remove_file(path)?;
+        }
+    }
+    let listener = UnixListener::bind(path).map_err(|e| TarFsError::MountError {
+        msg: format!("unable to listen on {}: {}", path.display(), e),
+    })?;
+    info!("Managing mounts via {}", path.display());
+    if let Some(start_signal) = start_signal {
+        let _ = start_signal.send(());
+    }
+    let manager = Arc::new(Manager {
+        options: options.clone(),
+        published: Mutex::new(HashMap::new()),
+        publishing: Mutex::new(HashMap::new()),
+        permits: Permits {
+            free: Mutex::new(options.threads.unwrap_or_else(readpool::default_threads).max(1)),
+            freed: Condvar::new(),
+        },
+    });
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let manager = manager.clone();
+                thread::spawn(move || {
+                    if let Err(e) = handle(stream, &manager) {
+                        debug!("manager connection: {}", e);
+                    }
+                });
+            },
+            Err(e) => error!("manager socket: {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn handle(stream: UnixStream, manager: &Manager) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let response = match words.as_slice() {
+            [] => continue,
+            ["publish", archive, target] => publish(manager, Path::new(archive), Path::new(target)),
+            ["unpublish", target] => unpublish(manager, Path::new(target)),
+            ["list"] => list(manager),
+            ["publish", ..] => error_json("usage: publish <archive> <target>"),
+            ["unpublish", ..] => error_json("usage: unpublish <target>"),
+            [command, ..] => error_json(&format!("unknown command: {}", command)),
+        };
+        writeln!(writer, "{}", response)?;
+    }
+    Ok(())
+}
+
+/// Mounts archive at target, unless it's mounted there already
+fn publish(manager: &Manager, archive: &Path, target: &Path) -> String {
+    {
+        let mut published = manager.published.lock().unwrap();
+        forget_ended(&mut published);
+        if let Some(existing) = published.get(target) {
+            if existing.archive == archive {
+                return String::from("{\"published\":true}");
+            }
+            return error_json(&format!("{} is published from {} already", target.display(), existing.archive.display()));
+        }
+        let mut publishing = manager.publishing.lock().unwrap();
+        if let Some(existing) = publishing.get(target) {
+            return error_json(&format!("{} is being published from {}", target.display(), existing.display()));
+        }
+        publishing.insert(target.to_path_buf(), archive.to_path_buf());
+    }
+
+    // Other targets are published meanwhile
+    let mounted = manager.permits.with(|| mount(manager, archive, target));
+    let mut published = manager.published.lock().unwrap();
+    manager.publishing.lock().unwrap().remove(target);
+    match mounted {
+        Ok(mount) => {
+            info!("Published {} at {}", archive.display(), target.display());
+            published.insert(target.to_path_buf(), mount);
+            String::from("{\"published\":true}")
+        },
+        Err(e) => error_json(&e),
+    }
+}
+
+/// Mounts archive at target, creating it if missing. Returns once it's mounted.
+fn mount(manager: &Manager, archive: &Path, target: &Path) -> Result<Published, String> {
+    let created = !target.exists();
+    if created {
+        if let Err(e) = fs::create_dir_all(target) {
+            return Err(format!("unable to create {}: {}", target.display(), e));
+        }
+    }
+    let (tx, rx) = mpsc::sync_channel(1);
+    let options = manager.options.clone();
+    let (session_archive, session_target) = (archive.to_path_buf(), target.to_path_buf());
+    let session = thread::spawn(move || {
+        let mounted = setup_tar_mount(&session_archive, &session_target, &options, Some(tx));
+        if let Err(e) = &mounted {
+            error!("mount of {} at {}: {}", session_archive.display(), session_target.display(), e);
+        }
+        mounted
+    });
+    // The signal is dropped without being sent if mounting fails
+    if rx.recv().is_err() {
+        let e = match session.join() {
+            Ok(Err(e)) => e.to_string(),
+            _ => String::from("the mount ended right away"),
+        };
+        if created {
+            let _ = fs::remove_dir(target);
+        }
+        return Err(format!("unable to publish {} at {}: {}", archive.display(), target.display(), e));
+    }
+    Ok(Published { archive: archive.to_path_buf(), created, session })
+}
+
+/// Unmounts target, if it's published
+fn unpublish(manager: &Manager, target: &Path) -> String {
+    let mount = {
+        let mut published = manager.published.lock().unwrap();
+        if let Some(archive) = manager.publishing.lock().unwrap().get(target) {
+            return error_json(&format!("{} is being published from {}", target.display(), archive.display()));
+        }
+        match published.remove(target) {
+            Some(mount) => mount,
+            None => return String::from("{\"unpublished\":true}"),
+        }
+    };
+    // Unmounted without holding the lock: lazy unmounts may take their time, the other requests don't wait for them
+    if !mount.session.is_finished() {
+        // Lazily, pods being torn down may still have files open
+        if let Err(e) = fusemount::unmount(target, true) {
+            let e = format!("unable to unmount {}: {}", target.display(), e);
+            manager.published.lock().unwrap().entry(target.to_path_buf()).or_insert(mount);
+            return error_json(&e);
+        }
+    }
+    info!("Unpublished {}", target.display());
+    // Sessions without open files end right away
+    let deadline = Instant::now() + SESSION_END_WAIT;
+    while !mount.session.is_finished() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    if mount.session.is_finished() {
+        reap(target, mount);
+    } else {
+        // Detached, the session ends once the last file is closed
+        let target = target.to_path_buf();
+        thread::spawn(move || reap(&target, mount));
+    }
+    String::from("{\"unpublished\":true}")
+}
+
+/// Waits for the session of an unmounted mount to end, then removes the target if publish created it
+fn reap(target: &Path, mount: Published) {
+    let _ = mount.session.join();
+    if mount.created {
+        let _ = fs::remove_dir(target);
+    }
+}
+
+/// {"published":[{"target":"...","archive":"..."}, ...]}, sorted by target. The ones being published are listed as
+/// "publishing" the same way, if there are any.
+fn list(manager: &Manager) -> String {
+    let mut published = manager.published.lock().unwrap();
+    forget_ended(&mut published);
+    let mounts = mounts_json(published.iter().map(|(target, mount)| (target, &mount.archive)));
+    let publishing = manager.publishing.lock().unwrap();
+    match publishing.is_empty() {
+        true => format!("{{\"published\":{}}}", mounts),
+        false => format!("{{\"published\":{},\"publishing\":{}}}", mounts, mounts_json(publishing.iter())),
+    }
+}
+
+/// [{"target":"...","archive":"..."}, ...], sorted by target
+fn mounts_json<'m>(mounts: impl Iterator<Item = (&'m PathBuf, &'m PathBuf)>) -> String {
+    let mut mounts: Vec<(&PathBuf, &PathBuf)> = mounts.collect();
+    mounts.sort();
+    let mounts: Vec<String> = mounts.iter()
+        .map(|(target, archive)| format!("{{\"target\":{},\"archive\":{}}}", json_string(&target.to_string_lossy()), json_string(&archive.to_string_lossy())))
+        .collect();
+    format!("[{}]", mounts.join(","))
+}
+
+/// Mounts unmounted by someone else are gone, their targets removed if publish created them
+fn forget_ended(published: &mut HashMap<PathBuf, Published>) {
+    for (target, mount) in published.extract_if(|_, mount| mount.session.is_finished()) {
+        info!("{} got unmounted", target.display());
+        reap(&target, mount);
+    }
+}
+
+fn error_json(msg: &str) -> String {
+    format!("{{\"error\":{}}}", json_string(msg))
+}
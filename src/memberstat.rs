@@ -0,0 +1,113 @@
+//! `tarfs stat`: the full metadata of a single member, straight from the index.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use fuser::FileType;
+
+use crate::tarindex::{IndexEntry, TarEntryPointer, TarIndex};
+use crate::tarindexer::archive_path;
+use crate::utils::{file_type_bits, iso8601_date_nanos, mode_string, relative};
+use crate::TarFsError;
+
+/// What the index knows about a member
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberStat {
+    /// Relative to the root, e.g. "dir/file"
+    pub path: PathBuf,
+    pub ino: u64,
+    /// Including the file type bits, as st_mode
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub atime: SystemTime,
+    pub mtime: SystemTime,
+    pub ctime: SystemTime,
+    /// What a symlink points to, or the path of the file a hard link is another name of
+    pub link_target: Option<PathBuf>,
+    pub hard_link: bool,
+    /// Where the member's headers start in the archive, none for synthesized directories and members of other formats
+    pub header_offset: Option<u64>,
+    /// Where the content is in the archive, as offset and length
+    pub data: Vec<(u64, u64)>,
+    /// The content is gzip compressed in the archive, size is its decompressed size (see --decompress-members)
+    pub compressed: bool,
+}
+
+/// Like stat(1), one field per line
+impl fmt::Display for MemberStat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "  Path: {}", self.path.display())?;
+        match &self.link_target {
+            Some(target) if self.hard_link => writeln!(f, "  Link: to {}", target.display())?,
+            Some(target) => writeln!(f, "  Link: -> {}", target.display())?,
+            None => (),
+        }
+        writeln!(f, "  Mode: {:04o} ({})", self.mode & 0o7777, mode_string(self.mode))?;
+        writeln!(f, " Owner: {}/{}", self.uid, self.gid)?;
+        writeln!(f, "  Size: {}{}", self.size, if self.compressed { " (decompressed)" } else { "" })?;
+        writeln!(f, " Inode: {}", self.ino)?;
+        writeln!(f, " Links: {}", self.nlink)?;
+        writeln!(f, "Access: {}", iso8601_date_nanos(self.atime))?;
+        writeln!(f, "Modify: {}", iso8601_date_nanos(self.mtime))?;
+        writeln!(f, "Change: {}", iso8601_date_nanos(self.ctime))?;
+        match self.header_offset {
+            Some(offset) => writeln!(f, "Header: {}", offset)?,
+            None => writeln!(f, "Header: -")?,
+        }
+        let data: Vec<String> = self.data.iter().map(|(offset, len)| format!("{}+{}", offset, len)).collect();
+        write!(f, "  Data: {}", if data.is_empty() { String::from("-") } else { data.join(", ") })
+    }
+}
+
+/// The metadata of the member at path (e.g. "dir/file", "./dir/file" or "/dir/file")
+pub fn stat(index: &TarIndex, path: &Path) -> Result<MemberStat, TarFsError> {
+    let entry = index.lookup_path(&archive_path(path)).ok_or_else(|| TarFsError::IndexError {
+        msg: format!("{}: no such member", path.display()),
+    })?;
+    Ok(of_entry(index, entry))
+}
+
+/// The metadata of entry
+pub fn of_entry(index: &TarIndex, entry: &IndexEntry) -> MemberStat {
+    let hard_link = entry.link_target_ino.is_some();
+    // A hard link's content is that of the file it names
+    let content = match entry.link_target_ino {
+        Some(ino) => index.get_entry_by_ino(ino).unwrap_or(entry),
+        None => entry,
+    };
+    MemberStat {
+        path: relative(&entry.path),
+        ino: entry.ino(),
+        mode: file_type_bits(entry.attrs.kind) | entry.attrs.perm as u32,
+        nlink: entry.attrs.nlink,
+        uid: entry.attrs.uid,
+        gid: entry.attrs.gid,
+        size: entry.attrs.size,
+        atime: entry.attrs.atime,
+        mtime: entry.attrs.mtime,
+        ctime: entry.attrs.ctime,
+        link_target: match (entry.attrs.kind, hard_link) {
+            (_, true) => entry.link_name.as_deref().map(relative),
+            (FileType::Symlink, _) => entry.link_name.clone(),
+            _ => None,
+        },
+        hard_link,
+        header_offset: entry.file_offsets.first().and_then(|pointer| header_offset(index, pointer)),
+        data: match entry.attrs.kind {
+            FileType::RegularFile => content.file_offsets.iter().map(|pointer| (pointer.raw_file_offset, pointer.filesize)).collect(),
+            _ => vec!(),
+        },
+        compressed: content.compressed,
+    }
+}
+
+/// The headers of a member end where its content starts
+fn header_offset(index: &TarIndex, pointer: &TarEntryPointer) -> Option<u64> {
+    let raw_headers = index.raw_headers();
+    raw_headers.binary_search_by_key(&pointer.raw_file_offset, |header| header.offset + header.size).ok()
+        .map(|i| raw_headers[i].offset)
+}
@@ -0,0 +1,40 @@
+use std::convert::TryFrom;
+use std::io;
+use std::io::Read;
+
+use crate::archivesource::ArchiveSource;
+
+/// An archive that's in memory already, e.g. embedded into the binary with include_bytes!
+#[derive(Debug)]
+pub struct MemorySource {
+    bytes: &'static [u8],
+}
+
+impl MemorySource {
+    pub fn new(bytes: &'static [u8]) -> MemorySource {
+        MemorySource {
+            bytes,
+        }
+    }
+}
+
+impl ArchiveSource for MemorySource {
+    fn stream(&self) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(self.bytes))
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let start = usize::try_from(offset).ok().filter(|start| *start <= self.bytes.len());
+        match start.and_then(|start| self.bytes[start..].get(..buf.len())) {
+            Some(bytes) => {
+                buf.copy_from_slice(bytes);
+                Ok(())
+            },
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("{} bytes at {} are beyond the end of the archive", buf.len(), offset))),
+        }
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.bytes.len() as u64)
+    }
+}
@@ -0,0 +1,112 @@
+//! Guesses the MIME type of members from their first bytes (`--mime-types`), presented as the xattr user.mime_type.
+//! Content without a known signature falls back to the file extension.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use log::warn;
+
+use crate::httpserver::content_type;
+use crate::tarindex::{IndexEntry, TarIndex};
+
+pub const XATTR_NAME: &str = "user.mime_type";
+
+/// How many bytes are looked at
+const SNIFF_SIZE: usize = 512;
+
+/// Magic bytes at an offset
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"\xfd7zXZ\x00", "application/x-xz"),
+    (0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (0, b"\x7fELF", "application/x-executable"),
+    (0, b"\x00asm", "application/wasm"),
+    (0, b"ID3", "audio/mpeg"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (0, b"\x1a\x45\xdf\xa3", "video/webm"),
+    (0, b"wOFF", "font/woff"),
+    (0, b"wOF2", "font/woff2"),
+    (0, b"SQLite format 3\x00", "application/vnd.sqlite3"),
+    (0, b"!<arch>\n", "application/x-archive"),
+    (0, b"070701", "application/x-cpio"),
+    (257, b"ustar", "application/x-tar"),
+    (4, b"ftyp", "video/mp4"),
+    (0, b"#!", "text/x-shellscript"),
+    (0, b"<?xml", "application/xml"),
+];
+
+/// The MIME type of content starting with head, for a file named name
+pub fn sniff(head: &[u8], name: &Path) -> &'static str {
+    for (offset, magic, mime_type) in SIGNATURES {
+        if head.get(*offset..offset + magic.len()) == Some(magic) {
+            return mime_type;
+        }
+    }
+    if head.starts_with(b"RIFF") && head.get(8..12) == Some(b"WEBP") {
+        return "image/webp";
+    }
+    let text = head.trim_ascii_start().to_ascii_lowercase();
+    if text.starts_with(b"<!doctype html") || text.starts_with(b"<html") {
+        return "text/html";
+    }
+    // Without parameters like charset
+    let by_extension = content_type(name).split(';').next().unwrap_or_default();
+    if by_extension != "application/octet-stream" {
+        return by_extension;
+    }
+    if head.is_empty() {
+        "application/x-empty"
+    } else if is_text(head) {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// UTF-8 without control characters other than whitespace. The head may end in the middle of a character.
+fn is_text(head: &[u8]) -> bool {
+    let valid = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).unwrap_or_default(),
+        Err(_) => return false,
+    };
+    !valid.chars().any(|c| c.is_control() && !c.is_whitespace())
+}
+
+/// The MIME types of the entries sniffed so far, by ino of the index with this generation
+#[derive(Debug, Default)]
+pub struct MimeTypes {
+    sniffed: HashMap<u64, &'static str>,
+    generation: u64,
+}
+
+impl MimeTypes {
+    /// The MIME type of entry, reading its first bytes the first time
+    pub fn of(&mut self, index: &TarIndex, entry: &IndexEntry) -> &'static str {
+        // Reloaded, inos might refer to other content now
+        if index.generation() != self.generation {
+            self.sniffed.clear();
+            self.generation = index.generation();
+        }
+        self.sniffed.entry(entry.ino()).or_insert_with(|| {
+            let mut head = vec![0; (entry.attrs.size as usize).min(SNIFF_SIZE)];
+            match index.read_into(entry, 0, &mut head) {
+                Ok(n) => head.truncate(n),
+                Err(e) => {
+                    warn!("Unable to sniff the MIME type: {}", e);
+                    return content_type(&entry.name).split(';').next().unwrap_or_default();
+                },
+            }
+            sniff(&head, &entry.name)
+        })
+    }
+}
@@ -0,0 +1,652 @@
+//! A read-only NFSv3 server (RFC 1813) for the index, including the MOUNT protocol on the same port.
+//! There is no portmapper, so clients have to be told the port, e.g.
+//! `mount -t nfs -o vers=3,proto=tcp,port=2049,mountport=2049,nolock host:/ /mnt`
+
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+use std::iter;
+use std::ffi::OsStr;
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use fuser::{FileAttr, FileType};
+use log::{debug, info, warn};
+
+use crate::tarfs::SharedIndex;
+use crate::tarindex::{IndexEntry, TarIndex};
+use crate::xdr::{XdrReader, XdrWriter};
+use crate::utils::back_off_after_accept_error;
+use crate::errorlog;
+
+const RPC_VERSION: u32 = 2;
+const MSG_ACCEPTED: u32 = 0;
+const MOUNT_PROGRAM: u32 = 100005;
+const MOUNT_VERSION: u32 = 3;
+const NFS_PROGRAM: u32 = 100003;
+const NFS_VERSION: u32 = 3;
+
+/// tarfs is read-only, so requests are small (there are no WRITEs)
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+const MAX_READ_SIZE: u32 = 1024 * 1024;
+const MAX_FH_SIZE: usize = 64;
+const MAX_NAME_LEN: usize = 255;
+const MAX_PATH_LEN: usize = 1024;
+/// "tarf"
+const FSID: u64 = 0x7461_7266;
+
+const AUTH_NONE: u32 = 0;
+const AUTH_UNIX: u32 = 1;
+/// Callers without AUTH_UNIX credentials
+const NOBODY: u32 = 65534;
+
+const ACCESS3_READ: u32 = 0x01;
+const ACCESS3_LOOKUP: u32 = 0x02;
+const ACCESS3_EXECUTE: u32 = 0x20;
+
+/// nfsstat3
+#[derive(Clone, Copy, Debug)]
+enum Status {
+    Ok = 0,
+    NoEnt = 2,
+    Io = 5,
+    NotDir = 20,
+    IsDir = 21,
+    Inval = 22,
+    RoFs = 30,
+    NameTooLong = 63,
+    Stale = 70,
+    BadHandle = 10001,
+    BadCookie = 10003,
+    TooSmall = 10005,
+}
+
+/// Reasons to not even run a procedure
+enum RpcError {
+    ProcUnavail,
+    GarbageArgs,
+}
+
+impl From<io::Error> for RpcError {
+    fn from(_: io::Error) -> RpcError {
+        RpcError::GarbageArgs
+    }
+}
+
+type RpcResult = Result<(), RpcError>;
+
+struct Credentials {
+    uid: u32,
+    gid: u32,
+    gids: Vec<u32>,
+}
+
+/// Serves the index until the listener fails, one thread per connection
+pub fn serve(index: SharedIndex, listener: TcpListener) -> io::Result<()> {
+    info!("Serving NFSv3 on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Unable to accept NFS connection: {}", e);
+                back_off_after_accept_error(&e);
+                continue;
+            },
+        };
+        let index = index.clone();
+        thread::spawn(move || {
+            let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+            debug!("NFS connection from {}", peer);
+            if let Err(e) = serve_connection(&index, stream) {
+                debug!("NFS connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn serve_connection(index: &SharedIndex, mut stream: TcpStream) -> io::Result<()> {
+    while let Some(request) = read_record(&mut stream)? {
+        if let Some(reply) = handle_call(index, &request) {
+            write_record(&mut stream, &reply)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads one RPC message, which is split into fragments with the highest bit of their length marking the last one (RFC 5531, 11)
+fn read_record(stream: &mut TcpStream) -> io::Result<Option<Vec<u8>>> {
+    let mut record = vec!();
+    loop {
+        let mut marker = [0; 4];
+        match stream.read_exact(&mut marker) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && record.is_empty() => return Ok(None),
+            res => res?,
+        }
+        let marker = u32::from_be_bytes(marker);
+        let len = (marker & 0x7fff_ffff) as usize;
+        if record.len() + len > MAX_REQUEST_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "request too large"));
+        }
+        let start = record.len();
+        record.resize(start + len, 0);
+        stream.read_exact(&mut record[start..])?;
+        if marker & 0x8000_0000 != 0 {
+            return Ok(Some(record));
+        }
+    }
+}
+
+fn write_record(stream: &mut TcpStream, reply: &[u8]) -> io::Result<()> {
+    let marker = 0x8000_0000 | reply.len() as u32;
+    let mut record = Vec::with_capacity(4 + reply.len());
+    record.extend_from_slice(&marker.to_be_bytes());
+    record.extend_from_slice(reply);
+    stream.write_all(&record)
+}
+
+/// Returns the reply to the call in request, if it is one
+fn handle_call(index: &SharedIndex, request: &[u8]) -> Option<Vec<u8>> {
+    const CALL: u32 = 0;
+    const REPLY: u32 = 1;
+    const MSG_DENIED: u32 = 1;
+    const RPC_MISMATCH: u32 = 0;
+    const SUCCESS: u32 = 0;
+    const PROG_UNAVAIL: u32 = 1;
+    const PROG_MISMATCH: u32 = 2;
+    const PROC_UNAVAIL: u32 = 3;
+    const GARBAGE_ARGS: u32 = 4;
+
+    let mut args = XdrReader::new(request);
+    let xid = args.u32().ok()?;
+    if args.u32().ok()? != CALL {
+        return None;
+    }
+    let mut reply = XdrWriter::new();
+    reply.u32(xid);
+    reply.u32(REPLY);
+
+    let header = (|| -> io::Result<_> {
+        Ok((args.u32()?, args.u32()?, args.u32()?, args.u32()?, read_credentials(&mut args)?))
+    })();
+    let (rpc_version, program, version, procedure, credentials) = match header {
+        Ok(header) => header,
+        Err(_) => {
+            accepted(&mut reply, GARBAGE_ARGS);
+            return Some(reply.into_inner());
+        },
+    };
+    if rpc_version != RPC_VERSION {
+        reply.u32(MSG_DENIED);
+        reply.u32(RPC_MISMATCH);
+        reply.u32(RPC_VERSION);
+        reply.u32(RPC_VERSION);
+        return Some(reply.into_inner());
+    }
+
+    let mut results = XdrWriter::new();
+    let res = match (program, version) {
+        (MOUNT_PROGRAM, MOUNT_VERSION) => mount_procedure(&index.read().unwrap(), procedure, &mut args, &mut results),
+        (NFS_PROGRAM, NFS_VERSION) => nfs_procedure(&index.read().unwrap(), procedure, &credentials, &mut args, &mut results),
+        (MOUNT_PROGRAM, _) | (NFS_PROGRAM, _) => {
+            let supported = if program == MOUNT_PROGRAM { MOUNT_VERSION } else { NFS_VERSION };
+            accepted(&mut reply, PROG_MISMATCH);
+            reply.u32(supported);
+            reply.u32(supported);
+            return Some(reply.into_inner());
+        },
+        _ => {
+            accepted(&mut reply, PROG_UNAVAIL);
+            return Some(reply.into_inner());
+        },
+    };
+    match res {
+        Ok(()) => {
+            accepted(&mut reply, SUCCESS);
+            reply.append(results);
+        },
+        Err(RpcError::ProcUnavail) => accepted(&mut reply, PROC_UNAVAIL),
+        Err(RpcError::GarbageArgs) => accepted(&mut reply, GARBAGE_ARGS),
+    }
+    Some(reply.into_inner())
+}
+
+/// Starts an accepted reply, we don't use verifiers
+fn accepted(reply: &mut XdrWriter, accept_stat: u32) {
+    reply.u32(MSG_ACCEPTED);
+    reply.u32(AUTH_NONE);
+    reply.opaque(&[]);
+    reply.u32(accept_stat);
+}
+
+fn read_credentials(args: &mut XdrReader) -> io::Result<Credentials> {
+    let flavor = args.u32()?;
+    let body = args.opaque(400)?;
+    // Verifier
+    args.u32()?;
+    args.opaque(400)?;
+
+    if flavor != AUTH_UNIX {
+        return Ok(Credentials { uid: NOBODY, gid: NOBODY, gids: vec!() });
+    }
+    let mut body = XdrReader::new(body);
+    body.u32()?;            // stamp
+    body.opaque(255)?;      // machine name
+    let uid = body.u32()?;
+    let gid = body.u32()?;
+    let count = body.u32()?.min(16);
+    let gids = (0..count).map(|_| body.u32()).collect::<io::Result<_>>()?;
+    Ok(Credentials { uid, gid, gids })
+}
+
+fn mount_procedure(index: &TarIndex, procedure: u32, args: &mut XdrReader, res: &mut XdrWriter) -> RpcResult {
+    const MNT3_OK: u32 = 0;
+    const MNT3ERR_NOENT: u32 = 2;
+    const MNT3ERR_NOTDIR: u32 = 20;
+
+    match procedure {
+        0 => (),    // NULL
+        1 => {
+            // MNT: Any directory of the archive can be mounted
+            let path = PathBuf::from(OsStr::from_bytes(args.opaque(MAX_PATH_LEN)?));
+            debug!("nfs mnt({})", path.display());
+            match index.lookup_path(&path) {
+                None => res.u32(MNT3ERR_NOENT),
+                Some(entry) if entry.attrs.kind != FileType::Directory => res.u32(MNT3ERR_NOTDIR),
+                Some(entry) => {
+                    res.u32(MNT3_OK);
+                    res.opaque(&file_handle(index, entry));
+                    res.u32(1);
+                    res.u32(AUTH_UNIX);
+                },
+            }
+        },
+        2 => res.bool(false),   // DUMP: We don't keep track of clients
+        3 => {
+            // UMNT
+            args.opaque(MAX_PATH_LEN)?;
+        },
+        4 => (),    // UMNTALL
+        5 => {
+            // EXPORT: "/" to everyone
+            res.bool(true);
+            res.opaque(b"/");
+            res.bool(false);
+            res.bool(false);
+        },
+        _ => return Err(RpcError::ProcUnavail),
+    }
+    Ok(())
+}
+
+fn nfs_procedure(index: &TarIndex, procedure: u32, credentials: &Credentials, args: &mut XdrReader, res: &mut XdrWriter) -> RpcResult {
+    match procedure {
+        0 => Ok(()),    // NULL
+        1 => getattr(index, args, res),
+        3 => lookup(index, args, res),
+        4 => access(index, credentials, args, res),
+        5 => readlink(index, args, res),
+        6 => read(index, args, res),
+        16 => readdir(index, args, res, false),
+        17 => readdir(index, args, res, true),
+        18 => fsstat(index, args, res),
+        19 => fsinfo(index, args, res),
+        20 => pathconf(index, args, res),
+        // Everything modifying: The results only consist of empty (pre/post op) attributes on failure
+        2 | 7 | 8 | 9 | 10 | 11 | 12 | 13 | 21 => read_only(res, 2),     // SETATTR, WRITE, CREATE, MKDIR, SYMLINK, MKNOD, REMOVE, RMDIR, COMMIT
+        14 => read_only(res, 4),    // RENAME
+        15 => read_only(res, 3),    // LINK
+        _ => Err(RpcError::ProcUnavail),
+    }
+}
+
+fn read_only(res: &mut XdrWriter, empty_attrs: usize) -> RpcResult {
+    res.u32(Status::RoFs as u32);
+    for _ in 0..empty_attrs {
+        res.bool(false);
+    }
+    Ok(())
+}
+
+/// Replies with status and, as all procedures but GETATTR do on failure, empty post_op_attr
+fn failed(res: &mut XdrWriter, status: Status) -> RpcResult {
+    res.u32(status as u32);
+    res.bool(false);
+    Ok(())
+}
+
+/// Resolves the handle argument, replying with the status and empty post_op_attr if that fails
+fn read_entry<'i>(index: &'i TarIndex, args: &mut XdrReader, res: &mut XdrWriter) -> Result<Option<&'i IndexEntry>, RpcError> {
+    let handle = args.opaque(MAX_FH_SIZE)?;
+    match entry_for_handle(index, handle) {
+        Ok(entry) => Ok(Some(entry)),
+        Err(status) => {
+            debug!("nfs: bad handle {:?}: {:?}", handle, status);
+            failed(res, status)?;
+            Ok(None)
+        },
+    }
+}
+
+fn getattr(index: &TarIndex, args: &mut XdrReader, res: &mut XdrWriter) -> RpcResult {
+    let handle = args.opaque(MAX_FH_SIZE)?;
+    match entry_for_handle(index, handle) {
+        Ok(entry) => {
+            res.u32(Status::Ok as u32);
+            write_fattr(res, &entry.attrs);
+        },
+        Err(status) => res.u32(status as u32),
+    }
+    Ok(())
+}
+
+fn lookup(index: &TarIndex, args: &mut XdrReader, res: &mut XdrWriter) -> RpcResult {
+    let dir = match read_entry(index, args, res)? {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+    let name = args.opaque(MAX_PATH_LEN)?;
+    debug!("nfs lookup(dir={}, name={})", dir.ino(), String::from_utf8_lossy(name));
+    if dir.attrs.kind != FileType::Directory {
+        return failed_with_attrs(res, Status::NotDir, &dir.attrs);
+    }
+    if name.len() > MAX_NAME_LEN {
+        return failed_with_attrs(res, Status::NameTooLong, &dir.attrs);
+    }
+
+    let entry = match name {
+        b"." => Some(dir),
+        b".." => index.get_entry_by_ino(dir.dotdot_ino()),
+        name => index.lookup_child(dir.id, PathBuf::from(OsStr::from_bytes(name))),
+    };
+    match entry {
+        Some(entry) => {
+            res.u32(Status::Ok as u32);
+            res.opaque(&file_handle(index, entry));
+            write_post_op_attr(res, &entry.attrs);
+            write_post_op_attr(res, &dir.attrs);
+        },
+        None => failed_with_attrs(res, Status::NoEnt, &dir.attrs)?,
+    }
+    Ok(())
+}
+
+fn access(index: &TarIndex, credentials: &Credentials, args: &mut XdrReader, res: &mut XdrWriter) -> RpcResult {
+    let entry = match read_entry(index, args, res)? {
+        Some(entry) => entry,
+        None => return Ok(()),
+    };
+    let requested = args.u32()?;
+
+    let attrs = &entry.attrs;
+    let perm = attrs.perm as u32;
+    let bits = if credentials.uid == 0 {
+        // root may read everything, but only execute what anybody may
+        0o4 | 0o2 | if perm & 0o111 != 0 { 0o1 } else { 0 }
+    } else if credentials.uid == attrs.uid {
+        perm >> 6 & 0o7
+    } else if credentials.gid == attrs.gid || credentials.gids.contains(&attrs.gid) {
+        perm >> 3 & 0o7
+    } else {
+        perm & 0o7
+    };
+    let mut granted = 0;
+    if bits & 0o4 != 0 {
+        granted |= ACCESS3_READ;
+    }
+    if bits & 0o1 != 0 {
+        granted |= if attrs.kind == FileType::Directory { ACCESS3_LOOKUP } else { ACCESS3_EXECUTE };
+    }
+
+    res.u32(Status::Ok as u32);
+    write_post_op_attr(res, attrs);
+    res.u32(requested & granted);
+    Ok(())
+}
+
+fn readlink(index: &TarIndex, args: &mut XdrReader, res: &mut XdrWriter) -> RpcResult {
+    let entry = match read_entry(index, args, res)? {
+        Some(entry) => entry,
+        None => return Ok(()),
+    };
+    match &entry.link_name {
+        Some(target) if entry.attrs.kind == FileType::Symlink => {
+            res.u32(Status::Ok as u32);
+            write_post_op_attr(res, &entry.attrs);
+            res.opaque(target.as_os_str().as_bytes());
+            Ok(())
+        },
+        _ => failed_with_attrs(res, Status::Inval, &entry.attrs),
+    }
+}
+
+fn read(index: &TarIndex, args: &mut XdrReader, res: &mut XdrWriter) -> RpcResult {
+    let entry = match read_entry(index, args, res)? {
+        Some(entry) => entry,
+        None => return Ok(()),
+    };
+    let offset = args.u64()?;
+    let count = args.u32()?.min(MAX_READ_SIZE) as u64;
+    debug!("nfs read(ino={}, offset={}, count={})", entry.ino(), offset, count);
+    match entry.attrs.kind {
+        FileType::RegularFile => (),
+        FileType::Directory => return failed_with_attrs(res, Status::IsDir, &entry.attrs),
+        _ => return failed_with_attrs(res, Status::Inval, &entry.attrs),
+    }
+
+    // Unlike FUSE, NFS wants short reads at the end of the file
+    let size = entry.attrs.size;
+    let count = count.min(size.saturating_sub(offset));
+    let data = if count == 0 {
+        vec!()
+    } else {
+        match index.read(entry, offset, count) {
+            Ok(data) => data,
+            Err(e) => {
+                errorlog::error("nfs read", entry.ino(), format_args!("{}", e));
+                return failed_with_attrs(res, Status::Io, &entry.attrs);
+            },
+        }
+    };
+    res.u32(Status::Ok as u32);
+    write_post_op_attr(res, &entry.attrs);
+    res.u32(data.len() as u32);
+    res.bool(offset + count >= size);
+    res.opaque(&data);
+    Ok(())
+}
+
+/// READDIR and READDIRPLUS. Cookies are the position of the entry in the listing, "." and ".." included.
+fn readdir(index: &TarIndex, args: &mut XdrReader, res: &mut XdrWriter, plus: bool) -> RpcResult {
+    let dir = match read_entry(index, args, res)? {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+    let cookie = args.u64()?;
+    let verifier = args.opaque_fixed(8)?;
+    let (dir_count, max_count) = if plus {
+        (args.u32()? as usize, args.u32()? as usize)
+    } else {
+        let count = args.u32()? as usize;
+        (count, count)
+    };
+    debug!("nfs readdir(ino={}, cookie={}, plus={})", dir.ino(), cookie, plus);
+    if dir.attrs.kind != FileType::Directory {
+        return failed_with_attrs(res, Status::NotDir, &dir.attrs);
+    }
+    // Listings only change with the index
+    let our_verifier = index.generation().to_be_bytes();
+    if cookie != 0 && verifier != our_verifier {
+        return failed_with_attrs(res, Status::BadCookie, &dir.attrs);
+    }
+
+    let parent = index.get_entry_by_ino(dir.dotdot_ino()).unwrap_or(dir);
+    // Children are skipped before mapping them, which skips them without walking them
+    let cookie = cookie as usize;
+    let listing = iter::once((OsStr::new("."), dir))
+        .chain(iter::once((OsStr::new(".."), parent)))
+        .skip(cookie)
+        .chain(index.children_iter(dir).skip(cookie.saturating_sub(2)).map(|child| (child.name.as_os_str(), child)));
+
+    // status, dir attributes, verifier, end of entries, eof
+    let overhead = 4 + 4 + 84 + 8 + 4 + 4;
+    let mut entries = XdrWriter::new();
+    let mut dir_bytes = 0;
+    let mut eof = true;
+    for (position, (name, entry)) in (cookie..).zip(listing) {
+        let mut xdr_entry = XdrWriter::new();
+        xdr_entry.bool(true);
+        xdr_entry.u64(entry.attrs.ino);
+        xdr_entry.opaque(name.as_bytes());
+        xdr_entry.u64(position as u64 + 1);
+        let entry_dir_bytes = xdr_entry.len();
+        if plus {
+            write_post_op_attr(&mut xdr_entry, &entry.attrs);
+            xdr_entry.bool(true);
+            xdr_entry.opaque(&file_handle(index, entry));
+        }
+        if overhead + entries.len() + xdr_entry.len() > max_count || (plus && dir_bytes + entry_dir_bytes > dir_count) {
+            eof = false;
+            break;
+        }
+        dir_bytes += entry_dir_bytes;
+        entries.append(xdr_entry);
+    }
+    if entries.is_empty() && !eof {
+        return failed_with_attrs(res, Status::TooSmall, &dir.attrs);
+    }
+
+    res.u32(Status::Ok as u32);
+    write_post_op_attr(res, &dir.attrs);
+    res.opaque_fixed(&our_verifier);
+    res.append(entries);
+    res.bool(false);
+    res.bool(eof);
+    Ok(())
+}
+
+fn fsstat(index: &TarIndex, args: &mut XdrReader, res: &mut XdrWriter) -> RpcResult {
+    let entry = match read_entry(index, args, res)? {
+        Some(entry) => entry,
+        None => return Ok(()),
+    };
+    res.u32(Status::Ok as u32);
+    write_post_op_attr(res, &entry.attrs);
+    res.u64(index.source().size().unwrap_or(0));   // total bytes
+    res.u64(0);     // free bytes
+    res.u64(0);     // available bytes
+    res.u64(index.len() as u64);   // total files
+    res.u64(0);     // free files
+    res.u64(0);     // available files
+    res.u32(0);     // invarsec
+    Ok(())
+}
+
+fn fsinfo(index: &TarIndex, args: &mut XdrReader, res: &mut XdrWriter) -> RpcResult {
+    const FSF3_LINK: u32 = 0x01;
+    const FSF3_SYMLINK: u32 = 0x02;
+    const FSF3_HOMOGENEOUS: u32 = 0x08;
+
+    let entry = match read_entry(index, args, res)? {
+        Some(entry) => entry,
+        None => return Ok(()),
+    };
+    res.u32(Status::Ok as u32);
+    write_post_op_attr(res, &entry.attrs);
+    res.u32(MAX_READ_SIZE);     // rtmax
+    res.u32(MAX_READ_SIZE);     // rtpref
+    res.u32(4096);              // rtmult
+    // Writes fail anyway, but some clients don't cope with 0
+    res.u32(64 * 1024);         // wtmax
+    res.u32(64 * 1024);         // wtpref
+    res.u32(4096);              // wtmult
+    res.u32(64 * 1024);         // dtpref
+    res.u64(u64::MAX);          // maxfilesize
+    res.u32(0);                 // time_delta
+    res.u32(1);
+    res.u32(FSF3_LINK | FSF3_SYMLINK | FSF3_HOMOGENEOUS);
+    Ok(())
+}
+
+fn pathconf(index: &TarIndex, args: &mut XdrReader, res: &mut XdrWriter) -> RpcResult {
+    let entry = match read_entry(index, args, res)? {
+        Some(entry) => entry,
+        None => return Ok(()),
+    };
+    res.u32(Status::Ok as u32);
+    write_post_op_attr(res, &entry.attrs);
+    res.u32(u32::MAX);              // linkmax
+    res.u32(MAX_NAME_LEN as u32);   // name_max
+    res.bool(true);                 // no_trunc
+    res.bool(true);                 // chown_restricted
+    res.bool(false);                // case_insensitive
+    res.bool(true);                 // case_preserving
+    Ok(())
+}
+
+fn failed_with_attrs(res: &mut XdrWriter, status: Status, attrs: &FileAttr) -> RpcResult {
+    res.u32(status as u32);
+    write_post_op_attr(res, attrs);
+    Ok(())
+}
+
+/// The ino plus the index generation, so handles turn stale when the archive gets rewritten
+fn file_handle(index: &TarIndex, entry: &IndexEntry) -> [u8; 16] {
+    let mut handle = [0; 16];
+    handle[..8].copy_from_slice(&entry.ino().to_be_bytes());
+    handle[8..].copy_from_slice(&index.generation().to_be_bytes());
+    handle
+}
+
+fn entry_for_handle<'i>(index: &'i TarIndex, handle: &[u8]) -> Result<&'i IndexEntry, Status> {
+    if handle.len() != 16 {
+        return Err(Status::BadHandle);
+    }
+    let ino = u64::from_be_bytes(handle[..8].try_into().unwrap());
+    let generation = u64::from_be_bytes(handle[8..].try_into().unwrap());
+    if generation != index.generation() {
+        return Err(Status::Stale);
+    }
+    index.get_entry_by_ino(ino).ok_or(Status::Stale)
+}
+
+fn write_post_op_attr(res: &mut XdrWriter, attrs: &FileAttr) {
+    res.bool(true);
+    write_fattr(res, attrs);
+}
+
+/// fattr3
+fn write_fattr(res: &mut XdrWriter, attrs: &FileAttr) {
+    let ftype = match attrs.kind {
+        FileType::RegularFile => 1,
+        FileType::Directory => 2,
+        FileType::BlockDevice => 3,
+        FileType::CharDevice => 4,
+        FileType::Symlink => 5,
+        FileType::Socket => 6,
+        FileType::NamedPipe => 7,
+    };
+    res.u32(ftype);
+    res.u32(attrs.perm as u32);
+    res.u32(attrs.nlink);
+    res.u32(attrs.uid);
+    res.u32(attrs.gid);
+    res.u64(attrs.size);
+    res.u64(attrs.blocks * 512);    // used
+    res.u32(0);                     // rdev
+    res.u32(0);
+    res.u64(FSID);
+    res.u64(attrs.ino);
+    write_time(res, attrs.atime);
+    write_time(res, attrs.mtime);
+    write_time(res, attrs.ctime);
+}
+
+/// nfstime3 can't express times before the epoch
+fn write_time(res: &mut XdrWriter, time: SystemTime) {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    res.u32(since_epoch.as_secs().min(u32::MAX as u64) as u32);
+    res.u32(since_epoch.subsec_nanos());
+}
@@ -0,0 +1,57 @@
+//! Unicode normalization of names (`--normalize`): archives created on macOS store names decomposed (NFD), most
+//! other systems composed (NFC). Normalizing names when indexing and in lookups makes both find the same entry.
+
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use unicode_normalization::{is_nfc, is_nfd, UnicodeNormalization};
+
+/// The normalization form of names in the index
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Normalization {
+    /// Names as they are in the archive
+    #[default]
+    None,
+    /// Composed, e.g. "é" as one code point
+    Nfc,
+    /// Decomposed, e.g. "é" as "e" followed by a combining accent
+    Nfd,
+}
+
+impl FromStr for Normalization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Normalization, String> {
+        match s {
+            "none" => Ok(Normalization::None),
+            "nfc" => Ok(Normalization::Nfc),
+            "nfd" => Ok(Normalization::Nfd),
+            _ => Err(format!("unknown normalization form: {}", s)),
+        }
+    }
+}
+
+impl Normalization {
+    /// The name in this form. Names that aren't valid UTF-8 are left alone.
+    pub fn name<'a>(&self, name: &'a OsStr) -> Cow<'a, OsStr> {
+        let s = match (self, name.to_str()) {
+            (Normalization::None, _) | (_, None) => return Cow::Borrowed(name),
+            (_, Some(s)) => s,
+        };
+        match self {
+            Normalization::Nfc if !is_nfc(s) => Cow::Owned(s.nfc().collect::<String>().into()),
+            Normalization::Nfd if !is_nfd(s) => Cow::Owned(s.nfd().collect::<String>().into()),
+            _ => Cow::Borrowed(name),
+        }
+    }
+
+    /// Each name of path in this form
+    pub fn path(&self, path: &Path) -> PathBuf {
+        match self {
+            Normalization::None => path.to_path_buf(),
+            _ => path.components().map(|component| self.name(component.as_os_str()).into_owned()).collect(),
+        }
+    }
+}
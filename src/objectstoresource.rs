@@ -0,0 +1,107 @@
+use std::io;
+use std::io::Read;
+use std::time::Duration;
+
+use futures::stream::{BoxStream, StreamExt};
+use object_store::{ObjectStore, ObjectStoreExt, path::Path as ObjectPath};
+use tokio::runtime::Runtime;
+use url::Url;
+
+use crate::archivesource::ArchiveSource;
+
+/// Serves an archive stored in any of the object stores supported by the object_store crate (s3://, gs://, az://, ...)
+/// Credentials and endpoints are configured via the usual environment variables (AWS_ACCESS_KEY_ID, GOOGLE_SERVICE_ACCOUNT, ...)
+#[derive(Debug)]
+pub struct ObjectStoreSource {
+    store: Box<dyn ObjectStore>,
+    path: ObjectPath,
+    /// object_store is async only: We drive it with a runtime of our own
+    runtime: Runtime,
+}
+
+impl ObjectStoreSource {
+    pub fn new(url: &str, timeout: Duration) -> io::Result<ObjectStoreSource> {
+        let url = Url::parse(url).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut options: Vec<(String, String)> = std::env::vars().collect();
+        options.push(("timeout".to_owned(), format!("{}ms", timeout.as_millis())));
+        let (store, path) = object_store::parse_url_opts(&url, options).map_err(to_io_error)?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(ObjectStoreSource {
+            store,
+            path,
+            runtime,
+        })
+    }
+
+    /// Whether the scheme of url is one we should handle through object_store
+    pub fn handles(url: &str) -> bool {
+        match Url::parse(url) {
+            Ok(url) => matches!(object_store::ObjectStoreScheme::parse(&url),
+                Ok((object_store::ObjectStoreScheme::AmazonS3, _))
+                | Ok((object_store::ObjectStoreScheme::GoogleCloudStorage, _))
+                | Ok((object_store::ObjectStoreScheme::MicrosoftAzure, _))),
+            Err(_) => false,
+        }
+    }
+}
+
+impl ArchiveSource for ObjectStoreSource {
+    fn stream(&self) -> io::Result<Box<dyn Read + '_>> {
+        let result = self.runtime.block_on(self.store.get(&self.path)).map_err(to_io_error)?;
+        Ok(Box::new(StreamReader {
+            runtime: &self.runtime,
+            stream: result.into_stream(),
+            chunk: bytes::Bytes::new(),
+        }))
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let range = offset..offset + buf.len() as u64;
+        let bytes = self.runtime.block_on(self.store.get_range(&self.path, range)).map_err(to_io_error)?;
+        if bytes.len() != buf.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "object store returned a short range"));
+        }
+        buf.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        let meta = self.runtime.block_on(self.store.head(&self.path)).map_err(to_io_error)?;
+        Ok(meta.size)
+    }
+}
+
+/// Adapts the async stream of chunks object_store hands out into a blocking Read
+struct StreamReader<'r> {
+    runtime: &'r Runtime,
+    stream: BoxStream<'static, object_store::Result<bytes::Bytes>>,
+    chunk: bytes::Bytes,
+}
+
+impl Read for StreamReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.runtime.block_on(self.stream.next()) {
+                None => return Ok(0),
+                Some(chunk) => self.chunk = chunk.map_err(to_io_error)?,
+            }
+        }
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk.split_to(n));
+        Ok(n)
+    }
+}
+
+fn to_io_error(err: object_store::Error) -> io::Error {
+    match err {
+        object_store::Error::NotFound { .. } => io::Error::new(io::ErrorKind::NotFound, err),
+        object_store::Error::PermissionDenied { .. }
+        | object_store::Error::Unauthenticated { .. } => io::Error::new(io::ErrorKind::PermissionDenied, err),
+        _ => io::Error::other(err),
+    }
+}
@@ -0,0 +1,55 @@
+//! Maps the user and group names of tar headers (uname/gname) to local ids, like GNU tar does when extracting without --numeric-owner.
+//! Lookups go through NSS (passwd/group), so they are cached for the lifetime of the process.
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::{Mutex, OnceLock};
+
+static USERS: OnceLock<Mutex<HashMap<String, Option<u64>>>> = OnceLock::new();
+static GROUPS: OnceLock<Mutex<HashMap<String, Option<u64>>>> = OnceLock::new();
+
+/// The uid of the local user name, if there is one
+pub fn uid_by_name(name: &str) -> Option<u64> {
+    cached(&USERS, name, |name| {
+        let mut passwd = MaybeUninit::<libc::passwd>::uninit();
+        lookup(|buf, result: &mut *mut libc::passwd| unsafe {
+            libc::getpwnam_r(name.as_ptr(), passwd.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), result)
+        }).map(|passwd| unsafe { (*passwd).pw_uid } as u64)
+    })
+}
+
+/// The gid of the local group name, if there is one
+pub fn gid_by_name(name: &str) -> Option<u64> {
+    cached(&GROUPS, name, |name| {
+        let mut group = MaybeUninit::<libc::group>::uninit();
+        lookup(|buf, result: &mut *mut libc::group| unsafe {
+            libc::getgrnam_r(name.as_ptr(), group.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), result)
+        }).map(|group| unsafe { (*group).gr_gid } as u64)
+    })
+}
+
+fn cached<F: FnOnce(&CString) -> Option<u64>>(cache: &OnceLock<Mutex<HashMap<String, Option<u64>>>>, name: &str, resolve: F) -> Option<u64> {
+    let mut cache = cache.get_or_init(Default::default).lock().unwrap();
+    if let Some(id) = cache.get(name) {
+        return *id;
+    }
+    let id = CString::new(name).ok().and_then(|name| resolve(&name));
+    cache.insert(name.to_string(), id);
+    id
+}
+
+/// Calls a reentrant NSS lookup with a buffer growing as long as it's too small
+fn lookup<T, F: FnMut(&mut [libc::c_char], &mut *mut T) -> libc::c_int>(mut call: F) -> Option<*mut T> {
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        let mut result = ptr::null_mut();
+        match call(&mut buf, &mut result) {
+            0 if result.is_null() => return None,
+            0 => return Some(result),
+            libc::ERANGE if buf.len() < 1024 * 1024 => buf.resize(buf.len() * 2, 0),
+            _ => return None,
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Permission checks done by tarfs itself (`--check-permissions`) instead of the kernel (mount option default_permissions),
+//! against the owners and modes of the archive's entries.
+
+use std::fs;
+
+use fuser::{FileAttr, FileType, Request};
+
+/// Who a request comes from
+#[derive(Debug)]
+pub struct Caller {
+    uid: u32,
+    gid: u32,
+    /// Supplementary groups, which FUSE requests don't carry
+    groups: Vec<u32>,
+}
+
+impl Caller {
+    pub fn of(req: &Request) -> Caller {
+        Caller {
+            uid: req.uid(),
+            gid: req.gid(),
+            groups: supplementary_groups(req.pid()),
+        }
+    }
+
+    /// Whether the caller may access an entry with attrs as asked for by mask (R_OK, W_OK and X_OK bits, like access(2))
+    pub fn may(&self, attrs: &FileAttr, mask: i32) -> bool {
+        // R_OK, W_OK and X_OK are the rwx bits of a mode
+        let wanted = (mask & 0o7) as u16;
+        if self.uid == 0 {
+            // root may do anything, but execute files nobody may execute
+            return mask & libc::X_OK == 0 || attrs.kind == FileType::Directory || attrs.perm & 0o111 != 0;
+        }
+        let granted = if self.uid == attrs.uid {
+            attrs.perm >> 6
+        } else if self.gid == attrs.gid || self.groups.contains(&attrs.gid) {
+            attrs.perm >> 3
+        } else {
+            attrs.perm
+        } & 0o7;
+        granted & wanted == wanted
+    }
+}
+
+/// From the "Groups:" line of /proc/<pid>/status, none if the process is gone already
+fn supplementary_groups(pid: u32) -> Vec<u32> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+    status.lines()
+        .find_map(|line| line.strip_prefix("Groups:"))
+        .map(|groups| groups.split_whitespace().filter_map(|gid| gid.parse().ok()).collect())
+        .unwrap_or_default()
+}
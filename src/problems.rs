@@ -0,0 +1,22 @@
+//! Problems found while indexing: entries skipped or presented differently than the archive has them. They are warned
+//! about as they are found, and collected for the summary of `--dry-run`.
+
+use std::cell::RefCell;
+
+use log::warn;
+
+thread_local! {
+    /// Indexing happens on one thread, so the problems of an index are those found on its thread meanwhile
+    static PROBLEMS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Warns about the problem and records it
+pub fn report(msg: String) {
+    warn!("{}", msg);
+    PROBLEMS.with(|problems| problems.borrow_mut().push(msg));
+}
+
+/// The problems recorded on this thread since the last call
+pub fn take() -> Vec<String> {
+    PROBLEMS.with(|problems| problems.take())
+}
@@ -0,0 +1,133 @@
+//! Access profiles: The members a mount session opened, in the order of their first open (`--record-profile`),
+//! so later mounts can read them ahead in the background (`--prefetch-profile`), e.g. when booting a container from the same layer again.
+//! A profile has one path per line, relative to the mount's root like "/usr/bin/sh".
+
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Instant;
+
+use fuser::FileType;
+use log::{debug, info, warn};
+
+use crate::stats::SharedStats;
+use crate::tarfs::SharedIndex;
+
+/// How much is read ahead at once, holding the index lock
+const PREFETCH_CHUNK: usize = 1024 * 1024;
+
+#[derive(Debug)]
+pub struct ProfileRecorder {
+    /// Opened when mounting, as the sandbox doesn't allow creating files later on
+    file: File,
+    seen: HashSet<PathBuf>,
+    paths: Vec<PathBuf>,
+}
+
+impl ProfileRecorder {
+    /// Opens the profile to write when the mount ends. Doesn't truncate yet, so it can still be read to prefetch from.
+    pub fn create(path: &Path) -> io::Result<ProfileRecorder> {
+        let file = OpenOptions::new().write(true).create(true).truncate(false).open(path)?;
+        Ok(ProfileRecorder { file, seen: HashSet::new(), paths: vec!() })
+    }
+
+    /// Records the entry at path (e.g. "./dir/file") got opened
+    pub fn opened(&mut self, path: &Path) {
+        // Names with line breaks can't be told apart in a profile
+        if path.as_os_str().as_bytes().contains(&b'\n') || self.seen.contains(path) {
+            return;
+        }
+        self.seen.insert(path.to_path_buf());
+        self.paths.push(path.to_path_buf());
+    }
+
+    /// Replaces the profile with what was recorded
+    pub fn save(&mut self) -> io::Result<()> {
+        let mut content = Vec::new();
+        for path in &self.paths {
+            let path = Path::new("/").join(path.strip_prefix(".").unwrap_or(path));
+            content.extend_from_slice(path.as_os_str().as_bytes());
+            content.push(b'\n');
+        }
+        self.file.set_len(0)?;
+        self.file.write_all_at(&content, 0)?;
+        info!("Recorded {} opened files in the access profile", self.paths.len());
+        Ok(())
+    }
+}
+
+/// Reads the paths of a profile. A missing profile is empty, e.g. the first time it's recorded to the same file.
+pub fn load(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            info!("No access profile at {} yet, nothing to prefetch", path.display());
+            return Ok(vec!());
+        },
+        Err(e) => return Err(e),
+    };
+    Ok(content.split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| PathBuf::from(OsStr::from_bytes(line)))
+        .collect())
+}
+
+/// Reads the content of the files at paths in the background, so it's cached (page cache or chunk cache) when opened.
+/// Each of this many threads takes every n-th path, so they are read roughly in order.
+pub fn spawn_prefetcher(index: SharedIndex, paths: Vec<PathBuf>, stats: SharedStats, threads: usize) {
+    if paths.is_empty() {
+        return;
+    }
+    thread::spawn(move || {
+        let now = Instant::now();
+        let threads = threads.clamp(1, paths.len());
+        let workers: Vec<_> = (0..threads).map(|n| {
+            let (index, stats) = (index.clone(), stats.clone());
+            let paths: Vec<PathBuf> = paths.iter().skip(n).step_by(threads).cloned().collect();
+            thread::spawn(move || prefetch(&index, &paths, &stats))
+        }).collect();
+        let prefetched: usize = workers.into_iter().map(|worker| worker.join().expect("prefetching panicked")).sum();
+        info!("Prefetched {} files. Took {}s.", prefetched, now.elapsed().as_secs());
+    });
+}
+
+/// Reads the content of the files at paths in order, returns how many of them are files
+fn prefetch(index: &SharedIndex, paths: &[PathBuf], stats: &SharedStats) -> usize {
+    let mut buf = vec![0; PREFETCH_CHUNK];
+    let mut prefetched = 0;
+    for path in paths {
+        let entry = {
+            let index = index.read().unwrap();
+            // Hard links have their content in their target
+            index.lookup_path(path).and_then(|entry| match entry.link_target_ino {
+                Some(target) => index.get_entry_by_ino(target),
+                None => Some(entry),
+            }).cloned()
+        };
+        let entry = match entry {
+            Some(entry) if entry.attrs.kind == FileType::RegularFile && !entry.file_offsets.is_empty() => entry,
+            _ => {
+                debug!("Not prefetching {}: no such file", path.display());
+                continue;
+            },
+        };
+        let mut offset = 0;
+        while offset < entry.attrs.size {
+            let n = (entry.attrs.size - offset).min(PREFETCH_CHUNK as u64) as usize;
+            if let Err(e) = index.read().unwrap().read_into(&entry, offset, &mut buf[..n]) {
+                warn!("Prefetching failed: {}", e);
+                break;
+            }
+            offset += n as u64;
+            stats.prefetched(n as u64);
+        }
+        prefetched += 1;
+    }
+    prefetched
+}
@@ -0,0 +1,65 @@
+//! Worker threads serving reads (--threads): the FUSE session dispatches requests on one thread, reading content from
+//! the archive (or fetching it from a remote one) happens on the workers, so slow reads don't hold up others.
+
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use log::debug;
+
+/// Caps the default number of workers, more rarely pay off for a single archive
+const MAX_DEFAULT_THREADS: usize = 8;
+
+/// Gets the worker's buffer to read into, re-used across jobs
+type Job = Box<dyn FnOnce(&mut Vec<u8>) + Send>;
+
+/// The number of threads serving reads if not given: one per CPU, up to MAX_DEFAULT_THREADS
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get()).min(MAX_DEFAULT_THREADS)
+}
+
+pub struct ReadPool {
+    jobs: Option<mpsc::Sender<Job>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl ReadPool {
+    pub fn new(threads: usize) -> ReadPool {
+        let (tx, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        let workers = (0..threads).map(|_| {
+            let rx = rx.clone();
+            thread::spawn(move || {
+                let mut buf = vec!();
+                loop {
+                    // Released before running the job, so the others can take the next ones meanwhile
+                    let job = rx.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(&mut buf),
+                        Err(_) => break,
+                    }
+                }
+            })
+        }).collect();
+        debug!("Serving reads with {} threads", threads);
+        ReadPool {
+            jobs: Some(tx),
+            workers,
+        }
+    }
+
+    pub fn submit<F: FnOnce(&mut Vec<u8>) + Send + 'static>(&self, job: F) {
+        if let Some(jobs) = &self.jobs {
+            jobs.send(Box::new(job)).expect("read workers are gone");
+        }
+    }
+}
+
+impl Drop for ReadPool {
+    /// Waits for the reads in flight to be answered
+    fn drop(&mut self) {
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
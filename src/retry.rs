@@ -0,0 +1,111 @@
+use std::io;
+use std::io::Read;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::archivesource::ArchiveSource;
+
+/// Upper bound for the wait between two attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// How often a failed request is repeated before giving up
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each following one
+    pub initial_backoff: Duration,
+    /// Timeout for a single request to the remote
+    pub timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Counters reported when the mount ends
+#[derive(Debug, Default)]
+struct RetryStats {
+    requests: AtomicU64,
+    retries: AtomicU64,
+    failures: AtomicU64,
+}
+
+/// Wraps a remote source and repeats failing requests with exponential backoff.
+/// Note that only opening the index stream is retried: Failures in the middle of it are not resumable.
+#[derive(Debug)]
+pub struct RetryingSource<S: ArchiveSource> {
+    inner: S,
+    policy: RetryPolicy,
+    stats: RetryStats,
+}
+
+impl<S: ArchiveSource> RetryingSource<S> {
+    pub fn new(inner: S, policy: &RetryPolicy) -> RetryingSource<S> {
+        RetryingSource {
+            inner,
+            policy: policy.clone(),
+            stats: RetryStats::default(),
+        }
+    }
+
+    fn with_retries<T, F>(&self, what: &str, mut f: F) -> io::Result<T>
+        where
+            F: FnMut() -> io::Result<T> {
+        let mut backoff = self.policy.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            self.stats.requests.fetch_add(1, Ordering::Relaxed);
+            let err = match f() {
+                Ok(t) => return Ok(t),
+                Err(err) => err,
+            };
+            if attempt >= self.policy.retries || !is_transient(&err) {
+                self.stats.failures.fetch_add(1, Ordering::Relaxed);
+                return Err(err);
+            }
+            attempt += 1;
+            self.stats.retries.fetch_add(1, Ordering::Relaxed);
+            warn!("{} failed ({}), retry {}/{} in {:?}", what, err, attempt, self.policy.retries, backoff);
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
+impl<S: ArchiveSource> ArchiveSource for RetryingSource<S> {
+    fn stream(&self) -> io::Result<Box<dyn Read + '_>> {
+        self.with_retries("opening archive stream", || self.inner.stream())
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let what = format!("reading {} bytes at {}", buf.len(), offset);
+        self.with_retries(&what, || self.inner.read_exact_at(buf, offset))
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.with_retries("querying archive size", || self.inner.size())
+    }
+}
+
+impl<S: ArchiveSource> Drop for RetryingSource<S> {
+    fn drop(&mut self) {
+        info!("Remote requests: {}, retries: {}, failures: {}",
+            self.stats.requests.load(Ordering::Relaxed),
+            self.stats.retries.load(Ordering::Relaxed),
+            self.stats.failures.load(Ordering::Relaxed));
+    }
+}
+
+/// Errors that are not worth repeating the request for
+fn is_transient(err: &io::Error) -> bool {
+    !matches!(err.kind(), io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied | io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData)
+}
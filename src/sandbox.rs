@@ -0,0 +1,107 @@
+//! Restricts the process to the syscalls needed to serve an archive (seccomp-bpf), as archives are untrusted input.
+//! Meant to be enabled once mounted (or bound): mounting needs more than serving does.
+
+use std::io;
+
+use log::info;
+
+/// From linux/audit.h, missing in libc
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// Offsets into struct seccomp_data
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+
+/// Serving FUSE requests, network clients, watching the archive and writing the chunk cache, but no exec, ptrace, mounts, ...
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn allowed_syscalls() -> Vec<libc::c_long> {
+    let mut syscalls = vec![
+        // Files, including /dev/fuse
+        libc::SYS_read, libc::SYS_readv, libc::SYS_pread64, libc::SYS_preadv,
+        libc::SYS_write, libc::SYS_writev, libc::SYS_pwrite64, libc::SYS_pwritev,
+        libc::SYS_lseek, libc::SYS_close, libc::SYS_openat, libc::SYS_fstat, libc::SYS_newfstatat, libc::SYS_statx, libc::SYS_statfs,
+        libc::SYS_fcntl, libc::SYS_dup, libc::SYS_dup3, libc::SYS_ioctl, libc::SYS_getdents64, libc::SYS_readlinkat,
+        libc::SYS_unlinkat, libc::SYS_ftruncate, libc::SYS_fallocate, libc::SYS_fsync, libc::SYS_fdatasync,
+        libc::SYS_umount2,
+        // Memory
+        libc::SYS_mmap, libc::SYS_munmap, libc::SYS_mremap, libc::SYS_mprotect, libc::SYS_madvise, libc::SYS_brk,
+        // Threads, signals and time
+        libc::SYS_futex, libc::SYS_clone, libc::SYS_clone3, libc::SYS_set_robust_list, libc::SYS_rseq,
+        libc::SYS_sched_yield, libc::SYS_sched_getaffinity, libc::SYS_exit, libc::SYS_exit_group,
+        libc::SYS_rt_sigprocmask, libc::SYS_rt_sigaction, libc::SYS_rt_sigreturn, libc::SYS_sigaltstack,
+        libc::SYS_gettid, libc::SYS_getpid, libc::SYS_tgkill, libc::SYS_prctl, libc::SYS_getrandom,
+        libc::SYS_clock_gettime, libc::SYS_clock_nanosleep, libc::SYS_nanosleep, libc::SYS_gettimeofday,
+        // --watch
+        libc::SYS_inotify_init1, libc::SYS_inotify_add_watch, libc::SYS_inotify_rm_watch,
+        // Remote archives, network servers and the control socket
+        libc::SYS_socket, libc::SYS_connect, libc::SYS_accept4, libc::SYS_bind, libc::SYS_listen,
+        libc::SYS_sendto, libc::SYS_recvfrom, libc::SYS_sendmsg, libc::SYS_recvmsg, libc::SYS_shutdown,
+        libc::SYS_getsockopt, libc::SYS_setsockopt, libc::SYS_getsockname, libc::SYS_getpeername,
+        libc::SYS_ppoll, libc::SYS_pselect6, libc::SYS_epoll_create1, libc::SYS_epoll_ctl, libc::SYS_epoll_pwait,
+        libc::SYS_eventfd2, libc::SYS_pipe2,
+    ];
+    // Legacy syscalls aarch64 doesn't have
+    #[cfg(target_arch = "x86_64")]
+    syscalls.extend_from_slice(&[
+        libc::SYS_open, libc::SYS_stat, libc::SYS_lstat, libc::SYS_access, libc::SYS_readlink, libc::SYS_unlink,
+        libc::SYS_rmdir, libc::SYS_poll, libc::SYS_select, libc::SYS_epoll_wait, libc::SYS_pipe, libc::SYS_dup2,
+        libc::SYS_accept,
+    ]);
+    syscalls
+}
+
+/// Applies to all threads of the process, including ones started later on
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub fn enable() -> io::Result<()> {
+    let syscalls = allowed_syscalls();
+    let mut filter = vec![
+        bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SECCOMP_DATA_ARCH),
+        // Syscall numbers of other architectures would mean something else
+        bpf_jump(AUDIT_ARCH, 1, 0),
+        bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SECCOMP_DATA_NR),
+    ];
+    for (i, nr) in syscalls.iter().enumerate() {
+        // Jump over the remaining comparisons and the denial
+        filter.push(bpf_jump(*nr as u32, (syscalls.len() - i) as u8, 0));
+    }
+    filter.push(bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ERRNO | (libc::EPERM as u32 & libc::SECCOMP_RET_DATA)));
+    filter.push(bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+
+    let program = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+    unsafe {
+        // Required to install filters without CAP_SYS_ADMIN
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let res = libc::syscall(libc::SYS_seccomp, libc::SECCOMP_SET_MODE_FILTER, libc::SECCOMP_FILTER_FLAG_TSYNC, &program as *const libc::sock_fprog);
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if res > 0 {
+            return Err(io::Error::other(format!("unable to sandbox thread {}", res)));
+        }
+    }
+    info!("Sandbox enabled: {} syscalls allowed", syscalls.len());
+    Ok(())
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn enable() -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "sandboxing is not supported on this architecture"))
+}
+
+fn bpf_stmt(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter { code: code as u16, jt: 0, jf: 0, k }
+}
+
+/// Compares the accumulator with k
+fn bpf_jump(k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16, jt, jf, k }
+}
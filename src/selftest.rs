@@ -0,0 +1,284 @@
+//! `tarfs selftest`: packs a directory (or a synthetic tree with the cases that tend to break), mounts the archive in a
+//! temporary location and compares the mount to the directory, as a one-command check of the kernel and FUSE setup.
+
+use std::collections::HashMap;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use log::warn;
+use tar::{Builder, EntryType, Header, HeaderMode};
+
+use crate::{fusemount, setup_tar_mount, MountOptions, TarFsError};
+
+/// How the mount compared to the directory, by paths relative to the root (e.g. "dir/file")
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SelfTest {
+    /// Entries that were compared
+    pub checked: u64,
+    /// What differs, one line per difference (e.g. "dir/file: size 3 != 4")
+    pub mismatches: Vec<String>,
+}
+
+impl SelfTest {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Tells apart the runs of one process
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Packs dir (the synthetic tree if none), mounts the archive below the temp directory and compares the two
+pub fn run(dir: Option<&Path>, options: &MountOptions) -> Result<SelfTest, TarFsError> {
+    let work = std::env::temp_dir().join(format!("tarfs-selftest-{}-{}", process::id(), RUNS.fetch_add(1, Ordering::Relaxed)));
+    fs::create_dir_all(&work)?;
+    let result = run_in(&work, dir, options);
+    match &result {
+        // Never remove a directory something might still be mounted on
+        Err(TarFsError::MountError { .. }) => warn!("leaving {} behind", work.display()),
+        _ => if let Err(e) = fs::remove_dir_all(&work) {
+            warn!("unable to remove {}: {}", work.display(), e);
+        },
+    }
+    result
+}
+
+fn run_in(work: &Path, dir: Option<&Path>, options: &MountOptions) -> Result<SelfTest, TarFsError> {
+    let source = match dir {
+        Some(dir) => dir.to_path_buf(),
+        None => {
+            let tree = work.join("tree");
+            create_synthetic_tree(&tree)?;
+            tree
+        },
+    };
+    let archive = work.join("selftest.tar");
+    pack(&source, &archive)?;
+
+    let mountpoint = work.join("mnt");
+    fs::create_dir_all(&mountpoint)?;
+    let (tx, rx) = mpsc::sync_channel(1);
+    let mount = {
+        let (archive, mountpoint, options) = (archive.clone(), mountpoint.clone(), options.clone());
+        thread::spawn(move || setup_tar_mount(&archive, &mountpoint, &options, Some(tx)))
+    };
+    // The sender is gone without a signal if mounting failed
+    if rx.recv().is_err() {
+        return match mount.join() {
+            Ok(Err(e)) => Err(e),
+            _ => Err(TarFsError::MountError { msg: "the self-test mount ended before it was up".to_string() }),
+        };
+    }
+
+    let result = compare(&source, &mountpoint);
+    unmount(&mountpoint)?;
+    match mount.join() {
+        Ok(Ok(())) => result,
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(TarFsError::MountError { msg: "the self-test mount panicked".to_string() }),
+    }
+}
+
+fn unmount(mountpoint: &Path) -> Result<(), TarFsError> {
+    // Handles of the comparison are released asynchronously, so the mount may be busy for a moment
+    let mut last = None;
+    for _ in 0..50 {
+        match fusemount::unmount(mountpoint, false) {
+            Ok(()) => return Ok(()),
+            Err(e) => last = Some(e),
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    Err(TarFsError::MountError {
+        msg: format!("unable to unmount {}: {}", mountpoint.display(), last.map(|e| e.to_string()).unwrap_or_default()),
+    })
+}
+
+/// Files, directories and links with the names, sizes and modes that exercise the corners of the tar format
+fn create_synthetic_tree(root: &Path) -> io::Result<()> {
+    fs::create_dir_all(root.join("dir/sub"))?;
+    fs::create_dir_all(root.join("empty"))?;
+    fs::write(root.join("file.txt"), "content\n")?;
+    fs::write(root.join("empty.txt"), "")?;
+    fs::write(root.join("dir/sub/nested.txt"), "nested\n")?;
+
+    fs::write(root.join("run.sh"), "#!/bin/sh\n")?;
+    fs::set_permissions(root.join("run.sh"), fs::Permissions::from_mode(0o755))?;
+    fs::write(root.join("readonly.txt"), "readonly\n")?;
+    fs::set_permissions(root.join("readonly.txt"), fs::Permissions::from_mode(0o444))?;
+
+    symlink("file.txt", root.join("link"))?;
+    symlink("../file.txt", root.join("dir/up-link"))?;
+    symlink("missing", root.join("dangling"))?;
+    fs::hard_link(root.join("file.txt"), root.join("dir/hardlink.txt"))?;
+
+    // Beyond the 100 bytes of a ustar name, and of a link target
+    let long_dir = root.join(format!("long-{}", "d".repeat(90)));
+    fs::create_dir_all(&long_dir)?;
+    let long_file = long_dir.join(format!("long-{}.txt", "f".repeat(120)));
+    fs::write(&long_file, "long\n")?;
+    symlink(long_file.strip_prefix(root).unwrap(), root.join("long-link"))?;
+
+    fs::write(root.join("ünïcödé-日本語.txt"), "unicode\n")?;
+    fs::create_dir_all(root.join("Ελληνικά"))?;
+    fs::write(root.join("Ελληνικά/🦀.txt"), "crab\n")?;
+
+    // Mostly holes, which have to read back as zeros
+    let mut sparse = File::create(root.join("sparse.bin"))?;
+    sparse.write_all(b"head")?;
+    sparse.seek(SeekFrom::Start(4 * 1024 * 1024))?;
+    sparse.write_all(b"tail")?;
+    Ok(())
+}
+
+/// Writes the tree below source as POSIX archive, with times to the nanosecond and hard links as links
+fn pack(source: &Path, archive: &Path) -> Result<(), TarFsError> {
+    let mut builder = Builder::new(File::create(archive)?);
+    builder.follow_symlinks(false);
+    let mut links: HashMap<(u64, u64), PathBuf> = HashMap::new();
+    for path in walk(source)? {
+        let meta = fs::symlink_metadata(source.join(&path))?;
+        let file_type = meta.file_type();
+        if !file_type.is_dir() && !file_type.is_file() && !file_type.is_symlink() {
+            continue;
+        }
+        let times = [("mtime", meta.mtime(), meta.mtime_nsec()), ("atime", meta.atime(), meta.atime_nsec()), ("ctime", meta.ctime(), meta.ctime_nsec())]
+            .map(|(key, secs, nsecs)| (key, format!("{}.{:09}", secs, nsecs)));
+        builder.append_pax_extensions(times.iter().map(|(key, value)| (*key, value.as_bytes())))?;
+
+        let mut header = Header::new_gnu();
+        header.set_metadata_in_mode(&meta, HeaderMode::Complete);
+        if file_type.is_symlink() {
+            builder.append_link(&mut header, &path, fs::read_link(source.join(&path))?)?;
+        } else if file_type.is_dir() {
+            builder.append_data(&mut header, &path, io::empty())?;
+        } else if let Some(first) = (meta.nlink() > 1).then(|| links.get(&(meta.dev(), meta.ino()))).flatten() {
+            header.set_entry_type(EntryType::Link);
+            header.set_size(0);
+            builder.append_link(&mut header, &path, first)?;
+        } else {
+            if meta.nlink() > 1 {
+                links.insert((meta.dev(), meta.ino()), path.clone());
+            }
+            builder.append_data(&mut header, &path, File::open(source.join(&path))?)?;
+        }
+    }
+    builder.into_inner()?.flush()?;
+    Ok(())
+}
+
+/// The paths below root relative to it, parents before children and siblings sorted by name
+fn walk(root: &Path) -> io::Result<Vec<PathBuf>> {
+    fn walk_into(root: &Path, dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+        let mut names = fs::read_dir(root.join(dir))?.map(|entry| entry.map(|e| e.file_name())).collect::<io::Result<Vec<_>>>()?;
+        names.sort();
+        for name in names {
+            let path = dir.join(name);
+            paths.push(path.clone());
+            if fs::symlink_metadata(root.join(&path))?.is_dir() {
+                walk_into(root, &path, paths)?;
+            }
+        }
+        Ok(())
+    }
+    let mut paths = vec!();
+    walk_into(root, Path::new(""), &mut paths)?;
+    Ok(paths)
+}
+
+/// Compares everything below the roots; the mounted root itself takes its attributes from the mountpoint
+fn compare(expected: &Path, mounted: &Path) -> Result<SelfTest, TarFsError> {
+    let mut result = SelfTest::default();
+    let expected_paths = walk(expected)?;
+    let mounted_paths = match walk(mounted) {
+        Ok(paths) => paths,
+        Err(e) => {
+            result.mismatches.push(format!("unable to list the mount: {}", e));
+            return Ok(result);
+        },
+    };
+    for path in &expected_paths {
+        let meta = fs::symlink_metadata(expected.join(path))?;
+        let file_type = meta.file_type();
+        if !file_type.is_dir() && !file_type.is_file() && !file_type.is_symlink() {
+            continue;
+        }
+        result.checked += 1;
+        match fs::symlink_metadata(mounted.join(path)) {
+            Ok(mounted_meta) => compare_entry(path, expected, &meta, mounted, &mounted_meta, &mut result.mismatches),
+            Err(e) => result.mismatches.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    for path in mounted_paths.iter().filter(|path| fs::symlink_metadata(expected.join(path)).is_err()) {
+        result.mismatches.push(format!("{}: not in the directory", path.display()));
+    }
+    Ok(result)
+}
+
+fn compare_entry(path: &Path, expected_root: &Path, expected: &Metadata, mounted_root: &Path, mounted: &Metadata, mismatches: &mut Vec<String>) {
+    let mut differs = |what: &str, expected: String, mounted: String| {
+        if expected != mounted {
+            mismatches.push(format!("{}: {} {} != {}", path.display(), what, expected, mounted));
+        }
+    };
+    let kind = |meta: &Metadata| if meta.is_dir() { "directory" } else if meta.file_type().is_symlink() { "symlink" } else { "file" };
+    differs("type", kind(expected).to_string(), kind(mounted).to_string());
+    if kind(expected) != kind(mounted) {
+        return;
+    }
+    differs("nlink", expected.nlink().to_string(), mounted.nlink().to_string());
+    differs("mtime", format!("{}.{:09}", expected.mtime(), expected.mtime_nsec()), format!("{}.{:09}", mounted.mtime(), mounted.mtime_nsec()));
+    differs("ctime", format!("{}.{:09}", expected.ctime(), expected.ctime_nsec()), format!("{}.{:09}", mounted.ctime(), mounted.ctime_nsec()));
+    differs("uid", expected.uid().to_string(), mounted.uid().to_string());
+    differs("gid", expected.gid().to_string(), mounted.gid().to_string());
+    differs("mode", format!("{:o}", expected.mode() & 0o7777), format!("{:o}", mounted.mode() & 0o7777));
+    if expected.is_file() {
+        differs("size", expected.len().to_string(), mounted.len().to_string());
+        match same_content(&expected_root.join(path), &mounted_root.join(path)) {
+            Ok(true) => {},
+            Ok(false) => mismatches.push(format!("{}: content differs", path.display())),
+            Err(e) => mismatches.push(format!("{}: {}", path.display(), e)),
+        }
+    } else if expected.file_type().is_symlink() {
+        match (fs::read_link(expected_root.join(path)), fs::read_link(mounted_root.join(path))) {
+            (Ok(target), Ok(mounted_target)) if target != mounted_target =>
+                mismatches.push(format!("{}: target {} != {}", path.display(), target.display(), mounted_target.display())),
+            (_, Err(e)) => mismatches.push(format!("{}: {}", path.display(), e)),
+            _ => {},
+        }
+    }
+}
+
+fn same_content(expected: &Path, mounted: &Path) -> io::Result<bool> {
+    let (mut expected, mut mounted) = (File::open(expected)?, File::open(mounted)?);
+    let (mut expected_buf, mut mounted_buf) = (vec![0; 64 * 1024], vec![0; 64 * 1024]);
+    loop {
+        let n = read_full(&mut expected, &mut expected_buf)?;
+        let m = read_full(&mut mounted, &mut mounted_buf)?;
+        if expected_buf[..n] != mounted_buf[..m] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Like read_exact, but reports how much there was up to the end
+fn read_full(file: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
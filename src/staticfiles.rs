@@ -0,0 +1,34 @@
+//! Serves the index as static web site: URLs map to archive members, directories to their index.html (or a listing)
+
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use fuser::FileType;
+
+use crate::httpserver::{content_type, dir_listing, file_response, resolve, url_path, Request, Response};
+use crate::tarindex::TarIndex;
+
+pub fn handle(index: &TarIndex, request: &Request) -> Response {
+    if request.method != "GET" && request.method != "HEAD" {
+        return Response::new(405).header("Allow", "GET, HEAD");
+    }
+    let entry = match index.lookup_path(&request.path).and_then(|entry| resolve(index, entry)) {
+        Some(entry) => entry,
+        None => return Response::new(404).body("text/plain", b"Not Found\n".to_vec()),
+    };
+    if entry.attrs.kind != FileType::Directory {
+        return file_response(index, request, entry, content_type(&entry.name));
+    }
+
+    // Relative links of index.html and listings only work with the trailing slash
+    if !request.path.as_os_str().as_bytes().ends_with(b"/") {
+        return Response::new(301).header("Location", url_path(entry));
+    }
+    let index_html = index.lookup_child(entry.id, PathBuf::from("index.html"))
+        .and_then(|index_html| resolve(index, index_html))
+        .filter(|index_html| index_html.attrs.kind != FileType::Directory);
+    match index_html {
+        Some(index_html) => file_response(index, request, index_html, content_type(&index_html.name)),
+        None => dir_listing(index, entry),
+    }
+}
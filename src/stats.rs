@@ -0,0 +1,89 @@
+//! Runtime statistics of a mount, exposed via /.tarfs/stats.json and the control socket
+
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::archivesource::ArchiveSource;
+
+pub type SharedStats = Arc<Stats>;
+
+/// Counters updated while serving requests. Requests for the virtual /.tarfs entries don't count,
+/// so looking at the stats doesn't make a mount look used.
+#[derive(Debug, Default)]
+pub struct Stats {
+    ops: AtomicU64,
+    bytes_read: AtomicU64,
+    open_handles: AtomicU64,
+    errors: AtomicU64,
+    /// Inodes the kernel holds on to (looked up and not forgotten yet)
+    referenced_inodes: AtomicU64,
+    /// Estimated bytes taken by the index
+    index_memory: AtomicU64,
+    /// Bytes read ahead as listed in the access profile
+    prefetched_bytes: AtomicU64,
+    /// Seconds since the epoch, 0 if nothing was served yet
+    last_op: AtomicU64,
+    /// The source reporting the hits and misses of its cache, remote archives with --cache-dir
+    cached_source: OnceLock<Arc<dyn ArchiveSource>>,
+}
+
+impl Stats {
+    pub fn op(&self) {
+        self.ops.fetch_add(1, Ordering::Relaxed);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        self.last_op.store(now, Ordering::Relaxed);
+    }
+
+    pub fn error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn read(&self, bytes: u64) {
+        self.bytes_read.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn prefetched(&self, bytes: u64) {
+        self.prefetched_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn opened(&self) {
+        self.open_handles.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn released(&self) {
+        let _ = self.open_handles.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+    }
+
+    pub fn set_referenced_inodes(&self, n: u64) {
+        self.referenced_inodes.store(n, Ordering::Relaxed);
+    }
+
+    pub fn set_index_memory(&self, bytes: u64) {
+        self.index_memory.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Reports the cache stats of source along with the others, if it has a cache
+    pub fn set_source(&self, source: Arc<dyn ArchiveSource>) {
+        if source.cache_stats().is_some() {
+            let _ = self.cached_source.set(source);
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let cache = match self.cached_source.get().and_then(|source| source.cache_stats()) {
+            Some(stats) => format!(",\"cache\":{}", stats.to_json()),
+            None => String::new(),
+        };
+        format!("{{\"ops\":{},\"bytes_read\":{},\"open_handles\":{},\"errors\":{},\"referenced_inodes\":{},\"index_memory\":{},\"prefetched_bytes\":{},\"last_op\":{}{}}}",
+            self.ops.load(Ordering::Relaxed),
+            self.bytes_read.load(Ordering::Relaxed),
+            self.open_handles.load(Ordering::Relaxed),
+            self.errors.load(Ordering::Relaxed),
+            self.referenced_inodes.load(Ordering::Relaxed),
+            self.index_memory.load(Ordering::Relaxed),
+            self.prefetched_bytes.load(Ordering::Relaxed),
+            self.last_op.load(Ordering::Relaxed),
+            cache)
+    }
+}
@@ -0,0 +1,78 @@
+use std::env;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Stdin, Write};
+use std::os::unix::fs::FileExt;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::debug;
+
+use crate::archivesource::ArchiveSource;
+
+/// An archive piped to stdin. As stdin can be read only once, everything streamed while indexing
+/// is spooled to an (already unlinked) temp file which all later reads are served from.
+#[derive(Debug)]
+pub struct StdinSource {
+    spool: File,
+    streamed: AtomicBool,
+}
+
+impl StdinSource {
+    pub fn new() -> io::Result<StdinSource> {
+        Ok(StdinSource {
+            spool: create_spool_file()?,
+            streamed: AtomicBool::new(false),
+        })
+    }
+}
+
+impl ArchiveSource for StdinSource {
+    fn stream(&self) -> io::Result<Box<dyn Read + '_>> {
+        if self.streamed.swap(true, Ordering::Relaxed) {
+            return Err(io::Error::other("stdin can only be streamed once"));
+        }
+        Ok(Box::new(Spooler {
+            input: io::stdin(),
+            spool: &self.spool,
+        }))
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        FileExt::read_exact_at(&self.spool, buf, offset)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.spool.metadata()?.len())
+    }
+}
+
+/// Copies everything read from stdin to the spool file
+struct Spooler<'s> {
+    input: Stdin,
+    spool: &'s File,
+}
+
+impl Read for Spooler<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.input.read(buf)?;
+        self.spool.write_all(&buf[..n])?;
+        Ok(n)
+    }
+}
+
+/// Creates a file in the temp dir ($TMPDIR) that's gone as soon as we close it
+fn create_spool_file() -> io::Result<File> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    let path = env::temp_dir().join(format!("tarfs-stdin-{}-{}", process::id(), nanos));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    fs::remove_file(&path)?;
+    debug!("Spooling stdin to {} (unlinked)", path.display());
+    Ok(file)
+}
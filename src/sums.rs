@@ -0,0 +1,266 @@
+//! Checksum manifests as written by sha256sum (`<hex digest>  <path>` per line), e.g. SHA256SUMS files distributed along with archives,
+//! and checking the content of members against them (`--verify-reads`).
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use fuser::FileType;
+use log::{error, info};
+use ring::digest::{Context, SHA256};
+
+use crate::tarindex::{IndexEntry, TarIndex};
+use crate::tarindexer::archive_path;
+use crate::TarFsError;
+
+/// How much content is hashed at once
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How far reads may get ahead of the content hashed so far (see Verifier::check)
+const MAX_AHEAD: u64 = 8 * 1024 * 1024;
+
+pub type Digest = [u8; 32];
+
+/// The digests of a manifest by archive path ("./dir/file")
+#[derive(Debug, Default)]
+pub struct Sums {
+    by_path: BTreeMap<PathBuf, Digest>,
+}
+
+impl Sums {
+    pub fn load(path: &Path) -> io::Result<Sums> {
+        Sums::parse(&fs::read(path)?)
+    }
+
+    /// Lines are "<digest>  <path>" or "<digest> *<path>" (binary mode, same thing here). Paths with a line break or backslash
+    /// are escaped ("\n", "\\"), marked by a backslash in front of the digest.
+    pub fn parse(content: &[u8]) -> io::Result<Sums> {
+        let mut by_path = BTreeMap::new();
+        for (i, line) in content.split(|b| *b == b'\n').enumerate() {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.is_empty() || line.starts_with(b"#") {
+                continue;
+            }
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid checksum line {}: expected \"<sha256 digest>  <path>\"", i + 1));
+            let (escaped, line) = match line.strip_prefix(b"\\") {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            if line.len() < 66 || line[64] != b' ' || (line[65] != b' ' && line[65] != b'*') {
+                return Err(invalid());
+            }
+            let digest = parse_digest(&line[..64]).ok_or_else(invalid)?;
+            let name = if escaped { unescape(&line[66..]).ok_or_else(invalid)? } else { line[66..].to_vec() };
+            by_path.insert(archive_path(Path::new(OsStr::from_bytes(&name))), digest);
+        }
+        Ok(Sums { by_path })
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&Digest> {
+        self.by_path.get(path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&PathBuf, &Digest)> {
+        self.by_path.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_path.len()
+    }
+}
+
+fn parse_digest(hex: &[u8]) -> Option<Digest> {
+    let mut digest = [0; 32];
+    for (byte, pair) in digest.iter_mut().zip(hex.chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+pub fn hex(digest: &Digest) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unescape(name: &[u8]) -> Option<Vec<u8>> {
+    let mut unescaped = Vec::with_capacity(name.len());
+    let mut bytes = name.iter();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\\' => match bytes.next()? {
+                b'\\' => unescaped.push(b'\\'),
+                b'n' => unescaped.push(b'\n'),
+                _ => return None,
+            },
+            b => unescaped.push(*b),
+        }
+    }
+    Some(unescaped)
+}
+
+/// The SHA-256 digest of the content of entry, as read through the mount
+pub fn member_digest(index: &TarIndex, entry: &IndexEntry) -> Result<Digest, TarFsError> {
+    let mut context = Context::new(&SHA256);
+    let mut buf = vec![0; CHUNK_SIZE.min(entry.attrs.size as usize)];
+    let mut offset = 0;
+    while offset < entry.attrs.size {
+        let n = (entry.attrs.size - offset).min(CHUNK_SIZE as u64) as usize;
+        let read = index.read_into(entry, offset, &mut buf[..n])?;
+        context.update(&buf[..read]);
+        if read < n {
+            break;
+        }
+        offset += n as u64;
+    }
+    let mut digest = [0; 32];
+    digest.copy_from_slice(context.finish().as_ref());
+    Ok(digest)
+}
+
+/// How the files of an archive compare to a manifest, by their paths relative to the root (e.g. "dir/file")
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Verification {
+    /// Listed with the digest of their content
+    pub ok: Vec<PathBuf>,
+    /// Listed with another digest
+    pub mismatched: Vec<PathBuf>,
+    /// Listed, but not a file of the archive
+    pub missing: Vec<PathBuf>,
+    /// Files of the archive that aren't listed
+    pub extra: Vec<PathBuf>,
+}
+
+impl Verification {
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty() && self.extra.is_empty()
+    }
+}
+
+/// Compares the files of the index to the manifest, reading the listed ones in the order they are in the archive
+pub fn verify(index: &TarIndex, sums: &Sums) -> Result<Verification, TarFsError> {
+    let mut verification = Verification::default();
+    let mut listed = vec!();
+    for (path, digest) in sums.iter() {
+        // Hard links have their content in their target
+        let entry = index.lookup_path(path).and_then(|entry| match entry.link_target_ino {
+            Some(target) => index.get_entry_by_ino(target),
+            None => Some(entry),
+        });
+        match entry {
+            Some(entry) if entry.attrs.kind == FileType::RegularFile => listed.push((entry, path, digest)),
+            _ => verification.missing.push(relative(path)),
+        }
+    }
+    listed.sort_by_key(|(entry, _, _)| entry.file_offsets.first().map(|pointer| pointer.raw_file_offset));
+    for (entry, path, digest) in listed {
+        if &member_digest(index, entry)? == digest {
+            verification.ok.push(relative(path));
+        } else {
+            verification.mismatched.push(relative(path));
+        }
+    }
+    verification.extra = index.entries()
+        .filter(|entry| entry.attrs.kind == FileType::RegularFile && sums.get(&entry.path).is_none())
+        .map(|entry| relative(&entry.path))
+        .collect();
+    for paths in [&mut verification.ok, &mut verification.mismatched, &mut verification.missing, &mut verification.extra] {
+        paths.sort();
+    }
+    Ok(verification)
+}
+
+fn relative(path: &Path) -> PathBuf {
+    path.strip_prefix(".").unwrap_or(path).to_path_buf()
+}
+
+/// Checks members against their digests as they're read (`--verify-reads`)
+pub struct Verifier {
+    sums: Sums,
+    members: Mutex<Members>,
+}
+
+/// The progress of the members being read, by ino of the index with this generation
+#[derive(Default)]
+struct Members {
+    generation: u64,
+    by_ino: HashMap<u64, Arc<Mutex<Progress>>>,
+}
+
+enum Progress {
+    /// The content up to hashed went into context. Reads that got ahead of it (e.g. on another read worker) wait in ahead
+    /// by their offset, up to MAX_AHEAD bytes.
+    Hashing { context: Box<Context>, hashed: u64, ahead: BTreeMap<u64, Vec<u8>> },
+    /// Whether the content matched the digest
+    Done(bool),
+}
+
+impl Verifier {
+    pub fn new(sums: Sums) -> Verifier {
+        info!("Verifying reads against {} checksums", sums.len());
+        Verifier { sums, members: Mutex::default() }
+    }
+
+    /// Hashes content read at offset of entry, unless it was hashed already. Whether the content may be handed out: false for
+    /// the read completing a member that doesn't match its digest, and for all reads of it from then on. Members without a
+    /// digest pass unchecked. Only reads continuing where the hashed content ends (or soon after it) make progress, members
+    /// read at random get verified once they're read from start to end.
+    pub fn check(&self, index: &TarIndex, entry: &IndexEntry, offset: u64, content: &[u8]) -> bool {
+        let expected = match self.sums.get(&entry.path) {
+            Some(expected) => expected,
+            None => return true,
+        };
+        let progress = self.progress(index.generation(), entry.ino());
+        let mut progress = progress.lock().unwrap();
+        let (context, hashed, ahead) = match &mut *progress {
+            Progress::Done(ok) => return *ok,
+            Progress::Hashing { context, hashed, ahead } => (context, hashed, ahead),
+        };
+        if offset > *hashed {
+            let waiting: usize = ahead.values().map(Vec::len).sum();
+            if offset - *hashed + content.len() as u64 <= MAX_AHEAD && waiting + content.len() <= MAX_AHEAD as usize {
+                ahead.insert(offset, content.to_vec());
+            }
+            return true;
+        }
+        hash(context, hashed, offset, content);
+        while let Some(waiting) = ahead.first_entry().filter(|waiting| *waiting.key() <= *hashed) {
+            let (offset, content) = waiting.remove_entry();
+            hash(context, hashed, offset, &content);
+        }
+        if *hashed < entry.attrs.size {
+            return true;
+        }
+        let mut digest = [0; 32];
+        digest.copy_from_slice(context.clone().finish().as_ref());
+        let ok = &digest == expected;
+        if !ok {
+            error!("{} is corrupt: its SHA-256 digest is {}, expected {}", entry.path.display(), hex(&digest), hex(expected));
+        }
+        *progress = Progress::Done(ok);
+        ok
+    }
+
+    fn progress(&self, generation: u64, ino: u64) -> Arc<Mutex<Progress>> {
+        let mut members = self.members.lock().unwrap();
+        // Reloaded, inos might refer to other content now
+        if members.generation != generation {
+            members.by_ino.clear();
+            members.generation = generation;
+        }
+        members.by_ino.entry(ino)
+            .or_insert_with(|| Arc::new(Mutex::new(Progress::Hashing { context: Box::new(Context::new(&SHA256)), hashed: 0, ahead: BTreeMap::new() })))
+            .clone()
+    }
+}
+
+/// Hashes what content read at offset adds to the content hashed so far
+fn hash(context: &mut Context, hashed: &mut u64, offset: u64, content: &[u8]) {
+    let end = offset + content.len() as u64;
+    if end > *hashed {
+        context.update(&content[(*hashed - offset) as usize..]);
+        *hashed = end;
+    }
+}
@@ -0,0 +1,164 @@
+//! Presents symlinks which resolve inside the archive as what they point to, for tools that refuse to follow symlinks:
+//! links to files become hard links of them, links to directories copies of them (whose files are hard links again).
+//! Links which don't resolve inside the archive, or would make the tree infinite, stay symlinks.
+
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+
+use fuser::FileType;
+use log::{debug, info};
+
+use crate::tarindex::{IndexEntry, TarIndex, ROOT_INO};
+use crate::tarindexer::{path_ino, InoMode};
+
+/// Like Linux' MAXSYMLINKS
+const MAX_SYMLINKS: usize = 40;
+
+pub fn follow_symlinks(index: &mut TarIndex, ino_mode: InoMode) {
+    let now = Instant::now();
+    let symlinks: Vec<u64> = index.entries()
+        .filter(|entry| entry.attrs.kind == FileType::Symlink && entry.link_target_ino.is_none())
+        .map(|entry| entry.id)
+        .collect();
+
+    let mut followed = 0;
+    for id in symlinks {
+        let link = index.get_entry_by_ino(id).expect("symlinks are indexed").clone();
+        let target = match resolve_link(index, &link) {
+            Some(target) => target.clone(),
+            None => {
+                debug!("Not following {}: its target is not part of the archive", link.path.display());
+                continue;
+            },
+        };
+        // The copy of a directory would contain the link again
+        let ancestors = ancestors(index, &link);
+        if target.attrs.kind == FileType::Directory && ancestors.contains(&target.id) {
+            debug!("Not following {}: it points to one of its parents", link.path.display());
+            continue;
+        }
+        let mut copier = Copier { index, ino_mode, stack: ancestors };
+        copier.present_as(link, &target);
+        followed += 1;
+    }
+    info!("Followed {} symlinks. Took {}s.", followed, now.elapsed().as_secs());
+}
+
+struct Copier<'i> {
+    index: &'i mut TarIndex,
+    ino_mode: InoMode,
+    /// The directories being copied (and the ones containing the link): Copying one of them again would never end
+    stack: Vec<u64>,
+}
+
+impl<'i> Copier<'i> {
+    /// Turns entry into a hard link of target (a file), or a copy of it (a directory)
+    fn present_as(&mut self, mut entry: IndexEntry, target: &IndexEntry) {
+        if target.attrs.kind != FileType::Directory {
+            entry.link_target_ino = Some(target.ino());
+            entry.link_name = Some(target.path.clone());
+            entry.attrs = target.attrs;
+            entry.file_offsets = target.file_offsets.clone();
+            self.index.insert(entry);
+            return;
+        }
+
+        entry.link_name = None;
+        entry.attrs = fuser::FileAttr {
+            ino: entry.id,
+            ..target.attrs
+        };
+        entry.file_offsets = target.file_offsets.clone();
+        entry.children = vec!();
+        self.stack.push(target.id);
+        let children: Vec<IndexEntry> = self.index.children_iter(target).cloned().collect();
+        for child in children {
+            let name = child.path.file_name().expect("children have a name");
+            let path = entry.path.join(name);
+            let id = self.new_ino(&path);
+            let copy = IndexEntry {
+                id,
+                parent_ino: Some(entry.id),
+                path,
+                name: PathBuf::from(name),
+                ..Default::default()
+            };
+            // Reserves the ino
+            self.index.insert(copy.clone());
+            let source = match child.attrs.kind {
+                FileType::Symlink if child.link_target_ino.is_none() => match resolve_link(self.index, &child) {
+                    Some(resolved) if !self.stack.contains(&resolved.id) => resolved.clone(),
+                    // Stays a symlink
+                    _ => child.clone(),
+                },
+                _ => child.clone(),
+            };
+            entry.children.push(id);
+            if source.attrs.kind == FileType::Symlink {
+                let mut copy = IndexEntry {
+                    attrs: fuser::FileAttr { ino: id, ..source.attrs },
+                    link_name: source.link_name.clone(),
+                    ..copy
+                };
+                copy.file_offsets = source.file_offsets.clone();
+                self.index.insert(copy);
+            } else {
+                self.present_as(copy, &source);
+            }
+        }
+        self.stack.pop();
+        self.index.insert(entry);
+    }
+
+    fn new_ino(&self, path: &Path) -> u64 {
+        match self.ino_mode {
+            InoMode::Sequential => self.index.next_ino(),
+            InoMode::Offset => path_ino(path, |ino| self.index.get_entry_by_ino(ino).is_some()),
+        }
+    }
+}
+
+/// The directories containing entry, up to the root
+fn ancestors(index: &TarIndex, entry: &IndexEntry) -> Vec<u64> {
+    let mut ancestors = vec!();
+    let mut parent = entry.parent_ino;
+    while let Some(ino) = parent {
+        ancestors.push(ino);
+        parent = index.get_entry_by_ino(ino).and_then(|dir| dir.parent_ino);
+    }
+    ancestors
+}
+
+/// Resolves the target of the symlink link, following further symlinks on the way
+fn resolve_link<'i>(index: &'i TarIndex, link: &IndexEntry) -> Option<&'i IndexEntry> {
+    let target = link.link_name.as_ref()?;
+    let mut followed = 0;
+    resolve(index, link.parent_ino.unwrap_or(ROOT_INO), target, &mut followed)
+}
+
+fn resolve<'i>(index: &'i TarIndex, dir: u64, path: &Path, followed: &mut usize) -> Option<&'i IndexEntry> {
+    let mut entry = index.get_entry_by_ino(dir)?;
+    let components: Vec<Component> = path.components().collect();
+    for (i, component) in components.iter().enumerate() {
+        entry = match component {
+            Component::RootDir => index.get_entry_by_ino(ROOT_INO)?,
+            Component::CurDir => entry,
+            // The root is its own parent
+            Component::ParentDir => index.get_entry_by_ino(entry.parent_ino.unwrap_or(ROOT_INO))?,
+            Component::Normal(name) => index.lookup_child(entry.id, PathBuf::from(name))?,
+            Component::Prefix(_) => return None,
+        };
+        let is_last = i + 1 == components.len();
+        if entry.attrs.kind == FileType::Symlink && entry.link_target_ino.is_none() {
+            *followed += 1;
+            if *followed > MAX_SYMLINKS {
+                return None;
+            }
+            entry = resolve(index, entry.parent_ino.unwrap_or(ROOT_INO), entry.link_name.as_ref()?, followed)?;
+        }
+        if !is_last && entry.attrs.kind != FileType::Directory {
+            return None;
+        }
+    }
+    Some(entry)
+}
@@ -1,78 +1,403 @@
 use std::path::{Path};
 use std::ffi::{OsStr};
 use std::{path::PathBuf};
+use std::collections::{BTreeSet, HashMap};
 use std::io;
-#[allow(unused_imports)]
-use std::cell::RefCell;
-use std::sync::mpsc;
-
-use time::Timespec;
-
-use libc::{ENOENT, ENODATA};
+use std::sync::{mpsc, Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+
+use fuser::{consts, FileAttr, FileType, Filesystem, KernelConfig, Notifier, Request, ReplyAttr, ReplyCreate, ReplyEmpty, ReplyEntry, ReplyDirectory, ReplyData, ReplyOpen, ReplyWrite, ReplyXattr, TimeOrNow};
+
+use log::{debug, info, error, trace, warn};
+
+use super::tarindex::{TarIndex, IndexEntry, ROOT_INO};
+use super::stats::{SharedStats, Stats};
+use super::lookups::SharedLookups;
+use super::handles::SharedHandles;
+use super::audit::AuditLog;
+use super::profile::ProfileRecorder;
+use super::sums::Verifier;
+use super::mime::{self, MimeTypes};
+use super::inject::Injector;
+use super::atime::Atimes;
+use super::permissions::Caller;
+use super::fserror::{entry_for, Expect, FsError};
+use super::errorlog;
+use super::readpool::{default_threads, ReadPool};
+use super::MountOptions;
+use super::fusemount;
+use super::utils::{default_fuse_file_attr, json_string};
+
+/// The index is shared with whoever might swap or extend it while mounted (e.g. --watch, --follow)
+pub type SharedIndex = Arc<RwLock<TarIndex>>;
+
+/// The virtual dir with information about the mount. It is not listed in the root and a .tarfs dir of the archive takes precedence.
+const TARFS_DIR_NAME: &str = ".tarfs";
+const STATS_FILE_NAME: &str = "stats.json";
+/// Metadata of the archive that isn't presented as entries (volume label, global PAX records)
+const INFO_FILE_NAME: &str = "info.json";
+/// The header blocks of each member as <n>.hdr, numbered in the order they are in the archive starting from 0
+const RAW_DIR_NAME: &str = "raw";
+const RAW_HEADER_SUFFIX: &str = ".hdr";
+/// Far off the inos of the archive's entries (see InoMode)
+const TARFS_DIR_INO: u64 = u64::MAX - 1;
+const STATS_FILE_INO: u64 = u64::MAX - 2;
+const RAW_DIR_INO: u64 = u64::MAX - 3;
+const INFO_FILE_INO: u64 = u64::MAX - 4;
+/// The one of the first member's header, counting down from there
+const RAW_HEADERS_INO: u64 = u64::MAX - 5;
+const MAX_RAW_HEADERS: u64 = 1 << 32;
+
+fn is_virtual(ino: u64) -> bool {
+    ino == TARFS_DIR_INO || ino == STATS_FILE_INO || ino == INFO_FILE_INO || ino == RAW_DIR_INO || raw_header_number(ino).is_some()
+}
 
-use fuse;
-use fuse::{FileType, Filesystem, Request, ReplyAttr, ReplyEntry, ReplyDirectory, ReplyData};
+fn raw_header_ino(n: usize) -> u64 {
+    RAW_HEADERS_INO - n as u64
+}
 
-use log;
-use log::{debug, info, error, trace};
+fn raw_header_number(ino: u64) -> Option<usize> {
+    (ino <= RAW_HEADERS_INO && ino > RAW_HEADERS_INO - MAX_RAW_HEADERS).then(|| (RAW_HEADERS_INO - ino) as usize)
+}
 
-use super::tarindex::{TarIndex};
-use super::utils::default_fuse_file_attr;
+/// The ino of the raw header named like "12.hdr", if there's such a member
+fn raw_header_ino_by_name(index: &TarIndex, name: &str) -> Option<u64> {
+    let number = name.strip_suffix(RAW_HEADER_SUFFIX)?;
+    // Only the canonical name, so each header has one
+    if number.is_empty() || (number.starts_with('0') && number != "0") || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let n: usize = number.parse().ok()?;
+    (n < index.raw_headers().len() && (n as u64) < MAX_RAW_HEADERS).then(|| raw_header_ino(n))
+}
 
-const NAME_OPTIONS: &[&str] = &[
-    "fsname=tarfs",
-    "subtype=tarfs",
-];
+/// Tuning of the FUSE connection, None keeps what the kernel (or fuser) picks
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseTuning {
+    /// Maximum size of a single read request in bytes (mount option max_read)
+    pub max_read: Option<u32>,
+    /// Maximum the kernel reads ahead of sequential reads in bytes
+    pub max_readahead: Option<u32>,
+    /// Maximum number of pending background requests (e.g. readahead)
+    pub max_background: Option<u16>,
+    /// Number of pending background requests at which the kernel considers the connection congested
+    pub congestion_threshold: Option<u16>,
+}
 
-const DEFAULT_OPTIONS: &[&str] = &[
-    // http://manpages.ubuntu.com/manpages/bionic/en/man8/mount.fuse.8.html#options
-    "default_permissions",  // Enable default kernel permission handling
-    "allow_other",          // Allow other users to access the files
-    "kernel_cache",         // Disable flushing the kernel cache on each "open"
-    "use_ino",              // IDK what it could mean to have this disabled...
-];
+/// What gets to see opens and reads, besides the kernel
+#[derive(Default)]
+pub struct Hooks {
+    pub audit_log: Option<Arc<AuditLog>>,
+    pub profile: Option<ProfileRecorder>,
+    pub verifier: Option<Verifier>,
+}
 
-pub struct TarFs<'f> {
-    index: &'f mut TarIndex<'f>,
+pub struct TarFs {
+    index: SharedIndex,
+    stats: SharedStats,
+    /// The content of stats.json as of opening it, by file handle, so it doesn't change while being read
+    stats_snapshots: HashMap<u64, Vec<u8>>,
+    next_fh: u64,
+    /// Content is read into it and handed to the kernel from there, unless the read pool serves reads
+    read_buf: Vec<u8>,
+    read_pool: Option<ReadPool>,
+    /// Tell the kernel we can be exported via NFS
+    nfs_export: bool,
+    tuning: FuseTuning,
+    /// Check permissions ourselves instead of leaving it to the kernel
+    check_permissions: bool,
+    lookups: SharedLookups,
+    handles: SharedHandles,
+    audit_log: Option<Arc<AuditLog>>,
+    profile: Option<ProfileRecorder>,
+    verifier: Option<Arc<Verifier>>,
+    /// Present the MIME types of files as xattr
+    mime_types: Option<MimeTypes>,
+    injector: Option<Injector>,
+    /// Opens beyond this many open handles fail with EMFILE
+    max_open_files: Option<u64>,
+    /// Synthetic atimes updated on reads (see --atime), attrs aren't cached by the kernel then
+    atimes: Option<Atimes>,
     pub start_signal: mpsc::SyncSender<()>,
 }
 
-impl<'f> TarFs<'f> {
-    pub fn new(index: &'f mut TarIndex<'f>, start_signal: mpsc::SyncSender<()>) -> TarFs<'f> {
+impl TarFs {
+    pub fn new(index: SharedIndex, stats: SharedStats, handles: SharedHandles, options: &MountOptions, hooks: Hooks, start_signal: mpsc::SyncSender<()>) -> TarFs {
         TarFs{
             index,
+            stats,
+            stats_snapshots: HashMap::new(),
+            next_fh: 1,
+            read_buf: vec!(),
+            read_pool: match options.threads.unwrap_or_else(default_threads) {
+                0 | 1 => None,
+                threads => Some(ReadPool::new(threads)),
+            },
+            nfs_export: options.nfs_export,
+            tuning: options.tuning,
+            check_permissions: options.check_permissions,
+            lookups: SharedLookups::default(),
+            handles,
+            audit_log: hooks.audit_log,
+            profile: hooks.profile,
+            verifier: hooks.verifier.map(Arc::new),
+            mime_types: options.mime_types.then(MimeTypes::default),
+            injector: options.inject.map(Injector::new),
+            max_open_files: options.max_open_files,
+            atimes: Atimes::new(options.atime),
             start_signal,
         }
     }
 
-    pub fn mount(self, mountpoint: &Path) -> io::Result<()> {
-        let oss = &mut Vec::new();
-        oss.extend(NAME_OPTIONS);
-        oss.extend(DEFAULT_OPTIONS);
-        let options = fuse_optionize(oss);
+    /// Blocks until the fs gets unmounted. on_mounted receives a handle to invalidate kernel caches.
+    pub fn mount<F: FnOnce(KernelCache) -> io::Result<()>>(self, mountpoint: &Path, on_mounted: F) -> io::Result<()> {
+        let options = fusemount::mount_options(self.tuning.max_read, !self.check_permissions);
+        let lookups = self.lookups.clone();
+        let stats = self.stats.clone();
+        let mut session = fuser::Session::new(self, mountpoint, &options)?;
+        on_mounted(KernelCache { notifier: session.notifier(), lookups, stats })?;
 
         info!("tarfs mounted.");
-        // TODO Would be cool to use fuse::spawn_mount here..
-        // But moving TarFs across thread boundaries seems impossible
-        let res = fuse::mount(self, &mountpoint, &options);
+        let res = session.run();
         info!("tarfs unmounted.");
         res
     }
+
+    /// The attrs of entry as presented, i.e. with the atime tracked for it
+    fn attrs_of(&self, entry: &IndexEntry) -> FileAttr {
+        match &self.atimes {
+            Some(atimes) => atimes.presented(&entry.attrs),
+            None => entry.attrs,
+        }
+    }
+
+    /// Tracked atimes change behind the kernel's back, so it must not cache attrs then
+    fn attr_ttl(&self) -> Duration {
+        match self.atimes {
+            Some(_) => Duration::ZERO,
+            None => ttl_max(),
+        }
+    }
+
+    /// Whether the caller of req may access ino as asked for by mask (see Caller::may), and search all directories above it
+    fn permitted(&self, req: &Request, ino: u64, mask: i32) -> bool {
+        if !self.check_permissions || is_virtual(ino) {
+            return true;
+        }
+        let caller = Caller::of(req);
+        let index = self.index.read().unwrap();
+        let mut entry = match index.get_entry_by_ino(ino) {
+            Some(entry) => entry,
+            None => return true,    // Answered with ENOENT anyway
+        };
+        if !caller.may(&entry.attrs, mask) {
+            return false;
+        }
+        // Checked even though the kernel checked the lookups on the way here: it might have had them cached from other callers
+        while let Some(parent) = entry.parent_ino.and_then(|ino| index.get_entry_by_ino(ino)) {
+            if !caller.may(&parent.attrs, libc::X_OK) {
+                return false;
+            }
+            entry = parent;
+        }
+        true
+    }
+
+    fn reply_entry(&self, reply: ReplyEntry, ttl: &Duration, attrs: &FileAttr, generation: u64) {
+        self.lookups.looked_up(attrs.ino);
+        self.stats.set_referenced_inodes(self.lookups.referenced() as u64);
+        reply.entry(ttl, attrs, generation);
+    }
+}
+
+/// Reading content of a member, on the session's thread or a worker of the read pool
+struct ContentRead {
+    index: SharedIndex,
+    stats: SharedStats,
+    audit_log: Option<Arc<AuditLog>>,
+    verifier: Option<Arc<Verifier>>,
+    ino: u64,
+    offset: u64,
+    size: usize,
+    uid: u32,
+    pid: u32,
+}
+
+impl ContentRead {
+    /// Reads straight into buf, which is handed to the reply
+    fn serve(self, buf: &mut Vec<u8>, reply: ReplyData) {
+        let index = self.index.read().unwrap();
+        // Gone if the archive got reloaded meanwhile
+        let entry = match index.get_entry_by_ino(self.ino) {
+            Some(entry) => entry,
+            None => {
+                reply.error(FsError::NotFound.errno());
+                return
+            },
+        };
+        buf.resize(self.size, 0);
+        let n = match index.read_into(entry, self.offset, buf) {
+            Ok(n) => n,
+            Err(e) => {
+                errorlog::error("read", self.ino, format_args!("{}", e));
+                self.stats.error();
+                reply.error(FsError::Io.errno());
+                return
+            },
+        };
+        if let Some(verifier) = &self.verifier {
+            if !verifier.check(&index, entry, self.offset, &buf[..n]) {
+                self.stats.error();
+                reply.error(FsError::Io.errno());
+                return
+            }
+        }
+        // Short at the end of the file, empty beyond it
+        self.stats.read(n as u64);
+        if let Some(audit_log) = &self.audit_log {
+            audit_log.record(self.uid, self.pid, "read", &entry.path, n as u64);
+        }
+        reply.data(&buf[..n]);
+    }
+}
+
+/// What the kernel caches of the mount, to make it forget what changed.
+/// Whoever changes the index holds on to it, so it keeps the stats about the index up to date as well.
+#[derive(Clone)]
+pub struct KernelCache {
+    notifier: Notifier,
+    lookups: SharedLookups,
+    stats: SharedStats,
+}
+
+impl KernelCache {
+    /// Replaces the served index and makes the kernel forget what it cached from the old one, if it differs in the new one.
+    /// Entries kept as they were (same ino, attrs and place in the archive) stay cached.
+    pub fn swap_index(&self, index: &SharedIndex, new_index: TarIndex) {
+        self.index_changed(&new_index);
+        let old_index = std::mem::replace(&mut *index.write().unwrap(), new_index);
+        // Collected first as the lock must not be held while notifying (see invalidate_entries)
+        let (dentries, inos) = stale_cache_entries(&old_index, &index.read().unwrap());
+        // The kernel has nothing cached of inodes it doesn't reference, nor below directories it doesn't
+        let dentries: Vec<_> = dentries.into_iter().filter(|(parent_ino, _)| self.is_referenced(*parent_ino)).collect();
+        let inos: Vec<_> = inos.into_iter().filter(|ino| self.is_referenced(*ino)).collect();
+        debug!("Invalidating {} names and {} inodes", dentries.len(), inos.len());
+        for (parent_ino, name) in dentries {
+            self.inval_entry(parent_ino, &name);
+        }
+        for ino in inos {
+            self.inval_inode(ino);
+        }
+    }
+
+    /// Makes the kernel forget the given entries, both their names and their attributes/content.
+    /// Must not be called while holding the index lock as the kernel might be waiting for us to answer a request.
+    pub fn invalidate_entries<'e, I: Iterator<Item = &'e IndexEntry>>(&self, entries: I) {
+        for entry in entries {
+            if let Some(parent_ino) = entry.parent_ino.filter(|ino| self.is_referenced(*ino)) {
+                self.inval_entry(parent_ino, &entry.name);
+            }
+            if self.is_referenced(entry.ino()) {
+                self.inval_inode(entry.ino());
+            }
+        }
+    }
+
+    /// Updates the stats about the index
+    pub fn index_changed(&self, index: &TarIndex) {
+        self.stats.set_index_memory(index.memory_usage());
+    }
+
+    fn is_referenced(&self, ino: u64) -> bool {
+        // The root is referenced without being looked up
+        ino == ROOT_INO || self.lookups.is_referenced(ino)
+    }
+
+    fn inval_entry(&self, parent_ino: u64, name: &Path) {
+        // ENOENT simply means the kernel does not know about the entry
+        if let Err(e) = self.notifier.inval_entry(parent_ino, name.as_os_str()) {
+            trace!("inval_entry({}, {}): {}", parent_ino, name.display(), e);
+        }
+    }
+
+    fn inval_inode(&self, ino: u64) {
+        if let Err(e) = self.notifier.inval_inode(ino, 0, 0) {
+            trace!("inval_inode({}): {}", ino, e);
+        }
+    }
 }
 
-fn fuse_optionize<'a>(os: &Vec<&'a str>) -> Vec<&'a OsStr> {
-    let mut result: Vec<&OsStr> = vec!();
-    let opts = os.iter()
-            .map(|o| o.to_owned().as_ref())
-            .collect::<Vec<&OsStr>>();
-    for i in (opts.len() - 1)..0 {
-        result.insert(i, "-o".as_ref());
+/// The names (parent ino, name) which resolve differently in the new index, and the inos whose attrs, content or children changed
+fn stale_cache_entries(old: &TarIndex, new: &TarIndex) -> (Vec<(u64, PathBuf)>, Vec<u64>) {
+    let mut dentries = vec!();
+    let mut inos = BTreeSet::new();
+    for entry in old.entries() {
+        if let Some(parent_ino) = entry.parent_ino {
+            let resolved = new.lookup_child(parent_ino, entry.name.clone()).map(|child| child.ino());
+            if resolved != Some(entry.ino()) {
+                dentries.push((parent_ino, entry.name.clone()));
+            }
+        }
+        let unchanged = new.get_entry_by_ino(entry.id).is_some_and(|new_entry| same_entry(entry, new_entry));
+        if !unchanged {
+            inos.insert(entry.ino());
+        }
     }
-    result
+    // Lookups which failed before
+    for entry in new.entries() {
+        if let Some(parent_ino) = entry.parent_ino {
+            if old.lookup_child(parent_ino, entry.name.clone()).is_none() {
+                dentries.push((parent_ino, entry.name.clone()));
+            }
+        }
+    }
+    (dentries, inos.into_iter().collect())
+}
+
+fn same_entry(old: &IndexEntry, new: &IndexEntry) -> bool {
+    old.path == new.path
+        && old.link_name == new.link_name
+        && old.link_target_ino == new.link_target_ino
+        && old.attrs == new.attrs
+        && old.file_offsets == new.file_offsets
+        && old.children == new.children
 }
 
-impl<'f> Filesystem for TarFs<'f> {
-    fn init(&mut self, _req: &Request) -> Result<(), i32> {
+/// Applies what the kernel accepts, and the nearest it accepts otherwise
+fn apply_tuning(tuning: &FuseTuning, config: &mut KernelConfig) {
+    // max_read is a mount option. Requests are capped at max_pages as well, which fuser already negotiates as high as possible.
+    if let Some(max_readahead) = tuning.max_readahead {
+        if let Err(nearest) = config.set_max_readahead(max_readahead) {
+            warn!("kernel does not accept a max_readahead of {} bytes, using {}", max_readahead, nearest);
+            let _ = config.set_max_readahead(nearest);
+        }
+    }
+    if let Some(max_background) = tuning.max_background {
+        if let Err(nearest) = config.set_max_background(max_background) {
+            warn!("kernel does not accept a max_background of {}, using {}", max_background, nearest);
+            let _ = config.set_max_background(nearest);
+        }
+    }
+    if let Some(threshold) = tuning.congestion_threshold {
+        if let Err(nearest) = config.set_congestion_threshold(threshold) {
+            warn!("kernel does not accept a congestion_threshold of {}, using {}", threshold, nearest);
+            let _ = config.set_congestion_threshold(nearest);
+        }
+    }
+}
+
+impl Filesystem for TarFs {
+    fn init(&mut self, _req: &Request, config: &mut KernelConfig) -> Result<(), i32> {
+        // Exporting requires answering lookups of "." and ".." (see lookup)
+        if self.nfs_export {
+            if let Err(unsupported) = config.add_capabilities(consts::FUSE_EXPORT_SUPPORT) {
+                error!("kernel does not support exporting FUSE file systems (capabilities {:#x}), NFS exports won't work", unsupported);
+            }
+        }
+
+        apply_tuning(&self.tuning, config);
+
         // Signal start
         if let Err(err) = self.start_signal.send(()) {
             debug!("error sending start signal: {}", err);
@@ -80,11 +405,65 @@ impl<'f> Filesystem for TarFs<'f> {
         Ok(())
     }
 
-    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+    fn destroy(&mut self) {
+        errorlog::flush();
+        if let Some(profile) = &mut self.profile {
+            if let Err(e) = profile.save() {
+                error!("Unable to save the access profile: {}", e);
+            }
+        }
+    }
+
+    fn lookup(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
         let path = PathBuf::from(name);
         debug!("lookup(parent={}, name={})", parent, path.to_str().unwrap());
 
-        let entry = match self.index.lookup_child(parent, PathBuf::from(name)) {
+        if !self.permitted(req, parent, libc::X_OK) {
+            reply.error(FsError::PermissionDenied.errno());
+            return;
+        }
+
+        let index = self.index.read().unwrap();
+        if is_virtual(parent) {
+            let attrs = match (parent, name.to_str()) {
+                (TARFS_DIR_INO, Some(".")) => virtual_attrs(&index, &self.stats, TARFS_DIR_INO),
+                (TARFS_DIR_INO, Some("..")) => index.get_entry_by_ino(ROOT_INO).map(|root| root.attrs),
+                (TARFS_DIR_INO, Some(STATS_FILE_NAME)) => virtual_attrs(&index, &self.stats, STATS_FILE_INO),
+                (TARFS_DIR_INO, Some(INFO_FILE_NAME)) => virtual_attrs(&index, &self.stats, INFO_FILE_INO),
+                (TARFS_DIR_INO, Some(RAW_DIR_NAME)) => virtual_attrs(&index, &self.stats, RAW_DIR_INO),
+                (RAW_DIR_INO, Some(".")) => virtual_attrs(&index, &self.stats, RAW_DIR_INO),
+                (RAW_DIR_INO, Some("..")) => virtual_attrs(&index, &self.stats, TARFS_DIR_INO),
+                (RAW_DIR_INO, Some(name)) => raw_header_ino_by_name(&index, name).and_then(|ino| virtual_attrs(&index, &self.stats, ino)),
+                _ => None,
+            };
+            match attrs {
+                Some(attrs) => self.reply_entry(reply, &Duration::ZERO, &attrs, 0),
+                None => reply.error(FsError::NotFound.errno()),
+            }
+            return;
+        }
+        // "." is looked up in files as well, to resolve NFS file handles (see below)
+        let expect = if name == "." { Expect::Any } else { Expect::Directory };
+        if let Err(e) = entry_for(&index, parent, expect) {
+            reply.error(e.errno());
+            return;
+        }
+        if parent == ROOT_INO && name == TARFS_DIR_NAME && index.lookup_child(parent, PathBuf::from(name)).is_none() {
+            if let Some(attrs) = virtual_attrs(&index, &self.stats, TARFS_DIR_INO) {
+                self.reply_entry(reply, &Duration::ZERO, &attrs, 0);
+                return;
+            }
+        }
+
+        self.stats.op();
+        let entry = match name.to_str() {
+            // Only sent to exported file systems, e.g. to resolve NFS file handles of entries the kernel forgot about
+            Some(".") => index.get_entry_by_ino(parent),
+            Some("..") => index.get_entry_by_ino(parent)
+                .and_then(|dir| index.get_entry_by_ino(dir.dotdot_ino())),
+            _ => index.lookup_child(parent, PathBuf::from(name)),
+        };
+        let entry = match entry {
             Some(a) => a,
             None => {
                 // According to https://github.com/libfuse/libfuse/blob/master/include/fuse_lowlevel.h#L60
@@ -96,41 +475,90 @@ impl<'f> Filesystem for TarFs<'f> {
                 return;
             },
         };
-        reply.entry(&ttl_max(), &entry.attrs, 0);
+        self.reply_entry(reply, &self.attr_ttl(), &self.attrs_of(entry), index.generation());
     }
 
-    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+    fn forget(&mut self, _req: &Request, ino: u64, nlookup: u64) {
+        trace!("forget(ino={}, nlookup={})", ino, nlookup);
+        self.lookups.forget(ino, nlookup);
+        self.stats.set_referenced_inodes(self.lookups.referenced() as u64);
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
         debug!("getattr(ino={})", ino);
 
-        let entry = match self.index.get_entry_by_ino(ino) {
-            None => {
-                reply.error(ENOENT);
-                error!("lookup: no entry");
+        let index = self.index.read().unwrap();
+        if is_virtual(ino) {
+            match virtual_attrs(&index, &self.stats, ino) {
+                Some(attrs) => reply.attr(&Duration::ZERO, &attrs),
+                None => reply.error(FsError::NotFound.errno()),
+            }
+            return;
+        }
+
+        self.stats.op();
+        let entry = match entry_for(&index, ino, Expect::Any) {
+            Ok(entry) => entry,
+            Err(e) => {
+                self.stats.error();
+                reply.error(e.errno());
+                errorlog::error("getattr", ino, format_args!("getattr: {:?}", e));
                 return
             },
-            Some(e) => e,
         };
 
-        reply.attr(&ttl_max(), &entry.attrs);
+        reply.attr(&self.attr_ttl(), &self.attrs_of(entry));
     }
 
     fn readdir(&mut self, _req: &Request, ino: u64, fh: u64, offset: i64, mut reply: ReplyDirectory) {
         debug!("readdir(ino={}, fh={}, offset={})", ino, fh, offset);
 
-        let entry = match self.index.get_entry_by_ino(ino) {
-            None => {
-                reply.error(ENOENT);
-                error!("readdir: no entry");
+        if ino == TARFS_DIR_INO {
+            let entries = [
+                (TARFS_DIR_INO, FileType::Directory, "."),
+                (ROOT_INO, FileType::Directory, ".."),
+                (STATS_FILE_INO, FileType::RegularFile, STATS_FILE_NAME),
+                (INFO_FILE_INO, FileType::RegularFile, INFO_FILE_NAME),
+                (RAW_DIR_INO, FileType::Directory, RAW_DIR_NAME),
+            ];
+            for (off, (ino, kind, name)) in (1..).zip(entries.iter()).skip(offset.max(0) as usize) {
+                if reply.add(*ino, off, *kind, name) {
+                    break;
+                }
+            }
+            reply.ok();
+            return;
+        }
+        if ino == RAW_DIR_INO {
+            let entries = [(RAW_DIR_INO, "."), (TARFS_DIR_INO, "..")];
+            for (off, (ino, name)) in (1..).zip(entries.iter()).skip(offset.max(0) as usize) {
+                if reply.add(*ino, off, FileType::Directory, name) {
+                    reply.ok();
+                    return;
+                }
+            }
+            let count = self.index.read().unwrap().raw_headers().len().min(MAX_RAW_HEADERS as usize);
+            for n in (offset.max(2) as usize - 2)..count {
+                if reply.add(raw_header_ino(n), n as i64 + 3, FileType::RegularFile, format!("{}{}", n, RAW_HEADER_SUFFIX)) {
+                    break;
+                }
+            }
+            reply.ok();
+            return;
+        }
+
+        self.stats.op();
+        let index = self.index.read().unwrap();
+        let entry = match entry_for(&index, ino, Expect::Directory) {
+            Ok(entry) => entry,
+            Err(e) => {
+                self.stats.error();
+                reply.error(e.errno());
+                errorlog::error("readdir", ino, format_args!("readdir: {:?}", e));
                 return
             },
-            Some(e) => e,
         };
 
-        if entry.attrs.kind != fuse::FileType::Directory {
-            error!("readdir: ino {} is no dir!", ino);
-            return
-        }
-
         let mut full;
         if offset == 0 {
             let off = 1;
@@ -144,12 +572,7 @@ impl<'f> Filesystem for TarFs<'f> {
         }
 
         if offset <= 1 {
-            // Handle fs root: same ino as
-            let ino = match entry.parent_ino {
-                None => entry.ino(),
-                Some(ino) => ino,
-            };
-
+            let ino = entry.dotdot_ino();
             let off = 2;
             let kind = FileType::Directory;
             full = reply.add(ino, off, kind, "..");
@@ -161,14 +584,13 @@ impl<'f> Filesystem for TarFs<'f> {
         }
 
         let children_offset = (offset - 2).max(0);
-        let mut off: i64 = 2 + children_offset + 1;
-        for child in self.index.children_iter(entry).skip(children_offset as usize) {
+        let children = index.children_iter(entry).skip(children_offset as usize);
+        for (off, child) in (2 + children_offset + 1..).zip(children) {
             let ino = child.ino();
             let kind = child.attrs.kind;
             let name = &child.name;
             trace!("reply.add inode {}, offset {}, file_type {:?}, base {} ", ino, off, kind, name.display());
             full = reply.add(ino, off, kind, name);
-            off += 1;
             if full {
                 break;
             }
@@ -176,39 +598,198 @@ impl<'f> Filesystem for TarFs<'f> {
         reply.ok();
     }
 
-    fn read(&mut self, _req: &Request, ino: u64, fh: u64, offset: i64, size: u32, reply: ReplyData) {
+    fn read(&mut self, req: &Request, ino: u64, fh: u64, offset: i64, size: u32, flags: i32, _lock_owner: Option<u64>, reply: ReplyData) {
         debug!("read(ino={}, fh={}, offset={}, size={})", ino, fh, offset, size);
 
-        let entry = match self.index.get_entry_by_ino(ino) {
-            None => {
-                reply.error(ENOENT);
-                error!("lookup: no entry");
+        if ino == STATS_FILE_INO {
+            let snapshot = self.stats_snapshots.get(&fh).map(Vec::as_slice).unwrap_or_default();
+            let start = (offset.max(0) as usize).min(snapshot.len());
+            let end = start.saturating_add(size as usize).min(snapshot.len());
+            reply.data(&snapshot[start..end]);
+            return;
+        }
+        if ino == INFO_FILE_INO {
+            let info = info_json(&self.index.read().unwrap()).into_bytes();
+            let start = (offset.max(0) as usize).min(info.len());
+            let end = start.saturating_add(size as usize).min(info.len());
+            reply.data(&info[start..end]);
+            return;
+        }
+        if let Some(n) = raw_header_number(ino) {
+            let index = self.index.read().unwrap();
+            let raw_header = match index.raw_headers().get(n) {
+                Some(raw_header) => *raw_header,
+                None => {
+                    reply.error(FsError::NotFound.errno());
+                    return;
+                },
+            };
+            self.read_buf.resize(size as usize, 0);
+            match index.read_raw_header(&raw_header, offset.max(0) as u64, &mut self.read_buf) {
+                Ok(n) => reply.data(&self.read_buf[..n]),
+                Err(e) => {
+                    errorlog::error("read", ino, format_args!("unable to read header #{} at offset {}: {}", n, raw_header.offset, e));
+                    reply.error(FsError::Io.errno());
+                },
+            }
+            return;
+        }
+
+        self.stats.op();
+        let index = self.index.read().unwrap();
+        let entry = match entry_for(&index, ino, Expect::NotDirectory) {
+            Ok(entry) => entry,
+            Err(e) => {
+                self.stats.error();
+                reply.error(e.errno());
+                errorlog::error("read", ino, format_args!("read: {:?}", e));
                 return
             },
-            Some(e) => e.clone(),
         };
 
-        let bytes = match self.index.read(&entry, offset as u64, size as u64) {
-            Err(e) => {
-                error!("Error reading from file {}: {}", entry.path.display(), e);
-                reply.error(ENODATA);
+        if let Some(injector) = &mut self.injector {
+            if injector.before_read() {
+                debug!("read: injecting an error for {}", ino);
+                self.stats.error();
+                reply.error(FsError::Io.errno());
                 return
-            },
-            Ok(bytes) => bytes,
+            }
+        }
+
+        // Tracked as the read starts, which might be on another thread. Like other file systems, reads of files opened
+        // with O_NOATIME don't count.
+        if let Some(atimes) = self.atimes.as_mut().filter(|_| flags & libc::O_NOATIME == 0) {
+            atimes.read(&entry.attrs);
+        }
+        let content_read = ContentRead {
+            index: self.index.clone(),
+            stats: self.stats.clone(),
+            audit_log: self.audit_log.clone(),
+            verifier: self.verifier.clone(),
+            ino,
+            offset: offset.max(0) as u64,
+            size: size as usize,
+            uid: req.uid(),
+            pid: req.pid(),
+        };
+        drop(index);
+        match &self.read_pool {
+            Some(read_pool) => read_pool.submit(move |buf| content_read.serve(buf, reply)),
+            None => content_read.serve(&mut self.read_buf, reply),
+        }
+    }
+
+    fn open(&mut self, req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+        debug!("open(ino={}, flags={:#x})", ino, flags);
+
+        let index = self.index.read().unwrap();
+        let kind = match is_virtual(ino) {
+            true => virtual_attrs(&index, &self.stats, ino).map(|attrs| attrs.kind).ok_or(FsError::NotFound),
+            false => entry_for(&index, ino, Expect::Any).map(|entry| entry.attrs.kind),
         };
-        reply.data(&bytes);
+        drop(index);
+        if let Err(e) = kind.and_then(|kind| check_open_flags(flags, kind)) {
+            debug!("open: refused for ino {}: {:?}", ino, e);
+            reply.error(e.errno());
+            return;
+        }
+
+        if ino == STATS_FILE_INO {
+            let fh = self.next_fh;
+            self.next_fh += 1;
+            self.stats_snapshots.insert(fh, format!("{}\n", self.stats.to_json()).into_bytes());
+            // The size reported by getattr might be outdated already
+            reply.opened(fh, consts::FOPEN_DIRECT_IO);
+            return;
+        }
+        if ino == INFO_FILE_INO || raw_header_number(ino).is_some() {
+            // Not cached, a reload might change them
+            reply.opened(0, consts::FOPEN_DIRECT_IO);
+            return;
+        }
+
+        if !self.permitted(req, ino, libc::R_OK) {
+            reply.error(FsError::PermissionDenied.errno());
+            return;
+        }
+        if self.max_open_files.is_some_and(|max| self.handles.count() >= max) {
+            debug!("open: {} files are open already", self.handles.count());
+            self.stats.error();
+            reply.error(FsError::TooManyOpenFiles.errno());
+            return;
+        }
+        self.stats.op();
+        self.stats.opened();
+        self.handles.opened(ino);
+        if self.audit_log.is_some() || self.profile.is_some() {
+            if let Some(entry) = self.index.read().unwrap().get_entry_by_ino(ino) {
+                if let Some(audit_log) = &self.audit_log {
+                    audit_log.record(req.uid(), req.pid(), "open", &entry.path, 0);
+                }
+                if let Some(profile) = &mut self.profile {
+                    profile.opened(&entry.path);
+                }
+            }
+        }
+        reply.opened(0, 0);
+    }
+
+    fn opendir(&mut self, req: &Request, ino: u64, _flags: i32, reply: ReplyOpen) {
+        if !is_virtual(ino) {
+            if let Err(e) = entry_for(&self.index.read().unwrap(), ino, Expect::Directory) {
+                reply.error(e.errno());
+                return;
+            }
+        }
+        if !self.permitted(req, ino, libc::R_OK) {
+            reply.error(FsError::PermissionDenied.errno());
+            return;
+        }
+        reply.opened(0, 0);
+    }
+
+    /// Only sent without default_permissions (see --check-permissions), e.g. for access(2) and chdir(2)
+    fn access(&mut self, req: &Request, ino: u64, mask: i32, reply: ReplyEmpty) {
+        debug!("access(ino={}, mask={:#o})", ino, mask);
+        if mask & libc::W_OK != 0 {
+            reply.error(FsError::ReadOnly.errno());
+        } else if self.permitted(req, ino, mask) {
+            reply.ok();
+        } else {
+            reply.error(FsError::PermissionDenied.errno());
+        }
+    }
+
+    /// Nothing to write back, but the default implementation complains on every close
+    fn flush(&mut self, _req: &Request, _ino: u64, _fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        reply.ok();
+    }
+
+    fn release(&mut self, _req: &Request, ino: u64, fh: u64, _flags: i32, _lock_owner: Option<u64>, _flush: bool, reply: ReplyEmpty) {
+        debug!("release(ino={}, fh={})", ino, fh);
+
+        if ino == STATS_FILE_INO {
+            self.stats_snapshots.remove(&fh);
+        } else if !is_virtual(ino) {
+            self.stats.released();
+            self.handles.released(ino);
+        }
+        reply.ok();
     }
 
     fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
         debug!("readlink(ino={})", ino);
 
-        let entry = match self.index.get_entry_by_ino(ino) {
-            None => {
-                reply.error(ENOENT);
-                error!("readlink: no entry");
+        self.stats.op();
+        let index = self.index.read().unwrap();
+        let entry = match entry_for(&index, ino, Expect::Symlink) {
+            Ok(entry) => entry,
+            Err(e) => {
+                self.stats.error();
+                reply.error(e.errno());
+                errorlog::error("readlink", ino, format_args!("readlink: {:?}", e));
                 return
             },
-            Some(e) => e.clone(),
         };
 
         match &entry.link_name {
@@ -219,15 +800,177 @@ impl<'f> Filesystem for TarFs<'f> {
                 reply.data(bytes);
             },
             None => {
-                error!("readlink: no link_name");
-                return
+                errorlog::error("readlink", ino, format_args!("readlink: no link_name"));
+                reply.error(FsError::Io.errno());
             }
         }
     }
+
+    // tarfs is read-only: answer everything that would modify it with EROFS instead of the default ENOSYS, which confuses some applications
+
+    fn setattr(&mut self, _req: &Request, ino: u64, _mode: Option<u32>, _uid: Option<u32>, _gid: Option<u32>, _size: Option<u64>, _atime: Option<TimeOrNow>, _mtime: Option<TimeOrNow>, _ctime: Option<SystemTime>, _fh: Option<u64>, _crtime: Option<SystemTime>, _chgtime: Option<SystemTime>, _bkuptime: Option<SystemTime>, _flags: Option<u32>, reply: ReplyAttr) {
+        debug!("setattr(ino={})", ino);
+        reply.error(FsError::ReadOnly.errno());
+    }
+
+    fn mknod(&mut self, _req: &Request, parent: u64, name: &OsStr, _mode: u32, _umask: u32, _rdev: u32, reply: ReplyEntry) {
+        debug!("mknod(parent={}, name={})", parent, name.to_string_lossy());
+        reply.error(FsError::ReadOnly.errno());
+    }
+
+    fn mkdir(&mut self, _req: &Request, parent: u64, name: &OsStr, _mode: u32, _umask: u32, reply: ReplyEntry) {
+        debug!("mkdir(parent={}, name={})", parent, name.to_string_lossy());
+        reply.error(FsError::ReadOnly.errno());
+    }
+
+    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        debug!("unlink(parent={}, name={})", parent, name.to_string_lossy());
+        reply.error(FsError::ReadOnly.errno());
+    }
+
+    fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        debug!("rmdir(parent={}, name={})", parent, name.to_string_lossy());
+        reply.error(FsError::ReadOnly.errno());
+    }
+
+    fn symlink(&mut self, _req: &Request, parent: u64, link_name: &OsStr, _target: &Path, reply: ReplyEntry) {
+        debug!("symlink(parent={}, link_name={})", parent, link_name.to_string_lossy());
+        reply.error(FsError::ReadOnly.errno());
+    }
+
+    fn rename(&mut self, _req: &Request, parent: u64, name: &OsStr, _newparent: u64, _newname: &OsStr, _flags: u32, reply: ReplyEmpty) {
+        debug!("rename(parent={}, name={})", parent, name.to_string_lossy());
+        reply.error(FsError::ReadOnly.errno());
+    }
+
+    fn link(&mut self, _req: &Request, ino: u64, _newparent: u64, _newname: &OsStr, reply: ReplyEntry) {
+        debug!("link(ino={})", ino);
+        reply.error(FsError::ReadOnly.errno());
+    }
+
+    fn write(&mut self, _req: &Request, ino: u64, fh: u64, _offset: i64, _data: &[u8], _write_flags: u32, _flags: i32, _lock_owner: Option<u64>, reply: ReplyWrite) {
+        debug!("write(ino={}, fh={})", ino, fh);
+        reply.error(FsError::ReadOnly.errno());
+    }
+
+    fn create(&mut self, _req: &Request, parent: u64, name: &OsStr, _mode: u32, _umask: u32, _flags: i32, reply: ReplyCreate) {
+        debug!("create(parent={}, name={})", parent, name.to_string_lossy());
+        reply.error(FsError::ReadOnly.errno());
+    }
+
+    fn getxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        debug!("getxattr(ino={}, name={}, size={})", ino, name.to_string_lossy(), size);
+        let mime_types = match &mut self.mime_types {
+            Some(mime_types) => mime_types,
+            // Makes the kernel stop asking
+            None => return reply.error(FsError::NotSupported.errno()),
+        };
+        if name != mime::XATTR_NAME || is_virtual(ino) {
+            return reply.error(FsError::NoAttribute.errno());
+        }
+        let index = self.index.read().unwrap();
+        let mime_type = match index.get_entry_by_ino(ino) {
+            Some(entry) if entry.attrs.kind == FileType::RegularFile => mime_types.of(&index, entry),
+            Some(_) => return reply.error(FsError::NoAttribute.errno()),
+            None => return reply.error(FsError::NotFound.errno()),
+        };
+        reply_xattr(reply, size, mime_type.as_bytes());
+    }
+
+    fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        debug!("listxattr(ino={}, size={})", ino, size);
+        if self.mime_types.is_none() {
+            return reply.error(FsError::NotSupported.errno());
+        }
+        let is_file = !is_virtual(ino) && self.index.read().unwrap().get_entry_by_ino(ino)
+            .is_some_and(|entry| entry.attrs.kind == FileType::RegularFile);
+        let names = if is_file { format!("{}\0", mime::XATTR_NAME) } else { String::new() };
+        reply_xattr(reply, size, names.as_bytes());
+    }
+
+    fn setxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, _value: &[u8], _flags: i32, _position: u32, reply: ReplyEmpty) {
+        debug!("setxattr(ino={}, name={})", ino, name.to_string_lossy());
+        reply.error(FsError::ReadOnly.errno());
+    }
+
+    fn removexattr(&mut self, _req: &Request, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        debug!("removexattr(ino={}, name={})", ino, name.to_string_lossy());
+        reply.error(FsError::ReadOnly.errno());
+    }
+
+    fn fallocate(&mut self, _req: &Request, ino: u64, fh: u64, _offset: i64, _length: i64, _mode: i32, reply: ReplyEmpty) {
+        debug!("fallocate(ino={}, fh={})", ino, fh);
+        reply.error(FsError::ReadOnly.errno());
+    }
+
+    fn copy_file_range(&mut self, _req: &Request, _ino_in: u64, _fh_in: u64, _offset_in: i64, ino_out: u64, fh_out: u64, _offset_out: i64, _len: u64, _flags: u32, reply: ReplyWrite) {
+        debug!("copy_file_range(ino_out={}, fh_out={})", ino_out, fh_out);
+        reply.error(FsError::ReadOnly.errno());
+    }
+}
+
+/// Whether a file of kind can be opened with flags. The kernel refuses most of what doesn't fit before asking, this is
+/// for the rest (e.g. O_APPEND without write access).
+fn check_open_flags(flags: i32, kind: FileType) -> Result<(), FsError> {
+    if flags & libc::O_ACCMODE != libc::O_RDONLY || flags & (libc::O_TRUNC | libc::O_APPEND) != 0 {
+        Err(FsError::ReadOnly)
+    } else if flags & libc::O_DIRECTORY != 0 && kind != FileType::Directory {
+        Err(FsError::NotADirectory)
+    } else if flags & libc::O_NOFOLLOW != 0 && kind == FileType::Symlink {
+        Err(FsError::SymlinkLoop)
+    } else {
+        Ok(())
+    }
+}
+
+/// Answers with the size of value if size is 0, else with value if it fits
+fn reply_xattr(reply: ReplyXattr, size: u32, value: &[u8]) {
+    if size == 0 {
+        reply.size(value.len() as u32);
+    } else if value.len() > size as usize {
+        reply.error(FsError::BufferTooSmall.errno());
+    } else {
+        reply.data(value);
+    }
+}
+
+/// The content of info.json
+fn info_json(index: &TarIndex) -> String {
+    let mut pax_globals: Vec<_> = index.pax_globals().iter().collect();
+    pax_globals.sort();
+    let pax_globals: Vec<String> = pax_globals.iter()
+        .map(|(key, value)| format!("{}:{}", json_string(key), json_string(value)))
+        .collect();
+    format!("{{\"volume_label\":{},\"pax_globals\":{{{}}}}}\n",
+        index.volume_label().map_or_else(|| "null".to_owned(), json_string),
+        pax_globals.join(","))
+}
+
+/// Attributes of the virtual entries, owned like the root
+fn virtual_attrs(index: &TarIndex, stats: &Stats, ino: u64) -> Option<FileAttr> {
+    let mut attrs = index.get_entry_by_ino(ROOT_INO)?.attrs;
+    attrs.ino = ino;
+    attrs.blocks = 0;
+    if ino == TARFS_DIR_INO || ino == RAW_DIR_INO {
+        attrs.kind = FileType::Directory;
+        attrs.perm = 0o555;
+        attrs.nlink = if ino == TARFS_DIR_INO { 3 } else { 2 };
+        attrs.size = 0;
+    } else {
+        attrs.kind = FileType::RegularFile;
+        attrs.perm = 0o444;
+        attrs.nlink = 1;
+        attrs.size = match raw_header_number(ino) {
+            Some(n) => index.raw_headers().get(n)?.size,
+            None if ino == INFO_FILE_INO => info_json(index).len() as u64,
+            None => stats.to_json().len() as u64 + 1,
+        };
+    }
+    Some(attrs)
 }
 
 /// As tarfs is a static file system in which files will never change, we use the highest possible timeout for entries and attributes read by the kernel
 /// Reference: Here's the best documentation about timeouts I could find: https://github.com/libfuse/libfuse/blob/master/include/fuse_lowlevel.h#L90
-fn ttl_max() -> Timespec {
-    Timespec::new(std::i64::MAX, 0)
+fn ttl_max() -> Duration {
+    Duration::new(i64::MAX as u64, 0)
 }
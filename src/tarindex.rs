@@ -1,16 +1,24 @@
-use std::fs::File;
 use std::fmt;
 use std::io;
-use std::io::{Seek, SeekFrom, Read};
-use std::{path::Path, path::PathBuf};
-use std::collections::BTreeMap;
+use std::{path::Component, path::Path, path::PathBuf};
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
 use std::vec::Vec;
-use std::ffi::{OsStr};
+use std::ffi::OsString;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
 
+use fuser::FileType;
 use log::{trace, error};
 
 use crate::utils::default_fuse_file_attr;
 use crate::arena::{ Arena, ChildrenIterator };
+use crate::archivesource::ArchiveSource;
+use crate::decompress::Decoders;
+use crate::walk::Walk;
+use crate::tarindexer::archive_path;
+use crate::normalize::Normalization;
+use crate::TarFsError;
 
 #[derive(Debug, Clone)]
 pub struct IndexEntry {
@@ -22,15 +30,23 @@ pub struct IndexEntry {
     pub path: PathBuf,
     pub name: PathBuf,
     pub link_name: Option<PathBuf>,
-    pub link_count: u64,    // TODO Needed? What for?
+    /// How many hard links point to this entry
+    pub link_count: u64,
     pub link_target_ino: Option<u64>,
-    pub attrs: fuse::FileAttr,
+    pub attrs: fuser::FileAttr,
 
     pub file_offsets: Vec<TarEntryPointer>,
+    /// The content is gzip compressed in the archive, attrs.size is its decompressed size (see decompress)
+    pub compressed: bool,
 
     pub children: Vec<u64>,
+    /// Directories: their children by name, for lookups. Maintained by TarIndex::insert, whatever entries come with.
+    pub child_map: ChildMap,
 }
 
+/// What an entry costs in the maps of the index (its lookup key plus the map nodes), roughly
+pub const MAP_OVERHEAD: u64 = 96;
+
 impl IndexEntry {
     pub fn ino(&self) -> u64 {
         match self.link_target_ino {
@@ -38,6 +54,21 @@ impl IndexEntry {
             None => self.id,
         }
     }
+
+    /// The ino ".." refers to: the parent directory, or the root itself for the root (ROOT_INO), like in any file system
+    pub fn dotdot_ino(&self) -> u64 {
+        self.parent_ino.unwrap_or(ROOT_INO)
+    }
+
+    /// Estimated bytes this entry occupies in the index
+    pub fn memory_usage(&self) -> u64 {
+        let heap = self.path.capacity()
+            + self.name.capacity() * 2  // Part of its parent's child_map as well
+            + self.link_name.as_ref().map_or(0, |link_name| link_name.capacity())
+            + self.children.capacity() * mem::size_of::<u64>()
+            + self.file_offsets.capacity() * mem::size_of::<TarEntryPointer>();
+        (mem::size_of::<IndexEntry>() + heap) as u64 + MAP_OVERHEAD
+    }
 }
 
 impl Default for IndexEntry {
@@ -54,124 +85,455 @@ impl Default for IndexEntry {
             attrs: default_fuse_file_attr(),
 
             file_offsets: vec!(),
+            compressed: false,
             children: vec!(),
+            child_map: ChildMap::new(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TarEntryPointer {
     pub raw_file_offset: u64,
     pub filesize: u64,
 }
 
-type ChildMap = BTreeMap<PathBuf, u64>;
+/// Where the header blocks of an archive member are, including its extension headers (e.g. PAX or GNU long names) and their payloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawHeader {
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Names an entry of an index, see TarIndex::read_range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryRef<'p> {
+    /// Relative to the root, e.g. "dir/file" (or "./dir/file", "/dir/file")
+    Path(&'p Path),
+    Ino(u64),
+}
+
+impl<'p> From<&'p Path> for EntryRef<'p> {
+    fn from(path: &'p Path) -> EntryRef<'p> {
+        EntryRef::Path(path)
+    }
+}
+
+impl From<u64> for EntryRef<'_> {
+    fn from(ino: u64) -> Self {
+        EntryRef::Ino(ino)
+    }
+}
+
+impl fmt::Display for EntryRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EntryRef::Path(path) => write!(f, "{}", path.display()),
+            EntryRef::Ino(ino) => write!(f, "ino {}", ino),
+        }
+    }
+}
+
+/// FUSE requires the root to have this ino
+pub const ROOT_INO: u64 = fuser::FUSE_ROOT_ID;
+
+pub type ChildMap = BTreeMap<OsString, u64>;
 type INodeMap = BTreeMap<u64, usize>;
 
 /// This is the resulting index struct.
-/// It holds a reference to the given archive file as it needs it to be open all time as it uses it not only to build the index but only to resolve content later.
+/// It owns the given archive source as it needs it to be open all time as it uses it not only to build the index but only to resolve content later.
 #[derive(Debug)]
-pub struct TarIndex<'f> {
-    /// The archive file. Used to create the tar::Archive and later used to read content.
-    file: &'f File,
+pub struct TarIndex {
+    /// The archive source. Used to create the tar::Archive and later used to read content.
+    source: Arc<dyn ArchiveSource>,
+
+    /// For reading compressed entries
+    decoders: Decoders,
 
     arena: Arena<IndexEntry>,
 
-    /// Maps <ino>/<file_name> to the INode
-    child_map: ChildMap,
+    /// Children of directories not inserted yet (e.g. while combining, in ino order), by the ino of their directory
+    orphans: HashMap<u64, ChildMap>,
 
-    /// Maps <ino> to the IndexEntry
-    /// TODO Could be replaced by ino_to_arena_index now...
-    /// Keep for now, maybe someone has an idea to replace the arena by "real" references
+    /// Maps <ino> to the IndexEntry's index in the arena.
+    /// Inos may be sparse (see InoMode), so they can't be used as arena indices directly.
     ino_map: INodeMap,
+
+    /// Where the entry following the last indexed one starts in the archive
+    end_offset: u64,
+
+    /// Defaults from global PAX headers, needed to index entries appended later on
+    pax_globals: HashMap<String, String>,
+
+    /// The label of a GNU volume label entry (or the GNU.volume.label global PAX record)
+    volume_label: Option<String>,
+
+    /// The archive format as detected from its first header, e.g. "tar (gnu)"
+    format: &'static str,
+
+    /// The headers of all members of a tar archive (included or not) in the order they are in the archive
+    raw_headers: Vec<RawHeader>,
+
+    /// Digests of the headers in raw_headers, to tell whether an archive extends the indexed one (see
+    /// TarIndexer::extend_index_for). Empty unless the index may get extended.
+    header_digests: Vec<u64>,
+
+    /// The form names got normalized to when indexing, lookups normalize names to it as well
+    normalization: Normalization,
+
+    /// The paths in the archive of the entries normalized, by their normalized path, to tell entries appended later on
+    /// colliding with them (see --collisions)
+    originals: HashMap<PathBuf, PathBuf>,
+
+    /// Sum of the entries' memory_usage()
+    memory_usage: u64,
+
+    /// Handed out with every ino so file handles (e.g. NFS ones) of an archive that got rewritten meanwhile become stale
+    generation: u64,
 }
 
-impl<'f> TarIndex<'f> {
-    pub fn new(file: &File, initial_capacity: usize) -> TarIndex {
+impl TarIndex {
+    pub(crate) fn new(source: Box<dyn ArchiveSource>, initial_capacity: usize) -> TarIndex {
+        let generation = source.modified()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or(0);
         TarIndex {
-            file: file,
+            source: Arc::from(source),
+            decoders: Decoders::default(),
             arena: Arena::with_capacity(initial_capacity),
-            child_map: BTreeMap::new(),
+            orphans: HashMap::new(),
             ino_map: BTreeMap::new(),
+            end_offset: 0,
+            pax_globals: HashMap::new(),
+            volume_label: None,
+            format: "tar",
+            raw_headers: vec!(),
+            header_digests: vec!(),
+            normalization: Normalization::None,
+            originals: HashMap::new(),
+            memory_usage: 0,
+            generation,
+        }
+    }
+
+    /// A copy of the index reading content from source, e.g. an extended version of the archive.
+    /// Handles stay valid, the generation is kept.
+    pub(crate) fn with_source(&self, source: Box<dyn ArchiveSource>) -> TarIndex {
+        TarIndex {
+            source: Arc::from(source),
+            decoders: Decoders::default(),
+            arena: self.arena.clone(),
+            orphans: self.orphans.clone(),
+            ino_map: self.ino_map.clone(),
+            end_offset: self.end_offset,
+            pax_globals: self.pax_globals.clone(),
+            volume_label: self.volume_label.clone(),
+            format: self.format,
+            raw_headers: self.raw_headers.clone(),
+            header_digests: self.header_digests.clone(),
+            normalization: self.normalization,
+            originals: self.originals.clone(),
+            memory_usage: self.memory_usage,
+            generation: self.generation,
         }
     }
 
-    pub fn get_entry_by_ino(&self, ino: u64) -> Option<&IndexEntry> {
+    pub(crate) fn source(&self) -> &dyn ArchiveSource {
+        self.source.as_ref()
+    }
+
+    /// The source, for reading from it beyond the index' lifetime
+    pub(crate) fn shared_source(&self) -> Arc<dyn ArchiveSource> {
+        self.source.clone()
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Estimated bytes the index occupies
+    pub(crate) fn memory_usage(&self) -> u64 {
+        self.memory_usage
+    }
+
+    /// How many entries there are, including the root
+    pub(crate) fn len(&self) -> usize {
+        self.ino_map.len()
+    }
+
+    pub(crate) fn end_offset(&self) -> u64 {
+        self.end_offset
+    }
+
+    pub(crate) fn set_end_offset(&mut self, end_offset: u64) {
+        self.end_offset = end_offset;
+    }
+
+    pub(crate) fn pax_globals(&self) -> &HashMap<String, String> {
+        &self.pax_globals
+    }
+
+    pub(crate) fn set_pax_globals(&mut self, pax_globals: HashMap<String, String>) {
+        self.pax_globals = pax_globals;
+    }
+
+    pub(crate) fn volume_label(&self) -> Option<&str> {
+        self.volume_label.as_deref().or_else(|| self.pax_globals.get("GNU.volume.label").map(String::as_str))
+    }
+
+    pub(crate) fn set_volume_label(&mut self, volume_label: Option<String>) {
+        self.volume_label = volume_label;
+    }
+
+    pub(crate) fn format(&self) -> &'static str {
+        self.format
+    }
+
+    pub(crate) fn set_format(&mut self, format: &'static str) {
+        self.format = format;
+    }
+
+    pub(crate) fn header_digests(&self) -> &[u64] {
+        &self.header_digests
+    }
+
+    /// Adds the digests of headers following the ones already added
+    pub(crate) fn add_header_digests<I: IntoIterator<Item = u64>>(&mut self, digests: I) {
+        let capacity = self.header_digests.capacity();
+        self.header_digests.extend(digests);
+        self.memory_usage += ((self.header_digests.capacity() - capacity) * mem::size_of::<u64>()) as u64;
+    }
+
+    pub(crate) fn set_normalization(&mut self, normalization: Normalization) {
+        self.normalization = normalization;
+    }
+
+    pub(crate) fn take_originals(&mut self) -> HashMap<PathBuf, PathBuf> {
+        mem::take(&mut self.originals)
+    }
+
+    pub(crate) fn set_originals(&mut self, originals: HashMap<PathBuf, PathBuf>) {
+        self.originals = originals;
+    }
+
+    pub(crate) fn raw_headers(&self) -> &[RawHeader] {
+        &self.raw_headers
+    }
+
+    /// Adds the headers of members following the ones already added
+    pub(crate) fn add_raw_headers<I: IntoIterator<Item = RawHeader>>(&mut self, raw_headers: I) {
+        let capacity = self.raw_headers.capacity();
+        self.raw_headers.extend(raw_headers);
+        self.memory_usage += ((self.raw_headers.capacity() - capacity) * mem::size_of::<RawHeader>()) as u64;
+    }
+
+    /// Fills buf with the header blocks starting at offset, returns how many bytes there were
+    pub(crate) fn read_raw_header(&self, raw_header: &RawHeader, offset: u64, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let n = raw_header.size.saturating_sub(offset).min(buf.len() as u64) as usize;
+        self.source.read_exact_at(&mut buf[..n], raw_header.offset + offset)?;
+        Ok(n)
+    }
+
+    /// The ino the next new entry should get
+    pub(crate) fn next_ino(&self) -> u64 {
+        match self.ino_map.keys().next_back() {
+            Some(ino) => ino + 1,
+            None => 1,
+        }
+    }
+
+    pub(crate) fn get_entry_by_ino(&self, ino: u64) -> Option<&IndexEntry> {
         match self.ino_map.get(&ino) {
             None => None,
             Some(arena_index) => self.arena.get(*arena_index),
         }
     }
 
-    pub fn lookup_child(&self, parent_ino: u64, path: PathBuf) -> Option<&IndexEntry> {
-        let key = lookup_key(parent_ino, path.as_os_str());
-        match self.child_map.get(&key) {
-            None => None,
-            Some(ino) => {
-                let arena_index = ino_to_arena_index(*ino);
-                self.arena.get(arena_index)
-            },
+    pub(crate) fn lookup_child(&self, parent_ino: u64, path: PathBuf) -> Option<&IndexEntry> {
+        let parent = self.get_entry_by_ino(parent_ino)?;
+        let ino = parent.child_map.get(&*self.normalization.name(path.as_os_str()))?;
+        self.get_entry_by_ino(*ino)
+    }
+
+    /// Resolves a path as found in the archive (e.g. "./dir/file") starting from the root entry
+    pub(crate) fn lookup_path(&self, path: &Path) -> Option<&IndexEntry> {
+        let mut entry = self.get_entry_by_ino(ROOT_INO)?;
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => entry = self.lookup_child(entry.id, PathBuf::from(name))?,
+                Component::CurDir | Component::RootDir => (),
+                _ => return None,
+            }
         }
+        Some(entry)
     }
 
-    pub fn read(&mut self, entry: &IndexEntry, offset: u64, size: u64) -> Result<Vec<u8>, io::Error> {
-        // TODO Support sparse tar files
-        let part1 = &entry.file_offsets[0];
+    /// Up to len bytes of the content of the file named by entry (a path relative to the root, e.g. "dir/file", or an
+    /// ino) starting at offset, fewer only at the end of the content. Hard links read the content of the file they name.
+    pub fn read_range<'p>(&self, entry: impl Into<EntryRef<'p>>, offset: u64, len: usize) -> Result<Vec<u8>, TarFsError> {
+        let named = entry.into();
+        let entry = match named {
+            EntryRef::Path(path) => self.lookup_path(&archive_path(path)),
+            EntryRef::Ino(ino) => self.get_entry_by_ino(ino),
+        }.ok_or_else(|| TarFsError::IndexError {
+            msg: format!("{}: no such member", named),
+        })?;
+        let content = entry.link_target_ino.and_then(|ino| self.get_entry_by_ino(ino)).unwrap_or(entry);
+        if content.attrs.kind != FileType::RegularFile {
+            return Err(TarFsError::IndexError {
+                msg: format!("{}: not a file", named),
+            });
+        }
+        let mut buf = vec![0; len.min(content.attrs.size.saturating_sub(offset) as usize)];
+        let n = self.read_into(content, offset, &mut buf)?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    pub(crate) fn read(&self, entry: &IndexEntry, offset: u64, size: u64) -> Result<Vec<u8>, TarFsError> {
+        let mut buf = vec![0; size as usize];
+        let n = self.read_into(entry, offset, &mut buf)?;
+        buf.truncate(n);
+        Ok(buf)
+    }
 
-        let offset_in_file = part1.raw_file_offset + (offset as u64);
-        let file_end = part1.raw_file_offset + part1.filesize;
-        let left = file_end - offset_in_file;
-        trace!("offset {}, size {}, off_f {}, file_end {}, left {}", offset, size, offset_in_file, file_end, left);
+    /// Fills buf with the content of entry starting at offset, without any intermediate copies.
+    /// Returns how many bytes there were, fewer than fit into buf only at the end of the content (none beyond it), like read(2).
+    pub(crate) fn read_into(&self, entry: &IndexEntry, offset: u64, buf: &mut [u8]) -> Result<usize, TarFsError> {
+        self.read_content(entry, offset, buf).map_err(|source| TarFsError::ReadError {
+            path: entry.path.clone(),
+            offset: entry.file_offsets.first().map_or(0, |part| part.raw_file_offset + if entry.compressed { 0 } else { offset }),
+            source,
+        })
+    }
 
-        self.file.seek(SeekFrom::Start(offset_in_file))?;
+    fn read_content(&self, entry: &IndexEntry, offset: u64, buf: &mut [u8]) -> Result<usize, io::Error> {
+        if entry.compressed {
+            let n = entry.attrs.size.saturating_sub(offset).min(buf.len() as u64) as usize;
+            return self.decoders.read(&self.source, entry, offset, &mut buf[..n]);
+        }
+        // TODO Support sparse tar files
+        let part1 = match entry.file_offsets.first() {
+            Some(part1) => part1,
+            None => return Ok(0),
+        };
 
-        if left < size {
-            let mut buf = vec![0; left as usize];
-            self.file.read_exact(&mut buf)?;
-            buf.append(&mut vec![0; (size - left) as usize]);
-            Ok(buf)
-        } else {
-            let mut buf = vec![0; size as usize];
-            self.file.read_exact(&mut buf)?;
-            Ok(buf)
+        let left = part1.filesize.saturating_sub(offset).min(buf.len() as u64);
+        if left == 0 {
+            return Ok(0);
         }
+        let offset_in_file = part1.raw_file_offset + offset;
+        trace!("offset {}, size {}, off_f {}, left {}", offset, buf.len(), offset_in_file, left);
+
+        self.source.read_exact_at(&mut buf[..left as usize], offset_in_file)?;
+        Ok(left as usize)
     }
 
-    pub fn insert(&mut self, new_entry: IndexEntry) {
-        let (arena_index, new_entry) = self.arena.insert(new_entry, |e| ino_to_arena_index(e.id));
+    pub(crate) fn insert(&mut self, mut new_entry: IndexEntry) {
+        // Replace an existing entry in place, keeping the names of its children
+        let arena_index = match self.ino_map.get(&new_entry.id) {
+            Some(arena_index) => {
+                let replaced = self.arena.get_mut(*arena_index).map_or(0, |entry| {
+                    new_entry.child_map = mem::take(&mut entry.child_map);
+                    entry.memory_usage()
+                });
+                self.memory_usage = self.memory_usage.saturating_sub(replaced);
+                *arena_index
+            },
+            None => {
+                new_entry.child_map = self.orphans.remove(&new_entry.id).unwrap_or_default();
+                self.arena.next_index()
+            },
+        };
         let ino = new_entry.id;
-        if let Some(parent_id) = new_entry.parent_ino {
-            let path = new_entry.path.as_path();
-            let filename = match path.file_name() {
-                Some(n) => n,
+        let name_in_parent = match new_entry.parent_ino {
+            Some(parent_id) => match new_entry.path.file_name() {
+                Some(filename) => Some((parent_id, filename.to_os_string())),
                 None => {
-                    error!("Unable to get file name from: {}", path.display());
+                    error!("Unable to get file name from: {}", new_entry.path.display());
                     return
                 }
+            },
+            None => None,
+        };
+        self.memory_usage += new_entry.memory_usage();
+        let (arena_index, _) = self.arena.insert(new_entry, |_| arena_index);
+        self.ino_map.insert(ino, arena_index);
+        if let Some((parent_id, filename)) = name_in_parent {
+            match self.get_entry_mut_by_ino(parent_id) {
+                Some(parent) => parent.child_map.insert(filename, ino),
+                None => self.orphans.entry(parent_id).or_default().insert(filename, ino),
             };
-            let key = lookup_key(parent_id, filename);
-            self.child_map.insert(key, ino);
         }
-        self.ino_map.insert(ino, arena_index);
     }
 
-    pub fn children_iter<'e>(&'e self, entry: &'e IndexEntry) -> ChildrenIterator<'e, IndexEntry> {
-        ChildrenIterator::new(&self.arena, &entry.children)
+    /// Removes everything below the directory ino, e.g. when it's replaced by a file
+    pub(crate) fn remove_descendants(&mut self, ino: u64) {
+        let mut pending: Vec<u64> = match self.get_entry_mut_by_ino(ino) {
+            Some(entry) => mem::take(&mut entry.child_map).into_values().collect(),
+            None => return,
+        };
+        while let Some(ino) = pending.pop() {
+            let arena_index = match self.ino_map.remove(&ino) {
+                Some(arena_index) => arena_index,
+                None => continue,
+            };
+            if let Some(entry) = self.arena.get_mut(arena_index).map(mem::take) {
+                self.memory_usage = self.memory_usage.saturating_sub(entry.memory_usage());
+                pending.extend(entry.child_map.into_values());
+            }
+        }
     }
-}
 
-fn lookup_key(id: u64, filename: &OsStr) -> PathBuf {
-    let mut key = PathBuf::new();
-    key.push(Path::new(&format!("{}/", id)));
-    key.push(filename);
-    key
-}
+    fn get_entry_mut_by_ino(&mut self, ino: u64) -> Option<&mut IndexEntry> {
+        let arena_index = *self.ino_map.get(&ino)?;
+        self.arena.get_mut(arena_index)
+    }
+
+    /// Replaces all entries, e.g. renumbered ones
+    pub(crate) fn replace_entries(&mut self, entries: Vec<IndexEntry>) {
+        self.arena = Arena::with_capacity(entries.len());
+        self.orphans.clear();
+        self.ino_map.clear();
+        self.memory_usage = 0;
+        for entry in entries {
+            self.insert(entry);
+        }
+    }
+
+    /// Gives the entry with id another name in the same directory
+    pub(crate) fn rename(&mut self, id: u64, name: &Path) {
+        let mut entry = match self.get_entry_by_ino(id) {
+            Some(entry) => entry.clone(),
+            None => return,
+        };
+        if let (Some(parent_id), Some(name)) = (entry.parent_ino, entry.path.file_name()) {
+            if let Some(parent) = self.get_entry_mut_by_ino(parent_id) {
+                parent.child_map.remove(name);
+            }
+        }
+        entry.path.set_file_name(name);
+        entry.name = name.to_path_buf();
+        self.insert(entry);
+    }
+
+    pub(crate) fn children_iter<'e>(&'e self, entry: &'e IndexEntry) -> ChildrenIterator<'e, IndexEntry> {
+        ChildrenIterator::new(&self.arena, &self.ino_map, &entry.children)
+    }
 
-fn ino_to_arena_index(ino: u64) -> usize {
-    (ino - 1) as usize      // Compensate the fact that inos start with 1
+    /// All entries but the root, depth-first, with readers of the files' content
+    pub fn walk(&self) -> Walk<'_> {
+        Walk::new(self)
+    }
+
+    /// Iterates all entries in ino order
+    pub(crate) fn entries(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.ino_map.values().filter_map(move |arena_index| self.arena.get(*arena_index))
+    }
 }
 
-impl fmt::Display for TarIndex<'_> {
+impl fmt::Display for TarIndex {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut content = String::new();
         for (_, node) in self.ino_map.iter() {
@@ -1,25 +1,42 @@
-use std::fs::File;
 use std::io;
-use std::path::PathBuf;
-use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::collections::{BTreeMap, HashSet};
 use std::cell::{RefCell};
 use std::rc::Rc;
+use std::sync::Arc;
+use std::mem;
 use std::vec::Vec;
-use std::time::{SystemTime, UNIX_EPOCH, Instant};
+use std::time::{Duration, SystemTime, Instant, UNIX_EPOCH};
 use std::collections::HashMap;
-
-use time::Timespec;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
 use tar::EntryType;
-use fuse::FileType;
+use fuser::FileType;
 
-use failure::Error;
+use super::TarFsError;
 use super::TarFsError::IndexError;
 
-use log;
-use log::{info};
+use log::{debug, info};
+
+use crate::tarindex::{TarIndex, IndexEntry, TarEntryPointer, RawHeader, MAP_OVERHEAD, ROOT_INO};
+use crate::utils::{default_fuse_file_attr, system_time_from_unix};
+use crate::archivesource::{ArchiveSource, SourceReader};
+use crate::cpio::{self, CpioEntry};
+use crate::ar::{self, ArMember};
+use crate::dedup;
+use crate::decompress;
+use crate::symlinks;
+use crate::owners;
+use crate::userns::UsernsMaps;
+use crate::problems;
+use crate::normalize::Normalization;
+use crate::ignore::IgnoreRules;
+use crate::deterministic;
 
-use crate::tarindex::{TarIndex, IndexEntry, TarEntryPointer};
+const BLOCK_SIZE: u64 = 512;
 
 /// Shorthand type
 type Ptr<T> = Rc<RefCell<T>>;
@@ -27,116 +44,1180 @@ fn ptr<T>(t: T) -> Ptr<T> {
     Rc::new(RefCell::new(t))
 }
 
+/// The largest valid uid/gid, (uid_t)-1 means "no id" to the kernel
+const MAX_ID: u64 = u32::MAX as u64 - 1;
+/// What the kernel presents ids as that it can't map (/proc/sys/kernel/overflowuid)
+const OVERFLOW_ID: u64 = 65534;
+
 type PathMap<'e> = BTreeMap<PathBuf, Ptr<IndexEntry>>;
+/// The paths of entries as the archive has them, by their normalized path (see Normalization)
+type Originals = HashMap<PathBuf, PathBuf>;
 
+#[derive(Clone)]
 pub struct Options {
     pub root_permissions: Permissions,
+    pub time_policy: TimePolicy,
+    pub unsupported: UnsupportedPolicy,
+    pub dir_mode: DirMode,
+    pub dir_attrs: DirAttrs,
+    pub root_from_archive: bool,
+    /// Where the root takes its times from, unless from the archive's "./" entry
+    pub root_time: RootTime,
+    pub ino_mode: InoMode,
+    pub decompress_members: bool,
+    pub dedup: Option<DedupMode>,
+    pub rewrite_absolute_symlinks: Option<SymlinkRewrite>,
+    pub follow_symlinks: bool,
+    pub numeric_owner: bool,
+    /// The Unicode normalization form of names
+    pub normalize: Normalization,
+    /// What to do with distinct entries normalized to the same path
+    pub collisions: CollisionPolicy,
+    /// Fail on paths defined again with a different type instead of the last definition winning
+    pub strict: bool,
+    /// Where the index gets mounted, if it does
+    pub mountpoint: Option<PathBuf>,
+    /// Give up indexing rather than letting the index grow beyond this many bytes
+    pub max_memory: Option<u64>,
+    /// Only index these members
+    pub selection: Option<Arc<Selection>>,
+    /// Hide the members these patterns match
+    pub ignore: Option<Arc<IgnoreRules>>,
+    pub limits: Limits,
+    /// Translate owners for the user namespace tarfs runs in
+    pub userns: Option<UsernsMaps>,
+    /// Present the archive the same way on every machine (see deterministic)
+    pub deterministic: bool,
+    /// The index may get extended by extend_index_for later on, which compares the headers indexed before
+    pub extendable: bool,
 }
 
+impl Options {
+    /// The time of entries the archive has none for (the root, directories missing in it): when the archive was modified,
+    /// or now. Deterministically, the epoch.
+    fn time_without_archive(&self, modified: Option<SystemTime>) -> SystemTime {
+        if self.deterministic {
+            return self.time_policy.apply(UNIX_EPOCH);
+        }
+        self.time_policy.apply(modified.unwrap_or_else(SystemTime::now))
+    }
+
+    /// The times of the root, given when the archive was modified. RootTime::Newest starts out like RootTime::Now,
+    /// until all entries are known (see set_newest_root_time).
+    fn root_time(&self, modified: Option<SystemTime>) -> SystemTime {
+        match self.root_time {
+            RootTime::Now | RootTime::Newest => self.time_without_archive(None),
+            RootTime::Archive => self.time_without_archive(modified),
+            RootTime::Fixed(time) => time,
+        }
+    }
+}
+
+/// Limits protecting against archives crafted to exhaust resources, None is unlimited
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// Maximum number of directories an entry may be nested in
+    pub max_depth: Option<usize>,
+    /// Maximum number of entries
+    pub max_entries: Option<u64>,
+    /// Maximum content size of a member in bytes
+    pub max_member_size: Option<u64>,
+    /// What to do with entries exceeding a limit
+    pub exceeded: LimitPolicy,
+}
+
+/// What to do with entries exceeding a limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LimitPolicy {
+    /// Fail indexing
+    #[default]
+    Error,
+    /// Leave them out, logging a warning
+    Skip,
+}
+
+impl FromStr for LimitPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LimitPolicy, String> {
+        match s {
+            "error" => Ok(LimitPolicy::Error),
+            "skip" => Ok(LimitPolicy::Skip),
+            _ => Err(format!("unknown policy for entries exceeding limits: {}", s)),
+        }
+    }
+}
+
+impl Limits {
+    /// Whether the entry at path (e.g. "./dir/file") with size bytes of content is within the depth and size limits
+    fn admits(&self, path: &Path, size: u64) -> Result<bool, TarFsError> {
+        if let Some(max) = self.max_depth {
+            let depth = path.components().filter(|component| matches!(component, Component::Normal(_))).count();
+            if depth > max {
+                return self.exceeded(format!("{} is nested deeper than {} levels (see --max-depth)", path.display(), max));
+            }
+        }
+        if let Some(max) = self.max_member_size {
+            if size > max {
+                return self.exceeded(format!("{} is larger than {} MiB (see --max-member-size)", path.display(), max / 1024 / 1024));
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether another entry is within the limit, with count entries indexed already
+    fn admits_another(&self, count: u64) -> Result<bool, TarFsError> {
+        match self.max_entries {
+            Some(max) if count >= max => match self.exceeded {
+                LimitPolicy::Error => Err(IndexError {
+                    msg: format!("the archive has more than {} entries (see --max-entries)", max),
+                }),
+                LimitPolicy::Skip => {
+                    // Once, the count stays at the limit
+                    if count == max {
+                        problems::report(format!("Skipping all entries beyond the first {} (see --max-entries)", max));
+                    }
+                    Ok(false)
+                },
+            },
+            _ => Ok(true),
+        }
+    }
+
+    fn exceeded(&self, msg: String) -> Result<bool, TarFsError> {
+        match self.exceeded {
+            LimitPolicy::Error => Err(IndexError { msg }),
+            LimitPolicy::Skip => {
+                problems::report(format!("Skipping {}", msg));
+                Ok(false)
+            },
+        }
+    }
+}
+
+/// The members named in a list (like tar's --files-from): Entries at the listed paths, everything below them and their parents
+#[derive(Debug)]
+pub struct Selection {
+    paths: HashSet<PathBuf>,
+    ancestors: HashSet<PathBuf>,
+}
+
+impl Selection {
+    pub fn new(paths: &[PathBuf]) -> Selection {
+        let paths: HashSet<PathBuf> = paths.iter().map(|path| archive_path(path)).collect();
+        let ancestors = paths.iter().flat_map(|path| path.ancestors().skip(1)).map(Path::to_path_buf).collect();
+        Selection { paths, ancestors }
+    }
+
+    /// Whether the entry at path (e.g. "./dir/file") is selected
+    pub fn selects(&self, path: &Path) -> bool {
+        self.ancestors.contains(path) || path.ancestors().any(|ancestor| self.paths.contains(ancestor))
+    }
+}
+
+#[derive(Clone)]
 pub struct Permissions {
     pub mode: u32,
     pub uid: u64,
     pub gid: u64,
 }
 
+/// Rewrites entry times while indexing, e.g. for reproducible builds or archives with absurd timestamps
+#[derive(Clone, Debug, Default)]
+pub struct TimePolicy {
+    /// Use this time for everything
+    pub epoch: Option<SystemTime>,
+    /// Shift all times by this many seconds
+    pub offset: i64,
+    /// Times after this one are set to it (applied after offset)
+    pub clamp: Option<SystemTime>,
+}
+
+impl TimePolicy {
+    pub fn apply(&self, time: SystemTime) -> SystemTime {
+        if let Some(epoch) = self.epoch {
+            return epoch;
+        }
+        let shifted = if self.offset >= 0 {
+            time.checked_add(Duration::from_secs(self.offset as u64))
+        } else {
+            time.checked_sub(Duration::from_secs(self.offset.unsigned_abs()))
+        };
+        // Beyond what's representable, times stay as they are
+        let time = shifted.unwrap_or(time);
+        match self.clamp {
+            Some(clamp) if time > clamp => clamp,
+            _ => time,
+        }
+    }
+}
+
+/// What to do with entries of types tarfs can't present (devices, fifos, sparse files, ...)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum UnsupportedPolicy {
+    /// Leave them out
+    Skip,
+    /// Leave them out, logging a warning
+    Warn,
+    /// Fail indexing
+    Error,
+    /// Present them as regular file with the entry's content, logging a warning
+    #[default]
+    AsFile,
+}
+
+impl FromStr for UnsupportedPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<UnsupportedPolicy, String> {
+        match s {
+            "skip" => Ok(UnsupportedPolicy::Skip),
+            "warn" => Ok(UnsupportedPolicy::Warn),
+            "error" => Ok(UnsupportedPolicy::Error),
+            "as-file" => Ok(UnsupportedPolicy::AsFile),
+            _ => Err(format!("unknown policy for unsupported entries: {}", s)),
+        }
+    }
+}
+
+/// What to do with distinct entries whose paths become the same once normalized (see Normalization), e.g. "é" stored
+/// both composed and decomposed. Directories colliding with directories are merged in any case.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    /// The latter replaces the former, like a path defined again, reporting it
+    #[default]
+    Replace,
+    /// Present the latter with a suffix ("name~1", "name~2", ...), reporting it
+    Rename,
+    /// Fail indexing
+    Error,
+}
+
+impl FromStr for CollisionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CollisionPolicy, String> {
+        match s {
+            "replace" => Ok(CollisionPolicy::Replace),
+            "rename" => Ok(CollisionPolicy::Rename),
+            "error" => Ok(CollisionPolicy::Error),
+            _ => Err(format!("unknown policy for colliding names: {}", s)),
+        }
+    }
+}
+
+/// The permissions of directories which are not part of the archive, but parents of entries
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DirMode {
+    Fixed(u32),
+    /// Use the ones of the closest ancestor
+    Inherit,
+}
+
+impl Default for DirMode {
+    fn default() -> DirMode {
+        DirMode::Fixed(0o755)
+    }
+}
+
+/// Where directories which are not part of the archive, but parents of entries, take their owner and times from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DirAttrs {
+    /// The owner of the closest ancestor and the archive's mtime
+    #[default]
+    Parent,
+    /// The owner of the first child in the archive and the newest mtime of all children, as if the directory was created with them
+    Children,
+}
+
+impl FromStr for DirAttrs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DirAttrs, String> {
+        match s {
+            "parent" => Ok(DirAttrs::Parent),
+            "children" => Ok(DirAttrs::Children),
+            _ => Err(format!("invalid directory attributes source: {} (expected parent or children)", s)),
+        }
+    }
+}
+
+/// Where the root directory takes its times from (unless from the archive's "./" entry, see root_from_archive)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RootTime {
+    /// When indexing
+    #[default]
+    Now,
+    /// The newest mtime of all entries
+    Newest,
+    /// The modification time of the archive
+    Archive,
+    /// As given, not rewritten by the time policy
+    Fixed(SystemTime),
+}
+
+impl FromStr for RootTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RootTime, String> {
+        match s {
+            "now" => Ok(RootTime::Now),
+            "newest" => Ok(RootTime::Newest),
+            "archive" => Ok(RootTime::Archive),
+            _ => Err(format!("invalid root time source: {} (expected now, newest, archive or a date)", s)),
+        }
+    }
+}
+
+impl FromStr for DirMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DirMode, String> {
+        match s {
+            "inherit" => Ok(DirMode::Inherit),
+            mode => match u32::from_str_radix(mode, 8) {
+                Ok(mode) if mode <= 0o7777 => Ok(DirMode::Fixed(mode)),
+                _ => Err(format!("invalid directory mode: {}", s)),
+            },
+        }
+    }
+}
+
+/// How entries get their inode numbers
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum InoMode {
+    /// Numbered in indexing order
+    #[default]
+    Sequential,
+    /// Derived from the offset of the entry's header, so they are the same for every mount of the same archive.
+    /// Directories missing in the archive get one derived from their path.
+    Offset,
+}
+
+impl FromStr for InoMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<InoMode, String> {
+        match s {
+            "sequential" => Ok(InoMode::Sequential),
+            "offset" => Ok(InoMode::Offset),
+            _ => Err(format!("unknown ino mode: {}", s)),
+        }
+    }
+}
+
+/// What files with identical content share
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DedupMode {
+    /// They are read from the same place in the archive (e.g. sharing chunk cache entries)
+    Content,
+    /// On top, they share the inode of the first of them like hard links (and with it its attrs and the page cache)
+    Inode,
+}
+
+impl FromStr for DedupMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DedupMode, String> {
+        match s {
+            "content" => Ok(DedupMode::Content),
+            "inode" => Ok(DedupMode::Inode),
+            _ => Err(format!("unknown dedup mode: {}", s)),
+        }
+    }
+}
+
+/// How absolute symlink targets (which would escape the mount) are rewritten
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymlinkRewrite {
+    /// Relative to the symlink, so they point to the same path inside the mount: /lib/x linked from /usr/lib becomes ../../lib/x
+    Relative,
+    /// Prefixed with the mountpoint: /lib/x becomes /mnt/lib/x
+    Mountpoint,
+}
+
+impl FromStr for SymlinkRewrite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SymlinkRewrite, String> {
+        match s {
+            "relative" => Ok(SymlinkRewrite::Relative),
+            "mountpoint" => Ok(SymlinkRewrite::Mountpoint),
+            _ => Err(format!("unknown way to rewrite symlinks: {}", s)),
+        }
+    }
+}
+
+/// Inos of entries without a header of their own (InoMode::Offset) have this bit set, so they never clash with header derived ones
+pub(crate) const PATH_INO_BIT: u64 = 1 << 63;
+
+/// Hands out inos while building an index
+pub(crate) struct Inos {
+    mode: InoMode,
+    /// Headers start at multiples of it
+    alignment: u64,
+    next: u64,
+    path_inos: HashSet<u64>,
+}
+
+impl Inos {
+    pub(crate) fn new(mode: InoMode, alignment: u64) -> Inos {
+        Inos {
+            mode,
+            alignment,
+            next: ROOT_INO + 1,
+            path_inos: HashSet::new(),
+        }
+    }
+
+    /// For an entry with its header at header_offset
+    fn for_header(&mut self, header_offset: u64) -> u64 {
+        match self.mode {
+            InoMode::Sequential => self.next(),
+            InoMode::Offset => header_ino(header_offset, self.alignment),
+        }
+    }
+
+    /// For an entry without a header (yet), e.g. a synthesized directory
+    pub(crate) fn for_path(&mut self, path: &Path) -> u64 {
+        match self.mode {
+            InoMode::Sequential => self.next(),
+            InoMode::Offset => {
+                let path_inos = &mut self.path_inos;
+                path_ino(path, |ino| !path_inos.insert(ino))
+            },
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        let ino = self.next;
+        self.next += 1;
+        ino
+    }
+}
+
+/// Sets nlink like a local filesystem would: 2 + the number of subdirectories for directories,
+/// the number of names for files (all of which present the same attrs, the ones of the hard link target).
+/// Returns the entries that changed.
+pub(crate) fn update_link_counts(index: &mut TarIndex) -> Vec<IndexEntry> {
+    let mut links: HashMap<u64, u64> = HashMap::new();
+    for entry in index.entries() {
+        if let Some(target) = entry.link_target_ino {
+            *links.entry(target).or_default() += 1;
+        }
+    }
+
+    let mut changed = vec!();
+    for entry in index.entries() {
+        let mut attrs = entry.attrs;
+        let link_count = links.get(&entry.id).copied().unwrap_or(0);
+        match entry.link_target_ino {
+            Some(target) => if let Some(target) = index.get_entry_by_ino(target) {
+                attrs = target.attrs;
+                attrs.nlink = 1 + links.get(&target.id).copied().unwrap_or(0) as u32;
+            },
+            None if attrs.kind == FileType::Directory => {
+                let subdirs = index.children_iter(entry).filter(|child| child.attrs.kind == FileType::Directory).count();
+                attrs.nlink = 2 + subdirs as u32;
+            },
+            None => attrs.nlink = 1 + link_count as u32,
+        }
+        if attrs != entry.attrs || link_count != entry.link_count {
+            let mut entry = entry.clone();
+            entry.attrs = attrs;
+            entry.link_count = link_count;
+            changed.push(entry);
+        }
+    }
+    for entry in &changed {
+        index.insert(entry.clone());
+    }
+    changed
+}
+
+fn header_ino(header_offset: u64, alignment: u64) -> u64 {
+    // Skip 0 (invalid) and ROOT_INO
+    header_offset / alignment + 2
+}
+
+/// Hashes path (FNV-1a, stable across versions and platforms) into the PATH_INO_BIT range, probing past inos already taken
+pub(crate) fn path_ino<F: FnMut(u64) -> bool>(path: &Path, mut is_taken: F) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in path.as_os_str().as_encoded_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    let mut ino = hash | PATH_INO_BIT;
+    while is_taken(ino) {
+        ino = ino.wrapping_add(1) | PATH_INO_BIT;
+    }
+    ino
+}
+
 pub struct TarIndexer {}
 
+/// What entries describing the archive or the next entry rather than being one (besides PAX headers) hold
+#[derive(Default)]
+struct Metadata {
+    /// From a GNU volume label ('V')
+    volume_label: Option<String>,
+    /// From a Solaris extended header ('X'), the PAX records of the next entry
+    next_exts: Option<HashMap<String, String>>,
+}
+
+/// Complete entries found behind the indexed part of a growing archive
+pub struct Appended {
+    entries: Vec<TarEntry>,
+    end_offset: u64,
+    pax_globals: HashMap<String, String>,
+    raw_headers: Vec<RawHeader>,
+}
+
+impl Appended {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
 impl TarIndexer {
-    pub fn build_index_for<'f>(&self, file: &'f File, options: &Options) -> Result<TarIndex<'f>, Error> {
+    pub fn build_index_for(&self, source: Box<dyn ArchiveSource>, options: &Options) -> Result<TarIndex, TarFsError> {
         let now = Instant::now();
         info!("Starting indexing archive...");
 
-        let mut archive: tar::Archive<&File> = tar::Archive::new(file);
+        let mut stream = source.stream()?;
+        let (format, first_block) = check_format(&mut stream)?;
+        let format_name = format.detailed_name(&first_block);
+        let stream = io::Cursor::new(first_block).chain(stream);
 
-        // Use sequential ino numbers
-        let mut inode_id = 1;
-        let get = |id: &mut u64| -> u64 {
-            let res = *id;
-            *id += 1;
-            res
+        let mut inos = match format {
+            Format::Tar => Inos::new(options.ino_mode, BLOCK_SIZE),
+            Format::Cpio => Inos::new(options.ino_mode, cpio::ALIGNMENT),
+            Format::Ar => Inos::new(options.ino_mode, ar::ALIGNMENT),
         };
 
         // Start with root_entry
         let mut path_map: PathMap = BTreeMap::new();
-        let root_entry = self.create_root_entry(get(&mut inode_id), &options.root_permissions);
+        let root_entry = self.create_root_entry(ROOT_INO, source.modified(), options);
         let root_path = root_entry.path.to_owned();
-        path_map.insert(root_path, ptr(root_entry));
-
-        // Iterate tar entries
-        for (idx, entry) in archive.entries()?.enumerate() {
-            let tar_entry = self.entry_to_tar_entry(idx as u64, &mut entry?)?;
-            //println!("{:?}", &tar_entry);
-
-            // Find parent!
-            let parent_path = tar_entry.path.parent().expect("a tar entry without parent component!");
-            let (parent_ino, parent) = self.get_or_create_path_entry(&mut path_map, &PathBuf::from(parent_path), || get(&mut inode_id));
-
-            // Entry already present?
-            let (ino, index_entry) = self.get_or_create_path_entry(&mut path_map, &tar_entry.path, || get(&mut inode_id));
-
-            // Create IndexEntry
-            let is_hard_link = tar_entry.is_hard_link();
-            tar_entry.set_to_index_entry(&mut index_entry.borrow_mut(), ino, Some(parent_ino));
+        let root_attrs = root_entry.attrs;
+        path_map.insert(root_path.clone(), ptr(root_entry));
 
-            // Add itself to parents children
-            parent.borrow_mut().children.push(index_entry.borrow().id);
-
-            // Hard link? Bump nlink count for link_name
-            if is_hard_link {
-                let target_attrs = {
-                    let index_entry_ref = &index_entry.borrow();
-                    let link_name = &index_entry_ref.link_name;
-                    if link_name.is_none() {
-                        let err_msg = format!("Found link without link_name {}, quitting!", index_entry_ref.path.display());
-                        return Err(IndexError { msg: err_msg }.into());
+        let mut end_offset = 0;
+        let mut pax_globals = HashMap::new();
+        let mut metadata = Metadata::default();
+        let mut raw_headers = vec!();
+        let mut synthesized = vec!();
+        let mut originals = Originals::new();
+        let mut budget = Budget::default();
+        match format {
+            Format::Tar => {
+                // Iterate tar entries
+                let mut archive = tar::Archive::new(stream);
+                for (idx, entry) in archive.entries()?.enumerate() {
+                    let mut entry = entry.map_err(|e| entry_error(idx, end_offset, None, e))?;
+                    raw_headers.push(raw_header(&entry, end_offset));
+                    let path = entry.path().ok().map(|path| path.into_owned());
+                    if is_global_header(&entry) {
+                        self.apply_global_header(&mut entry, &mut pax_globals)
+                            .map_err(|e| entry_error(idx, end_offset, path, e))?;
+                        end_offset = entry_end(&entry);
+                        continue;
                     }
-                    let (_, link_target) = self.get_or_create_path_entry(&mut path_map, &link_name.as_ref().unwrap(), || get(&mut inode_id));
-                    let mut link_target_mut = link_target.borrow_mut();
-                    link_target_mut.link_count += 1;
-                    link_target_mut.attrs.nlink += 1;
-                    link_target_mut.attrs.clone()
-                };
-                let mut index_entry_mut = index_entry.borrow_mut();
-                index_entry_mut.link_target_ino = Some(target_attrs.ino);
-                index_entry_mut.attrs = target_attrs;
+                    if self.consume_metadata(&mut entry, &mut metadata).map_err(|e| entry_error(idx, end_offset, path.clone(), e))? {
+                        end_offset = entry_end(&entry);
+                        continue;
+                    }
+                    let tar_entry = self.entry_to_tar_entry(idx as u64, &mut entry, &pax_globals, metadata.next_exts.take(), options)
+                        .map_err(|e| entry_error(idx, end_offset, path, e))?;
+                    end_offset = entry_end(&entry);
+                    let location = EntryLocation::of(&tar_entry);
+                    if self.is_included(&tar_entry, options).map_err(|e| location.locate(e))? && budget.charge(&tar_entry, options).map_err(|e| location.locate(e))? {
+                        self.add_entry(&mut path_map, &mut synthesized, &mut originals, &mut inos, tar_entry, options).map_err(|e| location.locate(e))?;
+                    }
+                }
+            },
+            Format::Cpio => {
+                let (entries, cpio_end) = self.cpio_entries(stream, options)?;
+                end_offset = cpio_end;
+                for tar_entry in entries {
+                    let location = EntryLocation::of(&tar_entry);
+                    if self.is_included(&tar_entry, options).map_err(|e| location.locate(e))? && budget.charge(&tar_entry, options).map_err(|e| location.locate(e))? {
+                        self.add_entry(&mut path_map, &mut synthesized, &mut originals, &mut inos, tar_entry, options).map_err(|e| location.locate(e))?;
+                    }
+                }
+            },
+            Format::Ar => {
+                let mut entries = vec!();
+                end_offset = ar::read_members(stream, |member, content| {
+                    entries.push(ar_to_tar_entry(entries.len(), member, options));
+                    if let Some(dir) = expanded_dir(member) {
+                        match self.member_tar_entries(content, member, &dir, entries.len(), options) {
+                            Ok(mut tar_entries) => entries.append(&mut tar_entries),
+                            Err(e) => problems::report(format!("Not expanding {}: {}", member.name.display(), e)),
+                        }
+                    }
+                    Ok(())
+                }).map_err(|e| IndexError {
+                    msg: format!("unable to read ar archive: {}", e),
+                })?;
+                for tar_entry in entries {
+                    let location = EntryLocation::of(&tar_entry);
+                    if self.is_included(&tar_entry, options).map_err(|e| location.locate(e))? && budget.charge(&tar_entry, options).map_err(|e| location.locate(e))? {
+                        self.add_entry(&mut path_map, &mut synthesized, &mut originals, &mut inos, tar_entry, options).map_err(|e| location.locate(e))?;
+                    }
+                }
+            },
+        }
+
+        // Directories that never showed up in the archive: Make them look like their parent.
+        // Parents got synthesized before their children, so their attrs are final already.
+        let archive_mtime = options.time_without_archive(source.modified());
+        // Not synthesized after all, or gone with a directory replaced by a file
+        synthesized.retain(|path| path_map.get(path).is_some_and(|entry| entry.borrow().file_offsets.is_empty()));
+        for path in &synthesized {
+            let entry = &path_map[path];
+            let parent_attrs = path_map[path.parent().unwrap()].borrow().attrs;
+            let id = entry.borrow().id;
+            entry.borrow_mut().attrs = self.synthesized_dir_attrs(id, &parent_attrs, archive_mtime, options);
+        }
+        // Children first this time, so synthesized ones are final by the time their parent looks at them
+        if options.dir_attrs == DirAttrs::Children {
+            let by_id: HashMap<u64, Ptr<IndexEntry>> = path_map.values().map(|entry| (entry.borrow().id, entry.clone())).collect();
+            for path in synthesized.iter().rev() {
+                let entry = &path_map[path];
+                let children: Vec<fuser::FileAttr> = entry.borrow().children.iter().map(|id| by_id[id].borrow().attrs).collect();
+                inherit_from_children(&mut entry.borrow_mut().attrs, &children);
             }
         }
 
+        // Unless the archive's "./" entry gave the root its attrs
+        let root_from_entry = path_map[&root_path].borrow().attrs != root_attrs;
+
         // Actually insert entries into index
-        let mut index = TarIndex::new(file, path_map.len());
+        let mut index = TarIndex::new(source, path_map.len());
+        index.set_normalization(options.normalize);
+        index.set_originals(originals);
+        index.set_end_offset(end_offset);
+        index.set_pax_globals(pax_globals);
+        index.set_volume_label(metadata.volume_label);
+        index.set_format(format_name);
+        index.add_raw_headers(raw_headers);
 
         // In order to get the IndexEntry out of Rc<RefCell<>> we have to:
         //  - get ownership of the Rc
         //  - to do so we have to remove() it from path_map
         //  - to do so for all entries we need a list of copies of all keys
-        let keys: Vec<PathBuf> = path_map.iter()
-            .map(|(k, _)| PathBuf::from(k))
+        let keys: Vec<PathBuf> = path_map.keys()
+            .map(PathBuf::from)
             .collect();
         for k in keys {
             let index_entry_rc = path_map.remove(&k).unwrap();  // Impossible to have an entry without value here
             let id = index_entry_rc.borrow().id;
             let index_entry_res = Rc::try_unwrap(index_entry_rc);
-            if let Err(_) = index_entry_res {
+            if index_entry_res.is_err() {
                 return Err(IndexError {
                     msg: format!("Unexpected multiple link to index_entry {}, quitting!", id)
-                }.into());
+                });
             }
             let index_entry_refc = index_entry_res.unwrap();
             index.insert(index_entry_refc.into_inner());
         }
+        if options.root_time == RootTime::Newest && !root_from_entry {
+            set_newest_root_time(&mut index);
+        }
+
+        if options.decompress_members {
+            decompress::decompress_members(&mut index);
+        }
+        if let Some(mode) = options.dedup {
+            dedup::dedup(&mut index, mode)?;
+        }
+        if options.follow_symlinks {
+            symlinks::follow_symlinks(&mut index, options.ino_mode);
+        }
+        update_link_counts(&mut index);
+        if options.deterministic {
+            deterministic::renumber(&mut index);
+        }
+        // Copies of followed symlinks add up as well
+        check_memory_usage(&index, options)?;
+        if matches!(format, Format::Tar) && options.extendable {
+            let digests = header_digests(index.source(), index.raw_headers())?;
+            index.add_header_digests(digests);
+        }
+
+        info!("Done indexing archive ({} KiB). Took {}s.", index.memory_usage() / 1024, now.elapsed().as_secs());
+        Ok(index)
+    }
+
+    /// Adds tar_entry to path_map, synthesizing missing parent directories
+    fn add_entry(&self, path_map: &mut PathMap, synthesized: &mut Vec<PathBuf>, originals: &mut Originals, inos: &mut Inos, mut tar_entry: TarEntry, options: &Options) -> Result<(), TarFsError> {
+        let original = tar_entry.path.clone();
+        normalize_names(&mut tar_entry, options);
+        if options.normalize != Normalization::None {
+            resolve_collision(|path| path_map.get(path).map(|entry| entry.borrow().attrs.kind), originals, &mut tar_entry, original, options)?;
+        }
+        translate_owner(&mut tar_entry, options);
+        if tar_entry.is_root() {
+            if options.root_from_archive {
+                let root = &path_map[Path::new(".")];
+                let id = root.borrow().id;
+                root.borrow_mut().attrs = tar_entry.attrs(id);
+            }
+            return Ok(());
+        }
+        //println!("{:?}", &tar_entry);
+        rewrite_absolute_symlink(&mut tar_entry, options);
+
+        // Find parent! Directories missing in the archive (so far) are synthesized
+        let parent_path = tar_entry.path.parent().expect("a tar entry without parent component!");
+        let (parent_ino, parent) = self.get_or_create_dir_entry(path_map, synthesized, parent_path, &mut |path| inos.for_path(path));
+
+        // Entry already present?
+        let (ino, index_entry) = self.get_or_create_path_entry(path_map, &tar_entry.path, || inos.for_header(tar_entry.header_offset));
+        let is_linked = index_entry.borrow().parent_ino.is_some();
+        if is_linked {
+            let replaced_kind = index_entry.borrow().attrs.kind;
+            check_redefinition(&tar_entry, replaced_kind, options)?;
+            if replaced_kind == FileType::Directory && tar_entry.ftype != EntryType::Directory {
+                // Its content goes with it
+                index_entry.borrow_mut().children.clear();
+                let descendants: Vec<PathBuf> = path_map.range(tar_entry.path.clone()..)
+                    .map(|(path, _)| path)
+                    .skip_while(|path| *path == &tar_entry.path)
+                    .take_while(|path| path.starts_with(&tar_entry.path))
+                    .cloned()
+                    .collect();
+                for path in descendants {
+                    path_map.remove(&path);
+                }
+            }
+            // Only the last definition counts, like when extracting
+            let mut index_entry = index_entry.borrow_mut();
+            index_entry.file_offsets.clear();
+            index_entry.link_target_ino = None;
+        }
+
+        // Create IndexEntry
+        let is_hard_link = tar_entry.is_hard_link();
+        tar_entry.set_to_index_entry(&mut index_entry.borrow_mut(), ino, Some(parent_ino));
+
+        // Add itself to parents children
+        if !is_linked {
+            parent.borrow_mut().children.push(index_entry.borrow().id);
+        }
+
+        // Hard link? Bump nlink count for link_name
+        if is_hard_link {
+            let target_attrs = {
+                let index_entry_ref = &index_entry.borrow();
+                let link_name = &index_entry_ref.link_name;
+                if link_name.is_none() {
+                    let err_msg = format!("Found link without link_name {}, quitting!", index_entry_ref.path.display());
+                    return Err(IndexError { msg: err_msg });
+                }
+                let link_name = link_name.as_ref().unwrap();
+                let (_, link_target) = self.get_or_create_path_entry(path_map, link_name, || inos.for_path(link_name));
+                let mut link_target_mut = link_target.borrow_mut();
+                link_target_mut.link_count += 1;
+                link_target_mut.attrs.nlink += 1;
+                link_target_mut.attrs
+            };
+            let mut index_entry_mut = index_entry.borrow_mut();
+            index_entry_mut.link_target_ino = Some(target_attrs.ino);
+            index_entry_mut.attrs = target_attrs;
+        }
+        Ok(())
+    }
+
+    /// Reads the entries of a cpio archive, presented like the corresponding tar entries.
+    /// Returns them together with the offset behind the archive.
+    fn cpio_entries<R: Read>(&self, stream: R, options: &Options) -> Result<(Vec<TarEntry>, u64), TarFsError> {
+        let (entries, end_offset) = cpio::read_entries(stream).map_err(|e| IndexError {
+            msg: format!("unable to read cpio archive: {}", e),
+        })?;
+
+        // Hard links share dev and ino, but only one of them has the content (the last one for GNU cpio): Make it the link target.
+        let mut targets: HashMap<(u64, u64, u64), usize> = HashMap::new();
+        for (idx, entry) in entries.iter().enumerate() {
+            if entry.nlink > 1 && entry.file_type() != libc::S_IFDIR {
+                let target = targets.entry((entry.dev.0, entry.dev.1, entry.ino)).or_insert(idx);
+                if entries[*target].filesize == 0 && entry.filesize > 0 {
+                    *target = idx;
+                }
+            }
+        }
+
+        // The link targets need to be indexed before their links, so they take the place of the first of them
+        let mut result = Vec::with_capacity(entries.len());
+        let mut emitted = HashSet::new();
+        for (idx, entry) in entries.iter().enumerate() {
+            let target = targets.get(&(entry.dev.0, entry.dev.1, entry.ino)).copied()
+                .filter(|_| entry.nlink > 1 && entry.file_type() != libc::S_IFDIR);
+            match target {
+                None => result.push(cpio_to_tar_entry(idx, entry, None, options)),
+                Some(target) => {
+                    if emitted.insert(target) {
+                        result.push(cpio_to_tar_entry(target, &entries[target], None, options));
+                    }
+                    if target != idx {
+                        result.push(cpio_to_tar_entry(idx, entry, Some(archive_path(&entries[target].name)), options));
+                    }
+                },
+            }
+        }
+        Ok((result, end_offset))
+    }
+
+    /// Reads the entries of the tar archive in an ar member, placing them in dir
+    fn member_tar_entries(&self, content: &mut dyn Read, member: &ArMember, dir: &Path, first_index: usize, options: &Options) -> Result<Vec<TarEntry>, TarFsError> {
+        let first_block = match check_format(content)? {
+            (Format::Tar, first_block) => first_block,
+            (format, _) => return Err(TarFsError::UnsupportedFormat {
+                msg: format!("member is a {} archive, not a tar archive", format.name()),
+            }),
+        };
+        let mut archive = tar::Archive::new(io::Cursor::new(first_block).chain(content));
+        let mut pax_globals = HashMap::new();
+        let mut metadata = Metadata::default();
+        let mut entries = vec!();
+        for (idx, entry) in archive.entries()?.enumerate() {
+            let mut entry = entry?;
+            if is_global_header(&entry) {
+                self.apply_global_header(&mut entry, &mut pax_globals)?;
+                continue;
+            }
+            if self.consume_metadata(&mut entry, &mut metadata)? {
+                continue;
+            }
+            let mut tar_entry = self.entry_to_tar_entry((first_index + idx) as u64, &mut entry, &pax_globals, metadata.next_exts.take(), options)?;
+            tar_entry.header_offset += member.data_offset;
+            tar_entry.raw_file_offset += member.data_offset;
+            tar_entry.path = dir.join(tar_entry.path.strip_prefix(".").expect("archive paths start with ."));
+            tar_entry.name = PathBuf::from(tar_entry.path.file_name().expect("entry without name"));
+            if tar_entry.is_hard_link() {
+                tar_entry.link_name = tar_entry.link_name.map(|l| dir.join(l.strip_prefix(".").expect("archive paths start with .")));
+            }
+            entries.push(tar_entry);
+        }
+        Ok(entries)
+    }
+
+    /// Indexes all complete entries of an archive that is still being written.
+    /// Entries written later on can be added with scan_appended and append.
+    pub fn build_growing_index_for(&self, source: Box<dyn ArchiveSource>, options: &Options) -> Result<TarIndex, TarFsError> {
+        let now = Instant::now();
+        info!("Starting indexing growing archive...");
+        match check_format(&mut source.stream()?) {
+            Ok((Format::Tar, _)) | Err(_) => (),
+            Ok((format, _)) => return Err(TarFsError::UnsupportedFormat {
+                msg: format!("following a growing {} archive is not supported", format.name()),
+            }),
+        }
+
+        let modified = source.modified();
+        let mut index = TarIndex::new(source, 1);
+        index.set_normalization(options.normalize);
+        index.insert(self.create_root_entry(ROOT_INO, modified, options));
+        let appended = self.scan_appended(&index, options)?;
+        let root_from_entry = options.root_from_archive && appended.entries.iter().any(TarEntry::is_root);
+        self.append(&mut index, appended, options)?;
+        if options.root_time == RootTime::Newest && !root_from_entry {
+            set_newest_root_time(&mut index);
+        }
 
         info!("Done indexing archive. Took {}s.", now.elapsed().as_secs());
         Ok(index)
     }
 
+    /// Scans the archive for complete entries behind the indexed part.
+    /// An entry which is not completely written yet ends the scan, it's picked up by the next one.
+    pub fn scan_appended(&self, index: &TarIndex, options: &Options) -> Result<Appended, TarFsError> {
+        let start = index.end_offset();
+        let size = index.source().size()?;
+        let mut appended = Appended {
+            entries: vec!(),
+            end_offset: start,
+            pax_globals: index.pax_globals().clone(),
+            raw_headers: vec!(),
+        };
+        if size < start + BLOCK_SIZE {
+            return Ok(appended);
+        }
+
+        let mut archive = tar::Archive::new(SourceReader::new(index.source(), start, size));
+        let mut metadata = Metadata::default();
+        // Counting on from the entries indexed before
+        let first = index.raw_headers().len();
+        for (idx, entry) in archive.entries()?.enumerate() {
+            let idx = first + idx;
+            let mut entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    debug!("Incomplete entry at offset {}: {}", appended.end_offset, e);
+                    break;
+                },
+            };
+            let end = start + entry_end(&entry);
+            if end > size {
+                break;
+            }
+            let header = raw_header(&entry, appended.end_offset - start);
+            appended.raw_headers.push(RawHeader { offset: header.offset + start, ..header });
+            let offset = appended.end_offset;
+            let path = entry.path().ok().map(|path| path.into_owned());
+
+            if is_global_header(&entry) {
+                self.apply_global_header(&mut entry, &mut appended.pax_globals)
+                    .map_err(|e| entry_error(idx, offset, path, e))?;
+                appended.end_offset = end;
+                continue;
+            }
+            // A Solaris extended header is picked up again with its entry if that isn't complete yet
+            if self.consume_metadata(&mut entry, &mut metadata).map_err(|e| entry_error(idx, offset, path.clone(), e))? {
+                if metadata.next_exts.is_none() {
+                    appended.end_offset = end;
+                }
+                continue;
+            }
+            let mut tar_entry = self.entry_to_tar_entry(idx as u64, &mut entry, &appended.pax_globals, metadata.next_exts.take(), options)
+                .map_err(|e| entry_error(idx, offset, path, e))?;
+            tar_entry.header_offset += start;
+            tar_entry.raw_file_offset += start;
+            appended.end_offset = end;
+            if self.is_included(&tar_entry, options).map_err(|e| EntryLocation::of(&tar_entry).locate(e))? {
+                appended.entries.push(tar_entry);
+            }
+        }
+        Ok(appended)
+    }
+
+    /// Adds the scanned entries to the index, later entries replacing earlier ones with the same path.
+    /// Returns all entries that got added or changed.
+    pub fn append(&self, index: &mut TarIndex, appended: Appended, options: &Options) -> Result<Vec<IndexEntry>, TarFsError> {
+        // The entries of one scan are added in any case, so they show up consistently
+        check_memory_usage(index, options)?;
+        let mut changed = vec!();
+        let mut originals = index.take_originals();
+        let added = appended.entries.into_iter()
+            .try_for_each(|tar_entry| {
+                let location = EntryLocation::of(&tar_entry);
+                self.append_entry(index, &mut originals, tar_entry, options, &mut changed).map_err(|e| location.locate(e))
+            });
+        index.set_originals(originals);
+        added?;
+        changed.extend(update_link_counts(index));
+        index.set_end_offset(appended.end_offset);
+        index.set_pax_globals(appended.pax_globals);
+        if options.extendable {
+            index.add_header_digests(header_digests(index.source(), &appended.raw_headers)?);
+        }
+        index.add_raw_headers(appended.raw_headers);
+        Ok(changed)
+    }
+
+    /// Adds an appended entry to the index, adding the entries it changes to changed
+    fn append_entry(&self, index: &mut TarIndex, originals: &mut Originals, mut tar_entry: TarEntry, options: &Options, changed: &mut Vec<IndexEntry>) -> Result<(), TarFsError> {
+        let original = tar_entry.path.clone();
+        normalize_names(&mut tar_entry, options);
+        if options.normalize != Normalization::None {
+            resolve_collision(|path| index.lookup_path(path).map(|entry| entry.attrs.kind), originals, &mut tar_entry, original, options)?;
+        }
+        translate_owner(&mut tar_entry, options);
+        rewrite_absolute_symlink(&mut tar_entry, options);
+        if tar_entry.is_root() {
+            if options.root_from_archive {
+                let mut root = index.get_entry_by_ino(ROOT_INO).expect("the root entry is always present").clone();
+                root.attrs = tar_entry.attrs(root.id);
+                index.insert(root.clone());
+                changed.push(root);
+            }
+            return Ok(());
+        }
+        // The root doesn't count
+        if index.lookup_path(&tar_entry.path).is_none() && !options.limits.admits_another(index.len() as u64 - 1)? {
+            return Ok(());
+        }
+        let parent_path = tar_entry.path.parent().expect("a tar entry without parent component!");
+        let mut parent = self.synthesize_dir(index, parent_path, &tar_entry.attrs(0), options, changed);
+
+        // Entry already present?
+        let existing = index.lookup_path(&tar_entry.path);
+        let is_new = existing.is_none();
+        let mut index_entry = IndexEntry::default();
+        let ino = match existing {
+            Some(existing) => {
+                check_redefinition(&tar_entry, existing.attrs.kind, options)?;
+                let id = existing.id;
+                if tar_entry.ftype == EntryType::Directory {
+                    index_entry.children = existing.children.clone();
+                } else if existing.attrs.kind == FileType::Directory {
+                    // Its content goes with it
+                    index.remove_descendants(id);
+                }
+                id
+            },
+            None => match options.ino_mode {
+                InoMode::Sequential => index.next_ino(),
+                InoMode::Offset => header_ino(tar_entry.header_offset, BLOCK_SIZE),
+            },
+        };
+
+        let is_hard_link = tar_entry.is_hard_link();
+        tar_entry.set_to_index_entry(&mut index_entry, ino, Some(parent.id));
+
+        // Hard link? Bump nlink count for link_name
+        if is_hard_link {
+            let link_name = match &index_entry.link_name {
+                Some(link_name) => link_name,
+                None => {
+                    let err_msg = format!("Found link without link_name {}, quitting!", index_entry.path.display());
+                    return Err(IndexError { msg: err_msg });
+                },
+            };
+            let mut link_target = match index.lookup_path(link_name) {
+                Some(link_target) => link_target.clone(),
+                None => {
+                    problems::report(format!("Skipping {}: link target {} not found", index_entry.path.display(), link_name.display()));
+                    return Ok(());
+                },
+            };
+            link_target.link_count += 1;
+            link_target.attrs.nlink += 1;
+            index_entry.link_target_ino = Some(link_target.attrs.ino);
+            index_entry.attrs = link_target.attrs;
+            index.insert(link_target.clone());
+            changed.push(link_target);
+        }
+
+        // Add itself to parents children
+        if is_new {
+            parent.children.push(ino);
+            index.insert(parent);
+        }
+        index.insert(index_entry.clone());
+        changed.push(index_entry);
+        Ok(())
+    }
+
+    /// Indexes source incrementally if it's an extended version of the archive index was built from (e.g. one appended
+    /// to with tar -r): a copy of index with the entries behind its end added. None if it's not, or the options need
+    /// the whole archive to be indexed at once, so it has to be indexed from scratch.
+    pub fn extend_index_for(&self, index: &TarIndex, source: Box<dyn ArchiveSource>, options: &Options) -> Result<Option<TarIndex>, TarFsError> {
+        if options.decompress_members || options.dedup.is_some() || options.follow_symlinks || options.deterministic {
+            return Ok(None);
+        }
+        if index.header_digests().is_empty() || index.header_digests().len() != index.raw_headers().len() {
+            return Ok(None);
+        }
+        if source.size()? < index.end_offset() {
+            return Ok(None);
+        }
+        // Rewritten archives may look the same in places: all headers have to be unchanged
+        let digests = header_digests(source.as_ref(), index.raw_headers())?;
+        if let Some(i) = digests.iter().zip(index.header_digests()).position(|(digest, expected)| digest != expected) {
+            debug!("The archive differs at offset {}, not extending the index", index.raw_headers()[i].offset);
+            return Ok(None);
+        }
+
+        let now = Instant::now();
+        let mut extended = index.with_source(source);
+        let appended = self.scan_appended(&extended, options)?;
+        let added = appended.entries.len();
+        self.append(&mut extended, appended, options)?;
+        info!("Extended the index by {} entries. Took {}s.", added, now.elapsed().as_secs());
+        Ok(Some(extended))
+    }
+
+    /// Returns the directory entry for path. If it's not part of the path_map yet, it's synthesized (including all missing ancestors)
+    /// and its path added to synthesized.
+    fn get_or_create_dir_entry(&self, path_map: &mut PathMap, synthesized: &mut Vec<PathBuf>, path: &Path, get_id: &mut dyn FnMut(&Path) -> u64) -> (u64, Ptr<IndexEntry>) {
+        if let Some(entry) = path_map.get(path) {
+            let id = entry.borrow().id;
+            return (id, entry.clone());
+        }
+
+        let parent_path = path.parent().expect("the root entry is always present");
+        let (parent_ino, parent) = self.get_or_create_dir_entry(path_map, synthesized, parent_path, get_id);
+        let id = get_id(path);
+        let entry = IndexEntry {
+            id,
+            parent_ino: Some(parent_ino),
+            path: path.to_path_buf(),
+            name: PathBuf::from(path.file_name().expect("entry without name")),
+            ..Default::default()
+        };
+        parent.borrow_mut().children.push(id);
+
+        let entry_ptr = ptr(entry);
+        path_map.insert(path.to_path_buf(), entry_ptr.clone());
+        synthesized.push(path.to_path_buf());
+        (id, entry_ptr)
+    }
+
+    /// Synthesizes the directory at path (and all its missing ancestors) in the index, for an entry with child_attrs
+    fn synthesize_dir(&self, index: &mut TarIndex, path: &Path, child_attrs: &fuser::FileAttr, options: &Options, changed: &mut Vec<IndexEntry>) -> IndexEntry {
+        if let Some(entry) = index.lookup_path(path) {
+            return entry.clone();
+        }
+
+        let parent_path = path.parent().expect("the root entry is always present");
+        let mut parent = self.synthesize_dir(index, parent_path, child_attrs, options, changed);
+        let id = match options.ino_mode {
+            InoMode::Sequential => index.next_ino(),
+            InoMode::Offset => path_ino(path, |ino| index.get_entry_by_ino(ino).is_some()),
+        };
+        let archive_mtime = options.time_without_archive(index.source().modified());
+        let mut attrs = self.synthesized_dir_attrs(id, &parent.attrs, archive_mtime, options);
+        if options.dir_attrs == DirAttrs::Children {
+            inherit_from_children(&mut attrs, std::slice::from_ref(child_attrs));
+        }
+        let entry = IndexEntry {
+            id,
+            parent_ino: Some(parent.id),
+            path: path.to_path_buf(),
+            name: PathBuf::from(path.file_name().expect("entry without name")),
+            attrs,
+            ..Default::default()
+        };
+        parent.children.push(id);
+        index.insert(parent);
+        index.insert(entry.clone());
+        changed.push(entry.clone());
+        entry
+    }
+
+    fn synthesized_dir_attrs(&self, ino: u64, parent_attrs: &fuser::FileAttr, mtime: SystemTime, options: &Options) -> fuser::FileAttr {
+        let perm = match options.dir_mode {
+            DirMode::Fixed(mode) => mode as u16,
+            DirMode::Inherit => parent_attrs.perm,
+        };
+        fuser::FileAttr {
+            ino,
+            size: 4096,     // Like explicit directories
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime: mtime,
+            kind: FileType::Directory,
+            perm,
+            nlink: 2,
+            uid: parent_attrs.uid,
+            gid: parent_attrs.gid,
+            ..default_fuse_file_attr()
+        }
+    }
+
     fn get_or_create_path_entry<IdSource>(&self, path_map: &mut PathMap, path: &PathBuf, mut get_id: IdSource) -> (u64, Ptr<IndexEntry>)
         where
             IdSource: FnMut() -> u64 {
         match path_map.get(path) {
             None => {
                 let id = get_id();
-                let mut entry = IndexEntry::default();
-                entry.id = id;
+                let entry = IndexEntry {
+                    id,
+                    ..Default::default()
+                };
                 let entry_ptr = ptr(entry);
                 path_map.insert(path.to_owned(), entry_ptr.clone());
                 (id, entry_ptr)
@@ -148,10 +1229,10 @@ impl TarIndexer {
         }
     }
 
-    fn create_root_entry(&self, ino: u64, root_permissions: &Permissions) -> IndexEntry {
-        let now = SystemTime::now();
-        let since_epoch = now.duration_since(UNIX_EPOCH).expect("SystemTime error");
-        let now = Timespec::new(since_epoch.as_secs() as i64, since_epoch.subsec_nanos() as i32);
+    /// modified is when the archive was modified
+    pub(crate) fn create_root_entry(&self, ino: u64, modified: Option<SystemTime>, options: &Options) -> IndexEntry {
+        let root_permissions = &options.root_permissions;
+        let now = options.root_time(modified);
 
         let root_tar_entry = TarEntry {
             index: 0,
@@ -174,18 +1255,40 @@ impl TarIndexer {
         root_entry
     }
 
-    fn entry_to_tar_entry(&self, index: u64, entry: &mut tar::Entry<'_, &File>) -> Result<TarEntry, io::Error> {
-        let link_name = entry.link_name()?.map(|l| l.to_path_buf());
-        let exts = self.collect_pax_extensions(entry)?;
+    /// solaris_exts are the records of a Solaris extended header preceding the entry
+    fn entry_to_tar_entry<R: Read>(&self, index: u64, entry: &mut tar::Entry<'_, R>, pax_globals: &HashMap<String, String>, solaris_exts: Option<HashMap<String, String>>, options: &Options) -> Result<TarEntry, io::Error> {
+        let solaris_exts = solaris_exts.unwrap_or_default();
+        let link_name = match solaris_exts.get("linkpath") {
+            Some(link_name) => Some(PathBuf::from(link_name)),
+            None => entry.link_name()?.map(|l| l.to_path_buf()),
+        };
+        let link_name = match entry.header().entry_type() {
+            // Hard links point to another entry of the archive
+            EntryType::Link => link_name.map(|l| archive_path(&l)),
+            _ => link_name,
+        };
+        // Entry specific extensions override the global ones
+        let mut exts = pax_globals.clone();
+        exts.extend(solaris_exts.clone());
+        exts.extend(self.collect_pax_extensions(entry)?);
         let header = entry.header();
 
-        let hdr_mtime = Timespec::new(header.mtime()? as i64, 0);
-        let mtime = self.get_timespec_for(&exts, "mtime", &hdr_mtime);
-        let atime = self.get_timespec_for(&exts, "atime", &mtime);
-        let ctime = self.get_timespec_for(&exts, "ctime", &mtime);
+        let hdr_mtime = system_time_from_unix(header.mtime()? as i64, 0);
+        let mtime = self.get_time_for(&exts, "mtime", &hdr_mtime);
+        let atime = self.get_time_for(&exts, "atime", &mtime);
+        let ctime = self.get_time_for(&exts, "ctime", &mtime);
 
-        let path = PathBuf::from(entry.path()?);
-        let name = PathBuf::from(path.as_path().file_name().expect("entry without name"));
+        let (path, trailing_slash) = match solaris_exts.get("path") {
+            Some(path) => (archive_path(Path::new(path)), path.ends_with('/')),
+            None => (archive_path(&entry.path()?), entry.path_bytes().ends_with(b"/")),
+        };
+        // The root entry "./" has no name of its own
+        let name = PathBuf::from(path.file_name().unwrap_or(path.as_os_str()));
+        // Pre-POSIX (V7) archives mark directories only by a trailing slash
+        let ftype = match header.entry_type() {
+            EntryType::Regular if trailing_slash => EntryType::Directory,
+            ftype => ftype,
+        };
 
         Ok(TarEntry{
             index,
@@ -194,78 +1297,121 @@ impl TarIndexer {
             name,
             path,
             link_name,
-            filesize: header.size()?,
+            // Includes a PAX size record, needed beyond 8 GiB unless the size is stored in binary (GNU)
+            filesize: entry.size(),
             mode: header.mode()?,
-            uid: header.uid()?,
-            gid: header.gid()?,
-            mtime,
-            atime,
-            ctime,
-            ftype: header.entry_type(),
+            uid: self.owner_id(&exts, "uname", header.username(), options, owners::uid_by_name).or_else(|| pax_id(&exts, "uid")).unwrap_or(header.uid()?),
+            gid: self.owner_id(&exts, "gname", header.groupname(), options, owners::gid_by_name).or_else(|| pax_id(&exts, "gid")).unwrap_or(header.gid()?),
+            mtime: options.time_policy.apply(mtime),
+            atime: options.time_policy.apply(atime),
+            ctime: options.time_policy.apply(ctime),
+            ftype,
         })
     }
 
-    fn collect_pax_extensions<'a>(&self, entry: &'a mut tar::Entry<'_, &File>) -> Result<HashMap<String, String>, io::Error> {
+    /// The local id of the user or group name of the entry (a PAX record or the header field), unless ids are taken as they are
+    fn owner_id<F: Fn(&str) -> Option<u64>>(&self, exts: &HashMap<String, String>, key: &str, header_name: Result<Option<&str>, std::str::Utf8Error>, options: &Options, by_name: F) -> Option<u64> {
+        if options.numeric_owner {
+            return None;
+        }
+        let name = match exts.get(key) {
+            Some(name) => Some(name.as_str()),
+            None => header_name.ok().flatten(),
+        };
+        name.filter(|name| !name.is_empty()).and_then(by_name)
+    }
+
+    /// Applies the UnsupportedPolicy
+    fn is_included(&self, tar_entry: &TarEntry, options: &Options) -> Result<bool, TarFsError> {
+        if options.selection.as_ref().is_some_and(|selection| !selection.selects(&options.normalize.path(&tar_entry.path))) {
+            return Ok(false);
+        }
+        if options.ignore.as_ref().is_some_and(|ignore| ignore.ignores(&options.normalize.path(&tar_entry.path), tar_entry.ftype == EntryType::Directory)) {
+            debug!("Ignoring {}", tar_entry.path.display());
+            return Ok(false);
+        }
+        if !options.limits.admits(&tar_entry.path, tar_entry.filesize)? {
+            return Ok(false);
+        }
+        if tar_entry.is_supported() {
+            return Ok(true);
+        }
+        let path = tar_entry.path.display();
+        let ftype = tar_entry.ftype;
+        match options.unsupported {
+            UnsupportedPolicy::Skip => {
+                debug!("Skipping {} of unsupported type {:?}", path, ftype);
+                Ok(false)
+            },
+            UnsupportedPolicy::Warn => {
+                problems::report(format!("Skipping {} of unsupported type {:?}", path, ftype));
+                Ok(false)
+            },
+            UnsupportedPolicy::Error => Err(IndexError {
+                msg: format!("{} is of unsupported type {:?}", path, ftype),
+            }),
+            UnsupportedPolicy::AsFile => {
+                problems::report(format!("Presenting {} of unsupported type {:?} as regular file", path, ftype));
+                Ok(true)
+            },
+        }
+    }
+
+    /// Global PAX headers (e.g. written by git archive) hold defaults for all entries following them
+    fn apply_global_header<R: Read>(&self, entry: &mut tar::Entry<'_, R>, pax_globals: &mut HashMap<String, String>) -> Result<(), io::Error> {
+        for (key, value) in self.collect_pax_extensions(entry)? {
+            if key == "comment" {
+                info!("Archive comment: {}", value);
+            } else if value.is_empty() {
+                // An empty value removes the default
+                pax_globals.remove(&key);
+            } else {
+                pax_globals.insert(key, value);
+            }
+        }
+        Ok(())
+    }
+
+    fn collect_pax_extensions<R: Read>(&self, entry: &mut tar::Entry<'_, R>) -> Result<HashMap<String, String>, io::Error> {
         let mut result = HashMap::new();
         let exts = match entry.pax_extensions() {
             Err(e) => return Err(e),
             Ok(None) => return Ok(result),
             Ok(Some(exts)) => exts,
         };
-        for ext in exts {
-            let ext = match ext {
-                Err(_) => continue,
-                Ok(ext) => ext,
-            };
-            let key = ext.key();
-            if key.is_err() {
-                continue;
-            }
-            let key: &str = key.unwrap();
-            let value: &str = ext.value().unwrap_or("");
-            result.insert(key.to_owned(), value.to_owned());
-
-            // let r = TarIndexer::debug_print_pax_extension(ext);
-            // if let Err(_e) = r {
-            //     continue;
-            // }
-        }
+        collect_pax_records(exts, &mut result);
         Ok(result)
     }
 
-    fn get_timespec_for(&self, exts: &HashMap<String, String>, key: &str, fallback: &Timespec) -> Timespec {
-        let mtime = self.parse_timespec_from_pax_extension(&exts, key);
-        return mtime.unwrap_or(*fallback);
+    /// Consumes GNU volume labels and Solaris extended headers, returns whether entry was one of them
+    fn consume_metadata<R: Read>(&self, entry: &mut tar::Entry<'_, R>, metadata: &mut Metadata) -> Result<bool, io::Error> {
+        match entry.header().entry_type().as_byte() {
+            b'V' => {
+                let label = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+                debug!("Volume label: {}", label);
+                metadata.volume_label = Some(label);
+                Ok(true)
+            },
+            b'X' => {
+                let mut records = vec!();
+                entry.read_to_end(&mut records)?;
+                let mut exts = HashMap::new();
+                collect_pax_records(tar::PaxExtensions::new(&records), &mut exts);
+                metadata.next_exts = Some(exts);
+                Ok(true)
+            },
+            _ => Ok(false),
+        }
     }
 
-    fn parse_timespec_from_pax_extension(&self, exts: &HashMap<String, String>, key: &str) -> Option<Timespec> {
-        let value = exts.get(key);
-        if value.is_none() {
-            return None;
-        }
+    fn get_time_for(&self, exts: &HashMap<String, String>, key: &str, fallback: &SystemTime) -> SystemTime {
+        let mtime = self.parse_time_from_pax_extension(exts, key);
+        mtime.unwrap_or(*fallback)
+    }
 
-        use std::num::ParseIntError;
-        type ParsedInt = Result<i64, ParseIntError>;
-
-        let splits: Vec<&str> = value.unwrap().split('.').collect();
-        let splits_parsed: Vec<ParsedInt> = splits.iter().map(|&s| s.parse::<i64>()).collect();
-        let splits_parsed_ref: &[ParsedInt] = &splits_parsed;
-        match splits_parsed_ref {
-            [Ok(s), Ok(ns)] => {
-                let mut ns = *ns as i32;
-                // tar seems to eat trailing zeros here.
-                // To exactlly mimick the source stats,
-                // adjust the exact amount of trailing zeros for nanoseconds
-                // Ex1:    27993590
-                // Tar1:   2799359
-                while ns / 10000000 == 0 {
-                    ns = ns * 10;
-                }
-                Some(Timespec::new(*s, ns))
-            },
-            [Ok(s)] => Some(Timespec::new(*s, 0)),
-            _ => return None,
-        }
+    fn parse_time_from_pax_extension(&self, exts: &HashMap<String, String>, key: &str) -> Option<SystemTime> {
+        let value = exts.get(key)?;
+        parse_pax_time(value)
     }
 
     // fn debug_print_pax_extension(ext: tar::PaxExtension) -> Result<(), std::str::Utf8Error> {
@@ -277,6 +1423,481 @@ impl TarIndexer {
     // }
 }
 
+/// Counts the entries and estimates the memory they take while indexing,
+/// to stop as soon as they exceed Options::max_memory or Limits::max_entries
+#[derive(Default)]
+struct Budget {
+    entries: u64,
+    used: u64,
+}
+
+impl Budget {
+    /// Whether tar_entry is to be indexed, within the entry limit
+    fn charge(&mut self, tar_entry: &TarEntry, options: &Options) -> Result<bool, TarFsError> {
+        if !options.limits.admits_another(self.entries)? {
+            return Ok(false);
+        }
+        self.entries += 1;
+        let max = match options.max_memory {
+            Some(max) => max,
+            None => return Ok(true),
+        };
+        // Path and name of the IndexEntry, plus the path as key while indexing
+        let path_len = tar_entry.path.as_os_str().len() * 3;
+        let link_len = tar_entry.link_name.as_ref().map_or(0, |link_name| link_name.as_os_str().len());
+        self.used += (mem::size_of::<IndexEntry>() + path_len + link_len) as u64 + MAP_OVERHEAD;
+        if self.used > max {
+            return Err(memory_error(max));
+        }
+        Ok(true)
+    }
+}
+
+/// A digest of each of the headers, including their extension headers
+fn header_digests(source: &dyn ArchiveSource, raw_headers: &[RawHeader]) -> io::Result<Vec<u64>> {
+    let mut buf = vec!();
+    raw_headers.iter()
+        .map(|header| {
+            buf.resize(header.size as usize, 0);
+            source.read_exact_at(&mut buf, header.offset)?;
+            let mut hasher = DefaultHasher::new();
+            buf.hash(&mut hasher);
+            Ok(hasher.finish())
+        })
+        .collect()
+}
+
+pub(crate) fn check_memory_usage(index: &TarIndex, options: &Options) -> Result<(), TarFsError> {
+    match options.max_memory {
+        Some(max) if index.memory_usage() > max => Err(memory_error(max)),
+        _ => Ok(()),
+    }
+}
+
+fn memory_error(max: u64) -> TarFsError {
+    IndexError {
+        msg: format!("the index would take more than the {} MiB allowed (see --max-memory)", max / 1024 / 1024),
+    }
+}
+
+/// The entry #idx with its header at offset can't be read
+fn entry_error(idx: usize, offset: u64, path: Option<PathBuf>, e: io::Error) -> TarFsError {
+    TarFsError::EntryError {
+        index: idx as u64,
+        offset,
+        msg: match &path {
+            Some(path) => format!("unable to read {}: {}", path.display(), e),
+            None => format!("unable to read it: {}", e),
+        },
+        path,
+    }
+}
+
+/// Where an entry is in the archive, for errors about it
+struct EntryLocation {
+    index: u64,
+    offset: u64,
+    path: PathBuf,
+}
+
+impl EntryLocation {
+    fn of(tar_entry: &TarEntry) -> EntryLocation {
+        EntryLocation {
+            index: tar_entry.index,
+            offset: tar_entry.header_offset,
+            path: tar_entry.path.clone(),
+        }
+    }
+
+    /// Indexing failed because of the entry: Tells where it is
+    fn locate(&self, e: TarFsError) -> TarFsError {
+        match e {
+            IndexError { msg } => TarFsError::EntryError {
+                index: self.index,
+                offset: self.offset,
+                path: Some(self.path.clone()),
+                msg,
+            },
+            e => e,
+        }
+    }
+}
+
+/// The archive formats that can be indexed
+enum Format {
+    Tar,
+    /// cpio "new ASCII" (newc), e.g. initramfs images
+    Cpio,
+    /// E.g. .deb packages
+    Ar,
+}
+
+impl Format {
+    fn name(&self) -> &'static str {
+        match self {
+            Format::Tar => "tar",
+            Format::Cpio => "cpio",
+            Format::Ar => "ar",
+        }
+    }
+
+    /// The name with the variant the first block of the archive is in, as far as it tells
+    fn detailed_name(&self, first_block: &[u8]) -> &'static str {
+        match self {
+            Format::Tar => match first_block.get(257..265) {
+                // pax archives have ustar headers, which only the extended headers following tell apart
+                Some(b"ustar\x0000") => "tar (ustar/pax)",
+                Some(b"ustar  \x00") => "tar (gnu)",
+                Some(_) if first_block.iter().any(|b| *b != 0) => "tar (v7)",
+                _ => "tar",
+            },
+            Format::Cpio => "cpio (newc)",
+            Format::Ar => "ar",
+        }
+    }
+}
+
+/// The tar crate reports a foreign file format just like a damaged archive, so check the first header ourselves.
+/// Returns the format along with the bytes consumed from stream.
+fn check_format(stream: &mut dyn Read) -> Result<(Format, Vec<u8>), TarFsError> {
+    let mut block = Vec::with_capacity(BLOCK_SIZE as usize);
+    stream.take(BLOCK_SIZE).read_to_end(&mut block)?;
+    if cpio::is_cpio(&block) {
+        return Ok((Format::Cpio, block));
+    }
+    if block.starts_with(ar::MAGIC) {
+        return Ok((Format::Ar, block));
+    }
+    if block.starts_with(ar::MAGIC_THIN) {
+        return Err(TarFsError::UnsupportedFormat {
+            msg: String::from("archive is a thin ar archive, its members are not part of it"),
+        });
+    }
+    if block.starts_with(cpio::MAGIC_ODC) {
+        return Err(TarFsError::UnsupportedFormat {
+            msg: String::from("archive is an old (odc) cpio archive, only the newc format is supported (cpio -H newc)"),
+        });
+    }
+    if block.len() < BLOCK_SIZE as usize {
+        // Empty or truncated: Let the tar crate decide
+        return Ok((Format::Tar, block));
+    }
+
+    const COMPRESSED: [(&[u8], &str); 4] = [
+        (b"\x1f\x8b", "gzip"),
+        (b"BZh", "bzip2"),
+        (b"\xfd7zXZ\x00", "xz"),
+        (b"\x28\xb5\x2f\xfd", "zstd"),
+    ];
+    if let Some((_, name)) = COMPRESSED.iter().find(|(magic, _)| block.starts_with(magic)) {
+        return Err(TarFsError::UnsupportedFormat {
+            msg: format!("archive is {} compressed, please decompress it first", name),
+        });
+    }
+
+    // An all-zero block marks the end of an (empty) archive
+    if block.iter().all(|b| *b == 0) {
+        return Ok((Format::Tar, block));
+    }
+    let header = tar::Header::from_byte_slice(&block);
+    let mut expected = header.clone();
+    expected.set_cksum();
+    match (header.cksum(), expected.cksum()) {
+        (Ok(cksum), Ok(expected)) if cksum == expected => Ok((Format::Tar, block)),
+        _ => Err(TarFsError::UnsupportedFormat {
+            msg: String::from("no valid tar header found"),
+        }),
+    }
+}
+
+/// Hard links get link_name set to the path of the entry holding the content
+fn cpio_to_tar_entry(idx: usize, entry: &CpioEntry, link_target: Option<PathBuf>, options: &Options) -> TarEntry {
+    let path = archive_path(&entry.name);
+    let name = PathBuf::from(path.file_name().unwrap_or(path.as_os_str()));
+    let ftype = match (&link_target, entry.file_type()) {
+        (Some(_), _) => EntryType::Link,
+        (None, libc::S_IFREG) => EntryType::Regular,
+        (None, libc::S_IFDIR) => EntryType::Directory,
+        (None, libc::S_IFLNK) => EntryType::Symlink,
+        (None, libc::S_IFCHR) => EntryType::Char,
+        (None, libc::S_IFBLK) => EntryType::Block,
+        (None, libc::S_IFIFO) => EntryType::Fifo,
+        // Sockets have no tar type
+        (None, _) => EntryType::new(b's'),
+    };
+    let mtime = options.time_policy.apply(system_time_from_unix(entry.mtime, 0));
+    TarEntry {
+        index: idx as u64,
+        header_offset: entry.header_offset,
+        raw_file_offset: entry.data_offset,
+        name,
+        path,
+        filesize: if link_target.is_some() { 0 } else { entry.filesize },
+        link_name: link_target.or_else(|| entry.link_name.clone()),
+        mode: entry.mode & 0o7777,
+        uid: entry.uid,
+        gid: entry.gid,
+        mtime,
+        atime: mtime,
+        ctime: mtime,
+        ftype,
+    }
+}
+
+/// Paths defined again replace the earlier definition, with options.strict only if the type stays the same
+fn check_redefinition(tar_entry: &TarEntry, replaced_kind: FileType, options: &Options) -> Result<(), TarFsError> {
+    let kind = tar_entry.attrs(0).kind;
+    if kind == replaced_kind {
+        return Ok(());
+    }
+    let msg = format!("{} is defined as {:?} after being defined as {:?}", tar_entry.path.display(), kind, replaced_kind);
+    if options.strict {
+        return Err(IndexError { msg });
+    }
+    debug!("{}, the latter wins", msg);
+    Ok(())
+}
+
+/// DirAttrs::Children: The owner of the first child and the newest mtime of all of them
+fn inherit_from_children(attrs: &mut fuser::FileAttr, children: &[fuser::FileAttr]) {
+    let (first, mtime) = match (children.first(), children.iter().map(|child| child.mtime).max()) {
+        (Some(first), Some(mtime)) => (first, mtime),
+        _ => return,
+    };
+    attrs.uid = first.uid;
+    attrs.gid = first.gid;
+    attrs.atime = mtime;
+    attrs.mtime = mtime;
+    attrs.ctime = mtime;
+    attrs.crtime = mtime;
+}
+
+/// Dates the root like its newest entry (see RootTime::Newest), if there are any
+pub(crate) fn set_newest_root_time(index: &mut TarIndex) {
+    let newest = match index.entries().filter(|entry| entry.id != ROOT_INO).map(|entry| entry.attrs.mtime).max() {
+        Some(newest) => newest,
+        None => return,
+    };
+    let mut root = index.get_entry_by_ino(ROOT_INO).expect("the root entry is always present").clone();
+    root.attrs.atime = newest;
+    root.attrs.mtime = newest;
+    root.attrs.ctime = newest;
+    root.attrs.crtime = newest;
+    index.insert(root);
+}
+
+/// Brings the path of the entry (and the one a hard link names) into the form of options.normalize.
+/// Symlink targets are left alone, they are resolved by lookups which normalize on their own.
+fn normalize_names(tar_entry: &mut TarEntry, options: &Options) {
+    if options.normalize == Normalization::None {
+        return;
+    }
+    tar_entry.path = options.normalize.path(&tar_entry.path);
+    tar_entry.name = options.normalize.path(&tar_entry.name);
+    if tar_entry.ftype == EntryType::Link {
+        tar_entry.link_name = tar_entry.link_name.as_deref().map(|link_name| options.normalize.path(link_name));
+    }
+}
+
+/// Another entry of the archive normalized to the path of tar_entry collides with it, unless both are directories.
+/// Handles that according to options.collisions, and remembers where tar_entry came from.
+/// kind_of tells the type of the entry indexed at a path, if there is one.
+fn resolve_collision(kind_of: impl Fn(&Path) -> Option<FileType>, originals: &mut Originals, tar_entry: &mut TarEntry, original: PathBuf, options: &Options) -> Result<(), TarFsError> {
+    let other = match (originals.get(&tar_entry.path), kind_of(&tar_entry.path)) {
+        (Some(other), Some(existing)) if *other != original
+            && !(tar_entry.ftype == EntryType::Directory && existing == FileType::Directory) => other.clone(),
+        _ => {
+            originals.insert(tar_entry.path.clone(), original);
+            return Ok(());
+        },
+    };
+    let msg = format!("{} collides with {} once normalized", original.display(), other.display());
+    match options.collisions {
+        CollisionPolicy::Replace => problems::report(format!("{}, the latter wins", msg)),
+        CollisionPolicy::Rename => {
+            let file_name = tar_entry.path.file_name().expect("only the root has no name").to_owned();
+            let renamed = (1..).map(|n| {
+                let mut name = file_name.clone();
+                name.push(format!("~{}", n));
+                tar_entry.path.with_file_name(name)
+            }).find(|path| kind_of(path).is_none()).expect("some suffix is free");
+            problems::report(format!("{}, presenting it as {}", msg, renamed.display()));
+            tar_entry.name = tar_entry.name.with_file_name(renamed.file_name().expect("renamed has a name"));
+            tar_entry.path = renamed;
+        },
+        CollisionPolicy::Error => return Err(IndexError { msg }),
+    }
+    originals.insert(tar_entry.path.clone(), original);
+    Ok(())
+}
+
+/// Presents the owner as it's mapped by options.userns. Ids beyond what the kernel supports become the overflow id, like
+/// the kernel presents unmapped ids.
+fn translate_owner(tar_entry: &mut TarEntry, options: &Options) {
+    if let Some(maps) = &options.userns {
+        tar_entry.uid = maps.uids.translate(tar_entry.uid);
+        tar_entry.gid = maps.gids.translate(tar_entry.gid);
+    }
+    for (kind, id) in [("uid", &mut tar_entry.uid), ("gid", &mut tar_entry.gid)] {
+        if *id > MAX_ID {
+            problems::report(format!("{} {} of {} is beyond 32 bits, presenting it as {}", kind, id, tar_entry.path.display(), OVERFLOW_ID));
+            *id = OVERFLOW_ID;
+        }
+    }
+}
+
+/// A PAX uid or gid record, which carries ids too large for the header fields
+fn pax_id(exts: &HashMap<String, String>, key: &str) -> Option<u64> {
+    let value = exts.get(key)?;
+    match value.parse() {
+        Ok(id) => Some(id),
+        Err(_) => {
+            problems::report(format!("Ignoring invalid PAX {} record: {}", key, value));
+            None
+        },
+    }
+}
+
+/// Applies options.rewrite_absolute_symlinks to symlinks. The size of the symlink follows the rewritten target.
+fn rewrite_absolute_symlink(tar_entry: &mut TarEntry, options: &Options) {
+    if tar_entry.ftype != EntryType::Symlink {
+        return;
+    }
+    let target = match &tar_entry.link_name {
+        Some(target) if target.is_absolute() => target,
+        _ => return,
+    };
+    let inside: PathBuf = target.components()
+        .filter(|component| !matches!(component, Component::RootDir))
+        .collect();
+    let rewritten = match (options.rewrite_absolute_symlinks, &options.mountpoint) {
+        (None, _) => return,
+        (Some(SymlinkRewrite::Relative), _) => {
+            // Entry paths are "./dir/link": One step up for each directory
+            let depth = tar_entry.path.components().count().saturating_sub(2);
+            let mut relative: PathBuf = std::iter::repeat_n(Component::ParentDir, depth).collect();
+            relative.push(&inside);
+            if relative.as_os_str().is_empty() {
+                relative.push(".");
+            }
+            relative
+        },
+        (Some(SymlinkRewrite::Mountpoint), Some(mountpoint)) => mountpoint.join(&inside),
+        (Some(SymlinkRewrite::Mountpoint), None) => return,
+    };
+    debug!("Rewriting symlink {}: {} -> {}", tar_entry.path.display(), target.display(), rewritten.display());
+    tar_entry.link_name = Some(rewritten);
+}
+
+fn ar_to_tar_entry(idx: usize, member: &ArMember, options: &Options) -> TarEntry {
+    let path = archive_path(&member.name);
+    let mtime = options.time_policy.apply(system_time_from_unix(member.mtime, 0));
+    TarEntry {
+        index: idx as u64,
+        header_offset: member.header_offset,
+        raw_file_offset: member.data_offset,
+        name: PathBuf::from(path.file_name().unwrap_or(path.as_os_str())),
+        path,
+        link_name: None,
+        filesize: member.size,
+        mode: member.mode & 0o7777,
+        uid: member.uid,
+        gid: member.gid,
+        mtime,
+        atime: mtime,
+        ctime: mtime,
+        ftype: EntryType::Regular,
+    }
+}
+
+/// Members which are tar archives themselves (like data.tar of .deb packages) are presented as directory as well,
+/// named like the member without the extension
+fn expanded_dir(member: &ArMember) -> Option<PathBuf> {
+    let name = member.name.to_str()?;
+    match name.strip_suffix(".tar") {
+        Some(stem) if !stem.is_empty() => return Some(archive_path(Path::new(stem))),
+        _ => (),
+    }
+    if name.contains(".tar.") {
+        info!("Not expanding {}: compressed members are not supported", name);
+    }
+    None
+}
+
+fn is_global_header<R: Read>(entry: &tar::Entry<'_, R>) -> bool {
+    entry.header().entry_type().is_pax_global_extensions()
+}
+
+/// Adds the records of a PAX extended header, skipping malformed ones
+fn collect_pax_records(exts: tar::PaxExtensions<'_>, result: &mut HashMap<String, String>) {
+    for ext in exts {
+        let ext = match ext {
+            Err(_) => continue,
+            Ok(ext) => ext,
+        };
+        let key = ext.key();
+        if key.is_err() {
+            continue;
+        }
+        let key: &str = key.unwrap();
+        let value: &str = ext.value().unwrap_or("");
+        result.insert(key.to_owned(), value.to_owned());
+
+        // let r = TarIndexer::debug_print_pax_extension(ext);
+        // if let Err(_e) = r {
+        //     continue;
+        // }
+    }
+}
+
+/// Archives name their entries "./dir/file" as well as "dir/file" (or even "/dir/file"):
+/// Use the former for all of them so they are all relative to the root entry "./"
+pub(crate) fn archive_path(path: &Path) -> PathBuf {
+    let mut result = PathBuf::from(".");
+    for component in path.components() {
+        if let Component::Normal(name) = component {
+            result.push(name);
+        }
+    }
+    result
+}
+
+/// Parses PAX time values like "1556277134.27993590": Seconds since the epoch with an optional fraction of arbitrary precision.
+/// Negative values count backwards as a whole, i.e. "-1.25" is 1.25s before the epoch.
+fn parse_pax_time(value: &str) -> Option<SystemTime> {
+    let (secs, fraction) = match value.split_once('.') {
+        Some((secs, fraction)) => (secs, fraction),
+        None => (value, ""),
+    };
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let secs: i64 = secs.parse().ok()?;
+
+    // The fraction is in seconds, so ".5" means 500000000ns: Pad to nanoseconds, drop what's more precise
+    let nanos: String = fraction.chars().chain(std::iter::repeat('0')).take(9).collect();
+    let nanos: u32 = nanos.parse().ok()?;
+
+    if value.starts_with('-') && nanos > 0 {
+        Some(system_time_from_unix(secs - 1, 1_000_000_000 - nanos))
+    } else {
+        Some(system_time_from_unix(secs, nanos))
+    }
+}
+
+/// Offset (relative to the start of the archive reader) of the next header after entry
+fn entry_end<R: Read>(entry: &tar::Entry<'_, R>) -> u64 {
+    let padded_size = entry.size().div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+    entry.raw_file_position() + padded_size
+}
+
+/// The header blocks of entry, starting at offset where the previous entry ended.
+/// The payload of a global PAX header belongs to it, there's no member it describes.
+fn raw_header<R: Read>(entry: &tar::Entry<'_, R>, offset: u64) -> RawHeader {
+    let end = if is_global_header(entry) { entry.raw_file_position() + entry.size() } else { entry.raw_file_position() };
+    RawHeader { offset, size: end - offset }
+}
+
 #[derive(Debug)]
 struct TarEntry {
     index: u64,
@@ -289,14 +1910,14 @@ struct TarEntry {
     mode: u32,
     uid: u64,
     gid: u64,
-    mtime: Timespec,
-    atime: Timespec,
-    ctime: Timespec,
+    mtime: SystemTime,
+    atime: SystemTime,
+    ctime: SystemTime,
     ftype: tar::EntryType,
 }
 
 impl TarEntry {
-    fn set_to_index_entry(self, entry: &mut IndexEntry, id: u64, parent_ino: Option<u64>) -> () {
+    fn set_to_index_entry(self, entry: &mut IndexEntry, id: u64, parent_ino: Option<u64>) {
         entry.id = id;
         entry.parent_ino = parent_ino;
         entry.attrs = self.attrs(id);
@@ -313,16 +1934,23 @@ impl TarEntry {
         self.ftype == tar::EntryType::Link
     }
 
-    fn attrs(&self, ino: u64) -> fuse::FileAttr {
+    fn is_root(&self) -> bool {
+        self.path == Path::new(".")
+    }
+
+    fn is_supported(&self) -> bool {
+        matches!(self.ftype, EntryType::Regular | EntryType::Continuous | EntryType::Directory | EntryType::Symlink | EntryType::Link)
+    }
+
+    fn attrs(&self, ino: u64) -> fuser::FileAttr {
         let kind = match self.ftype {
             EntryType::Regular => FileType::RegularFile,
             EntryType::Directory => FileType::Directory,
             EntryType::Symlink => FileType::Symlink,
             EntryType::Link => FileType::RegularFile,
-            t => {
-                println!("Unsupported EntryType: {:?}", t);
-                FileType::RegularFile
-            },
+            // Continuous files are regular files on all systems but a few historic ones.
+            // Anything else made it past the UnsupportedPolicy, so present it as file
+            _ => FileType::RegularFile,
         };
 
         let size = match &self.link_name {
@@ -340,7 +1968,7 @@ impl TarEntry {
             _ => 1,
         };
 
-        fuse::FileAttr {
+        fuser::FileAttr {
             ino,
             size,
             blocks: 0,
@@ -349,11 +1977,14 @@ impl TarEntry {
             ctime: self.ctime,
             crtime: self.ctime, // macOS only
             kind,
-            perm: self.mode as u16,
+            // Only the permission bits (including setuid, setgid and sticky): Some archivers store the file type
+            // in the mode field as well, which would otherwise end up in the type the kernel sees
+            perm: (self.mode & 0o7777) as u16,
             nlink,
             uid: self.uid as u32,
             gid: self.gid as u32,
             rdev: 0,
+            blksize: 0,
             flags: 0,
         }
     }
@@ -0,0 +1,116 @@
+//! Presents owners to processes in a user namespace (e.g. rootless containers): The ids of the archive are taken as ids inside
+//! the namespace and presented as the ids they map to outside of it, which the kernel maps back when inside processes look.
+//! Otherwise ids the namespace doesn't map (e.g. most ids of the host's root) show up as nobody.
+
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+use log::info;
+
+/// Ranges of ids as in /proc/<pid>/uid_map: (first id inside, first id outside, count)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IdMap {
+    ranges: Vec<(u64, u64, u64)>,
+}
+
+impl IdMap {
+    /// Parses the format of /proc/<pid>/uid_map: "<inside> <outside> <count>" per line
+    pub fn from_proc(content: &str) -> Result<IdMap, String> {
+        IdMap::parse(content.lines().filter(|line| !line.trim().is_empty()), char::is_whitespace)
+    }
+
+    fn parse<'s, I: Iterator<Item = &'s str>>(ranges: I, separator: fn(char) -> bool) -> Result<IdMap, String> {
+        let mut map = IdMap::default();
+        for range in ranges {
+            let numbers: Vec<u64> = range.split(separator)
+                .filter(|number| !number.is_empty())
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("invalid id range: {}", range.trim()))?;
+            match numbers[..] {
+                [inside, outside, count] => map.ranges.push((inside, outside, count)),
+                _ => return Err(format!("invalid id range: {} (expected <inside>:<outside>:<count>)", range.trim())),
+            }
+        }
+        Ok(map)
+    }
+
+    /// The id outside of the namespace, ids it doesn't map stay as they are
+    pub fn translate(&self, id: u64) -> u64 {
+        self.ranges.iter()
+            .find(|(inside, _, count)| id >= *inside && id - inside < *count)
+            .map_or(id, |(inside, outside, _)| outside + (id - inside))
+    }
+
+    /// Whether ids are the same inside and outside, as in the initial namespace
+    pub fn is_identity(&self) -> bool {
+        self.ranges.iter().all(|(inside, outside, _)| inside == outside)
+    }
+}
+
+/// "<inside>:<outside>:<count>[,...]", like the --uidmap of container runtimes
+impl FromStr for IdMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<IdMap, String> {
+        IdMap::parse(s.split(','), |c| c == ':')
+    }
+}
+
+/// How the owners are translated for user namespaces
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum UsernsMode {
+    /// Through the maps of the namespace tarfs runs in, if it's not the initial one
+    #[default]
+    Auto,
+    /// Not at all
+    Off,
+    /// Through this map, for uids and gids alike
+    Map(IdMap),
+}
+
+impl FromStr for UsernsMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<UsernsMode, String> {
+        match s {
+            "auto" => Ok(UsernsMode::Auto),
+            "none" => Ok(UsernsMode::Off),
+            s => Ok(UsernsMode::Map(s.parse()?)),
+        }
+    }
+}
+
+/// The maps uids and gids get translated through
+#[derive(Clone, Debug, Default)]
+pub struct UsernsMaps {
+    pub uids: IdMap,
+    pub gids: IdMap,
+}
+
+impl UsernsMode {
+    pub fn maps(&self) -> Option<UsernsMaps> {
+        match self {
+            UsernsMode::Auto => detect(),
+            UsernsMode::Off => None,
+            UsernsMode::Map(map) => Some(UsernsMaps { uids: map.clone(), gids: map.clone() }),
+        }
+    }
+}
+
+/// The maps of the namespace tarfs runs in, None in the initial namespace (or without /proc)
+fn detect() -> Option<UsernsMaps> {
+    let read = |path: &str| -> io::Result<IdMap> {
+        IdMap::from_proc(&fs::read_to_string(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    };
+    let maps = UsernsMaps {
+        uids: read("/proc/self/uid_map").ok()?,
+        gids: read("/proc/self/gid_map").ok()?,
+    };
+    if maps.uids.is_identity() && maps.gids.is_identity() {
+        return None;
+    }
+    info!("Running in a user namespace, presenting owners as mapped by it (see --userns-map)");
+    Some(maps)
+}
@@ -1,21 +1,194 @@
-use fuse;
-use time::Timespec;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-pub fn default_fuse_file_attr() -> fuse::FileAttr {
-    fuse::FileAttr {
+use log::{debug, warn};
+
+pub fn default_fuse_file_attr() -> fuser::FileAttr {
+    fuser::FileAttr {
         ino: 0,
         size: 0,
         blocks: 0,
-        atime: Timespec::new(0, 0),
-        mtime: Timespec::new(0, 0),
-        ctime: Timespec::new(0, 0),
-        crtime: Timespec::new(0, 0),
-        kind: fuse::FileType::RegularFile,
+        atime: UNIX_EPOCH,
+        mtime: UNIX_EPOCH,
+        ctime: UNIX_EPOCH,
+        crtime: UNIX_EPOCH,
+        kind: fuser::FileType::RegularFile,
         perm: 0,
         nlink: 0,
         uid: 0,
         gid: 0,
         rdev: 0,
+        blksize: 0,
         flags: 0,
     }
-}
\ No newline at end of file
+}
+
+/// Converts seconds (and nanoseconds on top of them) relative to the unix epoch, which might be negative, without losing precision
+pub fn system_time_from_unix(secs: i64, nsecs: u32) -> SystemTime {
+    let since_epoch = if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    };
+    since_epoch + Duration::from_nanos(nsecs as u64)
+}
+
+/// Formats a time as used in HTTP headers (RFC 7231), e.g. "Sun, 06 Nov 1994 08:49:37 GMT"
+pub fn http_date(time: SystemTime) -> String {
+    const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let (days, hh, mm, ss) = split_unix_time(time);
+    let (y, m, d) = civil_from_days(days);
+    // 1970-01-01 was a Thursday
+    let weekday = WEEKDAYS[(days + 4).rem_euclid(7) as usize];
+    format!("{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT", weekday, d, MONTHS[m as usize - 1], y, hh, mm, ss)
+}
+
+/// Formats a time as ISO 8601/RFC 3339 in UTC, e.g. "1994-11-06T08:49:37Z"
+pub fn iso8601_date(time: SystemTime) -> String {
+    let (days, hh, mm, ss) = split_unix_time(time);
+    let (y, m, d) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", y, m, d, hh, mm, ss)
+}
+
+/// Like iso8601_date, with the nanoseconds, e.g. "1994-11-06T08:49:37.123456789Z"
+pub fn iso8601_date_nanos(time: SystemTime) -> String {
+    let nanos = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.subsec_nanos(),
+        Err(e) => (1_000_000_000 - e.duration().subsec_nanos()) % 1_000_000_000,
+    };
+    let date = iso8601_date(time);
+    format!("{}.{:09}Z", date.trim_end_matches('Z'), nanos)
+}
+
+/// Days since the epoch and the time of that day
+fn split_unix_time(time: SystemTime) -> (i64, i64, i64, i64) {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs_f64().ceil() as i64),
+    };
+    let secs_of_day = secs.rem_euclid(86400);
+    (secs.div_euclid(86400), secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60)
+}
+
+/// The date of the proleptic Gregorian calendar that many days after 1970-01-01 (see http://howardhinnant.github.io/date_algorithms.html)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m as u32, d as u32)
+}
+
+/// Quotes s as JSON string
+pub fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Raises the soft limit of open file descriptors to the hard one, for serving many clients and open files at once
+pub fn raise_fd_limit() {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 || limit.rlim_cur >= limit.rlim_max {
+        return;
+    }
+    let raised = libc::rlimit { rlim_cur: limit.rlim_max, ..limit };
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &raised) } == 0 {
+        debug!("Raised the limit of open files from {} to {}", limit.rlim_cur, limit.rlim_max);
+    } else {
+        warn!("Unable to raise the limit of open files from {}: {}", limit.rlim_cur, io::Error::last_os_error());
+    }
+}
+
+/// Accepting a connection failed: If it's for a lack of file descriptors, waits for some to be closed rather than
+/// retrying right away (which would spin for as long as they are lacking)
+pub fn back_off_after_accept_error(e: &io::Error) {
+    if matches!(e.raw_os_error(), Some(libc::EMFILE) | Some(libc::ENFILE)) {
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// The file type bits of st_mode for kind
+pub fn file_type_bits(kind: fuser::FileType) -> u32 {
+    match kind {
+        fuser::FileType::Directory => libc::S_IFDIR,
+        fuser::FileType::Symlink => libc::S_IFLNK,
+        fuser::FileType::CharDevice => libc::S_IFCHR,
+        fuser::FileType::BlockDevice => libc::S_IFBLK,
+        fuser::FileType::NamedPipe => libc::S_IFIFO,
+        fuser::FileType::Socket => libc::S_IFSOCK,
+        fuser::FileType::RegularFile => libc::S_IFREG,
+    }
+}
+
+/// A mode (with file type bits) as ls -l shows it, e.g. "drwxr-xr-x"
+pub fn mode_string(mode: u32) -> String {
+    let kind = match mode & libc::S_IFMT {
+        libc::S_IFDIR => 'd',
+        libc::S_IFLNK => 'l',
+        libc::S_IFCHR => 'c',
+        libc::S_IFBLK => 'b',
+        libc::S_IFIFO => 'p',
+        libc::S_IFSOCK => 's',
+        _ => '-',
+    };
+    std::iter::once(kind)
+        .chain((0..9).rev().map(|bit| if mode & (1 << bit) == 0 { '-' } else { ['x', 'w', 'r'][bit % 3] }))
+        .collect()
+}
+
+/// An archive path like "./dir/file" relative to the root, i.e. "dir/file"
+pub fn relative(path: &Path) -> PathBuf {
+    path.strip_prefix(".").unwrap_or(path).to_path_buf()
+}
+
+/// Keeps track of the offset while reading an archive as stream
+pub struct CountingReader<R: Read> {
+    inner: R,
+    pub offset: u64,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(inner: R) -> CountingReader<R> {
+        CountingReader { inner, offset: 0 }
+    }
+
+    /// Returns how much got skipped, which is less than len at the end of the input only
+    pub fn skip(&mut self, len: u64) -> io::Result<u64> {
+        io::copy(&mut self.by_ref().take(len), &mut io::sink())
+    }
+
+    /// Skips to the next multiple of alignment
+    pub fn skip_padding(&mut self, alignment: u64) -> io::Result<u64> {
+        let padding = self.offset.next_multiple_of(alignment) - self.offset;
+        self.skip(padding)
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.offset += len as u64;
+        Ok(len)
+    }
+}
@@ -0,0 +1,103 @@
+//! Consuming an archive through its index without mounting it (see TarIndex::walk): all entries depth-first, with readers
+//! for the content of files reading it at random, unlike the strictly sequential iteration of tar::Archive.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::slice;
+
+use fuser::FileType;
+
+use crate::memberstat::{self, MemberStat};
+use crate::tarindex::{IndexEntry, TarIndex, ROOT_INO};
+use crate::TarFsError;
+
+/// The entries of an index, each directory before its children, in the order they are listed. Files come with a reader
+/// of their content.
+pub struct Walk<'i> {
+    index: &'i TarIndex,
+    /// The children left to walk of the directories walked into
+    stack: Vec<slice::Iter<'i, u64>>,
+}
+
+impl<'i> Walk<'i> {
+    pub(crate) fn new(index: &'i TarIndex) -> Walk<'i> {
+        Walk {
+            index,
+            stack: index.get_entry_by_ino(ROOT_INO).map(|root| root.children.iter()).into_iter().collect(),
+        }
+    }
+}
+
+impl<'i> Iterator for Walk<'i> {
+    type Item = (MemberStat, Option<Reader<'i>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let ino = match self.stack.last_mut()?.next() {
+                Some(ino) => *ino,
+                None => {
+                    self.stack.pop();
+                    continue;
+                },
+            };
+            let entry = match self.index.get_entry_by_ino(ino) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            if entry.attrs.kind == FileType::Directory {
+                self.stack.push(entry.children.iter());
+            }
+            let reader = match entry.attrs.kind {
+                FileType::RegularFile => Some(Reader::new(self.index, entry)),
+                _ => None,
+            };
+            return Some((memberstat::of_entry(self.index, entry), reader));
+        }
+    }
+}
+
+/// Reads the content of a file from the archive at its offset, starting with the first read
+pub struct Reader<'i> {
+    index: &'i TarIndex,
+    /// The file with the content, the one a hard link names
+    content: &'i IndexEntry,
+    pos: u64,
+}
+
+impl<'i> Reader<'i> {
+    fn new(index: &'i TarIndex, entry: &'i IndexEntry) -> Reader<'i> {
+        let content = entry.link_target_ino.and_then(|ino| index.get_entry_by_ino(ino)).unwrap_or(entry);
+        Reader { index, content, pos: 0 }
+    }
+
+    /// The size of the content
+    pub fn len(&self) -> u64 {
+        self.content.attrs.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Read for Reader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.index.read_into(self.content, self.pos, buf).map_err(|e| match e {
+            TarFsError::ReadError { source, .. } => source,
+            e => io::Error::other(e),
+        })?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Reader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = pos.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the content"))?;
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,105 @@
+//! Reads the content of selected members once (`tarfs warmup`, `--preload`), so the archive's byte ranges are in the
+//! page cache (or the chunk cache of remote archives) before the workload starts.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+use fuser::FileType;
+use log::{info, warn};
+
+use crate::archivesource::ArchiveSource;
+use crate::stats::SharedStats;
+use crate::tarindex::{IndexEntry, TarEntryPointer, TarIndex};
+use crate::TarFsError;
+
+/// How much is read at once
+const WARMUP_CHUNK: usize = 1024 * 1024;
+
+/// What got warmed up
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Warmup {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// The byte ranges of the files at paths and below, in the order they are in the archive. No paths selects all files.
+pub fn select(index: &TarIndex, paths: &[PathBuf]) -> Result<Vec<TarEntryPointer>, TarFsError> {
+    let mut roots = vec!();
+    for path in paths {
+        match index.lookup_path(path) {
+            Some(entry) => roots.push(entry),
+            None => return Err(TarFsError::IndexError {
+                msg: format!("unable to warm up {}: no such file or directory", path.display()),
+            }),
+        }
+    }
+    let mut ranges: Vec<TarEntryPointer> = if paths.is_empty() {
+        index.entries().flat_map(content_ranges).collect()
+    } else {
+        let mut ranges = vec!();
+        let mut pending = roots;
+        while let Some(entry) = pending.pop() {
+            ranges.extend(content_ranges(entry));
+            if entry.attrs.kind == FileType::Directory && entry.link_target_ino.is_none() {
+                pending.extend(index.children_iter(entry));
+            }
+        }
+        ranges
+    };
+    // Hard links and deduplicated files share their content
+    let mut seen = HashSet::new();
+    ranges.retain(|range| seen.insert(range.raw_file_offset));
+    ranges.sort_by_key(|range| range.raw_file_offset);
+    Ok(ranges)
+}
+
+fn content_ranges(entry: &IndexEntry) -> impl Iterator<Item = TarEntryPointer> + '_ {
+    let is_file = entry.attrs.kind == FileType::RegularFile;
+    entry.file_offsets.iter().filter(move |_| is_file).cloned()
+}
+
+/// Reads the ranges in order, reporting the bytes read after each chunk
+pub fn warm_up<F: FnMut(u64)>(source: &dyn ArchiveSource, ranges: &[TarEntryPointer], mut on_read: F) -> io::Result<Warmup> {
+    let mut buf = vec![0; WARMUP_CHUNK];
+    let mut warmup = Warmup::default();
+    for range in ranges {
+        let mut offset = 0;
+        while offset < range.filesize {
+            let n = (range.filesize - offset).min(WARMUP_CHUNK as u64) as usize;
+            source.read_exact_at(&mut buf[..n], range.raw_file_offset + offset)?;
+            offset += n as u64;
+            on_read(n as u64);
+        }
+        warmup.files += 1;
+        warmup.bytes += range.filesize;
+    }
+    Ok(warmup)
+}
+
+/// Warms up in the background while the mount already serves requests, on this many threads each reading a consecutive
+/// part of the ranges
+pub fn spawn_preloader(source: Arc<dyn ArchiveSource>, ranges: Vec<TarEntryPointer>, stats: SharedStats, threads: usize) {
+    thread::spawn(move || {
+        let now = Instant::now();
+        let per_thread = ranges.len().div_ceil(threads.max(1)).max(1);
+        let workers: Vec<_> = ranges.chunks(per_thread).map(|part| {
+            let (source, part, stats) = (source.clone(), part.to_vec(), stats.clone());
+            thread::spawn(move || warm_up(source.as_ref(), &part, |bytes| stats.prefetched(bytes)))
+        }).collect();
+        let mut preloaded = Warmup::default();
+        for worker in workers {
+            match worker.join().expect("preloading panicked") {
+                Ok(warmup) => {
+                    preloaded.files += warmup.files;
+                    preloaded.bytes += warmup.bytes;
+                },
+                Err(e) => warn!("Preloading failed: {}", e),
+            }
+        }
+        info!("Preloaded {} files ({} KiB). Took {}s.", preloaded.files, preloaded.bytes / 1024, now.elapsed().as_secs());
+    });
+}
@@ -0,0 +1,59 @@
+use std::io;
+use std::path::Path;
+use std::thread;
+
+use inotify::{Inotify, WatchMask};
+use log::{debug, error, info};
+
+use crate::TarFsError;
+use crate::tarfs::{KernelCache, SharedIndex};
+use crate::tarindex::TarIndex;
+
+/// Watches the archive at path and swaps in a freshly built index whenever it got replaced or rewritten.
+/// build_index gets the current index, to extend it if the archive got appended to.
+/// We watch the parent directory as the usual way to replace a file (rename) would make us lose track of it.
+pub fn spawn_watcher<B>(path: &Path, index: SharedIndex, kernel_cache: KernelCache, build_index: B) -> io::Result<()>
+    where
+        B: Fn(&Path, &TarIndex) -> Result<TarIndex, TarFsError> + Send + 'static {
+    let path = path.canonicalize()?;
+    let (dir, file_name) = match (path.parent(), path.file_name()) {
+        (Some(dir), Some(file_name)) => (dir.to_path_buf(), file_name.to_owned()),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("cannot watch {}", path.display()))),
+    };
+
+    let mut inotify = Inotify::init()?;
+    inotify.watches().add(&dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            let events = match inotify.read_events_blocking(&mut buffer) {
+                Ok(events) => events,
+                Err(e) => {
+                    error!("Stopped watching {}: {}", path.display(), e);
+                    return;
+                },
+            };
+            let changed = events.filter(|e| e.name == Some(file_name.as_os_str())).count() > 0;
+            if changed {
+                reload(&path, &index, &kernel_cache, &build_index);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn reload<B>(path: &Path, index: &SharedIndex, kernel_cache: &KernelCache, build_index: &B)
+    where
+        B: Fn(&Path, &TarIndex) -> Result<TarIndex, TarFsError> {
+    info!("{} changed, re-indexing...", path.display());
+    let built = build_index(path, &index.read().unwrap());
+    match built {
+        Ok(new_index) => {
+            kernel_cache.swap_index(index, new_index);
+            info!("Now serving the new version of {}", path.display());
+        },
+        // Most likely the archive is still being written: Keep serving the old index until the next event
+        Err(e) => debug!("Unable to index {}, keeping the old index: {}", path.display(), e),
+    }
+}
@@ -0,0 +1,111 @@
+//! Just enough XDR (RFC 4506) for the NFS server: big endian, everything padded to 4 bytes
+
+use std::io;
+
+pub struct XdrReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> XdrReader<'a> {
+    pub fn new(buf: &'a [u8]) -> XdrReader<'a> {
+        XdrReader {
+            buf,
+            pos: 0,
+        }
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        let high = self.u32()? as u64;
+        let low = self.u32()? as u64;
+        Ok(high << 32 | low)
+    }
+
+    /// Variable length opaque data of at most max bytes
+    pub fn opaque(&mut self, max: usize) -> io::Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        if len > max {
+            return Err(invalid(format!("opaque data of {} bytes exceeds {}", len, max)));
+        }
+        self.opaque_fixed(len)
+    }
+
+    pub fn opaque_fixed(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let data = self.take(len)?;
+        self.take(padding(len))?;
+        Ok(data)
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.buf.len() - self.pos < len {
+            return Err(invalid("unexpected end of message".to_owned()));
+        }
+        let data = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(data)
+    }
+}
+
+#[derive(Default)]
+pub struct XdrWriter {
+    buf: Vec<u8>,
+}
+
+impl XdrWriter {
+    pub fn new() -> XdrWriter {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u32(value as u32);
+    }
+
+    /// Variable length opaque data (or a string)
+    pub fn opaque(&mut self, data: &[u8]) {
+        self.u32(data.len() as u32);
+        self.opaque_fixed(data);
+    }
+
+    pub fn opaque_fixed(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+        self.buf.extend(std::iter::repeat_n(0, padding(data.len())));
+    }
+
+    /// Appends what another writer wrote
+    pub fn append(&mut self, other: XdrWriter) {
+        self.buf.extend(other.buf);
+    }
+}
+
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,116 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_ar_deb() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("ar_deb");
+    let archive_path = test.archive_path("hello_1.0_amd64.deb")?;
+    build_deb(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        assert_eq!(vec!["control.tar.gz", "data", "data.tar", "debian-binary"], list_dir(mountpoint)?);
+        assert_eq!("2.0\n", fs::read_to_string(mountpoint.join("debian-binary"))?);
+        let member = fs::metadata(mountpoint.join("debian-binary"))?;
+        assert_eq!(0o644, member.mode() & 0o7777);
+        assert_eq!(1_600_000_000, member.mtime());
+
+        // The uncompressed data.tar is expanded
+        assert!(fs::metadata(mountpoint.join("data"))?.is_dir());
+        assert_eq!("Hello, world!\n", fs::read_to_string(mountpoint.join("data/usr/share/hello/greeting"))?);
+        assert_eq!(fs::read_link(mountpoint.join("data/usr/share/hello/link"))?, Path::new("greeting"));
+        assert_eq!(fs::read(mountpoint.join("data.tar"))?.len() % 512, 0);
+
+        // Compressed members are not
+        assert!(fs::metadata(mountpoint.join("control.tar.gz"))?.is_file());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_ar_long_names() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("ar_long_names");
+    let archive_path = test.archive_path("libexample.a")?;
+    let mut archive = b"!<arch>\n".to_vec();
+    // GNU: a symbol table, then the table of long names
+    append_member(&mut archive, "/", &[0, 0, 0, 0]);
+    append_member(&mut archive, "//", b"a_rather_long_object_name.o/\n");
+    append_member(&mut archive, "/0", b"long");
+    // BSD: the name precedes the content
+    append_member(&mut archive, "#1/20", b"bsd_long_object.o\0\0\0odd");
+    append_member(&mut archive, "short.o/", b"short");
+    fs::write(&archive_path, archive)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        assert_eq!(vec!["a_rather_long_object_name.o", "bsd_long_object.o", "short.o"], list_dir(mountpoint)?);
+        assert_eq!("long", fs::read_to_string(mountpoint.join("a_rather_long_object_name.o"))?);
+        assert_eq!("odd", fs::read_to_string(mountpoint.join("bsd_long_object.o"))?);
+        assert_eq!("short", fs::read_to_string(mountpoint.join("short.o"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_ar_oversized_names() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("ar_oversized_names");
+    let mountpoint = test.archive_path("mnt")?;
+    fs::create_dir_all(&mountpoint)?;
+    // A long names table and a BSD name claiming far more than there is, refused rather than allocated
+    for (name, member) in [("table.a", "//"), ("bsd.a", "#1/9999999999")] {
+        let archive_path = test.archive_path(name)?;
+        let mut archive = b"!<arch>\n".to_vec();
+        let header = format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", member, 1_600_000_000, 0, 0, "100644", 9_999_999_999u64);
+        archive.extend_from_slice(header.as_bytes());
+        archive.extend_from_slice(b"short.o/\n");
+        fs::write(&archive_path, archive)?;
+
+        if tarfslib::setup_tar_mount(&archive_path, &mountpoint, &Default::default(), None).is_ok() {
+            panic!("{} got mounted", name);
+        }
+    }
+
+    Ok(())
+}
+
+fn list_dir(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+fn build_deb(archive_path: &Path) -> std::io::Result<()> {
+    let mut data = ArchiveBuilder::new(vec![]);
+    data.file("./usr/share/hello/greeting", b"Hello, world!\n")?;
+    data.symlink("./usr/share/hello/link", "greeting")?;
+    let data = data.into_inner()?;
+
+    let mut archive = b"!<arch>\n".to_vec();
+    append_member(&mut archive, "debian-binary", b"2.0\n");
+    // Not a valid gzip stream, it isn't looked into anyways
+    append_member(&mut archive, "control.tar.gz", b"\x1f\x8b\x08\x00control");
+    append_member(&mut archive, "data.tar", &data);
+    fs::write(archive_path, archive)
+}
+
+/// Appends a member header and content, padded to 2 bytes
+fn append_member(archive: &mut Vec<u8>, name: &str, content: &[u8]) {
+    let header = format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", name, 1_600_000_000, 0, 0, "100644", content.len());
+    assert_eq!(60, header.len());
+    archive.extend_from_slice(header.as_bytes());
+    archive.extend_from_slice(content);
+    if archive.len() % 2 == 1 {
+        archive.push(b'\n');
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{InoMode, MountOptions};
+
+mod common;
+use common::{ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_at_subpaths() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("at_subpaths");
+    let data = test.archive_path("data.tar")?;
+    build_archive(&data, &[("./file", b"data"), ("./dir/nested", b"nested in data")], Some(("./link", "./file")))?;
+    let configs = test.archive_path("configs.tar")?;
+    build_archive(&configs, &[("./app.toml", b"key = 1"), ("./dir/nested", b"nested in configs")], None)?;
+
+    let archives = vec!(
+        (PathBuf::from("/data"), data),
+        (PathBuf::from("/etc/configs"), configs),
+    );
+    let options = MountOptions {
+        ino_mode: InoMode::Offset,
+        ..Default::default()
+    };
+    test.perform_on_combined(&archives, &options, |mountpoint| {
+        assert_eq!(vec!["data", "etc"], names(mountpoint)?);
+        assert_eq!(vec!["configs"], names(&mountpoint.join("etc"))?);
+        assert_eq!(vec!["dir", "file", "link"], names(&mountpoint.join("data"))?);
+        assert_eq!(vec!["app.toml", "dir"], names(&mountpoint.join("etc/configs"))?);
+
+        assert_eq!(b"data".to_vec(), fs::read(mountpoint.join("data/file"))?);
+        assert_eq!(b"data".to_vec(), fs::read(mountpoint.join("data/link"))?);
+        assert_eq!(b"nested in data".to_vec(), fs::read(mountpoint.join("data/dir/nested"))?);
+        assert_eq!(b"key = 1".to_vec(), fs::read(mountpoint.join("etc/configs/app.toml"))?);
+        assert_eq!(b"nested in configs".to_vec(), fs::read(mountpoint.join("etc/configs/dir/nested"))?);
+
+        // Same offsets in their archives, different inos
+        let paths = ["", "data", "etc", "etc/configs", "data/file", "data/dir", "data/dir/nested", "etc/configs/app.toml", "etc/configs/dir", "etc/configs/dir/nested"];
+        let inos: HashSet<u64> = paths.iter()
+            .map(|path| fs::metadata(mountpoint.join(path)).map(|meta| meta.ino()))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(paths.len(), inos.len());
+        let file = fs::metadata(mountpoint.join("data/file"))?;
+        assert_eq!(file.ino(), fs::metadata(mountpoint.join("data/link"))?.ino());
+        assert_eq!(2, file.nlink());
+        // 2 + configs
+        assert_eq!(3, fs::metadata(mountpoint.join("etc"))?.nlink());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_at_overlapping_subpaths() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("at_overlapping_subpaths");
+    let data = test.archive_path("data.tar")?;
+    build_archive(&data, &[("./file", b"data")], None)?;
+    let mountpoint = test.archive_path("mnt")?;
+    fs::create_dir_all(&mountpoint)?;
+
+    let archives = vec!(
+        (PathBuf::from("/data"), data.clone()),
+        (PathBuf::from("/data/more"), data),
+    );
+    let err = tarfslib::setup_combined_mount(&archives, &mountpoint, &Default::default(), None).unwrap_err();
+    assert_eq!("subpaths /data and /data/more overlap", err.to_string());
+    Ok(())
+}
+
+fn names(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+fn build_archive(archive_path: &Path, files: &[(&str, &[u8])], hard_link: Option<(&str, &str)>) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for (path, content) in files {
+        builder.file(path, content)?;
+    }
+    if let Some((path, target)) = hard_link {
+        builder.hard_link(path, target)?;
+    }
+    builder.finish()
+}
@@ -0,0 +1,128 @@
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{AtimeMode, MountOptions};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+const MTIME: i64 = 1556277134;
+
+#[test]
+fn tarfs_atime_static() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("atime_static");
+    let archive_path = test.archive_path("atime.tar")?;
+    build_archive(&archive_path)?;
+
+    // Reads don't change anything by default
+    test.perform_on(&archive_path, |mountpoint| {
+        let file = mountpoint.join("file");
+        assert_eq!(b"content\n".to_vec(), fs::read(&file)?);
+        assert_eq!(MTIME, fs::metadata(&file)?.atime());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_atime_relatime() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("atime_relatime");
+    let archive_path = test.archive_path("atime.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        atime: AtimeMode::Relatime,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let file = mountpoint.join("file");
+        // Not read yet
+        assert_eq!(MTIME, fs::metadata(&file)?.atime());
+
+        let before = now();
+        fs::read(&file)?;
+        let atime = fs::metadata(&file)?.atime();
+        assert!(atime >= before, "atime {} not updated by the read", atime);
+        // Newer than mtime and less than a day old: Not updated again
+        thread::sleep(Duration::from_millis(1100));
+        fs::read(&file)?;
+        assert_eq!(atime, fs::metadata(&file)?.atime());
+        // Other attrs as they were, files never read as well
+        assert_eq!(MTIME, fs::metadata(&file)?.mtime());
+        assert_eq!(MTIME, fs::metadata(mountpoint.join("other"))?.atime());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_atime_strict() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("atime_strict");
+    let archive_path = test.archive_path("atime.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        atime: AtimeMode::Strict,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let file = mountpoint.join("file");
+        fs::read(&file)?;
+        let first = fs::metadata(&file)?.atime();
+        assert!(first >= MTIME);
+        thread::sleep(Duration::from_millis(1100));
+        fs::read(&file)?;
+        assert!(fs::metadata(&file)?.atime() > first, "atime not updated by the second read");
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_atime_noatime() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("atime_noatime");
+    let archive_path = test.archive_path("atime.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        atime: AtimeMode::Strict,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        // Reads of files opened with O_NOATIME don't update the atime
+        let file = mountpoint.join("file");
+        let mut content = String::new();
+        OpenOptions::new().read(true).custom_flags(libc::O_NOATIME).open(&file)?.read_to_string(&mut content)?;
+        assert_eq!("content\n", content);
+        assert_eq!(MTIME, fs::metadata(&file)?.atime());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for name in ["file", "other"] {
+        let mut header = header(tar::EntryType::Regular, 0o644, 0);
+        header.set_mtime(MTIME as u64);
+        builder.entry(header, name, b"content\n")?;
+    }
+    builder.finish()
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_audit_log() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("audit_log");
+    let archive_path = test.archive_path("secrets.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        audit_log: Some(test.archive_path("audit.log")?),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(content(), fs::read(mountpoint.join("backup/db.sql"))?);
+        // Listing and stat'ing isn't recorded
+        fs::read_dir(mountpoint.join("backup"))?.count();
+
+        let log = fs::read_to_string(mountpoint.parent().unwrap().join("audit.log"))?;
+        let lines: Vec<&str> = log.lines().collect();
+        let uid = format!("\"uid\":{},", unsafe { libc::getuid() });
+        assert!(lines.iter().all(|line| line.starts_with("{\"time\":\"") && line.contains(&uid) && line.contains("\"path\":\"/backup/db.sql\"")), "{}", log);
+        assert!(lines[0].contains("\"op\":\"open\"") && lines[0].ends_with("\"bytes\":0}"), "{}", log);
+        let read: u64 = lines[1..].iter()
+            .map(|line| {
+                assert!(line.contains("\"op\":\"read\""), "{}", line);
+                line.split("\"bytes\":").nth(1).unwrap().trim_end_matches('}').parse::<u64>()
+            })
+            .sum::<Result<_, _>>()?;
+        assert_eq!(content().len() as u64, read);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn content() -> Vec<u8> {
+    (0..200_000u32).map(|i| (i % 251) as u8).collect()
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .entry(header(tar::EntryType::Regular, 0o600, 0), "./backup/db.sql", &content())?
+        .finish()
+}
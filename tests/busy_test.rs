@@ -0,0 +1,104 @@
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{ArchiveBuilder, TarFsTest};
+
+const CONTROL_SOCKET: &str = "/workspace/tarfs/.test/busy/control.sock";
+const FORCE_CONTROL_SOCKET: &str = "/workspace/tarfs/.test/busy_force/control.sock";
+
+#[test]
+fn tarfs_busy_refuses_unmount() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("busy");
+    let archive_path = test.archive_path("busy.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        control_socket: Some(PathBuf::from(CONTROL_SOCKET)),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let mut control = UnixStream::connect(CONTROL_SOCKET)?;
+        let mut lines = BufReader::new(control.try_clone()?).lines();
+        let mut query = move |command: &str| -> std::io::Result<String> {
+            writeln!(control, "{}", command)?;
+            lines.next().unwrap()
+        };
+
+        assert_eq!("{\"busy\":false,\"open_files\":[]}", query("busy")?);
+
+        let file = File::open(mountpoint.join("dir/file"))?;
+        assert_eq!("{\"busy\":true,\"open_files\":[\"/dir/file\"]}", query("busy")?);
+        assert_eq!("{\"error\":\"busy: files are open (see busy, or unmount --force)\"}", query("unmount")?);
+        assert!(is_mounted(mountpoint)?);
+        drop(file);
+
+        // The kernel releases files asynchronously
+        let mut response = String::new();
+        for _ in 0..50 {
+            response = query("unmount")?;
+            if !response.contains("busy") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!("{\"unmounted\":true}", response);
+        assert!(!is_mounted(mountpoint)?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_busy_unmount_force() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("busy_force");
+    let archive_path = test.archive_path("busy.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        control_socket: Some(PathBuf::from(FORCE_CONTROL_SOCKET)),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let mut control = UnixStream::connect(FORCE_CONTROL_SOCKET)?;
+        let mut lines = BufReader::new(control.try_clone()?).lines();
+
+        let mut file = File::open(mountpoint.join("dir/file"))?;
+        control.write_all(b"unmount --force\n")?;
+        assert_eq!("{\"unmounted\":true}", lines.next().unwrap()?);
+        assert!(!is_mounted(mountpoint)?);
+
+        // Detached, but still served to the reader
+        let mut content = String::new();
+        std::io::Read::read_to_string(&mut file, &mut content)?;
+        assert_eq!("content\n", content);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn is_mounted(mountpoint: &Path) -> std::io::Result<bool> {
+    let mountpoint = mountpoint.to_string_lossy();
+    Ok(fs::read_to_string("/proc/self/mounts")?
+        .lines()
+        .any(|line| line.split_whitespace().nth(1) == Some(&mountpoint)))
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .dir("./dir/")?
+        .file("./dir/file", b"content\n")?
+        .finish()
+}
@@ -1,11 +1,17 @@
+// Shared by all integration tests, each of which only uses some of it
+#![allow(dead_code)]
+
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str;
 use std::fs;
+use std::io::{self, Write};
+use std::ops::{Deref, DerefMut};
 use std::thread;
-use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::{sync_channel, SyncSender};
+
+use tarfslib::{MountOptions, TarFsError};
 
-use tarfslib;
 
 const TEST_ROOT: &str = "/workspace/tarfs/.test";
 const TEST_MOUNTPOINT_SUBDIR: &str = "mnt";
@@ -14,42 +20,90 @@ type TarFsTestResult = Result<(), Box<dyn std::error::Error>>;
 
 pub struct TarFsTest {
     source_path: PathBuf,
+    /// Tests using different sources get different dirs so they can run in parallel
+    test_dir: PathBuf,
     mountpoint: PathBuf
 }
 
 impl TarFsTest {
     pub fn new(source_path: &str) -> TarFsTest {
-        let mut mountpoint = PathBuf::from(TEST_ROOT);
+        let source_path = PathBuf::from(source_path);
+        let mut test_dir = PathBuf::from(TEST_ROOT);
+        test_dir.push(source_path.file_name().unwrap());
+        let mut mountpoint = test_dir.clone();
         mountpoint.push(TEST_MOUNTPOINT_SUBDIR);
         TarFsTest {
-            source_path: PathBuf::from(source_path),
-            mountpoint: mountpoint,
+            source_path,
+            test_dir,
+            mountpoint,
         }
     }
 
     pub fn perform(&self, test: fn(&Path) -> TarFsTestResult) -> TarFsTestResult {
         let archive_path = self.create_test_tar()?;
-        self.setup_fs_mnt(&archive_path)?;
+        self.setup_fs_mnt(&archive_path, Default::default())?;
+
+        test(&self.mountpoint)?;
+
+        Ok(())
+    }
+
+    /// Mounts an archive the test built itself (see archive_path) instead of one created from source_path
+    pub fn perform_on(&self, archive_path: &Path, test: fn(&Path) -> TarFsTestResult) -> TarFsTestResult {
+        self.perform_on_with(archive_path, &Default::default(), test)
+    }
+
+    pub fn perform_on_with(&self, archive_path: &Path, options: &MountOptions, test: fn(&Path) -> TarFsTestResult) -> TarFsTestResult {
+        self.setup_fs_mnt(archive_path, options.clone())?;
+
+        test(&self.mountpoint)?;
+
+        Ok(())
+    }
+
+    /// Mounts several archives the test built itself, each at its subpath
+    pub fn perform_on_combined(&self, archives: &[(PathBuf, PathBuf)], options: &MountOptions, test: fn(&Path) -> TarFsTestResult) -> TarFsTestResult {
+        let archives = archives.to_vec();
+        let options = options.clone();
+        self.mount(move |mountpoint, tx| tarfslib::setup_combined_mount(&archives, mountpoint, &options, Some(tx)))?;
 
         test(&self.mountpoint)?;
 
         Ok(())
     }
 
+    /// Mounts an archive embedded into the test binary
+    pub fn perform_on_embedded(&self, archive: &'static [u8], options: &MountOptions, test: fn(&Path) -> TarFsTestResult) -> TarFsTestResult {
+        let options = options.clone();
+        self.mount(move |mountpoint, tx| tarfslib::embedded::mount_with(archive, mountpoint, &options, Some(tx)))?;
+
+        test(&self.mountpoint)?;
+
+        Ok(())
+    }
+
+    /// Where a test should put an archive it builds itself
+    pub fn archive_path(&self, archive_filename: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        fs::create_dir_all(&self.test_dir)?;
+        let mut archive_path = self.test_dir.clone();
+        archive_path.push(archive_filename);
+        Ok(archive_path)
+    }
+
     fn create_test_tar(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
-        let mut archive_path = PathBuf::from(TEST_ROOT);
+        let mut archive_path = self.test_dir.clone();
         let mut archive_filename = self.source_path.file_name().unwrap().to_os_string();
         archive_filename.push(".tar");
         archive_path.push(&archive_filename);
 
         let archive_parent = archive_path.parent().unwrap();
         if !archive_parent.exists() {
-            fs::create_dir_all(&archive_parent)?;
+            fs::create_dir_all(archive_parent)?;
         }
 
         match Command::new("bash")
             // posix format is needed for nanosecond precision for timestamps
-            .args(&["-c", &format!("tar cf {} -H posix ./*", archive_path.to_str().unwrap())])
+            .args(["-c", &format!("tar cf {} -H posix ./*", archive_path.to_str().unwrap())])
             .current_dir(&self.source_path)
             .output() {
             Ok(out) => {
@@ -66,8 +120,13 @@ impl TarFsTest {
         }
     }
 
-    fn setup_fs_mnt(&self, archive_path: &Path) -> TarFsTestResult {
+    fn setup_fs_mnt(&self, archive_path: &Path, options: MountOptions) -> TarFsTestResult {
         let archive_path = PathBuf::from(archive_path);
+        self.mount(move |mountpoint, tx| tarfslib::setup_tar_mount(&archive_path, mountpoint, &options, Some(tx)))
+    }
+
+    fn mount<F>(&self, mount: F) -> TarFsTestResult
+        where F: FnOnce(&Path, SyncSender<()>) -> Result<(), TarFsError> + Send + 'static {
         let mountpoint = self.mountpoint.clone();
 
         // Clean state
@@ -78,9 +137,9 @@ impl TarFsTest {
 
         let (tx, rx) = sync_channel(1);
         thread::spawn(move || {
-            match tarfslib::setup_tar_mount(&archive_path, &mountpoint, Some(tx)) {
+            match mount(&mountpoint, tx) {
                 Ok(_) => (),
-                Err(e) => println!("setup_tar_mount error: {}", e)
+                Err(e) => println!("mount error: {}", e)
             }
         });
         let r = rx.recv();
@@ -93,12 +152,12 @@ impl TarFsTest {
 
     fn teardown_fs_mnt(&self) {
         match Command::new("sudo")
-            .args(&["umount", self.mountpoint.to_str().unwrap()])
+            .args(["umount", self.mountpoint.to_str().unwrap()])
             .output() {
             Ok(_) => (),
             Err(e) => println!("sudo umount error: {}", e),
         };
-        match fs::remove_dir_all(TEST_ROOT) {
+        match fs::remove_dir_all(&self.test_dir) {
             Ok(_) => (),
             Err(e) => println!("error during cleanup: {}", e),
         };
@@ -109,4 +168,95 @@ impl Drop for TarFsTest {
     fn drop(&mut self) {
         self.teardown_fs_mnt();
     }
-}
\ No newline at end of file
+}
+
+/// Fills in a header as most tests need it: owned by root and dated 2019-04-26
+pub fn fill_header(mut header: tar::Header, entry_type: tar::EntryType, mode: u32, size: u64) -> tar::Header {
+    header.set_entry_type(entry_type);
+    header.set_size(size);
+    header.set_mode(mode);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(1556277134);
+    header
+}
+
+/// A GNU header filled in by fill_header
+pub fn header(entry_type: tar::EntryType, mode: u32, size: u64) -> tar::Header {
+    fill_header(tar::Header::new_gnu(), entry_type, mode, size)
+}
+
+/// Builds an archive of regular files (see ArchiveBuilder::file) at archive_path
+pub fn build_archive(archive_path: &Path, files: &[(&str, &[u8])]) -> io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for (path, content) in files {
+        builder.file(path, content)?;
+    }
+    builder.finish()
+}
+
+/// Appends members with headers as made by header(), derefs to tar::Builder for anything else
+pub struct ArchiveBuilder<W: Write = fs::File>(tar::Builder<W>);
+
+impl ArchiveBuilder {
+    pub fn create(archive_path: &Path) -> io::Result<ArchiveBuilder> {
+        Ok(ArchiveBuilder::new(fs::File::create(archive_path)?))
+    }
+}
+
+impl<W: Write> ArchiveBuilder<W> {
+    pub fn new(writer: W) -> ArchiveBuilder<W> {
+        ArchiveBuilder(tar::Builder::new(writer))
+    }
+
+    /// A regular file with mode 0o644
+    pub fn file(&mut self, path: impl AsRef<Path>, content: &[u8]) -> io::Result<&mut Self> {
+        self.entry(header(tar::EntryType::Regular, 0o644, 0), path, content)
+    }
+
+    /// A directory with mode 0o755
+    pub fn dir(&mut self, path: impl AsRef<Path>) -> io::Result<&mut Self> {
+        self.entry(header(tar::EntryType::Directory, 0o755, 0), path, b"")
+    }
+
+    /// A symlink with mode 0o777
+    pub fn symlink(&mut self, path: impl AsRef<Path>, target: impl AsRef<Path>) -> io::Result<&mut Self> {
+        self.link(header(tar::EntryType::Symlink, 0o777, 0), path, target)
+    }
+
+    /// A hard link with mode 0o644
+    pub fn hard_link(&mut self, path: impl AsRef<Path>, target: impl AsRef<Path>) -> io::Result<&mut Self> {
+        self.link(header(tar::EntryType::Link, 0o644, 0), path, target)
+    }
+
+    /// A member with a header of the test's own, its size taken from content
+    pub fn entry(&mut self, mut header: tar::Header, path: impl AsRef<Path>, content: &[u8]) -> io::Result<&mut Self> {
+        header.set_size(content.len() as u64);
+        self.0.append_data(&mut header, path, content)?;
+        Ok(self)
+    }
+
+    /// A hard or symbolic link with a header of the test's own
+    pub fn link(&mut self, mut header: tar::Header, path: impl AsRef<Path>, target: impl AsRef<Path>) -> io::Result<&mut Self> {
+        self.0.append_link(&mut header, path, target)?;
+        Ok(self)
+    }
+
+    pub fn into_inner(self) -> io::Result<W> {
+        self.0.into_inner()
+    }
+}
+
+impl<W: Write> Deref for ArchiveBuilder<W> {
+    type Target = tar::Builder<W>;
+
+    fn deref(&self) -> &tar::Builder<W> {
+        &self.0
+    }
+}
+
+impl<W: Write> DerefMut for ArchiveBuilder<W> {
+    fn deref_mut(&mut self) -> &mut tar::Builder<W> {
+        &mut self.0
+    }
+}
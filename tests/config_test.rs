@@ -0,0 +1,65 @@
+use std::fs;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{Config, ConfigValue};
+
+mod common;
+use common::TarFsTest;
+
+#[test]
+fn tarfs_config() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("config");
+    let path = test.archive_path("tarfs.toml")?;
+    fs::write(&path, r#"
+# Applies to all mounts
+[defaults]
+inos = "offset"
+time-offset = -1_000 # shifted
+
+[mounts.backup]
+archive = "/srv/backup.tar"
+watch = true
+
+[mounts."with space"]
+archive = '/srv/C:\raw.tar'
+mountpoint = "/mnt/\"quoted\"\u00e4"
+inos = "sequential"
+"#)?;
+    let config = Config::load(&path)?;
+
+    let defaults = config.settings(None)?;
+    assert_eq!(Some(&ConfigValue::Integer(-1000)), defaults.get("time-offset"));
+    assert_eq!(None, defaults.get("archive"));
+
+    let backup = config.settings(Some("backup"))?;
+    assert_eq!(Some(&ConfigValue::Boolean(true)), backup.get("watch"));
+    assert_eq!(Some(&ConfigValue::String("offset".to_owned())), backup.get("inos"));
+
+    let with_space = config.settings(Some("with space"))?;
+    assert_eq!("/srv/C:\\raw.tar", with_space["archive"].to_string());
+    assert_eq!("/mnt/\"quoted\"ä", with_space["mountpoint"].to_string());
+    assert_eq!("sequential", with_space["inos"].to_string());
+
+    assert!(config.settings(Some("missing")).is_err());
+    Ok(())
+}
+
+#[test]
+fn tarfs_config_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("config_errors");
+    let path = test.archive_path("tarfs.toml")?;
+    for (content, error) in [
+        ("[defaults]\nwatch = yes\n", "tarfs.toml:2: unsupported value"),
+        ("[mounts]\n", "tarfs.toml:1: unknown table [mounts]"),
+        ("inos = \"offset\"\ninos = \"offset\"\n", "tarfs.toml:2: inos set twice"),
+        ("archive = \"/srv/a.tar\n", "tarfs.toml:1: unterminated string"),
+        ("[mounts.a]\n[mounts.a]\n", "tarfs.toml:2: mount \"a\" defined twice"),
+    ].iter() {
+        fs::write(&path, content)?;
+        let e = Config::load(&path).expect_err(content).to_string();
+        assert!(e.contains(error), "{} for {:?}", e, content);
+    }
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::TarFsTest;
+
+const S_IFREG: u32 = 0o100000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFLNK: u32 = 0o120000;
+
+#[test]
+fn tarfs_cpio_newc() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("cpio_newc");
+    let archive_path = test.archive_path("initramfs.cpio")?;
+    build_cpio_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        let etc = fs::metadata(mountpoint.join("etc"))?;
+        assert!(etc.is_dir());
+        assert_eq!(0o700, etc.mode() & 0o7777);
+        assert_eq!("root:x:0:0::/root:/bin/sh\n", fs::read_to_string(mountpoint.join("etc/passwd"))?);
+
+        // Not part of the archive
+        assert!(fs::metadata(mountpoint.join("usr/bin"))?.is_dir());
+        assert_eq!(fs::read_link(mountpoint.join("usr/bin/sh"))?, Path::new("busybox"));
+
+        // GNU cpio stores the content with the last of the links only
+        let busybox = fs::metadata(mountpoint.join("usr/bin/busybox"))?;
+        let ls = fs::metadata(mountpoint.join("usr/bin/ls"))?;
+        assert_eq!(busybox.ino(), ls.ino());
+        assert_eq!(2, ls.nlink());
+        assert_eq!("#!busybox\n", fs::read_to_string(mountpoint.join("usr/bin/ls"))?);
+        assert_eq!("#!busybox\n", fs::read_to_string(mountpoint.join("usr/bin/busybox"))?);
+
+        // From the second, concatenated archive
+        assert_eq!("GenuineIntel\n", fs::read_to_string(mountpoint.join("kernel/x86/microcode/GenuineIntel.bin"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_cpio_odc_unsupported() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("cpio_odc");
+    let archive_path = test.archive_path("odc.cpio")?;
+    let mut archive = b"070707".to_vec();
+    archive.resize(512, b'0');
+    fs::write(&archive_path, archive)?;
+
+    let mountpoint = test.archive_path("mnt")?;
+    fs::create_dir_all(&mountpoint)?;
+    match tarfslib::setup_tar_mount(&archive_path, &mountpoint, &Default::default(), None) {
+        Err(tarfslib::TarFsError::UnsupportedFormat { msg }) => assert!(msg.contains("newc"), "unexpected message: {}", msg),
+        r => panic!("expected UnsupportedFormat, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_cpio_oversized_header() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("cpio_oversized");
+    let mountpoint = test.archive_path("mnt")?;
+    fs::create_dir_all(&mountpoint)?;
+    // A name and a symlink target of 4 GiB each, refused rather than allocated
+    for (name, mode, filesize, namesize) in [("name.cpio", S_IFREG | 0o644, 0, u32::MAX), ("link.cpio", S_IFLNK | 0o777, u32::MAX, 5)] {
+        let archive_path = test.archive_path(name)?;
+        let mut archive = b"070701".to_vec();
+        for field in [1, mode, 0, 0, 1, 1_600_000_000, filesize, 0, 0, 0, 0, namesize, 0] {
+            archive.extend_from_slice(format!("{:08X}", field).as_bytes());
+        }
+        archive.extend_from_slice(b"link\0\0\0\0");
+        fs::write(&archive_path, archive)?;
+
+        match tarfslib::setup_tar_mount(&archive_path, &mountpoint, &Default::default(), None) {
+            Err(e) => assert!(e.to_string().contains("bytes in the cpio header at offset 0"), "unexpected error: {}", e),
+            Ok(_) => panic!("{} got mounted", name),
+        }
+    }
+
+    Ok(())
+}
+
+fn build_cpio_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut out = File::create(archive_path)?;
+    let mut archive = vec![];
+    append_entry(&mut archive, 1, ".", S_IFDIR | 0o755, 2, b"");
+    append_entry(&mut archive, 2, "etc", S_IFDIR | 0o700, 2, b"");
+    append_entry(&mut archive, 3, "etc/passwd", S_IFREG | 0o644, 1, b"root:x:0:0::/root:/bin/sh\n");
+    append_entry(&mut archive, 4, "usr/bin/ls", S_IFREG | 0o755, 2, b"");
+    append_entry(&mut archive, 5, "usr/bin/sh", S_IFLNK | 0o777, 1, b"busybox");
+    append_entry(&mut archive, 4, "usr/bin/busybox", S_IFREG | 0o755, 2, b"#!busybox\n");
+    append_entry(&mut archive, 0, "TRAILER!!!", 0, 1, b"");
+    // Archives are padded to 512 bytes, then the next one follows
+    archive.resize(archive.len().div_ceil(512) * 512, 0);
+    append_entry(&mut archive, 1, "kernel/x86/microcode/GenuineIntel.bin", S_IFREG | 0o644, 1, b"GenuineIntel\n");
+    append_entry(&mut archive, 0, "TRAILER!!!", 0, 1, b"");
+    out.write_all(&archive)
+}
+
+/// Appends a newc header, name and content, both padded to 4 bytes
+fn append_entry(archive: &mut Vec<u8>, ino: u32, name: &str, mode: u32, nlink: u32, content: &[u8]) {
+    let fields = [ino, mode, 0, 0, nlink, 1_600_000_000, content.len() as u32, 0, 0, 0, 0, name.len() as u32 + 1, 0];
+    archive.extend_from_slice(b"070701");
+    for field in fields.iter() {
+        archive.extend_from_slice(format!("{:08X}", field).as_bytes());
+    }
+    archive.extend_from_slice(name.as_bytes());
+    archive.push(0);
+    archive.resize(archive.len().next_multiple_of(4), 0);
+    archive.extend_from_slice(content);
+    archive.resize(archive.len().next_multiple_of(4), 0);
+}
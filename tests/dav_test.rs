@@ -0,0 +1,79 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::sync_channel;
+use std::thread;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+const ADDRESS: &str = "127.0.0.1:20480";
+
+#[test]
+fn tarfs_dav() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("dav");
+    let archive_path = test.archive_path("dav.tar")?;
+    build_archive(&archive_path)?;
+    serve(archive_path);
+    let url = |path: &str| format!("http://{}{}", ADDRESS, path);
+
+    let options = ureq::request("OPTIONS", &url("/")).call()?;
+    assert_eq!(Some("1"), options.header("DAV"));
+
+    // Listing, names are percent-encoded
+    let propfind = ureq::request("PROPFIND", &url("/")).set("Depth", "1").call()?;
+    assert_eq!(207, propfind.status());
+    let xml = propfind.into_string()?;
+    for href in ["<D:href>/</D:href>", "<D:href>/dir/</D:href>", "<D:href>/with%20space</D:href>", "<D:href>/link</D:href>"].iter() {
+        assert!(xml.contains(href), "{} in {}", href, xml);
+    }
+    assert!(xml.contains("<D:getcontentlength>600</D:getcontentlength>"), "{}", xml);
+
+    let propfind = ureq::request("PROPFIND", &url("/dir/")).set("Depth", "0").call()?;
+    let xml = propfind.into_string()?;
+    assert!(xml.contains("<D:collection/>"), "{}", xml);
+    assert!(!xml.contains("file"), "depth 0 lists no children: {}", xml);
+
+    // Content, also via symlink
+    assert_eq!("with space\n", ureq::get(&url("/with%20space")).call()?.into_string()?);
+    let range = ureq::get(&url("/link")).set("Range", "bytes=590-").call()?;
+    assert_eq!(206, range.status());
+    assert_eq!(Some("bytes 590-599/600"), range.header("Content-Range"));
+    let mut content = vec!();
+    range.into_reader().read_to_end(&mut content)?;
+    assert_eq!(vec![b'x'; 10], content);
+
+    // Read-only and missing
+    assert_status(405, ureq::put(&url("/new")).send_string("new"));
+    assert_status(405, ureq::request("MKCOL", &url("/newdir/")).call());
+    assert_status(404, ureq::get(&url("/missing")).call());
+    Ok(())
+}
+
+fn assert_status(expected: u16, res: Result<ureq::Response, ureq::Error>) {
+    match res {
+        Err(ureq::Error::Status(status, _)) => assert_eq!(expected, status),
+        Ok(response) => panic!("expected {}, got {}", expected, response.status()),
+        Err(e) => panic!("expected {}, got {}", expected, e),
+    }
+}
+
+fn serve(archive_path: PathBuf) {
+    let (tx, rx) = sync_channel(1);
+    thread::spawn(move || {
+        if let Err(e) = tarfslib::serve_tar_dav(&archive_path, ADDRESS, &Default::default(), Some(tx)) {
+            println!("serve_tar_dav error: {}", e);
+        }
+    });
+    rx.recv().expect("server started");
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .file("./dir/file", &[b'x'; 600])?
+        .file("./with space", b"with space\n")?
+        .link(header(tar::EntryType::Symlink, 0o644, 0), "./link", "dir/file")?
+        .finish()
+}
@@ -0,0 +1,73 @@
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::TarFsTest;
+
+#[test]
+fn tarfs_decompress_members() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("decompress_members");
+    let archive_path = test.archive_path("logs.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        decompress_members: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let mut names: Vec<String> = fs::read_dir(mountpoint.join("logs"))?
+            .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_, _>>()?;
+        names.sort();
+        // error.log exists already, garbage.gz isn't gzip compressed
+        assert_eq!(vec!["access.log", "error.log", "error.log.gz", "garbage.gz"], names);
+
+        let access_log = mountpoint.join("logs/access.log");
+        assert_eq!(log().len() as u64, fs::metadata(&access_log)?.len());
+        assert_eq!(log(), fs::read(&access_log)?);
+        // Backwards, after the end of the previous read
+        let file = File::open(&access_log)?;
+        let mut buf = vec![0; 100];
+        file.read_exact_at(&mut buf, 200_000)?;
+        assert_eq!(&log()[200_000..200_100], &buf[..]);
+        file.read_exact_at(&mut buf, 1000)?;
+        assert_eq!(&log()[1000..1100], &buf[..]);
+
+        assert_eq!(b"not gzip".to_vec(), fs::read(mountpoint.join("logs/garbage.gz"))?);
+        assert_eq!(gzip(b"compressed")?, fs::read(mountpoint.join("logs/error.log.gz"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn log() -> Vec<u8> {
+    (0..10_000).flat_map(|i| format!("GET /index.html?page={} HTTP/1.1 200\n", i).into_bytes()).collect()
+}
+
+fn gzip(content: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(content)?;
+    encoder.finish()
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let (access, compressed) = (gzip(&log())?, gzip(b"compressed")?);
+    common::build_archive(archive_path, &[
+        ("./logs/access.log.gz", &access),
+        ("./logs/error.log", b"plain"),
+        ("./logs/error.log.gz", &compressed),
+        ("./logs/garbage.gz", b"not gzip"),
+    ])
+}
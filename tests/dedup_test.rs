@@ -0,0 +1,80 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{DedupMode, MountOptions};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_dedup_content() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("dedup_content");
+    let archive_path = test.archive_path("dedup.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        dedup: Some(DedupMode::Content),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let a = fs::metadata(mountpoint.join("a/LICENSE"))?;
+        let b = fs::metadata(mountpoint.join("b/LICENSE"))?;
+        assert_ne!(a.ino(), b.ino());
+        assert_eq!(1, b.nlink());
+        assert_eq!(0o600, b.mode() & 0o7777, "keeps its own attrs");
+        assert_eq!(license(), fs::read(mountpoint.join("b/LICENSE"))?);
+        assert_eq!(other(), fs::read(mountpoint.join("c/LICENSE"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_dedup_inode() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("dedup_inode");
+    let archive_path = test.archive_path("dedup.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        dedup: Some(DedupMode::Inode),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let a = fs::metadata(mountpoint.join("a/LICENSE"))?;
+        let b = fs::metadata(mountpoint.join("b/LICENSE"))?;
+        let c = fs::metadata(mountpoint.join("c/LICENSE"))?;
+        assert_eq!(a.ino(), b.ino());
+        assert_eq!(2, b.nlink());
+        assert_eq!(0o644, b.mode() & 0o7777, "takes the attrs of the first one");
+        assert_ne!(a.ino(), c.ino(), "same size, different content");
+        assert_eq!(license(), fs::read(mountpoint.join("b/LICENSE"))?);
+        assert_eq!(other(), fs::read(mountpoint.join("c/LICENSE"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn license() -> Vec<u8> {
+    (0..100_000u32).map(|i| (i % 251) as u8).collect()
+}
+
+/// Differs from license in the last byte only
+fn other() -> Vec<u8> {
+    let mut other = license();
+    *other.last_mut().unwrap() ^= 1;
+    other
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for (path, mode, content) in [("./a/LICENSE", 0o644, license()), ("./b/LICENSE", 0o600, license()), ("./c/LICENSE", 0o644, other())] {
+        builder.entry(header(tar::EntryType::Regular, mode, 0), path, &content)?;
+    }
+    builder.finish()
+}
@@ -0,0 +1,86 @@
+use std::fs;
+use std::os::unix::fs::{DirEntryExt, MetadataExt};
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_deterministic() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("deterministic");
+    let archive_path = test.archive_path("deterministic.tar")?;
+    build_archive(&archive_path, &["./b", "./dir/z", "./dir/c", "./B", "./missing/x", "./a"])?;
+
+    let options = MountOptions {
+        deterministic: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(expected_listing(), listing(mountpoint, "")?);
+        // Not taken from the mountpoint or the time of mounting
+        let root = fs::metadata(mountpoint)?;
+        assert_eq!((0o40755, 0, 0, 0), (root.mode(), root.uid(), root.gid(), root.mtime()));
+        assert_eq!(0, fs::metadata(mountpoint.join("missing"))?.mtime());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_deterministic_other_order() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("deterministic_other_order");
+    let archive_path = test.archive_path("deterministic.tar")?;
+    build_archive(&archive_path, &["./missing/x", "./a", "./dir/c", "./dir/z", "./B", "./b"])?;
+
+    let options = MountOptions {
+        deterministic: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(expected_listing(), listing(mountpoint, "")?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn expected_listing() -> Vec<(String, u64)> {
+    vec![
+        (String::from("B"), 2),
+        (String::from("a"), 3),
+        (String::from("b"), 4),
+        (String::from("dir"), 5),
+        (String::from("dir/c"), 6),
+        (String::from("dir/z"), 7),
+        (String::from("missing"), 8),
+        (String::from("missing/x"), 9),
+    ]
+}
+
+/// All paths below dir with their inos in the order readdir returns them, depth first
+fn listing(dir: &Path, prefix: &str) -> std::io::Result<Vec<(String, u64)>> {
+    let mut paths = vec!();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        paths.push((path.clone(), entry.ino()));
+        if entry.file_type()?.is_dir() {
+            paths.extend(listing(&entry.path(), &format!("{}/", path))?);
+        }
+    }
+    Ok(paths)
+}
+
+fn build_archive(archive_path: &Path, paths: &[&str]) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for path in paths {
+        builder.file(path, path.as_bytes())?;
+    }
+    builder.finish()
+}
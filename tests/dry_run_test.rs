@@ -0,0 +1,55 @@
+use std::io;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{DryRun, MountOptions, UnsupportedPolicy};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_dry_run() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("dry_run");
+    let archive_path = test.archive_path("dry_run.tar")?;
+    build_archive(&archive_path)?;
+
+    let dry_run = tarfslib::dry_run_tar(&archive_path, &Default::default())?;
+
+    assert_eq!(DryRun {
+        files: 2,
+        dirs: 1,
+        symlinks: 1,
+        hard_links: 1,
+        content_bytes: 8,
+        archive_bytes: 6 * 512,
+        memory_bytes: dry_run.memory_bytes,
+        problems: vec!("Presenting ./dir/fifo of unsupported type Fifo as regular file".to_string()),
+    }, dry_run);
+    assert!(dry_run.memory_bytes > 0);
+    Ok(())
+}
+
+#[test]
+fn tarfs_dry_run_error() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("dry_run_error");
+    let archive_path = test.archive_path("dry_run.tar")?;
+    build_archive(&archive_path)?;
+    let options = MountOptions { unsupported: UnsupportedPolicy::Error, ..Default::default() };
+
+    let e = tarfslib::dry_run_tar(&archive_path, &options).unwrap_err();
+
+    assert_eq!("entry #4 at offset 2560: ./dir/fifo is of unsupported type Fifo", e.to_string());
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .dir("dir")?
+        .file("dir/file", b"content\n")?
+        .hard_link("dir/hardlink", "dir/file")?
+        .symlink("dir/link", "file")?
+        .entry(header(tar::EntryType::Fifo, 0o644, 0), "dir/fifo", b"")?
+        .finish()
+}
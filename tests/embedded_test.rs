@@ -0,0 +1,26 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::TarFsTest;
+
+static ASSETS: &[u8] = include_bytes!("embedded.tar");
+
+#[test]
+fn tarfs_embedded() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("embedded");
+
+    test.perform_on_embedded(ASSETS, &Default::default(), |mountpoint| {
+        assert_eq!("hello\n", fs::read_to_string(mountpoint.join("index.html"))?);
+        assert_eq!("body {}\n", fs::read_to_string(mountpoint.join("css/app.css"))?);
+        let metadata = fs::metadata(mountpoint.join("css"))?;
+        assert!(metadata.is_dir());
+        assert_eq!(0o755, metadata.permissions().mode() & 0o7777);
+        Ok(())
+    })?;
+
+    Ok(())
+}
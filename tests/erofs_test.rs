@@ -0,0 +1,38 @@
+use std::fs;
+use std::fs::OpenOptions;
+use std::os::unix::fs::{symlink, PermissionsExt};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{build_archive, TarFsTest};
+
+#[test]
+fn tarfs_erofs() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("erofs");
+    let archive_path = test.archive_path("erofs.tar")?;
+    build_archive(&archive_path, &[("./file", b"content\n")])?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        let file = mountpoint.join("file");
+        let results = vec![
+            ("create", fs::write(mountpoint.join("new"), "new")),
+            ("write", OpenOptions::new().write(true).open(&file).map(|_| ())),
+            ("truncate", OpenOptions::new().read(true).write(true).truncate(true).open(&file).map(|_| ())),
+            ("mkdir", fs::create_dir(mountpoint.join("dir"))),
+            ("unlink", fs::remove_file(&file)),
+            ("rename", fs::rename(&file, mountpoint.join("renamed"))),
+            ("link", fs::hard_link(&file, mountpoint.join("link"))),
+            ("symlink", symlink("file", mountpoint.join("symlink"))),
+            ("chmod", fs::set_permissions(&file, fs::Permissions::from_mode(0o600))),
+        ];
+        for (op, result) in results {
+            assert_eq!((op, Some(libc::EROFS)), (op, result.err().and_then(|e| e.raw_os_error())));
+        }
+        assert_eq!(b"content\n".to_vec(), fs::read(&file)?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
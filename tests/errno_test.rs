@@ -0,0 +1,53 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_errnos() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("errnos");
+    let archive_path = test.archive_path("errnos.tar")?;
+    build_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        let file = mountpoint.join("dir/file");
+        let dir = mountpoint.join("dir");
+        let link = mountpoint.join("dir/link");
+
+        assert_eq!(Some(libc::ENOTDIR), errno(fs::read_dir(&file)));
+        assert_eq!(Some(libc::ENOTDIR), errno(fs::metadata(file.join("below"))));
+        assert_eq!(Some(libc::EISDIR), errno(fs::read(&dir)));
+        assert_eq!(Some(libc::EINVAL), errno(fs::read_link(&file)));
+        assert_eq!(Some(libc::EINVAL), errno(fs::read_link(&dir)));
+        assert_eq!(Path::new("file"), fs::read_link(&link)?);
+        assert_eq!(Some(libc::ENOENT), errno(fs::metadata(mountpoint.join("missing"))));
+        assert_eq!(Some(libc::ENOENT), errno(File::open(dir.join("missing"))));
+
+        assert_eq!(Some(libc::EROFS), errno(OpenOptions::new().write(true).open(&file)));
+        assert_eq!(Some(libc::EROFS), errno(File::create(dir.join("new"))));
+        assert_eq!(Some(libc::EROFS), errno(fs::create_dir(dir.join("new"))));
+        assert_eq!(Some(libc::EROFS), errno(fs::remove_file(&file)));
+        assert_eq!(Some(libc::EROFS), errno(fs::rename(&file, dir.join("renamed"))));
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn errno<T>(result: io::Result<T>) -> Option<i32> {
+    result.err().and_then(|e| e.raw_os_error())
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .dir("dir")?
+        .file("dir/file", b"content\n")?
+        .symlink("dir/link", "file")?
+        .finish()
+}
@@ -0,0 +1,74 @@
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+mod common;
+use common::{build_archive, TarFsTest};
+
+/// The errors logged by the mount running in this test process
+static ERRORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct ErrorCollector;
+
+impl Log for ErrorCollector {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() == Level::Error
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            ERRORS.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn tarfs_error_log_rate_limited() -> Result<(), Box<dyn std::error::Error>> {
+    log::set_boxed_logger(Box::new(ErrorCollector))?;
+    log::set_max_level(LevelFilter::Error);
+    let test = TarFsTest::new("error_log");
+    let archive_path = test.archive_path("error_log.tar")?;
+    build_archive(&archive_path, &[("./file", b"content\n")])?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        // Modified in place, which fails all reads from then on
+        let archive_path = mountpoint.parent().unwrap().join("error_log.tar");
+        OpenOptions::new().append(true).open(archive_path)?.write_all(&[0; 512])?;
+        for _ in 0..20 {
+            assert!(fs::read(mountpoint.join("file")).is_err());
+        }
+        assert_eq!(1, read_errors(|error| error.contains("changed since it was indexed")));
+
+        // The suppressed ones are summarized on unmount
+        Command::new("sudo").args(["umount", mountpoint.to_str().unwrap()]).output()?;
+        for _ in 0..50 {
+            if read_errors(|error| error.contains("similar errors of ino")) > 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        // The kernel may retry reads, so there are at least as many as were made after the first
+        let summaries: Vec<String> = ERRORS.lock().unwrap().iter().filter(|error| error.contains("similar errors of ino")).cloned().collect();
+        assert_eq!(1, summaries.len());
+        let suppressed: u64 = summaries[0].strip_prefix("read: ").and_then(|rest| rest.split(' ').next()).unwrap().parse()?;
+        assert!(suppressed >= 19, "{}", summaries[0]);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn read_errors<F: Fn(&str) -> bool>(matching: F) -> usize {
+    ERRORS.lock().unwrap().iter().filter(|error| matching(error)).count()
+}
@@ -0,0 +1,63 @@
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{CacheStats, EvictionPolicy, Evictor};
+
+/// Inserts the keys missed and evicts beyond capacity, like the chunk cache. Returns the keys evicted.
+fn access_all(evictor: &mut Evictor, keys: &[u64]) -> Vec<u64> {
+    let mut evicted = vec!();
+    for key in keys {
+        if !evictor.access(*key) {
+            evictor.insert(*key);
+        }
+        evicted.append(&mut evictor.evict());
+    }
+    evicted
+}
+
+#[test]
+fn tarfs_eviction_lru() {
+    let mut evictor = Evictor::new(EvictionPolicy::Lru, 2);
+    // 1 is used most often, but 2 most recently
+    assert_eq!(Vec::<u64>::new(), access_all(&mut evictor, &[1, 2, 1, 1, 2]));
+    assert_eq!(vec![1], access_all(&mut evictor, &[3]));
+    assert_eq!(vec![2, 3], { let mut keys = evictor.keys(); keys.sort(); keys });
+    assert_eq!(CacheStats { policy: EvictionPolicy::Lru, hits: 3, misses: 3, evictions: 1 }, evictor.stats());
+}
+
+#[test]
+fn tarfs_eviction_lfu() {
+    let mut evictor = Evictor::new(EvictionPolicy::Lfu, 2);
+    // 2 is used less often than 1, though more recently
+    assert_eq!(Vec::<u64>::new(), access_all(&mut evictor, &[1, 1, 1, 2]));
+    // Of the ones used once, the least recently used one goes
+    assert_eq!(vec![2], access_all(&mut evictor, &[3]));
+    assert_eq!(vec![3], access_all(&mut evictor, &[4]));
+    assert!(evictor.contains(1) && evictor.contains(4));
+    assert_eq!(CacheStats { policy: EvictionPolicy::Lfu, hits: 2, misses: 4, evictions: 2 }, evictor.stats());
+}
+
+#[test]
+fn tarfs_eviction_arc() {
+    let mut evictor = Evictor::new(EvictionPolicy::Arc, 2);
+    // 1 is used again, the others are scanned once: they make room for each other rather than evicting 1
+    assert_eq!(Vec::<u64>::new(), access_all(&mut evictor, &[1, 1]));
+    assert_eq!(vec![2, 3], access_all(&mut evictor, &[2, 3, 4]));
+    assert!(evictor.contains(1) && evictor.contains(4));
+    assert_eq!(2, evictor.len());
+
+    // LRU would have evicted 1 for the scan
+    let mut lru = Evictor::new(EvictionPolicy::Lru, 2);
+    assert_eq!(vec![1, 2], access_all(&mut lru, &[1, 1, 2, 3, 4]));
+    assert_eq!(CacheStats { policy: EvictionPolicy::Arc, hits: 1, misses: 4, evictions: 2 }, evictor.stats());
+}
+
+#[test]
+fn tarfs_eviction_policy_names() {
+    for policy in [EvictionPolicy::Lru, EvictionPolicy::Lfu, EvictionPolicy::Arc] {
+        assert_eq!(Ok(policy), policy.name().parse());
+    }
+    assert!("fifo".parse::<EvictionPolicy>().is_err());
+    assert_eq!("{\"policy\":\"arc\",\"hits\":1,\"misses\":2,\"evictions\":0}",
+        CacheStats { policy: EvictionPolicy::Arc, hits: 1, misses: 2, evictions: 0 }.to_json());
+}
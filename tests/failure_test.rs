@@ -0,0 +1,81 @@
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{FailureKind, TarFsError};
+
+mod common;
+use common::TarFsTest;
+
+#[test]
+fn tarfs_failure_kinds() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("failure_kinds");
+    let mountpoint = test.archive_path("mnt")?;
+    fs::create_dir_all(&mountpoint)?;
+    let garbage = test.archive_path("garbage.tar")?;
+    fs::write(&garbage, "not an archive")?;
+    let options = Default::default();
+
+    let missing = tarfslib::setup_tar_mount(&test.archive_path("missing.tar")?, &mountpoint, &options, None).unwrap_err();
+    assert_eq!(FailureKind::BadArchive, missing.kind());
+    assert!(missing.to_string().starts_with("unable to open the archive "), "unexpected message: {}", missing);
+
+    let corrupt = tarfslib::setup_tar_mount(&garbage, &mountpoint, &options, None).unwrap_err();
+    assert_eq!(FailureKind::BadArchive, corrupt.kind());
+
+    let no_mountpoint = tarfslib::setup_tar_mount(&garbage, &mountpoint.join("missing"), &options, None).unwrap_err();
+    assert_eq!(FailureKind::Mountpoint, no_mountpoint.kind());
+    Ok(())
+}
+
+#[test]
+fn tarfs_failure_json() {
+    assert_eq!(3, FailureKind::BadArchive.exit_code());
+    assert_eq!(r#"{"error":"bad_archive","exit_code":3,"message":"unable to read \"a\"\nb"}"#,
+        FailureKind::BadArchive.json("unable to read \"a\"\nb"));
+    assert_eq!(r#"{"error":"fuse_unavailable","exit_code":5,"message":"no /dev/fuse"}"#, FailureKind::FuseUnavailable.json("no /dev/fuse"));
+}
+
+#[test]
+fn tarfs_failure_entry_located() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("failure_entry_located");
+    let archive_path = test.archive_path("corrupt.tar")?;
+    build_archive(&archive_path)?;
+    // Breaks the checksum of the second header, behind the first one and its content
+    let mut archive = fs::read(&archive_path)?;
+    archive[1024 + 148] ^= 1;
+    fs::write(&archive_path, archive)?;
+
+    match tarfslib::index_tar(&archive_path, &Default::default()) {
+        Err(e @ TarFsError::EntryError { .. }) => {
+            assert_eq!(FailureKind::BadArchive, e.kind());
+            assert!(e.to_string().starts_with("entry #1 at offset 1024: unable to read it: "), "unexpected message: {}", e);
+        },
+        r => panic!("expected an EntryError, got {:?}", r.map(|_| ())),
+    }
+    Ok(())
+}
+
+#[test]
+fn tarfs_failure_read_located() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("failure_read_located");
+    let archive_path = test.archive_path("truncated.tar")?;
+    build_archive(&archive_path)?;
+
+    let index = tarfslib::index_tar(&archive_path, &Default::default())?;
+    // Gone after indexing: the content of b starts behind the second header
+    OpenOptions::new().write(true).open(&archive_path)?.set_len(1024 + 512 + 2)?;
+    match index.read_range(Path::new("b"), 4, 8) {
+        Err(TarFsError::ReadError { path, offset, .. }) => assert_eq!((PathBuf::from("./b"), 1536 + 4), (path, offset)),
+        r => panic!("expected a ReadError, got {:?}", r),
+    }
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> io::Result<()> {
+    common::build_archive(archive_path, &[("a", b"first\n"), ("b", b"second file\n")])
+}
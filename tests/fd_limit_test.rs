@@ -0,0 +1,70 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::TarFsTest;
+
+#[test]
+fn tarfs_max_open_files() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("max_open_files");
+    let archive_path = test.archive_path("files.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        max_open_files: Some(2),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let a = File::open(mountpoint.join("a"))?;
+        // Each handle counts, not each file
+        let _a_again = File::open(mountpoint.join("a"))?;
+        let err = File::open(mountpoint.join("b")).unwrap_err();
+        assert_eq!(Some(libc::EMFILE), err.raw_os_error());
+        // Metadata doesn't need a handle
+        assert!(mountpoint.join("b").metadata()?.is_file());
+
+        // Releasing is asynchronous
+        drop(a);
+        let mut opened = File::open(mountpoint.join("b"));
+        for _ in 0..50 {
+            if opened.is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+            opened = File::open(mountpoint.join("b"));
+        }
+        opened?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_raises_fd_limit() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("raises_fd_limit");
+    let archive_path = test.archive_path("files.tar")?;
+    build_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |_| {
+        // Mounting raised the soft limit of this process as far as it goes
+        let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        assert_eq!(0, unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) });
+        assert_eq!(limit.rlim_max, limit.rlim_cur);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> io::Result<()> {
+    common::build_archive(archive_path, &[("a", b"x\n"), ("b", b"x\n")])
+}
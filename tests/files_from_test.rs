@@ -0,0 +1,64 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_files_from() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("files_from");
+    let archive_path = test.archive_path("files_from.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        files_from: Some(vec!(PathBuf::from("etc/passwd"), PathBuf::from("/usr/lib"), PathBuf::from("./missing"))),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(vec!["etc", "usr"], names(mountpoint)?);
+        assert_eq!(vec!["passwd"], names(&mountpoint.join("etc"))?);
+        assert_eq!(vec!["lib"], names(&mountpoint.join("usr"))?);
+        assert_eq!(vec!["a", "b"], names(&mountpoint.join("usr/lib"))?);
+        // Parents as they are in the archive
+        assert_eq!(0o700, fs::metadata(mountpoint.join("etc"))?.permissions().mode() & 0o7777);
+        assert_eq!("passwd\n", fs::read_to_string(mountpoint.join("etc/passwd"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn names(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    let entries = [
+        ("./etc/", 0o700, None),
+        ("./etc/passwd", 0o644, Some("passwd\n")),
+        ("./etc/shadow", 0o600, Some("shadow\n")),
+        ("./usr/", 0o755, None),
+        ("./usr/bin/x", 0o755, Some("x\n")),
+        ("./usr/lib/a", 0o644, Some("a\n")),
+        ("./usr/lib/b", 0o644, Some("b\n")),
+        ("./top", 0o644, Some("top\n")),
+    ];
+    for (path, mode, content) in entries {
+        match content {
+            Some(content) => builder.entry(header(tar::EntryType::Regular, mode, 0), path, content.as_bytes())?,
+            None => builder.entry(header(tar::EntryType::Directory, mode, 0), path, b"")?,
+        };
+    }
+    builder.finish()
+}
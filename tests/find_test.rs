@@ -0,0 +1,94 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{FindPattern, Found};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_find_glob() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("find_glob");
+    let archive_path = test.archive_path("find.tar")?;
+    build_archive(&archive_path)?;
+    let options = Default::default();
+
+    // Without a slash, names match at any depth
+    assert_eq!(vec!["logs/a.log", "logs/old/b.log", "logs/same.log"], paths(tarfslib::find_in_tar(&archive_path, &FindPattern::glob("*.log")?, &options)?));
+    assert_eq!(vec!["logs/old/b.log"], paths(tarfslib::find_in_tar(&archive_path, &FindPattern::glob("[!a].log")?, &options)?));
+    // With one, whole paths
+    assert_eq!(vec!["logs/a.log", "logs/same.log"], paths(tarfslib::find_in_tar(&archive_path, &FindPattern::glob("logs/*.log")?, &options)?));
+    assert_eq!(vec!["logs/a.log", "logs/old/b.log", "logs/same.log"], paths(tarfslib::find_in_tar(&archive_path, &FindPattern::glob("logs/**.log")?, &options)?));
+    assert!(tarfslib::find_in_tar(&archive_path, &FindPattern::glob("*.txt")?, &options)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn tarfs_find_regex() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("find_regex");
+    let archive_path = test.archive_path("find.tar")?;
+    build_archive(&archive_path)?;
+    let options = Default::default();
+
+    assert_eq!(vec!["logs/old", "logs/old/b.log"], paths(tarfslib::find_in_tar(&archive_path, &FindPattern::regex("old")?, &options)?));
+    assert_eq!(vec!["current"], paths(tarfslib::find_in_tar(&archive_path, &FindPattern::regex("^cur")?, &options)?));
+    assert!(FindPattern::regex("(").is_err());
+    Ok(())
+}
+
+#[test]
+fn tarfs_find_metadata() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("find_metadata");
+    let archive_path = test.archive_path("find.tar")?;
+    build_archive(&archive_path)?;
+
+    let found = tarfslib::find_in_tar(&archive_path, &FindPattern::glob("*")?, &Default::default())?;
+
+    let a_log = &found[2];
+    assert_eq!(Found {
+        path: PathBuf::from("logs/a.log"),
+        mode: libc::S_IFREG | 0o640,
+        uid: 1000,
+        gid: 100,
+        size: 4,
+        mtime: UNIX_EPOCH + Duration::from_secs(1556277134),
+        link_target: None,
+        hard_link: false,
+    }, *a_log);
+    let lines: Vec<String> = found.iter().map(Found::to_string).collect();
+    assert_eq!(vec![
+        "lrwxrwxrwx 0/0 10 2019-04-26T11:12:14Z current -> logs/a.log",
+        "-rw-r----- 1000/100 4 2019-04-26T11:12:14Z logs/a.log",
+        "drwxr-xr-x 1000/100 4096 2019-04-26T11:12:14Z logs/old",
+        "-rw-r----- 1000/100 4 2019-04-26T11:12:14Z logs/old/b.log",
+        "hrw-r----- 1000/100 4 2019-04-26T11:12:14Z logs/same.log link to logs/a.log",
+    ], lines[..1].iter().chain(&lines[2..]).cloned().collect::<Vec<_>>());
+    // Synthesized
+    assert!(lines[1].starts_with('d') && lines[1].ends_with(" logs"), "unexpected line: {}", lines[1]);
+    Ok(())
+}
+
+fn paths(found: Vec<Found>) -> Vec<String> {
+    found.into_iter().map(|found| found.path.display().to_string()).collect()
+}
+
+fn build_archive(archive_path: &Path) -> io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .entry(user_header(tar::EntryType::Regular, 0o640), "logs/a.log", b"one\n")?
+        .entry(user_header(tar::EntryType::Directory, 0o755), "logs/old", b"")?
+        .entry(user_header(tar::EntryType::Regular, 0o640), "logs/old/b.log", b"two\n")?
+        .link(user_header(tar::EntryType::Link, 0o640), "logs/same.log", "logs/a.log")?
+        .symlink("current", "logs/a.log")?
+        .finish()
+}
+
+fn user_header(entry_type: tar::EntryType, mode: u32) -> tar::Header {
+    let mut header = header(entry_type, mode, 0);
+    header.set_uid(1000);
+    header.set_gid(100);
+    header
+}
@@ -0,0 +1,64 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{build_archive, TarFsTest};
+
+const ADDRESS: &str = "127.0.0.1:20500";
+const CONTROL_SOCKET: &str = "/workspace/tarfs/.test/health/control.sock";
+
+#[test]
+fn tarfs_healthz() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("health");
+    let archive_path = test.archive_path("health.tar")?;
+    build_archive(&archive_path, &[("file", b"content\n")])?;
+
+    let options = MountOptions {
+        health_address: Some(String::from(ADDRESS)),
+        control_socket: Some(PathBuf::from(CONTROL_SOCKET)),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |_| {
+        let url = format!("http://{}/healthz", ADDRESS);
+        let healthy = ureq::get(&url).call()?;
+        assert_eq!(Some("application/json"), healthy.header("Content-Type"));
+        assert_eq!("{\"healthy\":true}\n", healthy.into_string()?);
+        match ureq::get(&format!("http://{}/other", ADDRESS)).call() {
+            Err(ureq::Error::Status(status, _)) => assert_eq!(404, status),
+            other => panic!("expected 404, got {:?}", other.map(|response| response.status())),
+        }
+
+        let mut control = UnixStream::connect(CONTROL_SOCKET)?;
+        let mut lines = BufReader::new(control.try_clone()?).lines();
+        writeln!(control, "healthz")?;
+        assert_eq!("{\"healthy\":true}", lines.next().unwrap()?);
+
+        // Once the session ended, supervisors get to know
+        writeln!(control, "unmount")?;
+        assert_eq!("{\"unmounted\":true}", lines.next().unwrap()?);
+        let mut unhealthy = None;
+        for _ in 0..50 {
+            match ureq::get(&url).call() {
+                Err(ureq::Error::Status(status, response)) => {
+                    unhealthy = Some((status, response.into_string()?));
+                    break;
+                },
+                _ => thread::sleep(Duration::from_millis(20)),
+            }
+        }
+        let (status, body) = unhealthy.expect("still healthy after unmounting");
+        assert_eq!(503, status);
+        assert!(body.starts_with("{\"healthy\":false,\"error\":"), "unexpected body: {}", body);
+        Ok(())
+    })?;
+
+    Ok(())
+}
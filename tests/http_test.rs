@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+use std::sync::mpsc::sync_channel;
+use std::thread;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{build_archive, TarFsTest};
+
+const ADDRESS: &str = "127.0.0.1:20481";
+
+#[test]
+fn tarfs_http() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("http");
+    let archive_path = test.archive_path("http.tar")?;
+    build_archive(&archive_path, &[
+        ("./site/index.html", b"<h1>site</h1>\n"),
+        ("./site/style.css", b"body { color: red }"),
+        ("./data.bin", b"data"),
+    ])?;
+    serve(archive_path);
+    let url = |path: &str| format!("http://{}{}", ADDRESS, path);
+    let agent = ureq::AgentBuilder::new().redirects(0).build();
+
+    // Files with guessed types
+    let css = agent.get(&url("/site/style.css")).call()?;
+    assert_eq!(Some("text/css; charset=utf-8"), css.header("Content-Type"));
+    assert_eq!(Some("19"), css.header("Content-Length"));
+    let etag = css.header("ETag").expect("ETag").to_owned();
+    assert_eq!("body { color: red }", css.into_string()?);
+    assert_status(304, agent.get(&url("/site/style.css")).set("If-None-Match", &etag).call());
+    assert_status(416, agent.get(&url("/site/style.css")).set("Range", "bytes=100-").call());
+
+    // Directories: redirect to the trailing slash, then index.html or a listing
+    let redirect = agent.get(&url("/site")).call()?;
+    assert_eq!((301, Some("/site/")), (redirect.status(), redirect.header("Location")));
+    let index_html = agent.get(&url("/site/")).call()?;
+    assert_eq!(Some("text/html; charset=utf-8"), index_html.header("Content-Type"));
+    assert_eq!("<h1>site</h1>\n", index_html.into_string()?);
+    let listing = agent.get(&url("/")).call()?.into_string()?;
+    assert!(listing.contains("<a href=\"/site/\">site/</a>"), "{}", listing);
+    assert!(listing.contains("<a href=\"/data.bin\">data.bin</a>"), "{}", listing);
+
+    assert_status(404, agent.get(&url("/missing")).call());
+    assert_status(405, agent.post(&url("/data.bin")).call());
+    Ok(())
+}
+
+fn assert_status(expected: u16, res: Result<ureq::Response, ureq::Error>) {
+    match res {
+        Err(ureq::Error::Status(status, _)) => assert_eq!(expected, status),
+        Ok(response) => assert_eq!(expected, response.status()),
+        Err(e) => panic!("expected {}, got {}", expected, e),
+    }
+}
+
+fn serve(archive_path: PathBuf) {
+    let (tx, rx) = sync_channel(1);
+    thread::spawn(move || {
+        if let Err(e) = tarfslib::serve_tar_http(&archive_path, ADDRESS, &Default::default(), Some(tx)) {
+            println!("serve_tar_http error: {}", e);
+        }
+    });
+    rx.recv().expect("server started");
+}
@@ -0,0 +1,115 @@
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{fill_header, header, ArchiveBuilder, TarFsTest};
+
+/// Too large for the octal size field (8 GiB - 1 at most)
+const HUGE_SIZE: u64 = 9 * 1024 * 1024 * 1024 + 5;
+const TAIL: &[u8] = b"tail!";
+
+#[test]
+fn tarfs_huge_member_base256() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("huge_member_base256");
+    let archive_path = test.archive_path("huge.tar")?;
+    let mut header = header(tar::EntryType::Regular, 0o644, HUGE_SIZE);
+    // GNU: binary, marked by the high bit
+    assert_eq!(0x80, header.as_old().size[0] & 0x80);
+    header.set_path("./huge")?;
+    header.set_cksum();
+    build_archive(&archive_path, &[header.as_bytes()])?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        check_huge_member(mountpoint)
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_huge_member_pax() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("huge_member_pax");
+    let archive_path = test.archive_path("huge.tar")?;
+    let record = format!(" size={}\n", HUGE_SIZE);
+    let record = format!("{}{}", record.len() + 2, record);
+    let mut pax = fill_header(tar::Header::new_ustar(), tar::EntryType::XHeader, 0o644, record.len() as u64);
+    pax.set_path("./PaxHeaders/huge")?;
+    pax.set_cksum();
+    let mut pax_block = record.into_bytes();
+    pax_block.resize(512, 0);
+    // POSIX: the size field is ignored in favor of the PAX record
+    let mut header = fill_header(tar::Header::new_ustar(), tar::EntryType::Regular, 0o644, 0);
+    header.set_path("./huge")?;
+    header.set_cksum();
+    build_archive(&archive_path, &[pax.as_bytes(), &pax_block, header.as_bytes()])?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        check_huge_member(mountpoint)
+    })?;
+
+    Ok(())
+}
+
+/// Run with --ignored: builds an archive of a few GiB with more than 4M entries and takes a few GiB of memory
+#[test]
+#[ignore]
+fn tarfs_millions_of_entries() -> Result<(), Box<dyn std::error::Error>> {
+    const ENTRIES: u64 = 4_200_000;
+    let test = TarFsTest::new("millions_of_entries");
+    let archive_path = test.archive_path("millions.tar")?;
+    let mut builder = ArchiveBuilder::new(std::io::BufWriter::new(File::create(&archive_path)?));
+    for i in 0..ENTRIES {
+        builder.file(format!("./dir/{:08}", i), b"")?;
+    }
+    builder.into_inner()?.flush()?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        let dir = mountpoint.join("dir");
+        assert_eq!(ENTRIES, fs::read_dir(&dir)?.count() as u64);
+        assert!(fs::metadata(dir.join(format!("{:08}", ENTRIES - 1)))?.ino() > 4_000_000);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn check_huge_member(mountpoint: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let huge = mountpoint.join("huge");
+    assert_eq!(HUGE_SIZE, fs::metadata(&huge)?.len());
+    let file = File::open(&huge)?;
+    let mut tail = vec![0; TAIL.len()];
+    file.read_exact_at(&mut tail, HUGE_SIZE - TAIL.len() as u64)?;
+    assert_eq!(TAIL, &tail[..]);
+    let mut file = file;
+    file.seek(SeekFrom::Start(8 * 1024 * 1024 * 1024))?;
+    let mut zeros = vec![1; 4096];
+    file.read_exact(&mut zeros)?;
+    assert!(zeros.iter().all(|b| *b == 0));
+
+    // Starts beyond 9 GiB
+    assert_eq!("after", fs::read_to_string(mountpoint.join("after"))?);
+    Ok(())
+}
+
+/// Writes the given header blocks followed by HUGE_SIZE bytes of content, which are a hole up to TAIL,
+/// then a small file "after"
+fn build_archive(archive_path: &Path, blocks: &[&[u8]]) -> std::io::Result<()> {
+    let mut file = File::create(archive_path)?;
+    for block in blocks {
+        file.write_all(block)?;
+    }
+    let content_start = file.stream_position()?;
+    let content_end = content_start + HUGE_SIZE;
+    file.write_all_at(TAIL, content_end - TAIL.len() as u64)?;
+    file.seek(SeekFrom::Start(content_end.next_multiple_of(512)))?;
+
+    ArchiveBuilder::new(file)
+        .file("./after", b"after")?
+        .finish()
+}
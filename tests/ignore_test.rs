@@ -0,0 +1,83 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{IgnoreRules, MountOptions};
+
+mod common;
+use common::{ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_ignore_file_next_to_archive() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("ignore_adjacent");
+    let archive_path = test.archive_path("ignore.tar")?;
+    build_archive(&archive_path)?;
+    fs::write(archive_path.with_file_name(".tarfsignore"), "# secrets\n.git/\n*.key\n!public.key\n/cache\n")?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        assert_eq!(vec!["public.key", "src"], names(mountpoint)?);
+        assert_eq!(vec!["cache", "main.rs"], names(&mountpoint.join("src"))?);
+        assert!(!mountpoint.join(".git/config").exists());
+        assert!(!mountpoint.join("src/deploy.key").exists());
+        assert_eq!("pub\n", fs::read_to_string(mountpoint.join("public.key"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_ignore_file_option() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("ignore_option");
+    let archive_path = test.archive_path("ignore.tar")?;
+    build_archive(&archive_path)?;
+    // Given rules replace the ones next to the archive
+    fs::write(archive_path.with_file_name(".tarfsignore"), "*\n")?;
+
+    let options = MountOptions {
+        ignore: Some(Arc::new(IgnoreRules::parse("src/**/*.rs\ncache/\n").unwrap())),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(vec![".git", "cache", "private.key", "public.key", "src"], names(mountpoint)?);
+        assert_eq!(vec!["deploy.key"], names(&mountpoint.join("src"))?);
+        assert_eq!(vec!["config"], names(&mountpoint.join(".git"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn names(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    let entries = [
+        ("./.git/", None),
+        ("./.git/config", Some("[core]\n")),
+        ("./cache", Some("top-level cache\n")),
+        ("./private.key", Some("priv\n")),
+        ("./public.key", Some("pub\n")),
+        ("./src/", None),
+        ("./src/main.rs", Some("fn main() {}\n")),
+        ("./src/deploy.key", Some("deploy\n")),
+        ("./src/cache/", None),
+        ("./src/cache/x.rs", Some("x\n")),
+    ];
+    for (path, content) in entries {
+        match content {
+            Some(content) => builder.file(path, content.as_bytes())?,
+            None => builder.dir(path)?,
+        };
+    }
+    builder.finish()
+}
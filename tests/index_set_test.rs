@@ -0,0 +1,114 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{build_archive, TarFsTest};
+
+use tarfslib::TarIndexSet;
+
+#[test]
+fn tarfs_index_set_lookup() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("index_set_lookup");
+    let (lower, upper, extra) = build_archives(&test)?;
+    let mut set = TarIndexSet::new(&Default::default());
+    set.add(Path::new("/"), &lower)?.add(Path::new("/"), &upper)?.add(Path::new("/opt/extra"), &extra)?;
+    assert_eq!(3, set.len());
+
+    // Found in one archive only
+    let (archive, stat) = set.lookup(Path::new("lib/only-lower"))
+        .ok_or("lib/only-lower not found")?;
+    assert_eq!((0, PathBuf::from("lib/only-lower"), 5), (archive, stat.path, stat.size));
+    let (archive, stat) = set.lookup(Path::new("/opt/extra/tool"))
+        .ok_or("opt/extra/tool not found")?;
+    assert_eq!((2, PathBuf::from("opt/extra/tool"), 6), (archive, stat.path, stat.size));
+
+    // The archive added last wins by default
+    let (archive, stat) = set.lookup(Path::new("etc/config")).ok_or("etc/config not found")?;
+    assert_eq!((1, 9), (archive, stat.size));
+
+    assert_eq!(None, set.lookup(Path::new("etc/missing")));
+    // Not a member of any archive, just the way to one
+    assert_eq!(None, set.lookup(Path::new("opt")));
+    Ok(())
+}
+
+#[test]
+fn tarfs_index_set_conflict_resolver() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("index_set_conflict_resolver");
+    let (lower, upper, _) = build_archives(&test)?;
+    let mut set = TarIndexSet::new(&Default::default());
+    set.add(Path::new("/"), &lower)?.add(Path::new("/"), &upper)?;
+
+    // The smallest member wins
+    set.on_conflict(|path, members| {
+        assert_eq!(Path::new("etc/config"), path);
+        (0..members.len()).min_by_key(|i| members[*i].size).unwrap()
+    });
+    let (archive, stat) = set.lookup(Path::new("etc/config")).ok_or("etc/config not found")?;
+    assert_eq!((0, 4), (archive, stat.size));
+
+    // Picking none of them finds nothing
+    set.on_conflict(|_, members| members.len());
+    assert_eq!(None, set.lookup(Path::new("etc/config")));
+    Ok(())
+}
+
+#[test]
+fn tarfs_index_set_read_dir() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("index_set_read_dir");
+    let (lower, upper, extra) = build_archives(&test)?;
+    let mut set = TarIndexSet::new(&Default::default());
+    set.add(Path::new("/"), &lower)?.add(Path::new("/"), &upper)?.add(Path::new("/opt/extra"), &extra)?;
+
+    assert_eq!(Some(names(&["etc", "lib", "opt"])), set.read_dir(Path::new("/")));
+    assert_eq!(Some(names(&["config", "only-upper"])), set.read_dir(Path::new("etc")));
+    assert_eq!(Some(names(&["extra"])), set.read_dir(Path::new("opt")));
+    assert_eq!(Some(names(&["tool"])), set.read_dir(Path::new("opt/extra")));
+    assert_eq!(None, set.read_dir(Path::new("etc/config")));
+    assert_eq!(None, set.read_dir(Path::new("missing")));
+    Ok(())
+}
+
+#[test]
+fn tarfs_index_set_add_all() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("index_set_add_all");
+    let (lower, upper, extra) = build_archives(&test)?;
+    let mut set = TarIndexSet::new(&Default::default());
+    let archives = vec!(
+        (PathBuf::from("/"), lower.clone()),
+        (PathBuf::from("/"), upper),
+        (PathBuf::from("/opt/extra"), extra),
+    );
+    set.add_all(&archives, 2)?;
+
+    // Added in the order given, however they got indexed
+    assert_eq!(3, set.len());
+    assert_eq!(Some(0), set.lookup(Path::new("lib/only-lower")).map(|(archive, _)| archive));
+    assert_eq!(Some(1), set.lookup(Path::new("etc/config")).map(|(archive, _)| archive));
+    assert_eq!(Some(2), set.lookup(Path::new("opt/extra/tool")).map(|(archive, _)| archive));
+
+    // One archive failing adds none of them
+    let mut set = TarIndexSet::new(&Default::default());
+    let missing = lower.with_file_name("missing.tar");
+    let err = set.add_all(&[(PathBuf::from("/"), lower), (PathBuf::from("/missing"), missing)], 2).err().ok_or("indexed a missing archive")?;
+    assert!(err.to_string().contains("missing.tar"), "unexpected error: {}", err);
+    assert!(set.is_empty());
+    Ok(())
+}
+
+fn names(names: &[&str]) -> Vec<OsString> {
+    names.iter().map(OsString::from).collect()
+}
+
+fn build_archives(test: &TarFsTest) -> Result<(PathBuf, PathBuf, PathBuf), Box<dyn std::error::Error>> {
+    let lower = test.archive_path("lower.tar")?;
+    build_archive(&lower, &[("etc/config", &b"old\n"[..]), ("lib/only-lower", &b"lower"[..])])?;
+    let upper = test.archive_path("upper.tar")?;
+    build_archive(&upper, &[("etc/config", &b"new = 1\n\n"[..]), ("etc/only-upper", &b""[..])])?;
+    let extra = test.archive_path("extra.tar")?;
+    build_archive(&extra, &[("tool", &b"#!/bin"[..])])?;
+    Ok((lower, upper, extra))
+}
@@ -0,0 +1,81 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_info() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("info");
+    let archive_path = test.archive_path("info.tar")?;
+    build_archive(&archive_path)?;
+
+    let info = tarfslib::info_tar(&archive_path, &Default::default())?;
+    assert_eq!("tar (gnu)", info.format);
+    // data and data/deep/er are synthesized, the fifo is presented as file by default
+    assert_eq!((5, 4, 1, 1, 0), (info.files, info.dirs, info.symlinks, info.hard_links, info.others));
+    assert_eq!(3000 + 20 + 100 + gzip(&[b'x'; 5000])?.len() as u64, info.content_bytes);
+    assert_eq!((0, 0), (info.compressed_files, info.compressed_bytes));
+    assert_eq!(PathBuf::from("big.bin"), info.largest[0].0);
+    assert_eq!(5, info.largest.len());
+    assert_eq!((PathBuf::from("data/deep/er/nested"), 4), info.deepest[0]);
+    assert_eq!(vec![((0, 0), 8), ((1000, 100), 3)], info.owners);
+
+    let lines: Vec<String> = info.to_string().lines().map(String::from).collect();
+    assert!(lines.contains(&String::from("Entries: 5 files, 4 directories, 1 symlinks, 1 hard links, 0 others")), "unexpected output: {:?}", lines);
+    assert!(lines.contains(&String::from("          3000 big.bin")), "unexpected output: {:?}", lines);
+    assert!(lines.contains(&String::from("  1000:100 3")), "unexpected output: {:?}", lines);
+    Ok(())
+}
+
+#[test]
+fn tarfs_info_compressed_members() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("info_compressed_members");
+    let archive_path = test.archive_path("info.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        decompress_members: true,
+        ..Default::default()
+    };
+    let info = tarfslib::info_tar(&archive_path, &options)?;
+    assert_eq!((1, gzip(&[b'x'; 5000])?.len() as u64), (info.compressed_files, info.compressed_bytes));
+    assert_eq!(3000 + 20 + 100 + 5000, info.content_bytes);
+    // Decompressed, it's the largest file
+    assert_eq!((PathBuf::from("data/packed"), 5000), info.largest[0]);
+    Ok(())
+}
+
+fn gzip(content: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(vec!(), Compression::default());
+    encoder.write_all(content)?;
+    encoder.finish()
+}
+
+fn build_archive(archive_path: &Path) -> io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .entry(owned_header(tar::EntryType::Directory, 0), "etc", b"")?
+        .entry(owned_header(tar::EntryType::Regular, 1000), "etc/config", &[b'c'; 20])?
+        .entry(owned_header(tar::EntryType::Regular, 1000), "big.bin", &[0; 3000])?
+        .entry(owned_header(tar::EntryType::Regular, 0), "data/deep/er/nested", &[b'n'; 100])?
+        .entry(owned_header(tar::EntryType::Regular, 0), "data/packed.gz", &gzip(&[b'x'; 5000])?)?
+        .link(owned_header(tar::EntryType::Link, 1000), "etc/config.bak", "etc/config")?
+        .link(owned_header(tar::EntryType::Symlink, 0), "latest", "big.bin")?
+        .entry(owned_header(tar::EntryType::Fifo, 0), "pipe", b"")?
+        .finish()
+}
+
+fn owned_header(entry_type: tar::EntryType, uid: u64) -> tar::Header {
+    let mut header = header(entry_type, 0o644, 0);
+    header.set_uid(uid);
+    header.set_gid(if uid == 0 { 0 } else { 100 });
+    header
+}
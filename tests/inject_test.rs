@@ -0,0 +1,51 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{Inject, MountOptions};
+
+mod common;
+use common::TarFsTest;
+
+fn build_archive(archive_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(common::build_archive(archive_path, &[("file", b"content\n")])?)
+}
+
+#[test]
+fn tarfs_inject_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("inject_errors");
+    let archive_path = test.archive_path("inject.tar")?;
+    build_archive(&archive_path)?;
+    let options = MountOptions { inject: Some("errors=1".parse()?), ..Default::default() };
+
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        // Only reads fail, the metadata is there
+        assert_eq!(8, fs::metadata(mountpoint.join("file"))?.len());
+        assert_eq!(Some(libc::EIO), fs::read(mountpoint.join("file")).unwrap_err().raw_os_error());
+        Ok(())
+    })
+}
+
+#[test]
+fn tarfs_inject_latency() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("inject_latency");
+    let archive_path = test.archive_path("inject.tar")?;
+    build_archive(&archive_path)?;
+    let options = MountOptions { inject: Some("errors=0,latency=20ms".parse()?), ..Default::default() };
+
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(b"content\n".to_vec(), fs::read(mountpoint.join("file"))?);
+        Ok(())
+    })
+}
+
+#[test]
+fn tarfs_inject_spec() {
+    assert_eq!(Ok(Inject { errors: 0.01, latency: Duration::from_millis(50) }), "errors=0.01,latency=50ms".parse());
+    assert_eq!(Ok(Inject { errors: 0.0, latency: Duration::from_secs(2) }), "latency=2s".parse());
+    assert!("errors=2".parse::<Inject>().is_err());
+    assert!("jitter=5ms".parse::<Inject>().is_err());
+}
@@ -0,0 +1,45 @@
+use std::fs;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{InoMode, MountOptions};
+
+mod common;
+use common::{ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_offset_inos() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("offset_inos");
+    let archive_path = test.archive_path("inos.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        ino_mode: InoMode::Offset,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        use std::os::unix::fs::MetadataExt;
+        // Headers at 0 and 1024 (512 bytes of content in between)
+        assert_eq!(2, fs::metadata(mountpoint.join("dir/big"))?.ino());
+        assert_eq!(4, fs::metadata(mountpoint.join("dir/small"))?.ino());
+        assert_eq!(2, fs::metadata(mountpoint.join("hardlink"))?.ino(), "hard link shares its target's ino");
+        // Missing in the archive
+        assert_ne!(0, fs::metadata(mountpoint.join("dir"))?.ino() & (1 << 63));
+
+        assert_eq!(vec![b'x'; 512], fs::read(mountpoint.join("dir/big"))?);
+        assert_eq!(b"small\n".to_vec(), fs::read(mountpoint.join("dir/small"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .file("./dir/big", &[b'x'; 512])?
+        .file("./dir/small", b"small\n")?
+        .hard_link("./hardlink", "./dir/big")?
+        .finish()
+}
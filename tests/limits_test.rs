@@ -0,0 +1,76 @@
+use std::fs;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{LimitPolicy, Limits, MountOptions};
+
+mod common;
+use common::TarFsTest;
+
+#[test]
+fn tarfs_limits_error() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("limits_error");
+    let archive_path = test.archive_path("bomb.tar")?;
+    build_archive(&archive_path)?;
+    let mountpoint = test.archive_path("mnt")?;
+    fs::create_dir_all(&mountpoint)?;
+
+    let limited = |limits: Limits| -> String {
+        let options = MountOptions { limits, ..Default::default() };
+        tarfslib::setup_tar_mount(&archive_path, &mountpoint, &options, None).unwrap_err().to_string()
+    };
+    assert_eq!("entry #0 at offset 0: ./a/b/c/deep is nested deeper than 3 levels (see --max-depth)",
+        limited(Limits { max_depth: Some(3), ..Default::default() }));
+    assert_eq!("entry #1 at offset 1024: ./big is larger than 1 MiB (see --max-member-size)",
+        limited(Limits { max_member_size: Some(1024 * 1024), ..Default::default() }));
+    assert_eq!("entry #2 at offset 2098688: the archive has more than 2 entries (see --max-entries)",
+        limited(Limits { max_entries: Some(2), ..Default::default() }));
+    Ok(())
+}
+
+#[test]
+fn tarfs_limits_skip() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("limits_skip");
+    let archive_path = test.archive_path("bomb.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        limits: Limits {
+            max_depth: Some(3),
+            max_entries: Some(3),
+            max_member_size: Some(1024 * 1024),
+            exceeded: LimitPolicy::Skip,
+        },
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        // The first three entries within the other limits
+        assert_eq!(vec!["a", "one", "two"], names(mountpoint)?);
+        assert_eq!(vec!["shallow"], names(&mountpoint.join("a/b"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn names(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let big = vec![0; 2 * 1024 * 1024];
+    common::build_archive(archive_path, &[
+        ("./a/b/c/deep", b"deep\n"),
+        ("./big", &big),
+        ("./one", b"1\n"),
+        ("./a/b/shallow", b"shallow\n"),
+        ("./two", b"2\n"),
+        ("./three", b"3\n"),
+    ])
+}
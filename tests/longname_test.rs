@@ -0,0 +1,86 @@
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{ArchiveBuilder, TarFsTest};
+
+/// Deep enough for the full path to need several GNU long name blocks
+const DEPTH: usize = 12;
+const CONTENT: &[u8] = b"long names, long links\n";
+
+fn dir_name(level: usize) -> String {
+    format!("{:03}-{}", level, "d".repeat(150))
+}
+
+/// The nested directories, relative to the root
+fn deep_dir() -> PathBuf {
+    (0..DEPTH).map(dir_name).collect()
+}
+
+/// The longest file name Linux allows
+fn file_name() -> String {
+    "f".repeat(255)
+}
+
+#[test]
+fn tarfs_gnu_long_names() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("long_names");
+    let archive_path = test.archive_path("long_names.tar")?;
+    build_long_names_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        use std::os::unix::fs::MetadataExt;
+
+        // Walk down level by level: lookups and readdir
+        let mut dir = PathBuf::from(mountpoint);
+        for level in 0..DEPTH {
+            let names: Vec<String> = fs::read_dir(&dir)?
+                .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<_, _>>()?;
+            assert_eq!(vec![dir_name(level)], names, "readdir at level {}", level);
+            dir.push(dir_name(level));
+        }
+        let mut names: Vec<String> = fs::read_dir(&dir)?
+            .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_, _>>()?;
+        names.sort();
+        assert_eq!(vec![file_name(), String::from("hardlink"), String::from("symlink")], names);
+
+        // Content
+        let file_path = dir.join(file_name());
+        assert_eq!(CONTENT, fs::read(&file_path)?.as_slice());
+
+        // Long symlink target
+        let expected_target = PathBuf::from("/").join(deep_dir()).join(file_name());
+        assert_eq!(expected_target, fs::read_link(dir.join("symlink"))?, "symlink target");
+        assert_eq!(expected_target.as_os_str().len() as u64, fs::symlink_metadata(dir.join("symlink"))?.len(), "symlink size");
+
+        // Long hard link target
+        assert_eq!(CONTENT, fs::read(dir.join("hardlink"))?.as_slice());
+        assert_eq!(fs::metadata(&file_path)?.ino(), fs::metadata(dir.join("hardlink"))?.ino(), "hard link ino");
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn build_long_names_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+
+    let mut dir = PathBuf::from(".");
+    for level in 0..DEPTH {
+        dir.push(dir_name(level));
+        builder.dir(&dir)?;
+    }
+
+    let file_path = dir.join(file_name());
+    builder.file(&file_path, CONTENT)?;
+    builder.symlink(dir.join("symlink"), PathBuf::from("/").join(deep_dir()).join(file_name()))?;
+    builder.hard_link(dir.join("hardlink"), &file_path)?;
+
+    builder.finish()
+}
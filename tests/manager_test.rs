@@ -0,0 +1,122 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc::sync_channel;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{build_archive, TarFsTest};
+
+const SOCKET: &str = "/workspace/tarfs/.test/manager/manager.sock";
+
+#[test]
+fn tarfs_manager_publish_unpublish() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("manager");
+    let archive_path = test.archive_path("v1.tar")?;
+    build_archive(&archive_path, &[("file", b"one\n")])?;
+    let other_archive_path = test.archive_path("v2.tar")?;
+    build_archive(&other_archive_path, &[("file", b"two\n")])?;
+    let target = archive_path.with_file_name("pod/volume");
+
+    let (tx, rx) = sync_channel(1);
+    thread::spawn(move || {
+        if let Err(e) = tarfslib::serve_manager(Path::new(SOCKET), &Default::default(), Some(tx)) {
+            println!("serve_manager error: {}", e);
+        }
+    });
+    rx.recv().expect("manager started");
+
+    let mut manager = UnixStream::connect(SOCKET)?;
+    let mut lines = BufReader::new(manager.try_clone()?).lines();
+    let mut query = move |command: String| -> std::io::Result<String> {
+        writeln!(manager, "{}", command)?;
+        lines.next().unwrap()
+    };
+    let (archive, other_archive, target_str) = (archive_path.display(), other_archive_path.display(), target.display());
+
+    // The target gets created, publishing again changes nothing
+    assert_eq!("{\"published\":true}", query(format!("publish {} {}", archive, target_str))?);
+    assert_eq!("one\n", fs::read_to_string(target.join("file"))?);
+    assert_eq!("{\"published\":true}", query(format!("publish {} {}", archive, target_str))?);
+    assert_eq!(format!("{{\"error\":\"{} is published from {} already\"}}", target_str, archive), query(format!("publish {} {}", other_archive, target_str))?);
+    assert_eq!(format!("{{\"published\":[{{\"target\":\"{}\",\"archive\":\"{}\"}}]}}", target_str, archive), query(String::from("list"))?);
+
+    // Unpublishing removes the target again, and is fine if there's nothing to do
+    assert_eq!("{\"unpublished\":true}", query(format!("unpublish {}", target_str))?);
+    assert!(!target.exists());
+    assert_eq!("{\"unpublished\":true}", query(format!("unpublish {}", target_str))?);
+    assert_eq!("{\"published\":[]}", query(String::from("list"))?);
+
+    // Unmounted by someone else: forgotten, the target removed as well
+    assert_eq!("{\"published\":true}", query(format!("publish {} {}", archive, target_str))?);
+    assert!(Command::new("umount").arg(&target).status()?.success());
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while query(String::from("list"))? != "{\"published\":[]}" {
+        assert!(Instant::now() < deadline, "the unmount went unnoticed");
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert!(!target.exists());
+
+    // Failures leave nothing behind
+    let missing = archive_path.with_file_name("missing.tar");
+    let response = query(format!("publish {} {}", missing.display(), target_str))?;
+    assert!(response.starts_with(&format!("{{\"error\":\"unable to publish {} at {}: ", missing.display(), target_str)), "unexpected response: {}", response);
+    assert!(!target.exists());
+    assert_eq!("{\"error\":\"usage: unpublish <target>\"}", query(String::from("unpublish"))?);
+    Ok(())
+}
+
+#[test]
+fn tarfs_manager_publish_concurrently() -> Result<(), Box<dyn std::error::Error>> {
+    const SOCKET: &str = "/workspace/tarfs/.test/manager_concurrently/manager.sock";
+    let test = TarFsTest::new("manager_concurrently");
+    let mut volumes = vec!();
+    for i in 0..4 {
+        let archive_path = test.archive_path(&format!("v{}.tar", i))?;
+        build_archive(&archive_path, &[("file", format!("{}\n", i).as_bytes())])?;
+        let target = archive_path.with_file_name(format!("pod/volume{}", i));
+        volumes.push((archive_path, target));
+    }
+
+    let (tx, rx) = sync_channel(1);
+    let options = tarfslib::MountOptions {
+        threads: Some(2),
+        ..Default::default()
+    };
+    thread::spawn(move || {
+        if let Err(e) = tarfslib::serve_manager(Path::new(SOCKET), &options, Some(tx)) {
+            println!("serve_manager error: {}", e);
+        }
+    });
+    rx.recv().expect("manager started");
+
+    // A connection each, like separate requests of an orchestrator
+    let publishers: Vec<_> = volumes.iter().cloned().map(|(archive_path, target)| thread::spawn(move || -> std::io::Result<String> {
+        let mut manager = UnixStream::connect(SOCKET)?;
+        writeln!(manager, "publish {} {}", archive_path.display(), target.display())?;
+        BufReader::new(manager).lines().next().unwrap()
+    })).collect();
+    for publisher in publishers {
+        assert_eq!("{\"published\":true}", publisher.join().unwrap()?);
+    }
+    for (i, (_, target)) in volumes.iter().enumerate() {
+        assert_eq!(format!("{}\n", i), fs::read_to_string(target.join("file"))?);
+    }
+
+    let mut manager = UnixStream::connect(SOCKET)?;
+    let mut lines = BufReader::new(manager.try_clone()?).lines();
+    writeln!(manager, "list")?;
+    let list = lines.next().unwrap()?;
+    assert_eq!(volumes.len(), list.matches("\"target\"").count(), "unexpected list: {}", list);
+    for (_, target) in &volumes {
+        writeln!(manager, "unpublish {}", target.display())?;
+        assert_eq!("{\"unpublished\":true}", lines.next().unwrap()?);
+    }
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::Path;
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_max_memory_exceeded() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("max_memory_exceeded");
+    let archive_path = test.archive_path("many.tar")?;
+    build_archive(&archive_path)?;
+    let mountpoint = test.archive_path("mnt")?;
+    fs::create_dir_all(&mountpoint)?;
+
+    let options = MountOptions {
+        max_memory: Some(64 * 1024),
+        ..Default::default()
+    };
+    let e = tarfslib::setup_tar_mount(&archive_path, &mountpoint, &options, None).expect_err("the index takes more");
+    assert!(e.to_string().contains("--max-memory"), "{}", e);
+    Ok(())
+}
+
+#[test]
+fn tarfs_max_memory_stats() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("max_memory_stats");
+    let archive_path = test.archive_path("many.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        max_memory: Some(64 * 1024 * 1024),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(1000, fs::read_dir(mountpoint.join("files"))?.count());
+        let stats = fs::read_to_string(mountpoint.join(".tarfs/stats.json"))?;
+        let memory: u64 = stats.split("\"index_memory\":").nth(1).and_then(|rest| rest.split(',').next())
+            .expect("index_memory is reported").parse()?;
+        assert!(memory > 1000 * 100 && memory < 64 * 1024 * 1024, "{}", stats);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for i in 0..1000 {
+        builder.file(format!("./files/file{}", i), b"")?;
+    }
+    builder.finish()
+}
@@ -0,0 +1,95 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{fill_header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_metadata_entries() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("metadata_entries");
+    let archive_path = test.archive_path("star.tar")?;
+    build_star_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        // Neither the volume label nor the extended headers show up as files
+        let mut names: Vec<String> = fs::read_dir(mountpoint)?
+            .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_, _>>()?;
+        names.sort();
+        assert_eq!(vec!["file", "long-directory-name"], names);
+
+        // The Solaris extended header applies to the entry following it
+        let solaris = mountpoint.join("long-directory-name/with-a-file-whose-name-is-longer-than-the-header-allows");
+        assert_eq!(b"solaris\n".to_vec(), fs::read(&solaris)?);
+        assert_eq!(1556277135, fs::metadata(&solaris)?.mtime());
+        assert_eq!(1556277134, fs::metadata(mountpoint.join("file"))?.mtime());
+
+        assert_eq!("{\"volume_label\":\"backup 2019-04-26\",\"pax_globals\":{\"SCHILY.archtype\":\"exustar\"}}\n",
+            fs::read_to_string(mountpoint.join(".tarfs/info.json"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_metadata_gnu_volume_label_record() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("metadata_volume_label_record");
+    let archive_path = test.archive_path("gnu-posix.tar")?;
+    let mut builder = ArchiveBuilder::create(&archive_path)?;
+    append_metadata(&mut builder, b'g', "pax_global_header", &pax_records(&[("GNU.volume.label", "nightly")]))?;
+    append_file(&mut builder, "./file", b"content\n")?;
+    builder.finish()?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        assert_eq!("{\"volume_label\":\"nightly\",\"pax_globals\":{\"GNU.volume.label\":\"nightly\"}}\n",
+            fs::read_to_string(mountpoint.join(".tarfs/info.json"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// Like star -c artype=exustar with a volume label writes them, plus a Solaris extended header as written by Solaris tar -E
+fn build_star_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    append_metadata(&mut builder, b'V', "backup 2019-04-26", b"")?;
+    append_metadata(&mut builder, b'g', "./GlobalHead.0.0", &pax_records(&[("SCHILY.archtype", "exustar")]))?;
+    append_file(&mut builder, "./file", b"content\n")?;
+    let records = pax_records(&[
+        ("path", "long-directory-name/with-a-file-whose-name-is-longer-than-the-header-allows"),
+        ("mtime", "1556277135"),
+    ]);
+    append_metadata(&mut builder, b'X', "./XHeader", &records)?;
+    append_file(&mut builder, "./long-directory-name/with-a-file-whose-name-is-", b"solaris\n")?;
+    builder.finish()
+}
+
+fn append_metadata(builder: &mut ArchiveBuilder, entry_type: u8, name: &str, content: &[u8]) -> std::io::Result<()> {
+    let mut header = fill_header(tar::Header::new_ustar(), tar::EntryType::new(entry_type), 0o644, content.len() as u64);
+    header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+    header.set_cksum();
+    builder.append(&header, content)
+}
+
+fn append_file(builder: &mut ArchiveBuilder, path: &str, content: &[u8]) -> std::io::Result<()> {
+    append_metadata(builder, b'0', path, content)
+}
+
+/// Records of a PAX extended header: "<length> <key>=<value>\n", the length including itself
+fn pax_records(records: &[(&str, &str)]) -> Vec<u8> {
+    let mut result = vec!();
+    for (key, value) in records {
+        let rest = format!(" {}={}\n", key, value);
+        let mut len = rest.len() + 1;
+        while len.to_string().len() + rest.len() != len {
+            len += 1;
+        }
+        result.extend(format!("{}{}", len, rest).into_bytes());
+    }
+    result
+}
@@ -0,0 +1,91 @@
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::TarFsTest;
+
+#[test]
+fn tarfs_mime_types() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("mime_types");
+    let archive_path = test.archive_path("mime.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        mime_types: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        // By content first, misleading names don't matter
+        assert_eq!("image/png", mime_type(&mountpoint.join("picture.txt"))?);
+        assert_eq!("text/x-shellscript", mime_type(&mountpoint.join("bin/run"))?);
+        assert_eq!("text/html", mime_type(&mountpoint.join("page"))?);
+        // Then by extension
+        assert_eq!("application/json", mime_type(&mountpoint.join("data.json"))?);
+        assert_eq!("text/plain", mime_type(&mountpoint.join("README"))?);
+        assert_eq!("application/octet-stream", mime_type(&mountpoint.join("blob"))?);
+        assert_eq!("application/x-empty", mime_type(&mountpoint.join("empty"))?);
+
+        assert_eq!(b"user.mime_type\0".to_vec(), xattr_names(&mountpoint.join("blob"))?);
+        assert_eq!(Vec::<u8>::new(), xattr_names(&mountpoint.join("bin"))?);
+        assert_eq!(Some(libc::ENODATA), mime_type(&mountpoint.join("bin")).unwrap_err().raw_os_error());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_mime_types_disabled() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("mime_types_disabled");
+    let archive_path = test.archive_path("mime.tar")?;
+    build_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        assert_eq!(Some(libc::EOPNOTSUPP), mime_type(&mountpoint.join("blob")).unwrap_err().raw_os_error());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn mime_type(path: &Path) -> io::Result<String> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = CString::new("user.mime_type")?;
+    let mut buf = vec![0u8; 256];
+    let n = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    buf.truncate(n as usize);
+    Ok(String::from_utf8(buf).unwrap())
+}
+
+fn xattr_names(path: &Path) -> io::Result<Vec<u8>> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut buf = vec![0u8; 256];
+    let n = unsafe { libc::listxattr(path.as_ptr(), buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    buf.truncate(n as usize);
+    Ok(buf)
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    common::build_archive(archive_path, &[
+        ("./picture.txt", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+        ("./bin/run", b"#!/bin/sh\necho hi\n"),
+        ("./page", b"\n  <!DOCTYPE html>\n<html></html>\n"),
+        ("./data.json", b"{\"a\": 1}\n"),
+        ("./README", "Grüße\n".as_bytes()),
+        ("./blob", b"\x00\x01\x02\x03"),
+        ("./empty", b""),
+    ])
+}
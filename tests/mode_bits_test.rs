@@ -0,0 +1,47 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{fill_header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_special_mode_bits() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("special_mode_bits");
+    let archive_path = test.archive_path("rootfs.tar")?;
+    build_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        let mode = |path: &str| fs::symlink_metadata(mountpoint.join(path)).map(|meta| meta.permissions().mode());
+        assert_eq!(libc::S_IFREG | 0o4755, mode("usr/bin/passwd")?);
+        assert_eq!(libc::S_IFREG | 0o2755, mode("usr/bin/wall")?);
+        assert_eq!(libc::S_IFDIR | 0o1777, mode("tmp")?);
+        assert_eq!(libc::S_IFDIR | 0o2775, mode("srv/shared")?);
+        // Some archivers store the file type in the mode field as well, it must not leak into the presented type
+        assert_eq!(libc::S_IFREG | 0o6755, mode("usr/bin/typed")?);
+        assert_eq!(libc::S_IFDIR | 0o1755, mode("typed-dir")?);
+        assert!(fs::metadata(mountpoint.join("usr/bin/typed"))?.is_file());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    let entries = [
+        ("./tmp/", tar::EntryType::Directory, 0o1777),
+        ("./srv/shared/", tar::EntryType::Directory, 0o2775),
+        ("./typed-dir/", tar::EntryType::Directory, libc::S_IFDIR | 0o1755),
+        ("./usr/bin/passwd", tar::EntryType::Regular, 0o4755),
+        ("./usr/bin/wall", tar::EntryType::Regular, 0o2755),
+        ("./usr/bin/typed", tar::EntryType::Regular, libc::S_IFDIR | 0o6755),
+    ];
+    for (path, entry_type, mode) in entries {
+        builder.entry(fill_header(tar::Header::new_ustar(), entry_type, mode, 0), path, b"")?;
+    }
+    builder.finish()
+}
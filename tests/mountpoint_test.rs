@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::sync_channel;
+use std::thread;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::TarFsTest;
+
+#[test]
+fn tarfs_mountpoint_mkdir() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("mountpoint_mkdir");
+    let archive_path = test.archive_path("mkdir.tar")?;
+    build_archive(&archive_path)?;
+    let mountpoint = test.archive_path("missing")?.join("mnt");
+
+    let e = tarfslib::setup_tar_mount(&archive_path, &mountpoint, &Default::default(), None).expect_err("mountpoint is missing");
+    assert!(e.to_string().contains("does not exist"), "{}", e);
+
+    let options = MountOptions {
+        mkdir: true,
+        ..Default::default()
+    };
+    mount(archive_path, mountpoint.clone(), options);
+    assert_eq!(b"content\n".to_vec(), fs::read(mountpoint.join("file"))?);
+    Command::new("sudo").args(["umount", mountpoint.to_str().unwrap()]).output()?;
+    Ok(())
+}
+
+#[test]
+fn tarfs_mountpoint_nonempty() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("mountpoint_nonempty");
+    let archive_path = test.archive_path("nonempty.tar")?;
+    build_archive(&archive_path)?;
+    let mountpoint = test.archive_path("populated")?;
+    fs::create_dir_all(&mountpoint)?;
+    fs::write(mountpoint.join("hidden"), "hidden")?;
+
+    let e = tarfslib::setup_tar_mount(&archive_path, &mountpoint, &Default::default(), None).expect_err("mountpoint is populated");
+    assert!(e.to_string().contains("is not empty"), "{}", e);
+
+    let options = MountOptions {
+        nonempty: true,
+        ..Default::default()
+    };
+    mount(archive_path, mountpoint.clone(), options);
+    assert!(!mountpoint.join("hidden").exists());
+    assert_eq!(b"content\n".to_vec(), fs::read(mountpoint.join("file"))?);
+    Command::new("sudo").args(["umount", mountpoint.to_str().unwrap()]).output()?;
+    assert!(mountpoint.join("hidden").exists());
+    Ok(())
+}
+
+fn mount(archive_path: PathBuf, mountpoint: PathBuf, options: MountOptions) {
+    let (tx, rx) = sync_channel(1);
+    thread::spawn(move || {
+        if let Err(e) = tarfslib::setup_tar_mount(&archive_path, &mountpoint, &options, Some(tx)) {
+            println!("setup_tar_mount error: {}", e);
+        }
+    });
+    rx.recv().expect("mounted");
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    common::build_archive(archive_path, &[("./file", b"content\n")])
+}
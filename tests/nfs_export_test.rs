@@ -0,0 +1,61 @@
+use std::ffi::CString;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{InoMode, MountOptions};
+
+mod common;
+use common::{build_archive, TarFsTest};
+
+/// Like knfsd does: resolves a file handle after the kernel forgot about the entry
+#[test]
+fn tarfs_nfs_export_file_handles() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("nfs_export");
+    let archive_path = test.archive_path("nfs_export.tar")?;
+    build_archive(&archive_path, &[("./dir/file", b"file\n")])?;
+
+    let options = MountOptions {
+        ino_mode: InoMode::Offset,
+        nfs_export: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let handle = name_to_handle(&mountpoint.join("dir/file"))?;
+        // Drop the kernel's dentries and inodes so it has to ask tarfs for the ino in the handle
+        fs::write("/proc/sys/vm/drop_caches", "2")?;
+
+        let mount_fd = File::open(mountpoint)?;
+        let fd = unsafe { libc::open_by_handle_at(mount_fd.as_raw_fd(), handle.as_ptr() as *mut libc::file_handle, libc::O_RDONLY) };
+        if fd < 0 {
+            return Err(Box::new(std::io::Error::last_os_error()));
+        }
+        let mut content = String::new();
+        unsafe { File::from_raw_fd(fd) }.read_to_string(&mut content)?;
+        assert_eq!("file\n", content);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// Returns a struct file_handle with room for its f_handle
+fn name_to_handle(path: &Path) -> std::io::Result<Vec<u64>> {
+    const MAX_HANDLE_SZ: usize = 128;
+    let mut handle = vec![0u64; (8 + MAX_HANDLE_SZ) / 8];
+    let header = handle.as_mut_ptr() as *mut libc::file_handle;
+    unsafe { (*header).handle_bytes = MAX_HANDLE_SZ as u32 };
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut mount_id = 0;
+    let res = unsafe { libc::name_to_handle_at(libc::AT_FDCWD, path.as_ptr(), header, &mut mount_id, 0) };
+    if res < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(handle)
+}
@@ -0,0 +1,207 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::sync_channel;
+use std::thread;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+const ADDRESS: &str = "127.0.0.1:20490";
+const MOUNT_PROGRAM: u32 = 100005;
+const NFS_PROGRAM: u32 = 100003;
+const NFS3_OK: u32 = 0;
+const NFS3ERR_NOENT: u32 = 2;
+const NFS3ERR_ROFS: u32 = 30;
+const NFS3ERR_STALE: u32 = 70;
+
+#[test]
+fn tarfs_nfs() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("nfs");
+    let archive_path = test.archive_path("nfs.tar")?;
+    build_archive(&archive_path)?;
+    serve(archive_path);
+
+    let mut client = RpcClient::connect()?;
+
+    // MOUNT MNT
+    let mut reply = client.call(MOUNT_PROGRAM, 1, &opaque(b"/"))?;
+    assert_eq!(0, reply.u32());
+    let root = reply.opaque();
+    assert_eq!(vec![1], (0..reply.u32()).map(|_| reply.u32()).collect::<Vec<_>>(), "AUTH_UNIX");
+
+    // LOOKUP dir/file
+    let dir = lookup(&mut client, &root, "dir")?.expect("dir");
+    let file = lookup(&mut client, &dir, "file")?.expect("file");
+    assert_eq!(None, lookup(&mut client, &dir, "missing")?);
+    assert_eq!(Some(root.clone()), lookup(&mut client, &dir, "..")?);
+
+    // GETATTR
+    let mut reply = client.call(NFS_PROGRAM, 1, &opaque(&file))?;
+    assert_eq!(NFS3_OK, reply.u32());
+    let (ftype, mode, size) = (reply.u32(), reply.u32(), { reply.skip(12); reply.u64() });
+    assert_eq!((1, 0o644, 600), (ftype, mode, size));
+
+    // READ: short read at the end
+    let mut args = opaque(&file);
+    args.extend(&590u64.to_be_bytes());
+    args.extend(&4096u32.to_be_bytes());
+    let mut reply = client.call(NFS_PROGRAM, 6, &args)?;
+    assert_eq!(NFS3_OK, reply.u32());
+    reply.post_op_attr();
+    assert_eq!(10, reply.u32());
+    assert_eq!(1, reply.u32(), "eof");
+    assert_eq!(vec![b'x'; 10], reply.opaque());
+
+    // READDIR
+    let mut args = opaque(&root);
+    args.extend(&[0; 16]);  // cookie, verifier
+    args.extend(&4096u32.to_be_bytes());
+    let mut reply = client.call(NFS_PROGRAM, 16, &args)?;
+    assert_eq!(NFS3_OK, reply.u32());
+    reply.post_op_attr();
+    reply.skip(8);
+    let mut names = vec!();
+    while reply.u32() == 1 {
+        reply.skip(8);
+        names.push(String::from_utf8(reply.opaque())?);
+        reply.skip(8);
+    }
+    assert_eq!(vec![".", "..", "dir", "link"], names);
+    assert_eq!(1, reply.u32(), "eof");
+
+    // READLINK
+    let link = lookup(&mut client, &root, "link")?.expect("link");
+    let mut reply = client.call(NFS_PROGRAM, 5, &opaque(&link))?;
+    assert_eq!(NFS3_OK, reply.u32());
+    reply.post_op_attr();
+    assert_eq!(b"dir/file".to_vec(), reply.opaque());
+
+    // Read-only
+    let mut args = opaque(&dir);
+    args.extend(opaque(b"new"));
+    assert_eq!(NFS3ERR_ROFS, client.call(NFS_PROGRAM, 12, &args)?.u32(), "REMOVE");
+
+    // A handle from another generation
+    let mut stale = file.clone();
+    stale[15] ^= 1;
+    assert_eq!(NFS3ERR_STALE, client.call(NFS_PROGRAM, 1, &opaque(&stale))?.u32());
+
+    Ok(())
+}
+
+fn serve(archive_path: PathBuf) {
+    let (tx, rx) = sync_channel(1);
+    thread::spawn(move || {
+        if let Err(e) = tarfslib::serve_tar_nfs(&archive_path, ADDRESS, &Default::default(), Some(tx)) {
+            println!("serve_tar_nfs error: {}", e);
+        }
+    });
+    rx.recv().expect("server started");
+}
+
+/// Returns the handle of name in dir, None for NFS3ERR_NOENT
+fn lookup(client: &mut RpcClient, dir: &[u8], name: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    let mut args = opaque(dir);
+    args.extend(opaque(name.as_bytes()));
+    let mut reply = client.call(NFS_PROGRAM, 3, &args)?;
+    match reply.u32() {
+        NFS3_OK => Ok(Some(reply.opaque())),
+        NFS3ERR_NOENT => Ok(None),
+        status => Err(format!("lookup of {} failed: {}", name, status).into()),
+    }
+}
+
+struct RpcClient {
+    stream: TcpStream,
+    xid: u32,
+}
+
+impl RpcClient {
+    fn connect() -> std::io::Result<RpcClient> {
+        Ok(RpcClient {
+            stream: TcpStream::connect(ADDRESS)?,
+            xid: 0,
+        })
+    }
+
+    /// Returns the results of a successful call with AUTH_UNIX credentials of root
+    fn call(&mut self, program: u32, procedure: u32, args: &[u8]) -> std::io::Result<Reply> {
+        self.xid += 1;
+        let version = 3;
+        let mut call = vec!();
+        for word in [self.xid, 0, 2, program, version, procedure].iter() {
+            call.extend(&word.to_be_bytes());
+        }
+        let mut credentials = vec!();
+        credentials.extend(&0u32.to_be_bytes());    // stamp
+        credentials.extend(opaque(b"test"));
+        credentials.extend(&[0; 12]);               // uid, gid, no gids
+        call.extend(&1u32.to_be_bytes());
+        call.extend(opaque(&credentials));
+        call.extend(&[0; 8]);                       // AUTH_NONE verifier
+        call.extend(args);
+
+        self.stream.write_all(&(0x8000_0000 | call.len() as u32).to_be_bytes())?;
+        self.stream.write_all(&call)?;
+
+        let mut marker = [0; 4];
+        self.stream.read_exact(&mut marker)?;
+        let mut reply = Reply { buf: vec![0; (u32::from_be_bytes(marker) & 0x7fff_ffff) as usize], pos: 0 };
+        self.stream.read_exact(&mut reply.buf)?;
+        assert_eq!([self.xid, 1, 0, 0, 0, 0], [reply.u32(), reply.u32(), reply.u32(), reply.u32(), reply.u32(), reply.u32()], "accepted reply with SUCCESS");
+        Ok(reply)
+    }
+}
+
+struct Reply {
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl Reply {
+    fn u32(&mut self) -> u32 {
+        let mut word = [0; 4];
+        word.copy_from_slice(&self.buf[self.pos..self.pos + 4]);
+        self.pos += 4;
+        u32::from_be_bytes(word)
+    }
+
+    fn u64(&mut self) -> u64 {
+        (self.u32() as u64) << 32 | self.u32() as u64
+    }
+
+    fn opaque(&mut self) -> Vec<u8> {
+        let len = self.u32() as usize;
+        let data = self.buf[self.pos..self.pos + len].to_vec();
+        self.pos += len.div_ceil(4) * 4;
+        data
+    }
+
+    fn skip(&mut self, len: usize) {
+        self.pos += len;
+    }
+
+    fn post_op_attr(&mut self) {
+        if self.u32() == 1 {
+            self.skip(84);
+        }
+    }
+}
+
+fn opaque(data: &[u8]) -> Vec<u8> {
+    let mut xdr = (data.len() as u32).to_be_bytes().to_vec();
+    xdr.extend(data);
+    xdr.resize(xdr.len().div_ceil(4) * 4, 0);
+    xdr
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .file("./dir/file", &[b'x'; 600])?
+        .link(header(tar::EntryType::Symlink, 0o644, 0), "./link", "dir/file")?
+        .finish()
+}
@@ -0,0 +1,219 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{CollisionPolicy, MountOptions, Normalization};
+
+mod common;
+use common::{ArchiveBuilder, TarFsTest};
+
+// "café/résumé.txt", decomposed as macOS stores it and composed as most other systems do
+const NFD_DIR: &str = "cafe\u{301}";
+const NFD_FILE: &str = "cafe\u{301}/re\u{301}sume\u{301}.txt";
+const NFC_DIR: &str = "caf\u{e9}";
+const NFC_FILE: &str = "caf\u{e9}/r\u{e9}sum\u{e9}.txt";
+
+#[test]
+fn tarfs_normalize_nfc() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("normalize_nfc");
+    let archive_path = test.archive_path("normalize.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        normalize: Normalization::Nfc,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        // Listed composed, found in either form
+        assert_eq!(vec![NFC_DIR], names(mountpoint)?);
+        assert_eq!(vec!["link.txt", "r\u{e9}sum\u{e9}.txt"], names(&mountpoint.join(NFC_DIR))?);
+        assert_eq!("cv\n", fs::read_to_string(mountpoint.join(NFC_FILE))?);
+        assert_eq!("cv\n", fs::read_to_string(mountpoint.join(NFD_FILE))?);
+        // The hard link named the decomposed path
+        assert_eq!("cv\n", fs::read_to_string(mountpoint.join(NFC_DIR).join("link.txt"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_normalize_nfd() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("normalize_nfd");
+    let archive_path = test.archive_path("normalize.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        normalize: Normalization::Nfd,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(vec![NFD_DIR], names(mountpoint)?);
+        assert_eq!("cv\n", fs::read_to_string(mountpoint.join(NFC_FILE))?);
+        assert_eq!("cv\n", fs::read_to_string(mountpoint.join(NFD_FILE))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_normalize_none() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("normalize_none");
+    let archive_path = test.archive_path("normalize.tar")?;
+    build_archive(&archive_path)?;
+
+    // Names as they are in the archive
+    test.perform_on(&archive_path, |mountpoint| {
+        assert_eq!(vec![NFD_DIR], names(mountpoint)?);
+        assert_eq!("cv\n", fs::read_to_string(mountpoint.join(NFD_FILE))?);
+        assert_eq!(io::ErrorKind::NotFound, fs::metadata(mountpoint.join(NFC_FILE)).unwrap_err().kind());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_normalize_stat() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("normalize_stat");
+    let archive_path = test.archive_path("normalize.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        normalize: Normalization::Nfc,
+        ..Default::default()
+    };
+    let stat = tarfslib::stat_in_tar(&archive_path, Path::new(NFD_FILE), &options)?;
+    assert_eq!(Path::new(NFC_FILE), stat.path);
+    assert!(tarfslib::stat_in_tar(&archive_path, Path::new(NFC_FILE), &Default::default()).is_err());
+    Ok(())
+}
+
+#[test]
+fn tarfs_normalize_collisions_replace() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("normalize_collisions_replace");
+    let archive_path = test.archive_path("collisions.tar")?;
+    build_colliding_archive(&archive_path)?;
+
+    let options = collision_options(CollisionPolicy::Replace);
+    // The directories merge, the latter file wins
+    assert_eq!(vec![
+        (PathBuf::from(NFC_DIR), None),
+        (PathBuf::from(NFC_FILE), Some(String::from("v2\n"))),
+    ], walked(&archive_path, &options)?);
+    let dry_run = tarfslib::dry_run_tar(&archive_path, &options)?;
+    assert_eq!(vec![format!("./{} collides with ./{} once normalized, the latter wins", NFC_FILE, NFD_FILE)], dry_run.problems);
+    Ok(())
+}
+
+#[test]
+fn tarfs_normalize_collisions_rename() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("normalize_collisions_rename");
+    let archive_path = test.archive_path("collisions.tar")?;
+    build_colliding_archive(&archive_path)?;
+
+    let options = collision_options(CollisionPolicy::Rename);
+    let renamed = format!("{}~1", NFC_FILE);
+    assert_eq!(vec![
+        (PathBuf::from(NFC_DIR), None),
+        (PathBuf::from(NFC_FILE), Some(String::from("cv\n"))),
+        (PathBuf::from(&renamed), Some(String::from("v2\n"))),
+    ], walked(&archive_path, &options)?);
+    let dry_run = tarfslib::dry_run_tar(&archive_path, &options)?;
+    assert_eq!(vec![format!("./{} collides with ./{} once normalized, presenting it as ./{}", NFC_FILE, NFD_FILE, renamed)], dry_run.problems);
+    Ok(())
+}
+
+#[test]
+fn tarfs_normalize_collisions_error() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("normalize_collisions_error");
+    let archive_path = test.archive_path("collisions.tar")?;
+    build_colliding_archive(&archive_path)?;
+
+    let err = tarfslib::dry_run_tar(&archive_path, &collision_options(CollisionPolicy::Error)).err().ok_or("indexed colliding names")?;
+    assert!(err.to_string().contains("collides with"), "unexpected error: {}", err);
+    // Without normalizing, nothing collides
+    assert_eq!(4, walked(&archive_path, &Default::default())?.len());
+    Ok(())
+}
+
+#[test]
+fn tarfs_normalize_collisions_followed() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("normalize_collisions_followed");
+    let archive_path = test.archive_path("collisions.tar")?;
+    build_colliding_archive(&archive_path)?;
+
+    // Entries of growing archives are appended to the index one by one, colliding all the same
+    let options = MountOptions {
+        follow: true,
+        ..collision_options(CollisionPolicy::Rename)
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let dir = mountpoint.join(NFC_DIR);
+        let file = Path::new(NFC_FILE).file_name().unwrap().to_string_lossy().into_owned();
+        assert_eq!(vec![file.clone(), format!("{}~1", file)], names(&dir)?);
+        assert_eq!("cv\n", fs::read_to_string(mountpoint.join(NFC_FILE))?);
+        assert_eq!("v2\n", fs::read_to_string(mountpoint.join(format!("{}~1", NFC_FILE)))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn collision_options(collisions: CollisionPolicy) -> MountOptions {
+    MountOptions {
+        normalize: Normalization::Nfc,
+        collisions,
+        ..Default::default()
+    }
+}
+
+/// The paths of the entries of an archive as indexed, with the content of files
+type Walked = Vec<(PathBuf, Option<String>)>;
+
+fn walked(archive_path: &Path, options: &MountOptions) -> Result<Walked, Box<dyn std::error::Error>> {
+    let index = tarfslib::index_tar(archive_path, options)?;
+    let mut walked = vec!();
+    for (stat, reader) in index.walk() {
+        let content = match reader {
+            Some(mut reader) => {
+                let mut content = String::new();
+                reader.read_to_string(&mut content)?;
+                Some(content)
+            },
+            None => None,
+        };
+        walked.push((stat.path, content));
+    }
+    Ok(walked)
+}
+
+/// Both forms of the same names, as when archiving directories copied between macOS and Linux
+fn build_colliding_archive(archive_path: &Path) -> io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .dir(NFD_DIR)?
+        .file(NFD_FILE, b"cv\n")?
+        .dir(NFC_DIR)?
+        .file(NFC_FILE, b"v2\n")?
+        .finish()
+}
+
+fn names(dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<io::Result<Vec<String>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+fn build_archive(archive_path: &Path) -> io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .dir(NFD_DIR)?
+        .file(NFD_FILE, b"cv\n")?
+        .hard_link(format!("{}/link.txt", NFD_DIR), NFD_FILE)?
+        .finish()
+}
@@ -0,0 +1,48 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_open_flags() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("open_flags");
+    let archive_path = test.archive_path("open_flags.tar")?;
+    build_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        let file = mountpoint.join("dir/file");
+        let errno = |options: &OpenOptions, path: &Path| options.open(path).err().and_then(|e| e.raw_os_error());
+
+        // Anything that would modify the file
+        assert_eq!(Some(libc::EROFS), errno(OpenOptions::new().write(true), &file));
+        assert_eq!(Some(libc::EROFS), errno(OpenOptions::new().read(true).write(true), &file));
+        assert_eq!(Some(libc::EROFS), errno(OpenOptions::new().read(true).custom_flags(libc::O_TRUNC), &file));
+        assert_eq!(Some(libc::EROFS), errno(OpenOptions::new().read(true).custom_flags(libc::O_APPEND), &file));
+
+        assert_eq!(Some(libc::ENOTDIR), errno(OpenOptions::new().read(true).custom_flags(libc::O_DIRECTORY), &file));
+        assert!(OpenOptions::new().read(true).custom_flags(libc::O_DIRECTORY).open(mountpoint.join("dir")).is_ok());
+
+        let link = mountpoint.join("link");
+        assert_eq!(Some(libc::ELOOP), errno(OpenOptions::new().read(true).custom_flags(libc::O_NOFOLLOW), &link));
+        assert!(OpenOptions::new().read(true).open(&link).is_ok());
+
+        assert!(OpenOptions::new().read(true).custom_flags(libc::O_NOFOLLOW).open(&file).is_ok());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .entry(header(tar::EntryType::Directory, 0o644, 0), "dir", b"")?
+        .file("dir/file", b"content\n")?
+        .link(header(tar::EntryType::Symlink, 0o644, 0), "link", "dir/file")?
+        .finish()
+}
@@ -0,0 +1,99 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{fill_header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_owner_names() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("owner_names");
+    let archive_path = test.archive_path("owners.tar")?;
+    build_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        // root exists everywhere
+        let known = fs::metadata(mountpoint.join("known"))?;
+        assert_eq!((0, 0), (known.uid(), known.gid()));
+        let unknown = fs::metadata(mountpoint.join("unknown"))?;
+        assert_eq!((4242, 4343), (unknown.uid(), unknown.gid()));
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_numeric_owner() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("numeric_owner");
+    let archive_path = test.archive_path("owners.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        numeric_owner: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let known = fs::metadata(mountpoint.join("known"))?;
+        assert_eq!((4242, 4343), (known.uid(), known.gid()));
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_pax_ids() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("pax_ids");
+    let archive_path = test.archive_path("pax_ids.tar")?;
+    let mut builder = ArchiveBuilder::create(&archive_path)?;
+    // Ids from PAX records win over the header fields, up to 32 bits
+    append_with_pax(&mut builder, "./large", &[("uid", "3000000000"), ("gid", "3000000001")])?;
+    append_with_pax(&mut builder, "./huge", &[("uid", "5000000000"), ("gid", "4294967295")])?;
+    append_with_pax(&mut builder, "./invalid", &[("uid", "many"), ("gid", "-1")])?;
+    // Including global ones
+    let records = b"14 uid=700000\n14 gid=800000\n";
+    builder.entry(fill_header(tar::Header::new_ustar(), tar::EntryType::XGlobalHeader, 0o644, 0), "pax_global_header", records)?;
+    append_with_pax(&mut builder, "./global", &[])?;
+    builder.finish()?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        let owner = |name: &str| fs::metadata(mountpoint.join(name)).map(|meta| (meta.uid(), meta.gid()));
+        assert_eq!((3000000000, 3000000001), owner("large")?);
+        assert_eq!((65534, 65534), owner("huge")?);
+        assert_eq!((12, 13), owner("invalid")?);
+        assert_eq!((700000, 800000), owner("global")?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn append_with_pax(builder: &mut ArchiveBuilder, path: &str, records: &[(&str, &str)]) -> std::io::Result<()> {
+    if !records.is_empty() {
+        builder.append_pax_extensions(records.iter().map(|(key, value)| (*key, value.as_bytes())))?;
+    }
+    let mut header = fill_header(tar::Header::new_ustar(), tar::EntryType::Regular, 0o644, 0);
+    header.set_uid(12);
+    header.set_gid(13);
+    builder.entry(header, path, b"")?;
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for (path, user, group) in [("./known", "root", "root"), ("./unknown", "tarfs-no-such-user", "tarfs-no-such-group")] {
+        let mut header = fill_header(tar::Header::new_ustar(), tar::EntryType::Regular, 0o644, 0);
+        header.set_uid(4242);
+        header.set_gid(4343);
+        header.set_username(user)?;
+        header.set_groupname(group)?;
+        builder.entry(header, path, b"")?;
+    }
+    builder.finish()
+}
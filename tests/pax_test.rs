@@ -0,0 +1,119 @@
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{fill_header, ArchiveBuilder, TarFsTest};
+
+/// PAX mtime values and the (secs, nsecs) they should show up as
+const PAX_TIMES: [(&str, &str, i64, i64); 7] = [
+    ("no-fraction", "1556277134", 1556277134, 0),
+    ("zero-fraction", "1556277134.0", 1556277134, 0),
+    ("half", "1556277134.5", 1556277134, 500_000_000),
+    ("millis", "1556277134.123", 1556277134, 123_000_000),
+    ("trailing-zero", "1556277134.27993590", 1556277134, 279_935_900),
+    ("nanos", "1556277134.000000005", 1556277134, 5),
+    ("too-precise", "1556277134.1234567891234", 1556277134, 123_456_789),
+];
+
+#[test]
+fn tarfs_pax_sub_second_times() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("pax_times");
+    let archive_path = test.archive_path("pax_times.tar")?;
+    build_pax_times_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        use std::os::unix::fs::MetadataExt;
+        for (name, value, secs, nsecs) in PAX_TIMES.iter() {
+            let mut path = PathBuf::from(mountpoint);
+            path.push(name);
+            let meta = fs::metadata(&path)?;
+            assert_eq!((*secs, *nsecs), (meta.mtime(), meta.mtime_nsec()), "mtime {}", value);
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_pax_global_header() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("pax_global");
+    let archive_path = test.archive_path("pax_global.tar")?;
+    build_pax_global_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        use std::os::unix::fs::MetadataExt;
+        // The global header itself must not show up as a file
+        let mut names: Vec<String> = fs::read_dir(mountpoint)?
+            .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_, _>>()?;
+        names.sort();
+        assert_eq!(vec!["global", "overridden"], names);
+
+        let meta = fs::metadata(mountpoint.join("global"))?;
+        assert_eq!((1556277134, 500_000_000), (meta.mtime(), meta.mtime_nsec()), "global mtime");
+        let meta = fs::metadata(mountpoint.join("overridden"))?;
+        assert_eq!((1556277135, 0), (meta.mtime(), meta.mtime_nsec()), "local mtime");
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn build_pax_times_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for (name, value, _, _) in PAX_TIMES.iter() {
+        builder.append_pax_extensions([("mtime", value.as_bytes())])?;
+
+        // set_path() would strip the leading "./" GNU tar writes
+        let mut header = fill_header(tar::Header::new_ustar(), tar::EntryType::Regular, 0o644, 0);
+        let path = format!("./{}", name);
+        header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_mtime(0);
+        header.set_cksum();
+        builder.append(&header, std::io::empty())?;
+    }
+    builder.finish()
+}
+
+/// Looks like git archive output: A global header first, entry paths without "./"
+fn build_pax_global_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+
+    let records = pax_records(&[("comment", "0123456789abcdef"), ("mtime", "1556277134.5")]);
+    let mut header = fill_header(tar::Header::new_ustar(), tar::EntryType::XGlobalHeader, 0o644, records.len() as u64);
+    header.set_path("pax_global_header")?;
+    header.set_mtime(0);
+    header.set_cksum();
+    builder.append(&header, records.as_slice())?;
+
+    for name in ["global", "overridden"].iter() {
+        if *name == "overridden" {
+            builder.append_pax_extensions([("mtime", "1556277135".as_bytes())])?;
+        }
+        let mut header = fill_header(tar::Header::new_ustar(), tar::EntryType::Regular, 0o644, 0);
+        header.set_path(name)?;
+        header.set_mtime(0);
+        header.set_cksum();
+        builder.append(&header, std::io::empty())?;
+    }
+    builder.finish()
+}
+
+/// Encodes "<length> <key>=<value>\n" records, the length including itself
+fn pax_records(records: &[(&str, &str)]) -> Vec<u8> {
+    let mut result = vec![];
+    for (key, value) in records {
+        let rest = format!(" {}={}\n", key, value);
+        let mut len = rest.len();
+        while len.to_string().len() + rest.len() != len {
+            len = len.to_string().len() + rest.len();
+        }
+        result.extend(format!("{}{}", len, rest).into_bytes());
+    }
+    result
+}
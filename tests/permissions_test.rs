@@ -0,0 +1,73 @@
+use std::path::Path;
+use std::process::Command;
+
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_check_permissions() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("check_permissions");
+    let archive_path = test.archive_path("secrets.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        check_permissions: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        // As root
+        assert!(as_user(None, mountpoint, "cat secret private/file && test -r secret")?);
+        assert!(!as_user(None, mountpoint, "test -x secret")?);
+
+        assert!(as_user(Some(65534), mountpoint, "cat public && test -r public && test -x public-dir && cd public-dir")?);
+        assert!(!as_user(Some(65534), mountpoint, "cat secret")?);
+        assert!(!as_user(Some(65534), mountpoint, "test -r secret")?);
+        assert!(!as_user(Some(65534), mountpoint, "ls private")?);
+        assert!(!as_user(Some(65534), mountpoint, "cd private")?);
+        // Looked up by root already, but still not searchable
+        assert!(!as_user(Some(65534), mountpoint, "cat private/file")?);
+        // Writable by its mode, but the mount isn't
+        assert!(!as_user(Some(65534), mountpoint, "test -w public")?);
+
+        // The owner may, even though others may not
+        assert!(as_user(Some(1000), mountpoint, "cat owned && cat private-group/file")?);
+        assert!(!as_user(Some(1001), mountpoint, "cat owned")?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// Whether script succeeds in mountpoint as user uid (with the same gid, no supplementary groups), root if None
+fn as_user(uid: Option<u32>, mountpoint: &Path, script: &str) -> std::io::Result<bool> {
+    let mut command = Command::new("setpriv");
+    if let Some(uid) = uid {
+        command.args([format!("--reuid={}", uid), format!("--regid={}", uid), String::from("--clear-groups")]);
+    }
+    let status = command.args(["sh", "-c", script]).current_dir(mountpoint).output()?.status;
+    Ok(status.success())
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    let entries = [
+        ("./secret", tar::EntryType::Regular, 0o600, 0, 0),
+        ("./public", tar::EntryType::Regular, 0o666, 0, 0),
+        ("./public-dir/", tar::EntryType::Directory, 0o755, 0, 0),
+        ("./private/", tar::EntryType::Directory, 0o700, 0, 0),
+        ("./private/file", tar::EntryType::Regular, 0o644, 0, 0),
+        ("./owned", tar::EntryType::Regular, 0o600, 1000, 1000),
+        ("./private-group/", tar::EntryType::Directory, 0o750, 0, 1000),
+        ("./private-group/file", tar::EntryType::Regular, 0o644, 0, 0),
+    ];
+    for (path, entry_type, mode, uid, gid) in entries {
+        let mut header = header(entry_type, mode, 0);
+        header.set_uid(uid);
+        header.set_gid(gid);
+        builder.entry(header, path, b"")?;
+    }
+    builder.finish()
+}
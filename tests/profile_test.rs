@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_record_and_prefetch_profile() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("record_and_prefetch_profile");
+    let archive_path = test.archive_path("layer.tar")?;
+    build_archive(&archive_path)?;
+    let profile = test.archive_path("profile")?;
+
+    let options = MountOptions {
+        record_profile: Some(profile.clone()),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        fs::read(mountpoint.join("bin/sh"))?;
+        fs::read(mountpoint.join("etc/passwd"))?;
+        fs::read(mountpoint.join("bin/sh"))?;
+        fs::metadata(mountpoint.join("usr/lib/libc.so"))?;
+
+        // The profile is written when unmounting
+        let profile = mountpoint.parent().unwrap().join("profile");
+        Command::new("umount").arg(mountpoint).output()?;
+        let start = Instant::now();
+        while fs::read(&profile)?.is_empty() && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!("/bin/sh\n/etc/passwd\n", fs::read_to_string(&profile)?);
+        Ok(())
+    })?;
+
+    // Still recording to the same profile
+    let options = MountOptions {
+        prefetch_profile: Some(profile.clone()),
+        record_profile: Some(profile),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let start = Instant::now();
+        loop {
+            let stats = fs::read_to_string(mountpoint.join(".tarfs/stats.json"))?;
+            if stats.contains(&format!("\"prefetched_bytes\":{},", 300_000 + 1000)) {
+                break;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "{}", stats);
+            thread::sleep(Duration::from_millis(10));
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for (path, size) in [("./bin/sh", 300_000), ("./etc/passwd", 1000), ("./usr/lib/libc.so", 2_000_000)] {
+        builder.entry(header(tar::EntryType::Regular, 0o755, 0), path, &vec![7; size])?;
+    }
+    builder.finish()
+}
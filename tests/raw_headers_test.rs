@@ -0,0 +1,44 @@
+use std::fs;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{build_archive, TarFsTest};
+
+const LONG_NAME: &str = "./a-name-longer-than-the-100-bytes-a-ustar-header-has-room-for-so-it-goes-into-a-gnu-long-name-header-before-it";
+
+#[test]
+fn tarfs_raw_headers() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("raw_headers");
+    let archive_path = test.archive_path("raw.tar")?;
+    build_archive(&archive_path, &[("./file", b"content\n"), (LONG_NAME, b"content\n")])?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        let archive = fs::read(mountpoint.parent().unwrap().join("raw.tar"))?;
+        let raw = mountpoint.join(".tarfs/raw");
+
+        let mut names: Vec<_> = fs::read_dir(mountpoint.join(".tarfs"))?.map(|e| e.map(|e| e.file_name())).collect::<Result<_, _>>()?;
+        names.sort();
+        assert_eq!(vec![Path::new("info.json").as_os_str(), Path::new("raw").as_os_str(), Path::new("stats.json").as_os_str()], names);
+        let names: Vec<_> = fs::read_dir(&raw)?.map(|e| e.map(|e| e.file_name())).collect::<Result<_, _>>()?;
+        assert_eq!(vec![Path::new("0.hdr").as_os_str(), Path::new("1.hdr").as_os_str()], names);
+
+        // A plain header
+        assert_eq!(512, fs::metadata(raw.join("0.hdr"))?.len());
+        assert_eq!(&archive[0..512], fs::read(raw.join("0.hdr"))?.as_slice());
+        // The long name header, its payload and the actual header, following the first member's content
+        assert_eq!(3 * 512, fs::metadata(raw.join("1.hdr"))?.len());
+        let header = fs::read(raw.join("1.hdr"))?;
+        assert_eq!(&archive[1024..1024 + 3 * 512], header.as_slice());
+        assert_eq!(b"././@LongLink", &header[..13]);
+
+        assert!(fs::metadata(raw.join("2.hdr")).is_err());
+        assert!(fs::metadata(raw.join("01.hdr")).is_err());
+        assert!(fs::metadata(raw.join("1")).is_err());
+        Ok(())
+    })?;
+
+    Ok(())
+}
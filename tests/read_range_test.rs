@@ -0,0 +1,49 @@
+use std::io;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_read_range() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("read_range");
+    let archive_path = test.archive_path("read_range.tar")?;
+    build_archive(&archive_path)?;
+
+    let index = tarfslib::index_tar(&archive_path, &Default::default())?;
+    assert_eq!(b"0123456789".to_vec(), index.read_range(Path::new("dir/digits"), 0, 100)?);
+    assert_eq!(b"345".to_vec(), index.read_range(Path::new("/dir/digits"), 3, 3)?);
+    // Fewer at the end, none beyond it
+    assert_eq!(b"89".to_vec(), index.read_range(Path::new("dir/digits"), 8, 5)?);
+    assert_eq!(Vec::<u8>::new(), index.read_range(Path::new("dir/digits"), 20, 5)?);
+    // Hard links read the file they name
+    assert_eq!(b"012".to_vec(), index.read_range(Path::new("link"), 0, 3)?);
+
+    let (stat, _) = index.walk().find(|(stat, _)| stat.path == Path::new("dir/digits")).ok_or("dir/digits not walked")?;
+    assert_eq!(b"9".to_vec(), index.read_range(stat.ino, 9, 1)?);
+
+    let error = index.read_range(Path::new("dir"), 0, 1).err().ok_or("read a directory")?;
+    assert_eq!("dir: not a file", error.to_string());
+    let error = index.read_range(Path::new("missing"), 0, 1).err().ok_or("read a missing file")?;
+    assert_eq!("missing: no such member", error.to_string());
+    let error = index.read_range(12345, 0, 1).err().ok_or("read a missing ino")?;
+    assert_eq!("ino 12345: no such member", error.to_string());
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .entry(user_header(tar::EntryType::Regular), "dir/digits", b"0123456789")?
+        .link(user_header(tar::EntryType::Link), "link", "dir/digits")?
+        .finish()
+}
+
+fn user_header(entry_type: tar::EntryType) -> tar::Header {
+    let mut header = header(entry_type, 0o644, 0);
+    header.set_uid(1000);
+    header.set_gid(100);
+    header
+}
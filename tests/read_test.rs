@@ -0,0 +1,69 @@
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::TarFsTest;
+
+/// 700 bytes, so the member ends in the middle of a block with the next one's header behind it
+fn content() -> Vec<u8> {
+    (0..700).map(|i| b'a' + (i % 26) as u8).collect()
+}
+
+#[test]
+fn tarfs_read_member_boundaries() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("read_boundaries");
+    let archive_path = test.archive_path("boundaries.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        decompress_members: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        for name in ["first", "compressed", "last"] {
+            check_reads(&mountpoint.join(name))?;
+        }
+        // Nothing to read at all
+        let mut buf = [0xff; 16];
+        assert_eq!(0, File::open(mountpoint.join("empty"))?.read_at(&mut buf, 0)?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn check_reads(path: &Path) -> std::io::Result<()> {
+    let content = content();
+    let file = File::open(path)?;
+    let mut buf = vec![0xff; 1024];
+    let name = path.display();
+
+    // Up to the end, short at the end, nothing beyond it
+    for (offset, expected) in [(0, 700), (100, 600), (699, 1), (700, 0), (701, 0), (4096, 0), (1 << 40, 0)] {
+        buf.fill(0xff);
+        let n = file.read_at(&mut buf, offset)?;
+        assert_eq!(expected, n, "{} bytes at {}", name, offset);
+        let start = (offset as usize).min(content.len());
+        assert_eq!(&content[start..start + n], &buf[..n], "{} content at {}", name, offset);
+        // No padding either
+        assert!(buf[n..].iter().all(|b| *b == 0xff), "{} untouched beyond {} bytes at {}", name, n, offset);
+    }
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(&content())?;
+    let compressed = encoder.finish()?;
+    common::build_archive(archive_path, &[("first", &content()), ("compressed.gz", &compressed), ("empty", b""), ("last", &content())])
+}
@@ -0,0 +1,176 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{MountOptions, TarFsError};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_redefined_file_as_dir() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("redefined_file_as_dir");
+    let archive_path = test.archive_path("redefined.tar")?;
+    build_archive(&archive_path, &[
+        ("foo", tar::EntryType::Regular, b"old\n"),
+        ("foo", tar::EntryType::Directory, b""),
+        ("foo/bar", tar::EntryType::Regular, b"bar\n"),
+    ])?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        assert_eq!(vec!["foo"], list_dir(mountpoint)?);
+        let meta = fs::metadata(mountpoint.join("foo"))?;
+        assert!(meta.is_dir(), "foo is a directory");
+        assert_eq!(0o700, meta.permissions().mode() & 0o7777);
+        assert_eq!(vec!["bar"], list_dir(&mountpoint.join("foo"))?);
+        assert_eq!(b"bar\n".to_vec(), fs::read(mountpoint.join("foo/bar"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_redefined_dir_as_file() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("redefined_dir_as_file");
+    let archive_path = test.archive_path("redefined.tar")?;
+    build_archive(&archive_path, &[
+        ("foo", tar::EntryType::Directory, b""),
+        ("foo/bar", tar::EntryType::Regular, b"bar\n"),
+        ("foo/sub/baz", tar::EntryType::Regular, b"baz\n"),
+        ("foo", tar::EntryType::Regular, b"new\n"),
+        ("other", tar::EntryType::Regular, b"other\n"),
+    ])?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        assert_eq!(vec!["foo", "other"], list_dir(mountpoint)?);
+        let meta = fs::metadata(mountpoint.join("foo"))?;
+        assert!(meta.is_file(), "foo is a file");
+        assert_eq!(0o644, meta.permissions().mode() & 0o7777);
+        // Its content went with the directory
+        assert_eq!(b"new\n".to_vec(), fs::read(mountpoint.join("foo"))?);
+        assert_eq!(Some(libc::ENOTDIR), fs::metadata(mountpoint.join("foo/bar")).unwrap_err().raw_os_error());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_redefined_dir_as_file_followed() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("redefined_dir_as_file_followed");
+    let archive_path = test.archive_path("redefined.tar")?;
+    build_archive(&archive_path, &[
+        ("foo", tar::EntryType::Directory, b""),
+        ("foo/bar", tar::EntryType::Regular, b"bar\n"),
+        ("foo/sub/baz", tar::EntryType::Regular, b"baz\n"),
+        ("foo", tar::EntryType::Regular, b"new\n"),
+        ("other", tar::EntryType::Directory, b""),
+        ("other/bar", tar::EntryType::Regular, b"bar\n"),
+        ("other", tar::EntryType::Regular, b"file\n"),
+        ("other", tar::EntryType::Directory, b""),
+    ])?;
+
+    // Appended entries are added one by one, rather than by indexing the archive as a whole
+    let options = MountOptions {
+        follow: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(vec!["foo", "other"], list_dir(mountpoint)?);
+        assert!(fs::metadata(mountpoint.join("foo"))?.is_file(), "foo is a file");
+        assert_eq!(b"new\n".to_vec(), fs::read(mountpoint.join("foo"))?);
+        assert_eq!(Some(libc::ENOTDIR), fs::metadata(mountpoint.join("foo/sub/baz")).unwrap_err().raw_os_error());
+        // A directory again, without the content of the first one
+        assert_eq!(Vec::<String>::new(), list_dir(&mountpoint.join("other"))?);
+        assert_eq!(Some(libc::ENOENT), fs::metadata(mountpoint.join("other/bar")).unwrap_err().raw_os_error());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_redefined_file() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("redefined_file");
+    let archive_path = test.archive_path("redefined.tar")?;
+    // Like tar -r appending a newer version
+    build_archive(&archive_path, &[
+        ("foo", tar::EntryType::Regular, b"first\n"),
+        ("foo", tar::EntryType::Regular, b"the second version\n"),
+    ])?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        assert_eq!(vec!["foo"], list_dir(mountpoint)?);
+        assert_eq!(b"the second version\n".to_vec(), fs::read(mountpoint.join("foo"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_redefined_strict() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("redefined_strict");
+    let options = MountOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let mountpoint = test.archive_path("mnt")?;
+    fs::create_dir_all(&mountpoint)?;
+
+    let archive_path = test.archive_path("file_then_dir.tar")?;
+    build_archive(&archive_path, &[
+        ("foo", tar::EntryType::Regular, b"old\n"),
+        ("foo", tar::EntryType::Directory, b""),
+    ])?;
+    match tarfslib::setup_tar_mount(&archive_path, &mountpoint, &options, None).unwrap_err() {
+        TarFsError::EntryError { index, offset, path, msg } => {
+            assert_eq!((1, 1024, Some(PathBuf::from("./foo"))), (index, offset, path));
+            assert_eq!("./foo is defined as Directory after being defined as RegularFile", msg);
+        },
+        e => panic!("unexpected error: {:?}", e),
+    }
+
+    let archive_path = test.archive_path("dir_then_file.tar")?;
+    build_archive(&archive_path, &[
+        ("foo", tar::EntryType::Directory, b""),
+        ("foo", tar::EntryType::Regular, b"new\n"),
+    ])?;
+    match tarfslib::setup_tar_mount(&archive_path, &mountpoint, &options, None).unwrap_err() {
+        TarFsError::EntryError { msg, .. } => assert_eq!("./foo is defined as RegularFile after being defined as Directory", msg),
+        e => panic!("unexpected error: {:?}", e),
+    }
+
+    // The same type again is fine
+    let archive_path = test.archive_path("file_twice.tar")?;
+    build_archive(&archive_path, &[
+        ("foo", tar::EntryType::Regular, b"first\n"),
+        ("foo", tar::EntryType::Regular, b"second\n"),
+    ])?;
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(b"second\n".to_vec(), fs::read(mountpoint.join("foo"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn list_dir(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+fn build_archive(archive_path: &Path, entries: &[(&str, tar::EntryType, &[u8])]) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for (path, entry_type, content) in entries {
+        builder.entry(header(*entry_type, if entry_type.is_dir() { 0o700 } else { 0o644 }, 0), path, content)?;
+    }
+    builder.finish()
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{build_archive, ArchiveBuilder, TarFsTest};
+
+const CONTROL_SOCKET: &str = "/workspace/tarfs/.test/reload/control.sock";
+
+#[test]
+fn tarfs_reload() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("reload");
+    let archive_path = test.archive_path("old.tar")?;
+    build_archive(&archive_path, &[("./file", b"old\n")])?;
+    build_archive(&test.archive_path("new.tar")?, &[("./file", b"new content\n"), ("./added", b"added\n")])?;
+
+    let options = MountOptions {
+        control_socket: Some(PathBuf::from(CONTROL_SOCKET)),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let dir = mountpoint.parent().unwrap();
+        let mut control = UnixStream::connect(CONTROL_SOCKET)?;
+        let mut lines = BufReader::new(control.try_clone()?).lines();
+        let mut query = move |command: &str| -> std::io::Result<String> {
+            writeln!(control, "{}", command)?;
+            lines.next().unwrap()
+        };
+
+        assert_eq!("old\n", fs::read_to_string(mountpoint.join("file"))?);
+        assert!(!mountpoint.join("added").exists());
+
+        assert_eq!("{\"error\":\"reload needs the path of the archive\"}", query("reload")?);
+        let response = query(&format!("reload {}", dir.join("missing.tar").display()))?;
+        assert!(response.starts_with("{\"error\":\"unable to reload from "), "{}", response);
+        assert_eq!("old\n", fs::read_to_string(mountpoint.join("file"))?);
+
+        let file = File::open(mountpoint.join("file"))?;
+        let new_archive = dir.join("new.tar");
+        assert_eq!("{\"error\":\"busy: files are open (see busy, or reload --force)\"}", query(&format!("reload {}", new_archive.display()))?);
+        drop(file);
+
+        // The kernel releases files asynchronously
+        let mut response = String::new();
+        for _ in 0..50 {
+            response = query(&format!("reload {}", new_archive.display()))?;
+            if !response.contains("busy") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!("{\"reloaded\":true}", response);
+        assert_eq!("new content\n", fs::read_to_string(mountpoint.join("file"))?);
+        assert_eq!("added\n", fs::read_to_string(mountpoint.join("added"))?);
+
+        // Forced while busy
+        let file = File::open(mountpoint.join("added"))?;
+        assert_eq!("{\"reloaded\":true}", query(&format!("reload --force {}", dir.join("old.tar").display()))?);
+        drop(file);
+        assert_eq!("old\n", fs::read_to_string(mountpoint.join("file"))?);
+        assert!(!mountpoint.join("added").exists());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_reload_extended_failure() -> Result<(), Box<dyn std::error::Error>> {
+    const CONTROL_SOCKET: &str = "/workspace/tarfs/.test/reload_extended/control.sock";
+    let test = TarFsTest::new("reload_extended");
+    let archive_path = test.archive_path("old.tar")?;
+    build_archive(&archive_path, &[("./file", b"old\n")])?;
+
+    // Appended to as tar -r does, redefining file as directory
+    let mut content = fs::read(&archive_path)?;
+    content.truncate(content.len() - 1024);
+    let mut builder = ArchiveBuilder::new(content);
+    builder.dir("./file")?;
+    fs::write(test.archive_path("extended.tar")?, builder.into_inner()?)?;
+
+    let options = MountOptions {
+        control_socket: Some(PathBuf::from(CONTROL_SOCKET)),
+        strict: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let extended = mountpoint.parent().unwrap().join("extended.tar");
+        let mut control = UnixStream::connect(CONTROL_SOCKET)?;
+        writeln!(control, "reload {}", extended.display())?;
+        let response = BufReader::new(control).lines().next().unwrap()?;
+        // Located in the extended archive as a whole, not just the part scanned
+        let expected = format!("{{\"error\":\"unable to reload from {}, still serving the old archive: entry #1 at offset 1024: ./file is defined as Directory after being defined as RegularFile\"}}", extended.display());
+        assert_eq!(expected, response);
+        assert_eq!("old\n", fs::read_to_string(mountpoint.join("file"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
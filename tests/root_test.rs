@@ -0,0 +1,215 @@
+use std::fs;
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{InoMode, MountOptions, RootTime};
+
+mod common;
+use common::{fill_header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_root_from_mountpoint() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("root_from_mountpoint");
+    let archive_path = test.archive_path("root.tar")?;
+    build_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        use std::os::unix::fs::MetadataExt;
+        // The "./" entry neither shows up as a child nor changes the root
+        assert_eq!(vec!["file"], list_dir(mountpoint)?);
+        assert_ne!(0o750, fs::metadata(mountpoint)?.mode() & 0o7777);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_root_from_archive() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("root_from_archive");
+    let archive_path = test.archive_path("root.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        root_from_archive: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        use std::os::unix::fs::MetadataExt;
+        let meta = fs::metadata(mountpoint)?;
+        assert!(meta.is_dir());
+        assert_eq!((0o750, 1000, 1001, 1556277134), (meta.mode() & 0o7777, meta.uid(), meta.gid(), meta.mtime()));
+        assert_eq!(vec!["file"], list_dir(mountpoint)?);
+        assert_eq!(b"file\n".to_vec(), fs::read(mountpoint.join("file"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_root_dotdot() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("root_dotdot");
+    let archive_path = test.archive_path("dotdot.tar")?;
+    build_nested_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, check_dotdot)?;
+    Ok(())
+}
+
+#[test]
+fn tarfs_root_dotdot_offset_inos() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("root_dotdot_offset_inos");
+    let archive_path = test.archive_path("dotdot.tar")?;
+    build_nested_archive(&archive_path)?;
+
+    // The root keeps ino 1 whatever the other entries get
+    let options = MountOptions {
+        ino_mode: InoMode::Offset,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, check_dotdot)?;
+    Ok(())
+}
+
+#[test]
+fn tarfs_root_time_newest() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("root_time_newest");
+    let archive_path = build_dated_archive(&test)?;
+
+    let options = MountOptions {
+        root_time: RootTime::Newest,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        use std::os::unix::fs::MetadataExt;
+        let meta = fs::metadata(mountpoint)?;
+        assert_eq!((1556277134, 1556277134), (meta.mtime(), meta.ctime()));
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_root_time_archive() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("root_time_archive");
+    let archive_path = build_dated_archive(&test)?;
+
+    let options = MountOptions {
+        root_time: RootTime::Archive,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        use std::os::unix::fs::MetadataExt;
+        assert_eq!(1600000000, fs::metadata(mountpoint)?.mtime());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_root_time_fixed() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("root_time_fixed");
+    let archive_path = build_dated_archive(&test)?;
+
+    let options = MountOptions {
+        root_time: RootTime::Fixed(UNIX_EPOCH + Duration::from_secs(1500000000)),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        use std::os::unix::fs::MetadataExt;
+        assert_eq!(1500000000, fs::metadata(mountpoint)?.mtime());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn check_dotdot(mountpoint: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::MetadataExt;
+    // The root is ino 1, and its ".." is itself
+    assert_eq!(1, fs::metadata(mountpoint)?.ino());
+    assert_eq!((1, 1), dot_inos(mountpoint)?);
+    // Everywhere else ".." is the parent, as getattr presents it
+    let dir = mountpoint.join("dir");
+    let sub = dir.join("sub");
+    assert_eq!((fs::metadata(&dir)?.ino(), 1), dot_inos(&dir)?);
+    assert_eq!((fs::metadata(&sub)?.ino(), fs::metadata(&dir)?.ino()), dot_inos(&sub)?);
+    Ok(())
+}
+
+/// The inos readdir gives for "." and ".." (which std's read_dir skips)
+fn dot_inos(dir: &Path) -> io::Result<(u64, u64)> {
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    let (mut dot, mut dotdot) = (None, None);
+    unsafe {
+        let stream = libc::opendir(path.as_ptr());
+        if stream.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        loop {
+            let entry = libc::readdir(stream);
+            if entry.is_null() {
+                break;
+            }
+            match CStr::from_ptr((*entry).d_name.as_ptr()).to_bytes() {
+                b"." => dot = Some((*entry).d_ino),
+                b".." => dotdot = Some((*entry).d_ino),
+                _ => (),
+            }
+        }
+        libc::closedir(stream);
+    }
+    match (dot, dotdot) {
+        (Some(dot), Some(dotdot)) => Ok((dot, dotdot)),
+        _ => Err(io::Error::other(format!("no . or .. in {}", dir.display()))),
+    }
+}
+
+fn list_dir(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+/// Like "tar cf root.tar -C dir ." creates it: an explicit "./" entry first
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+
+    // set_path() would reject "./"
+    let mut header = fill_header(tar::Header::new_ustar(), tar::EntryType::Directory, 0o750, 0);
+    header.as_old_mut().name[..2].copy_from_slice(b"./");
+    header.set_uid(1000);
+    header.set_gid(1001);
+    header.set_cksum();
+    builder.append(&header, std::io::empty())?;
+
+    builder.file("./file", b"file\n")?;
+    builder.finish()
+}
+
+/// Entries of 2019, the archive itself modified in 2020
+fn build_dated_archive(test: &TarFsTest) -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    let archive_path = test.archive_path("root_time.tar")?;
+    build_nested_archive(&archive_path)?;
+    File::options().write(true).open(&archive_path)?.set_modified(UNIX_EPOCH + Duration::from_secs(1600000000))?;
+    Ok(archive_path)
+}
+
+fn build_nested_archive(archive_path: &Path) -> std::io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .dir("dir")?
+        .dir("dir/sub")?
+        .finish()
+}
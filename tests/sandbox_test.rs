@@ -0,0 +1,38 @@
+// The sandbox applies to the whole process, so this is the only test in here
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::process::Command;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{build_archive, TarFsTest};
+
+#[test]
+fn tarfs_sandbox() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("sandbox");
+    let archive_path = test.archive_path("sandbox.tar")?;
+    build_archive(&archive_path, &[("./file", b"content\n")])?;
+
+    let options = MountOptions {
+        sandbox: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(b"content\n".to_vec(), fs::read(mountpoint.join("file"))?);
+
+        let e = Command::new("true").status().expect_err("exec is not allowed");
+        assert_eq!(Some(libc::EPERM), e.raw_os_error());
+
+        // sudo umount (see TarFsTest) isn't possible anymore either
+        let mountpoint = CString::new(mountpoint.as_os_str().as_bytes())?;
+        assert_eq!(0, unsafe { libc::umount2(mountpoint.as_ptr(), libc::MNT_DETACH) });
+        Ok(())
+    })?;
+
+    Ok(())
+}
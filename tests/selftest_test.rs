@@ -0,0 +1,41 @@
+use std::fs;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::TarFsTest;
+
+#[test]
+fn tarfs_selftest_synthetic_tree() -> Result<(), Box<dyn std::error::Error>> {
+    let selftest = tarfslib::selftest(None, &Default::default())?;
+
+    assert_eq!(Vec::<String>::new(), selftest.mismatches);
+    assert_eq!(19, selftest.checked);
+    Ok(())
+}
+
+#[test]
+fn tarfs_selftest_dir() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("selftest");
+    let dir = test.archive_path("dir")?;
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("sub"))?;
+    fs::write(dir.join("sub/file"), "content\n")?;
+    std::os::unix::fs::symlink("sub/file", dir.join("link"))?;
+
+    let selftest = tarfslib::selftest(Some(&dir), &Default::default())?;
+
+    assert_eq!(Vec::<String>::new(), selftest.mismatches);
+    assert_eq!(3, selftest.checked);
+    Ok(())
+}
+
+#[test]
+fn tarfs_selftest_missing_dir() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("selftest_missing");
+    let dir = test.archive_path("missing")?;
+
+    assert!(tarfslib::selftest(Some(&dir), &Default::default()).is_err());
+    Ok(())
+}
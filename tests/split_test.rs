@@ -0,0 +1,34 @@
+use std::fs;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{build_archive, TarFsTest};
+
+/// Not a multiple of the block size, so headers and content span parts
+const PART_SIZE: usize = 1000;
+
+#[test]
+fn tarfs_split_archive() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("split");
+    let archive_path = test.archive_path("split.tar")?;
+    build_archive(&archive_path, &[("./big", &content()), ("./dir/small", b"small\n")])?;
+    let archive = fs::read(&archive_path)?;
+    for (n, part) in archive.chunks(PART_SIZE).enumerate() {
+        fs::write(archive_path.with_extension(format!("tar.{:03}", n)), part)?;
+    }
+    fs::remove_file(&archive_path)?;
+
+    test.perform_on(&archive_path.with_extension("tar.000"), |mountpoint| {
+        assert_eq!(content(), fs::read(mountpoint.join("big"))?);
+        assert_eq!("small\n", fs::read_to_string(mountpoint.join("dir/small"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn content() -> Vec<u8> {
+    (0..5000u32).map(|i| (i % 251) as u8).collect()
+}
@@ -0,0 +1,99 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_stat_file() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("stat_file");
+    let archive_path = test.archive_path("stat.tar")?;
+    build_archive(&archive_path)?;
+
+    let stat = tarfslib::stat_in_tar(&archive_path, Path::new("logs/old/b.log"), &Default::default())?;
+    assert_eq!(PathBuf::from("logs/old/b.log"), stat.path);
+    assert_eq!(libc::S_IFREG | 0o640, stat.mode);
+    assert_eq!((1000, 100, 4, 1), (stat.uid, stat.gid, stat.size, stat.nlink));
+    assert_eq!(UNIX_EPOCH + Duration::from_secs(1556277134), stat.mtime);
+    assert_eq!(None, stat.link_target);
+    assert_eq!(Some(1536), stat.header_offset);
+    assert_eq!(vec![(2048, 4)], stat.data);
+    assert!(!stat.compressed);
+
+    let lines: Vec<String> = stat.to_string().lines().map(String::from).collect();
+    assert!(lines.contains(&String::from("  Mode: 0640 (-rw-r-----)")), "unexpected output: {:?}", lines);
+    assert!(lines.contains(&String::from("Modify: 2019-04-26T11:12:14.000000000Z")), "unexpected output: {:?}", lines);
+    assert!(lines.contains(&String::from("  Data: 2048+4")), "unexpected output: {:?}", lines);
+
+    // Leading "./" and "/" name the same member
+    assert_eq!(stat, tarfslib::stat_in_tar(&archive_path, Path::new("./logs/old/b.log"), &Default::default())?);
+    assert_eq!(stat, tarfslib::stat_in_tar(&archive_path, Path::new("/logs/old/b.log"), &Default::default())?);
+    Ok(())
+}
+
+#[test]
+fn tarfs_stat_links() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("stat_links");
+    let archive_path = test.archive_path("stat.tar")?;
+    build_archive(&archive_path)?;
+    let options = Default::default();
+
+    // A hard link has its own header, but the content of the file it names
+    let same = tarfslib::stat_in_tar(&archive_path, Path::new("logs/same.log"), &options)?;
+    let a_log = tarfslib::stat_in_tar(&archive_path, Path::new("logs/a.log"), &options)?;
+    assert!(same.hard_link);
+    assert_eq!(Some(PathBuf::from("logs/a.log")), same.link_target);
+    assert_eq!((Some(2560), Some(0)), (same.header_offset, a_log.header_offset));
+    assert_eq!(vec![(512, 4)], same.data);
+    assert_eq!(a_log.data, same.data);
+    assert_eq!((2, 2), (same.nlink, a_log.nlink));
+    assert_eq!(a_log.ino, same.ino);
+
+    let current = tarfslib::stat_in_tar(&archive_path, Path::new("current"), &options)?;
+    assert!(!current.hard_link);
+    assert_eq!(libc::S_IFLNK | 0o777, current.mode);
+    assert_eq!(Some(PathBuf::from("logs/a.log")), current.link_target);
+    assert_eq!(Some(3072), current.header_offset);
+    assert!(current.data.is_empty());
+    assert!(current.to_string().contains("  Link: -> logs/a.log\n"));
+
+    // Synthesized, so without a header
+    let logs = tarfslib::stat_in_tar(&archive_path, Path::new("logs"), &options)?;
+    assert_eq!(libc::S_IFDIR, logs.mode & libc::S_IFMT);
+    assert_eq!(None, logs.header_offset);
+    assert!(logs.to_string().contains("Header: -\n"));
+    Ok(())
+}
+
+#[test]
+fn tarfs_stat_missing() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("stat_missing");
+    let archive_path = test.archive_path("stat.tar")?;
+    build_archive(&archive_path)?;
+
+    let err = tarfslib::stat_in_tar(&archive_path, Path::new("logs/c.log"), &Default::default()).unwrap_err();
+    assert_eq!(tarfslib::FailureKind::BadArchive, err.kind());
+    assert!(err.to_string().contains("logs/c.log: no such member"), "unexpected error: {}", err);
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .entry(user_header(tar::EntryType::Regular, 0o640), "logs/a.log", b"one\n")?
+        .entry(user_header(tar::EntryType::Directory, 0o755), "logs/old", b"")?
+        .entry(user_header(tar::EntryType::Regular, 0o640), "logs/old/b.log", b"two\n")?
+        .link(user_header(tar::EntryType::Link, 0o640), "logs/same.log", "logs/a.log")?
+        .link(user_header(tar::EntryType::Symlink, 0o777), "current", "logs/a.log")?
+        .finish()
+}
+
+fn user_header(entry_type: tar::EntryType, mode: u32) -> tar::Header {
+    let mut header = header(entry_type, mode, 0);
+    header.set_uid(1000);
+    header.set_gid(100);
+    header
+}
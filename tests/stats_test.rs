@@ -0,0 +1,112 @@
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::TarFsTest;
+
+const CONTROL_SOCKET: &str = "/workspace/tarfs/.test/stats/control.sock";
+const LOOKUPS_CONTROL_SOCKET: &str = "/workspace/tarfs/.test/stats_lookups/control.sock";
+
+#[test]
+fn tarfs_stats() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("stats");
+    let archive_path = test.archive_path("stats.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        control_socket: Some(PathBuf::from(CONTROL_SOCKET)),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        // Only found by name
+        let names: Vec<_> = fs::read_dir(mountpoint)?.map(|e| e.map(|e| e.file_name())).collect::<Result<_, _>>()?;
+        assert_eq!(vec![Path::new("file").as_os_str()], names);
+        assert_eq!(vec![Path::new("stats.json").as_os_str(), Path::new("info.json").as_os_str(), Path::new("raw").as_os_str()], fs::read_dir(mountpoint.join(".tarfs"))?
+            .map(|e| e.map(|e| e.file_name())).collect::<Result<Vec<_>, _>>()?);
+
+        let file = File::open(mountpoint.join("file"))?;
+        assert_eq!(b"content\n".to_vec(), fs::read(mountpoint.join("file"))?);
+        assert!(fs::metadata(mountpoint.join("missing")).is_err());
+
+        let stats = fs::read_to_string(mountpoint.join(".tarfs/stats.json"))?;
+        assert!(stats.contains("\"bytes_read\":8,"), "{}", stats);
+        assert!(stats.contains("\"open_handles\":1,"), "{}", stats);
+        assert!(stats.contains("\"errors\":0,"), "{}", stats);
+        drop(file);
+
+        // Reading stats.json doesn't count
+        let mut control = UnixStream::connect(CONTROL_SOCKET)?;
+        let mut lines = BufReader::new(control.try_clone()?).lines();
+        // The kernel releases files asynchronously
+        let mut stats = String::new();
+        for _ in 0..50 {
+            control.write_all(b"stats\n")?;
+            stats = lines.next().unwrap()?;
+            if stats.contains("\"open_handles\":0,") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(stats.contains("\"bytes_read\":8,"), "{}", stats);
+        assert!(stats.contains("\"open_handles\":0,"), "{}", stats);
+
+        control.write_all(b"nonsense\n")?;
+        assert_eq!("{\"error\":\"unknown command: nonsense\"}", lines.next().unwrap()?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_stats_referenced_inodes() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("stats_lookups");
+    let archive_path = test.archive_path("stats.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        control_socket: Some(PathBuf::from(LOOKUPS_CONTROL_SOCKET)),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let mut control = UnixStream::connect(LOOKUPS_CONTROL_SOCKET)?;
+        let mut lines = BufReader::new(control.try_clone()?).lines();
+        let mut query = move || -> std::io::Result<String> {
+            control.write_all(b"stats\n")?;
+            lines.next().unwrap()
+        };
+
+        fs::metadata(mountpoint.join("file"))?;
+        let stats = query()?;
+        assert!(stats.contains("\"referenced_inodes\":1,"), "{}", stats);
+
+        // Makes the kernel forget all unused inodes
+        fs::write("/proc/sys/vm/drop_caches", "2")?;
+        let mut stats = String::new();
+        for _ in 0..50 {
+            stats = query()?;
+            if stats.contains("\"referenced_inodes\":0,") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(stats.contains("\"referenced_inodes\":0,"), "{}", stats);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    common::build_archive(archive_path, &[("./file", b"content\n")])
+}
@@ -0,0 +1,129 @@
+use std::fs;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{MountOptions, SymlinkRewrite};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_absolute_symlinks_kept() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("absolute_symlinks_kept");
+    let archive_path = test.archive_path("symlinks.tar")?;
+    build_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        assert_eq!(Path::new("/lib/libx.so"), fs::read_link(mountpoint.join("usr/lib/libx.so"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_absolute_symlinks_relative() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("absolute_symlinks_relative");
+    let archive_path = test.archive_path("symlinks.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        rewrite_absolute_symlinks: Some(SymlinkRewrite::Relative),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(Path::new("../../lib/libx.so"), fs::read_link(mountpoint.join("usr/lib/libx.so"))?);
+        assert_eq!(Path::new("."), fs::read_link(mountpoint.join("root"))?);
+        assert_eq!(Path::new("../lib/libx.so"), fs::read_link(mountpoint.join("lib/relative"))?, "relative ones are kept");
+        assert_eq!(17, fs::symlink_metadata(mountpoint.join("usr/lib/libx.so"))?.len());
+        // Resolves inside the mount now
+        assert_eq!("ELF", fs::read_to_string(mountpoint.join("usr/lib/libx.so"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_absolute_symlinks_mountpoint() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("absolute_symlinks_mountpoint");
+    let archive_path = test.archive_path("symlinks.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        rewrite_absolute_symlinks: Some(SymlinkRewrite::Mountpoint),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(mountpoint.join("lib/libx.so"), fs::read_link(mountpoint.join("usr/lib/libx.so"))?);
+        assert_eq!("ELF", fs::read_to_string(mountpoint.join("usr/lib/libx.so"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_follow_symlinks() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("follow_symlinks");
+    let archive_path = test.archive_path("follow.tar")?;
+    build_follow_archive(&archive_path)?;
+
+    let options = MountOptions {
+        follow_symlinks: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        use std::os::unix::fs::MetadataExt;
+        let file = fs::symlink_metadata(mountpoint.join("data/file"))?;
+        for link in ["file-link", "chain"] {
+            let meta = fs::symlink_metadata(mountpoint.join(link))?;
+            assert!(meta.is_file(), "{} is presented as file", link);
+            assert_eq!(file.ino(), meta.ino(), "{} shares the ino", link);
+            assert_eq!("x", fs::read_to_string(mountpoint.join(link))?);
+        }
+
+        assert!(fs::symlink_metadata(mountpoint.join("dir-link"))?.is_dir());
+        assert_eq!("y", fs::read_to_string(mountpoint.join("dir-link/sub/deep"))?);
+        assert_eq!(file.ino(), fs::symlink_metadata(mountpoint.join("dir-link/file"))?.ino());
+
+        // Would make the tree infinite, or point nowhere
+        for link in ["loop", "loop2", "dangling", "data/up", "dir-link/up"] {
+            assert!(fs::symlink_metadata(mountpoint.join(link))?.file_type().is_symlink(), "{} stays a symlink", link);
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn build_follow_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for (path, content) in [("./data/file", "x"), ("./data/sub/deep", "y")] {
+        builder.entry(header(tar::EntryType::Regular, 0o777, 0), path, content.as_bytes())?;
+    }
+    let links = [
+        ("./file-link", "data/file"),
+        ("./dir-link", "data"),
+        ("./chain", "./file-link"),
+        ("./loop", "loop2"),
+        ("./loop2", "loop"),
+        ("./dangling", "/nonexistent"),
+        ("./data/up", ".."),
+    ];
+    for (path, target) in links {
+        builder.symlink(path, target)?;
+    }
+    builder.finish()
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .entry(header(tar::EntryType::Regular, 0o777, 0), "./lib/libx.so", b"ELF")?
+        .symlink("./usr/lib/libx.so", "/lib/libx.so")?
+        .symlink("./root", "/")?
+        .symlink("./lib/relative", "../lib/libx.so")?
+        .finish()
+}
@@ -0,0 +1,115 @@
+
+use std::fs;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{DirAttrs, DirMode, MountOptions};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_synthesized_dirs() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("synthesized_dirs");
+    let archive_path = test.archive_path("synthesized_dirs.tar")?;
+    build_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        use std::os::unix::fs::MetadataExt;
+        let root_meta = fs::metadata(mountpoint)?;
+        let archive_mtime = fs::metadata(mountpoint.parent().unwrap().join("synthesized_dirs.tar"))?.mtime();
+
+        for dir in ["a", "a/b", "a/b/c"].iter() {
+            let meta = fs::metadata(mountpoint.join(dir))?;
+            assert!(meta.is_dir(), "{} is dir", dir);
+            assert_eq!(0o755, meta.mode() & 0o7777, "{} mode", dir);
+            assert_eq!((root_meta.uid(), root_meta.gid()), (meta.uid(), meta.gid()), "{} owner", dir);
+            assert_eq!(archive_mtime, meta.mtime(), "{} mtime", dir);
+        }
+        assert_eq!(b"file\n".to_vec(), fs::read(mountpoint.join("a/b/c/file"))?);
+
+        // Showing up after its content, the archive's entry wins. Its children are listed once.
+        let meta = fs::metadata(mountpoint.join("late"))?;
+        assert_eq!((0o700, 1000, 1556277134), (meta.mode() & 0o7777, meta.uid(), meta.mtime()), "late dir attrs");
+        assert_eq!(vec!["f1", "f2"], list_dir(&mountpoint.join("late"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_synthesized_dirs_inherit_mode() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("synthesized_dirs_inherit");
+    let archive_path = test.archive_path("synthesized_dirs.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        dir_mode: DirMode::Inherit,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        use std::os::unix::fs::MetadataExt;
+        let root_mode = fs::metadata(mountpoint)?.mode() & 0o7777;
+        for dir in ["a", "a/b", "a/b/c"].iter() {
+            assert_eq!(root_mode, fs::metadata(mountpoint.join(dir))?.mode() & 0o7777, "{} mode", dir);
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_synthesized_dirs_from_children() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("synthesized_dirs_children");
+    let archive_path = test.archive_path("synthesized_dirs_children.tar")?;
+    let mut builder = ArchiveBuilder::create(&archive_path)?;
+    for (path, uid, mtime) in [("x/y/one", 1000, 1556277134), ("x/y/two", 2000, 1556277200), ("x/three", 3000, 1556277100)] {
+        let mut header = header(tar::EntryType::Regular, 0o644, 0);
+        header.set_uid(uid);
+        header.set_gid(uid);
+        header.set_mtime(mtime);
+        builder.entry(header, path, b"")?;
+    }
+    builder.finish()?;
+
+    let options = MountOptions {
+        dir_attrs: DirAttrs::Children,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        use std::os::unix::fs::MetadataExt;
+        // The owner of the first child, the newest mtime of all of them (including synthesized ones)
+        for dir in ["x", "x/y"] {
+            let meta = fs::metadata(mountpoint.join(dir))?;
+            assert_eq!((1000, 1000, 1556277200), (meta.uid(), meta.gid(), meta.mtime()), "{} attrs", dir);
+            assert_eq!(0o755, meta.mode() & 0o7777, "{} mode", dir);
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn list_dir(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+/// No directory entries but for "late", which comes after its content
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for path in ["a/b/c/file", "late/f1", "late/f2"].iter() {
+        builder.file(path, b"file\n")?;
+    }
+    let mut header = header(tar::EntryType::Directory, 0o700, 0);
+    header.set_uid(1000);
+    builder.entry(header, "late", b"")?;
+    builder.finish()
+}
@@ -1,10 +1,8 @@
-use pretty_assertions;
-use walkdir;
 
 use std::process::Command;
 use std::str;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::cmp::Ordering;
 
 #[cfg(test)]
@@ -12,7 +10,7 @@ use pretty_assertions::{assert_eq};
 use walkdir::WalkDir;
 
 mod common;
-use common::TarFsTest;
+use common::{header, ArchiveBuilder, TarFsTest};
 
 const HARDLINK_DST: &str = "hardlinkToa";
 const HARDLINK_SRC: &str = "a";
@@ -111,26 +109,29 @@ fn tarfs_recursive_compare() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 #[test]
-#[ignore]   // TODO Should word with hard links, why doesn't it??
 fn tarfs_hard_link() -> Result<(), Box<dyn std::error::Error>> {
-    let src_path = "tests/ar.dir";
-    let test = TarFsTest::new(src_path);
-
-    setup_hard_link(src_path)?;
-    println!("successfully prepared test");
-
-    test.perform(|mountpoint| {
-        let mut exp_link_path = PathBuf::from(mountpoint);
-        exp_link_path.push(HARDLINK_SRC);
-        let mut act_link_path = PathBuf::from(mountpoint);
-        act_link_path.push(HARDLINK_DST);
+    // Not tests/ar.dir: tarfs_recursive_compare mounts that one, in the same test dir
+    let test = TarFsTest::new("hard_link");
+    let archive_path = test.archive_path("hard_link.tar")?;
+    build_hard_link_archive(&archive_path)?;
 
+    test.perform_on(&archive_path, |mountpoint| {
         use std::os::unix::fs::MetadataExt;
-        let exp_meta = fs::metadata(&exp_link_path)?;
-        let act_meta = fs::metadata(&act_link_path)?;
-
-        // hard links should return same ino as target file
-        assert_eq!(exp_meta.ino(), act_meta.ino(), "ino");
+        let target = fs::metadata(mountpoint.join("dir/target"))?;
+        for link in ["dir/target", "link1", "dir/sub/link2"] {
+            let meta = fs::metadata(mountpoint.join(link))?;
+            // hard links should return same ino as target file
+            assert_eq!(target.ino(), meta.ino(), "ino of {}", link);
+            assert_eq!(3, meta.nlink(), "nlink of {}", link);
+            assert_eq!(target.mtime(), meta.mtime(), "mtime of {}", link);
+        }
+        assert_eq!(b"content".to_vec(), fs::read(mountpoint.join("dir/sub/link2"))?);
+
+        // Directories: 2 + subdirectories, whether part of the archive or not
+        assert_eq!(3, fs::metadata(mountpoint)?.nlink(), "root");
+        assert_eq!(4, fs::metadata(mountpoint.join("dir"))?.nlink(), "dir");
+        assert_eq!(2, fs::metadata(mountpoint.join("dir/sub"))?.nlink(), "dir/sub");
+        assert_eq!(2, fs::metadata(mountpoint.join("dir/empty"))?.nlink(), "dir/empty");
         Ok(())
     })?;
 
@@ -140,7 +141,7 @@ fn tarfs_hard_link() -> Result<(), Box<dyn std::error::Error>> {
 // Utils
 fn ls_al(path: &str) -> Result<String, Box<dyn std::error::Error>> {
     let out = Command::new("ls")
-            .args(&["-al", path])
+            .args(["-al", path])
             .output()?;
     Ok(str::from_utf8(&out.stdout)?.to_owned())
 }
@@ -151,6 +152,17 @@ fn path_cmp(e1: &walkdir::DirEntry, e2: &walkdir::DirEntry) -> Ordering {
         .unwrap_or(std::cmp::Ordering::Greater)
 }
 
+fn build_hard_link_archive(archive_path: &Path) -> std::io::Result<()> {
+    // dir/sub is missing in the archive
+    ArchiveBuilder::create(archive_path)?
+        .dir("./dir/")?
+        .dir("./dir/empty/")?
+        .entry(header(tar::EntryType::Regular, 0o755, 0), "./dir/target", b"content")?
+        .link(header(tar::EntryType::Link, 0o755, 0), "./link1", "./dir/target")?
+        .link(header(tar::EntryType::Link, 0o755, 0), "./dir/sub/link2", "./dir/target")?
+        .finish()
+}
+
 fn setup_hard_link(src_path: &str) -> std::io::Result<()> {
     let mut src = PathBuf::from(src_path);
     src.push(HARDLINK_SRC);
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::Path;
+use std::thread;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{ArchiveBuilder, TarFsTest};
+
+const FILES: u32 = 16;
+
+#[test]
+fn tarfs_threads() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("threads");
+    let archive_path = test.archive_path("threads.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        threads: Some(4),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, read_concurrently)?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_single_thread() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("single_thread");
+    let archive_path = test.archive_path("single_thread.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        threads: Some(1),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, read_concurrently)?;
+
+    Ok(())
+}
+
+fn read_concurrently(mountpoint: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let readers: Vec<_> = (0..FILES).map(|n| {
+        let path = mountpoint.join(format!("file{}", n));
+        thread::spawn(move || fs::read(path))
+    }).collect();
+    for (n, reader) in (0..FILES).zip(readers) {
+        assert_eq!(content(n), reader.join().unwrap()?);
+    }
+    Ok(())
+}
+
+fn content(n: u32) -> Vec<u8> {
+    (0..300_000u32).map(|i| ((i + n) % 251) as u8).collect()
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for n in 0..FILES {
+        builder.file(format!("./file{}", n), &content(n))?;
+    }
+    builder.finish()
+}
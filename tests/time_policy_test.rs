@@ -0,0 +1,16 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::TimePolicy;
+
+#[test]
+fn tarfs_time_policy_out_of_range() {
+    let time = UNIX_EPOCH + Duration::from_secs(1556277134);
+    let shifted = TimePolicy { offset: -3600, ..Default::default() };
+    assert_eq!(UNIX_EPOCH + Duration::from_secs(1556273534), shifted.apply(time));
+
+    // Shifted beyond what's representable, times stay as they are
+    assert_eq!(time, TimePolicy { offset: i64::MAX, ..Default::default() }.apply(time));
+}
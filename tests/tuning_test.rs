@@ -0,0 +1,41 @@
+use std::fs;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{FuseTuning, MountOptions};
+
+mod common;
+use common::{build_archive, TarFsTest};
+
+#[test]
+fn tarfs_fuse_tuning() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("fuse_tuning");
+    let archive_path = test.archive_path("tuning.tar")?;
+    build_archive(&archive_path, &[("./big", &content())])?;
+
+    let options = MountOptions {
+        tuning: FuseTuning {
+            max_read: Some(1024 * 1024),
+            max_readahead: Some(1024 * 1024),
+            max_background: Some(32),
+            congestion_threshold: Some(24),
+        },
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let mounts = fs::read_to_string("/proc/self/mounts")?;
+        let mount = mounts.lines()
+            .find(|line| line.split(' ').nth(1) == mountpoint.to_str())
+            .expect("tarfs is mounted");
+        assert!(mount.contains("max_read=1048576"), "{}", mount);
+        assert_eq!(content(), fs::read(mountpoint.join("big"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn content() -> Vec<u8> {
+    (0..4 * 1024 * 1024u32).map(|i| (i % 253) as u8).collect()
+}
@@ -0,0 +1,90 @@
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{MountOptions, TarFsError, UnsupportedPolicy};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_unsupported_as_file() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("unsupported_as_file");
+    let archive_path = test.archive_path("unsupported.tar")?;
+    build_unsupported_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        assert_eq!(vec!["fifo", "file", "null"], list_dir(mountpoint)?);
+        assert!(fs::metadata(mountpoint.join("fifo"))?.is_file(), "fifo is presented as file");
+        assert!(fs::metadata(mountpoint.join("null"))?.is_file(), "char device is presented as file");
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_unsupported_skip() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("unsupported_skip");
+    let archive_path = test.archive_path("unsupported.tar")?;
+    build_unsupported_archive(&archive_path)?;
+
+    let options = MountOptions {
+        unsupported: UnsupportedPolicy::Skip,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(vec!["file"], list_dir(mountpoint)?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_unsupported_error() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("unsupported_error");
+    let archive_path = test.archive_path("unsupported.tar")?;
+    build_unsupported_archive(&archive_path)?;
+
+    let options = MountOptions {
+        unsupported: UnsupportedPolicy::Error,
+        ..Default::default()
+    };
+    // Fails while indexing, before anything gets mounted
+    let mountpoint = test.archive_path("mnt")?;
+    fs::create_dir_all(&mountpoint)?;
+    match tarfslib::setup_tar_mount(&archive_path, &mountpoint, &options, None) {
+        Err(TarFsError::EntryError { path, msg, .. }) => {
+            assert_eq!(Some(PathBuf::from("./fifo")), path);
+            assert!(msg.contains("./fifo"), "unexpected message: {}", msg);
+        },
+        r => panic!("expected an EntryError, got {:?}", r),
+    }
+
+    Ok(())
+}
+
+fn list_dir(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+fn build_unsupported_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    let entries = [
+        ("./fifo", tar::EntryType::Fifo),
+        ("./file", tar::EntryType::Regular),
+        ("./null", tar::EntryType::Char),
+    ];
+    for (path, entry_type) in entries {
+        builder.entry(header(entry_type, 0o644, 0), path, b"")?;
+    }
+    builder.finish()
+}
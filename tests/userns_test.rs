@@ -0,0 +1,76 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{MountOptions, UsernsMode};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_userns_map() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("userns_map");
+    let archive_path = test.archive_path("owners.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        userns: UsernsMode::Map("0:100000:1000,1000:1000:1".parse()?),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!((100000, 100000), owner(&mountpoint.join("root"))?);
+        assert_eq!((100033, 100034), owner(&mountpoint.join("www"))?);
+        assert_eq!((1000, 1000), owner(&mountpoint.join("user"))?);
+        // Not mapped at all, presented as stored
+        assert_eq!((5000, 5000), owner(&mountpoint.join("other"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_userns_none() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("userns_none");
+    let archive_path = test.archive_path("owners.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        userns: UsernsMode::Off,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!((0, 0), owner(&mountpoint.join("root"))?);
+        assert_eq!((33, 34), owner(&mountpoint.join("www"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_userns_invalid_map() {
+    assert!("0:100000".parse::<UsernsMode>().is_err());
+    assert!("a:b:c".parse::<UsernsMode>().is_err());
+    assert_eq!(UsernsMode::Auto, "auto".parse().unwrap());
+}
+
+fn owner(path: &Path) -> std::io::Result<(u32, u32)> {
+    let metadata = fs::symlink_metadata(path)?;
+    Ok((metadata.uid(), metadata.gid()))
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    let files = [("./root", 0, 0), ("./www", 33, 34), ("./user", 1000, 1000), ("./other", 5000, 5000)];
+    for (path, uid, gid) in files {
+        let mut header = header(tar::EntryType::Regular, 0o644, 0);
+        header.set_uid(uid);
+        header.set_gid(gid);
+        builder.entry(header, path, b"")?;
+    }
+    builder.finish()
+}
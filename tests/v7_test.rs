@@ -0,0 +1,52 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{fill_header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_v7_directories() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("v7_directories");
+    let archive_path = test.archive_path("v7.tar")?;
+    build_v7_archive(&archive_path)?;
+
+    test.perform_on(&archive_path, |mountpoint| {
+        // Marked as directories only by the trailing slash
+        let meta = fs::metadata(mountpoint.join("dir"))?;
+        assert!(meta.is_dir(), "dir/ is a directory");
+        assert_eq!(0o750, meta.permissions().mode() & 0o7777);
+        assert!(fs::metadata(mountpoint.join("dir/sub"))?.is_dir(), "dir/sub/ is a directory");
+        assert_eq!(b"v7\n".to_vec(), fs::read(mountpoint.join("dir/file"))?);
+
+        // Empty files without the slash stay files
+        let meta = fs::metadata(mountpoint.join("empty"))?;
+        assert!(meta.is_file(), "empty is a file");
+        assert_eq!(0, meta.len());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// As written by V7 tar: old headers, directories with a trailing slash and typeflag NUL like files
+fn build_v7_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    let entries: [(&str, u32, &[u8]); 4] = [
+        ("dir/", 0o750, b""),
+        ("dir/file", 0o644, b"v7\n"),
+        ("dir/sub/", 0o755, b""),
+        ("empty", 0o644, b""),
+    ];
+    for (path, mode, content) in entries {
+        let mut header = fill_header(tar::Header::new_old(), tar::EntryType::Regular, mode, content.len() as u64);
+        header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.as_old_mut().linkflag[0] = 0;
+        header.set_cksum();
+        builder.append(&header, content)?;
+    }
+    builder.finish()
+}
@@ -0,0 +1,113 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::TarFsTest;
+
+const FILES: [(&str, &str); 3] = [("./good", "good content\n"), ("./dir/rotten", "rotten content\n"), ("./unlisted", "unlisted\n")];
+
+#[test]
+fn tarfs_verify_reads() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("verify_reads");
+    let archive_path = test.archive_path("verify.tar")?;
+    build_archive(&archive_path)?;
+
+    // Listed as sha256sum would, before a bit flipped in the archive
+    let sums_path = test.archive_path("SHA256SUMS")?;
+    let mut sums = String::from("# comments are fine\n");
+    for (path, content) in &FILES[..2] {
+        sums.push_str(&format!("{}  {}\n", sha256(content.as_bytes())?, path.trim_start_matches("./")));
+    }
+    fs::write(&sums_path, sums)?;
+    let mut archive = fs::read(&archive_path)?;
+    let rotten = archive.windows(7).position(|w| w == b"rotten ").unwrap();
+    archive[rotten] = b'R';
+    fs::write(&archive_path, archive)?;
+
+    let options = MountOptions {
+        verify_reads: Some(sums_path),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!("good content\n", fs::read_to_string(mountpoint.join("good"))?);
+        assert_eq!("unlisted\n", fs::read_to_string(mountpoint.join("unlisted"))?);
+        // Again, answered from memory
+        for _ in 0..2 {
+            let e = fs::read(mountpoint.join("dir/rotten")).unwrap_err();
+            assert_eq!(Some(libc::EIO), e.raw_os_error());
+        }
+        // Listing and stat work as always
+        assert_eq!(15, fs::metadata(mountpoint.join("dir/rotten"))?.len());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_verify_reads_incrementally() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("verify_reads_incrementally");
+    let archive_path = test.archive_path("verify.tar")?;
+    // Takes many reads, rotten at its very end
+    let mut content = vec![b'x'; 4 * 1024 * 1024];
+    let sums_path = test.archive_path("SHA256SUMS")?;
+    fs::write(&sums_path, format!("{}  big\n", sha256(&content)?))?;
+    *content.last_mut().unwrap() = b'y';
+    common::build_archive(&archive_path, &[("./big", &content)])?;
+
+    let options = MountOptions {
+        verify_reads: Some(sums_path),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        // Served before the member is verified
+        let mut start = [0; 4096];
+        File::open(mountpoint.join("big"))?.read_exact_at(&mut start, 0)?;
+        assert_eq!([b'x'; 4096], start);
+        // Reading on to the end reveals the corruption
+        let e = fs::read(mountpoint.join("big")).unwrap_err();
+        assert_eq!(Some(libc::EIO), e.raw_os_error());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_verify_reads_invalid_sums() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("verify_reads_invalid");
+    let archive_path = test.archive_path("verify.tar")?;
+    build_archive(&archive_path)?;
+    let sums_path = test.archive_path("SHA256SUMS")?;
+    fs::write(&sums_path, "not a digest  ./good\n")?;
+
+    let options = MountOptions {
+        verify_reads: Some(sums_path.clone()),
+        ..Default::default()
+    };
+    let mountpoint = test.archive_path("mnt")?;
+    fs::create_dir_all(&mountpoint)?;
+    let err = tarfslib::setup_tar_mount(&archive_path, &mountpoint, &options, None).unwrap_err();
+    assert_eq!(format!("unable to read the checksums {}: invalid checksum line 1: expected \"<sha256 digest>  <path>\"", sums_path.display()), err.to_string());
+    Ok(())
+}
+
+fn sha256(content: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+    let mut child = Command::new("sha256sum").stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+    child.stdin.take().unwrap().write_all(content)?;
+    let output = child.wait_with_output()?;
+    Ok(String::from_utf8(output.stdout)?.split_whitespace().next().unwrap().to_string())
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let files: Vec<_> = FILES.iter().map(|(path, content)| (*path, content.as_bytes())).collect();
+    common::build_archive(archive_path, &files)
+}
@@ -0,0 +1,69 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::Verification;
+
+mod common;
+use common::{ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_verify() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("verify");
+    let archive_path = test.archive_path("dataset.tar")?;
+    build_archive(&archive_path)?;
+
+    // Escaped by sha256sum, as the name contains a backslash
+    let sums = format!("{}  ./data/a.csv\n{}  data/b.csv\n{}  /data/gone.csv\n{} *data/link.csv\n\\{}  data/back\\\\slash.csv\n",
+        sha256("a\n")?, sha256("something else\n")?, sha256("gone\n")?, sha256("a\n")?, sha256("slash\n")?);
+    let sums_path = test.archive_path("SHA256SUMS")?;
+    fs::write(&sums_path, sums)?;
+
+    let verification = tarfslib::verify_tar(&archive_path, &sums_path, &Default::default())?;
+    assert_eq!(Verification {
+        ok: vec!(PathBuf::from("data/a.csv"), PathBuf::from("data/back\\slash.csv"), PathBuf::from("data/link.csv")),
+        mismatched: vec!(PathBuf::from("data/b.csv")),
+        missing: vec!(PathBuf::from("data/gone.csv")),
+        extra: vec!(PathBuf::from("data/extra.csv")),
+    }, verification);
+    assert!(!verification.is_ok());
+    Ok(())
+}
+
+#[test]
+fn tarfs_verify_ok() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("verify_ok");
+    let archive_path = test.archive_path("dataset.tar")?;
+    build_archive(&archive_path)?;
+
+    let sums = format!("{}  data/a.csv\n{}  data/b.csv\n{}  data/link.csv\n{}  data/extra.csv\n\\{}  data/back\\\\slash.csv\n",
+        sha256("a\n")?, sha256("b\n")?, sha256("a\n")?, sha256("extra\n")?, sha256("slash\n")?);
+    let sums_path = test.archive_path("SHA256SUMS")?;
+    fs::write(&sums_path, sums)?;
+
+    let verification = tarfslib::verify_tar(&archive_path, &sums_path, &Default::default())?;
+    assert!(verification.is_ok(), "{:?}", verification);
+    assert_eq!(5, verification.ok.len());
+    Ok(())
+}
+
+fn sha256(content: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut child = Command::new("sha256sum").stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+    child.stdin.take().unwrap().write_all(content.as_bytes())?;
+    let output = child.wait_with_output()?;
+    Ok(String::from_utf8(output.stdout)?.split_whitespace().next().unwrap().to_string())
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    let files = [("./data/a.csv", "a\n"), ("./data/b.csv", "b\n"), ("./data/extra.csv", "extra\n"), ("./data/back\\slash.csv", "slash\n")];
+    for (path, content) in files {
+        builder.file(path, content.as_bytes())?;
+    }
+    builder.hard_link("./data/link.csv", "./data/a.csv")?;
+    builder.finish()
+}
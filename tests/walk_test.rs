@@ -0,0 +1,80 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_walk() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("walk");
+    let archive_path = test.archive_path("walk.tar")?;
+    build_archive(&archive_path)?;
+
+    let index = tarfslib::index_tar(&archive_path, &Default::default())?;
+    let mut walked = vec!();
+    for (stat, reader) in index.walk() {
+        let content = match reader {
+            Some(mut reader) => {
+                let mut content = String::new();
+                reader.read_to_string(&mut content)?;
+                Some(content)
+            },
+            None => None,
+        };
+        walked.push((stat.path, content));
+    }
+    // Directories before their children, in the order of the archive
+    assert_eq!(vec![
+        (PathBuf::from("logs"), None),
+        (PathBuf::from("logs/old"), None),
+        (PathBuf::from("logs/old/b.log"), Some(String::from("two\n"))),
+        (PathBuf::from("logs/a.log"), Some(String::from("one\n"))),
+        (PathBuf::from("current"), None),
+        (PathBuf::from("same.log"), Some(String::from("one\n"))),
+    ], walked);
+    Ok(())
+}
+
+#[test]
+fn tarfs_walk_seek() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("walk_seek");
+    let archive_path = test.archive_path("walk.tar")?;
+    build_archive(&archive_path)?;
+
+    let index = tarfslib::index_tar(&archive_path, &Default::default())?;
+    let (_, reader) = index.walk().find(|(stat, _)| stat.path == Path::new("logs/a.log")).ok_or("logs/a.log not walked")?;
+    let mut reader = reader.ok_or("no reader for a file")?;
+    assert_eq!(4, reader.len());
+
+    let mut buf = [0; 2];
+    assert_eq!(2, reader.seek(SeekFrom::End(-2))?);
+    reader.read_exact(&mut buf)?;
+    assert_eq!(b"e\n", &buf);
+    // Nothing beyond the end
+    assert_eq!(0, reader.read(&mut buf)?);
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut buf)?;
+    assert_eq!(b"on", &buf);
+    assert!(reader.seek(SeekFrom::Current(-3)).is_err());
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> io::Result<()> {
+    ArchiveBuilder::create(archive_path)?
+        .entry(user_header(tar::EntryType::Directory), "logs/old", b"")?
+        .entry(user_header(tar::EntryType::Regular), "logs/old/b.log", b"two\n")?
+        .entry(user_header(tar::EntryType::Regular), "logs/a.log", b"one\n")?
+        .link(user_header(tar::EntryType::Symlink), "current", "logs/a.log")?
+        .link(user_header(tar::EntryType::Link), "same.log", "logs/a.log")?
+        .finish()
+}
+
+fn user_header(entry_type: tar::EntryType) -> tar::Header {
+    let mut header = header(entry_type, 0o644, 0);
+    header.set_uid(1000);
+    header.set_gid(100);
+    header
+}
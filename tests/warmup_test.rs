@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::{MountOptions, Warmup};
+
+mod common;
+use common::{header, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_warmup() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("warmup");
+    let archive_path = test.archive_path("layer.tar")?;
+    build_archive(&archive_path)?;
+
+    let all = tarfslib::warmup_tar(&archive_path, &[], &Default::default())?;
+    assert_eq!(Warmup { files: 3, bytes: 300_000 + 1000 + 20_000 }, all);
+
+    let paths = [PathBuf::from("/bin"), PathBuf::from("/usr/lib/libc.so"), PathBuf::from("/usr/lib/libc.so")];
+    let some = tarfslib::warmup_tar(&archive_path, &paths, &Default::default())?;
+    assert_eq!(Warmup { files: 2, bytes: 300_000 + 20_000 }, some);
+
+    let e = tarfslib::warmup_tar(&archive_path, &[PathBuf::from("/missing")], &Default::default()).expect_err("missing");
+    assert!(e.to_string().contains("/missing"), "{}", e);
+    Ok(())
+}
+
+#[test]
+fn tarfs_preload() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("preload");
+    let archive_path = test.archive_path("layer.tar")?;
+    build_archive(&archive_path)?;
+
+    let options = MountOptions {
+        preload: Some(vec!(PathBuf::from("/etc"))),
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        let start = Instant::now();
+        loop {
+            let stats = std::fs::read_to_string(mountpoint.join(".tarfs/stats.json"))?;
+            if stats.contains("\"prefetched_bytes\":1000,") {
+                break;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "{}", stats);
+            thread::sleep(Duration::from_millis(10));
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn build_archive(archive_path: &Path) -> std::io::Result<()> {
+    let mut builder = ArchiveBuilder::create(archive_path)?;
+    for (path, size) in [("./bin/sh", 300_000), ("./etc/passwd", 1000), ("./usr/lib/libc.so", 20_000)] {
+        builder.entry(header(tar::EntryType::Regular, 0o755, 0), path, &vec![7; size])?;
+    }
+    builder.finish()
+}
@@ -0,0 +1,114 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+use pretty_assertions::{assert_eq};
+
+use tarfslib::MountOptions;
+
+mod common;
+use common::{build_archive, ArchiveBuilder, TarFsTest};
+
+#[test]
+fn tarfs_watch_invalidates_changed_entries() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("watch_invalidate");
+    let archive_path = test.archive_path("watch.tar")?;
+    build_archive(&archive_path, &[("./a", b"one"), ("./b", b"two"), ("./sub/c", b"three")])?;
+
+    let options = MountOptions {
+        watch: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        // Gets the kernel to cache names, attrs and content
+        assert_eq!(3, fs::metadata(mountpoint.join("a"))?.len());
+        assert_eq!("two", fs::read_to_string(mountpoint.join("b"))?);
+        assert_eq!("three", fs::read_to_string(mountpoint.join("sub/c"))?);
+        let b_ino = fs::metadata(mountpoint.join("b"))?.ino();
+
+        // Replaced the usual way: written aside, then renamed over it
+        let archive_path = mountpoint.parent().unwrap().join("watch.tar");
+        let new_path = archive_path.with_extension("new");
+        build_archive(&new_path, &[("./a", b"eins"), ("./b", b"two"), ("./d", b"four")])?;
+        fs::rename(&new_path, &archive_path)?;
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while fs::metadata(mountpoint.join("a"))?.len() != 4 {
+            assert!(Instant::now() < deadline, "the attrs of a were not invalidated");
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!("eins", fs::read_to_string(mountpoint.join("a"))?);
+        assert_eq!("four", fs::read_to_string(mountpoint.join("d"))?);
+        assert!(!mountpoint.join("sub").exists());
+        let mut names: Vec<String> = fs::read_dir(mountpoint)?
+            .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_, _>>()?;
+        names.sort();
+        assert_eq!(vec!["a", "b", "d"], names);
+        // Unchanged
+        assert_eq!(b_ino, fs::metadata(mountpoint.join("b"))?.ino());
+        assert_eq!("two", fs::read_to_string(mountpoint.join("b"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn tarfs_watch_extends_appended_archive() -> Result<(), Box<dyn std::error::Error>> {
+    let test = TarFsTest::new("watch_extend");
+    let archive_path = test.archive_path("watch.tar")?;
+    build_archive(&archive_path, &[("./a", b"one"), ("./b", b"two"), ("./c", b"three")])?;
+
+    let options = MountOptions {
+        watch: true,
+        ..Default::default()
+    };
+    test.perform_on_with(&archive_path, &options, |mountpoint| {
+        assert_eq!(0o644, fs::metadata(mountpoint.join("b"))?.mode() & 0o7777);
+        let a_ino = fs::metadata(mountpoint.join("a"))?.ino();
+
+        // Appended to, as tar -r does: the new entry overwrites the end-of-archive blocks
+        let archive_path = mountpoint.parent().unwrap().join("watch.tar");
+        let mut content = fs::read(&archive_path)?;
+        content.truncate(content.len() - 1024);
+        let new_path = archive_path.with_extension("new");
+        fs::write(&new_path, &content)?;
+        let mut file = fs::OpenOptions::new().append(true).open(&new_path)?;
+        let mut builder = ArchiveBuilder::new(&mut file);
+        builder.file("./d", b"four")?;
+        builder.finish()?;
+        drop(builder);
+        fs::rename(&new_path, &archive_path)?;
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !mountpoint.join("d").exists() {
+            assert!(Instant::now() < deadline, "d was not added");
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!("four", fs::read_to_string(mountpoint.join("d"))?);
+        assert_eq!("three", fs::read_to_string(mountpoint.join("c"))?);
+        assert_eq!(a_ino, fs::metadata(mountpoint.join("a"))?.ino());
+
+        // Rewritten with the same layout, but the header of b changed: not an extended version, indexed from scratch
+        let mut content = fs::read(&archive_path)?;
+        let mut b_header = tar::Header::from_byte_slice(&content[1024..1536]).clone();
+        b_header.set_mode(0o600);
+        b_header.set_cksum();
+        content[1024..1536].copy_from_slice(b_header.as_bytes());
+        fs::write(&new_path, &content)?;
+        fs::rename(&new_path, &archive_path)?;
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while fs::metadata(mountpoint.join("b"))?.mode() & 0o7777 != 0o600 {
+            assert!(Instant::now() < deadline, "the mode of b didn't change");
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!("four", fs::read_to_string(mountpoint.join("d"))?);
+        Ok(())
+    })?;
+
+    Ok(())
+}